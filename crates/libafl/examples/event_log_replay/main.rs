@@ -0,0 +1,34 @@
+//! Reads an event log recorded by the `LlmpEventLogHook` of a broker, and prints a post-mortem report:
+//! the contribution of each client, the corpus size over time, and the given user stats over time.
+//!
+//! Usage: `event_log_replay <log> [user stat name]...`, for example `event_log_replay events.log edges`.
+//! The series are printed as CSV, in seconds since the start of the campaign.
+
+use std::env;
+
+use libafl::{Error, events::event_log::EventLogReplay};
+
+pub fn main() -> Result<(), Error> {
+    let mut args = env::args().skip(1);
+    let Some(path) = args.next() else {
+        return Err(Error::illegal_argument(
+            "Usage: event_log_replay <log> [user stat name]...",
+        ));
+    };
+    let replay = EventLogReplay::from_file(path)?;
+
+    print!("{replay}");
+
+    println!("\ncorpus over time\nseconds,testcases");
+    for (time, testcases) in replay.corpus_over_time() {
+        println!("{},{testcases}", time.as_secs());
+    }
+
+    for name in args {
+        println!("\n{name} over time\nseconds,{name}");
+        for (time, value) in replay.user_stat_over_time(&name) {
+            println!("{},{value}", time.as_secs());
+        }
+    }
+    Ok(())
+}
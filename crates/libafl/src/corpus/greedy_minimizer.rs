@@ -0,0 +1,457 @@
+//! A greedy corpus minimizer in the style of `afl-cmin`, without the `z3` dependency.
+//!
+//! For each covered map entry (optionally split by hitcount bucket), the input with the lowest weight
+//! (by default the smallest and fastest input) is the candidate for this entry.
+//! Entries are then visited from the rarest to the most common one, keeping the candidate of
+//! every entry not yet covered by the inputs kept so far.
+//! This scales to corpora with hundreds of thousands of entries.
+//!
+//! Besides minimizing the corpus of a state, the minimizer can distill an on-disk corpus directory.
+//! To run this on all cores, let each client of a `Launcher` call [`GreedyCorpusMinimizer::trace_dir`]
+//! with its own shard (see [`GreedyCorpusMinimizer::with_shard`]) and then call [`distill_dir`]
+//! once all clients are done.
+
+use alloc::vec::Vec;
+use core::{marker::PhantomData, time::Duration};
+#[cfg(feature = "std")]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use hashbrown::{HashMap, HashSet};
+#[cfg(feature = "std")]
+use libafl_bolts::fs::write_file_atomic;
+use libafl_bolts::{
+    Named, current_time,
+    tuples::{Handle, Handled},
+};
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "std")]
+use crate::corpus::Testcase;
+use crate::{
+    Error, HasScheduler,
+    corpus::{Corpus, CorpusId},
+    events::{EventFirer, LogSeverity},
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::Input,
+    observers::{MapObserver, ObserversTuple},
+    schedulers::{LenTimeMulTestcaseScore, RemovableScheduler, TestcaseScore},
+    state::{HasCorpus, HasExecutions},
+};
+
+/// The file extension of the traces written by [`GreedyCorpusMinimizer::trace_dir`]
+#[cfg(feature = "std")]
+pub const TRACE_FILE_EXTENSION: &str = "trace";
+
+/// A covered map entry: its index and, if hitcounts are considered, the bucket of its hitcount
+pub type CoverageFeature = (usize, u8);
+
+/// The coverage of a single input, together with the weight of this input (lower is better)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoverageTrace {
+    /// All features covered by this input
+    pub features: Vec<CoverageFeature>,
+    /// The weight of this input, usually a product of length and execution time
+    pub weight: u64,
+}
+
+impl CoverageTrace {
+    /// Collects the features covered in `map`, optionally bucketing the hitcounts like AFL does
+    #[must_use]
+    pub fn from_map<O>(map: &O, hitcounts: bool, weight: u64) -> Self
+    where
+        O: MapObserver,
+        O::Entry: ToPrimitive,
+    {
+        let initial = map.initial();
+        let features = (0..map.usable_count())
+            .filter_map(|idx| {
+                let entry = map.get(idx);
+                if entry == initial {
+                    return None;
+                }
+                let bucket = if hitcounts {
+                    hitcount_bucket(entry.to_u64().unwrap_or(u64::MAX))
+                } else {
+                    0
+                };
+                Some((idx, bucket))
+            })
+            .collect();
+        Self { features, weight }
+    }
+}
+
+/// The AFL hitcount bucket of `count`
+fn hitcount_bucket(count: u64) -> u8 {
+    match count {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        3 => 3,
+        4..=7 => 4,
+        8..=15 => 5,
+        16..=31 => 6,
+        32..=127 => 7,
+        _ => 8,
+    }
+}
+
+/// Greedily selects a subset of `traces` covering all of their features.
+///
+/// Returns the sorted indices of the selected traces.
+#[must_use]
+pub fn greedy_select(traces: &[CoverageTrace]) -> Vec<usize> {
+    // The lightest trace and the number of traces for each feature
+    let mut best: HashMap<CoverageFeature, usize> = HashMap::new();
+    let mut counts: HashMap<CoverageFeature, usize> = HashMap::new();
+    for (idx, trace) in traces.iter().enumerate() {
+        for feature in &trace.features {
+            *counts.entry(*feature).or_default() += 1;
+            let candidate = best.entry(*feature).or_insert(idx);
+            if trace.weight < traces[*candidate].weight {
+                *candidate = idx;
+            }
+        }
+    }
+
+    // Rarest features first, their candidates are the only way to cover them anyway
+    let mut features: Vec<(usize, CoverageFeature)> = counts
+        .into_iter()
+        .map(|(feature, count)| (count, feature))
+        .collect();
+    features.sort_unstable();
+
+    let mut covered = HashSet::new();
+    let mut selected = HashSet::new();
+    for (_, feature) in features {
+        if covered.contains(&feature) {
+            continue;
+        }
+        let idx = best[&feature];
+        if selected.insert(idx) {
+            covered.extend(traces[idx].features.iter().copied());
+        }
+    }
+
+    let mut selected: Vec<usize> = selected.into_iter().collect();
+    selected.sort_unstable();
+    selected
+}
+
+/// Minimizes a corpus by greedily keeping the lightest input for each covered map entry,
+/// weighting inputs by the specified `TestcaseScore`.
+///
+/// Works with any [`MapObserver`], and does not need `z3`, as opposed to `MapCorpusMinimizer`.
+#[derive(Debug)]
+pub struct GreedyCorpusMinimizer<C, O, TS> {
+    observer_handle: Handle<C>,
+    hitcounts: bool,
+    shard: (usize, usize),
+    phantom: PhantomData<(O, TS)>,
+}
+
+/// Standard greedy corpus minimizer, which weights inputs by length and time.
+pub type StdGreedyCorpusMinimizer<C, O> = GreedyCorpusMinimizer<C, O, LenTimeMulTestcaseScore>;
+
+impl<C, O, TS> GreedyCorpusMinimizer<C, O, TS>
+where
+    C: Named,
+{
+    /// Constructs a new [`GreedyCorpusMinimizer`], using the map of the given observer.
+    /// Hitcounts are ignored, every map entry different from the initial value counts as covered.
+    #[must_use]
+    pub fn new(obs: &C) -> Self {
+        Self {
+            observer_handle: obs.handle(),
+            hitcounts: false,
+            shard: (0, 1),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Consider the AFL hitcount buckets of map entries as separate features
+    #[must_use]
+    pub fn with_hitcounts(mut self, hitcounts: bool) -> Self {
+        self.hitcounts = hitcounts;
+        self
+    }
+
+    /// Only trace every `count`-th file, starting at `index`, in [`Self::trace_dir`].
+    /// Use this to split the tracing of a directory between multiple clients.
+    #[must_use]
+    pub fn with_shard(mut self, index: usize, count: usize) -> Self {
+        assert!(
+            index < count,
+            "Shard index {index} out of range for {count} shards"
+        );
+        self.shard = (index, count);
+        self
+    }
+}
+
+impl<C, O, TS> GreedyCorpusMinimizer<C, O, TS>
+where
+    C: AsRef<O>,
+    O: MapObserver,
+    O::Entry: ToPrimitive,
+{
+    /// Runs `input` and returns the covered features, the exit kind and the execution time
+    fn trace<E, EM, I, S, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        manager: &mut EM,
+        state: &mut S,
+        input: &I,
+    ) -> Result<(CoverageTrace, ExitKind, Duration), Error>
+    where
+        E: Executor<EM, I, S, Z> + HasObservers,
+        E::Observers: ObserversTuple<I, S>,
+    {
+        // Execute the input; we cannot rely on the metadata already being present.
+        executor.observers_mut().pre_exec_all(state, input)?;
+        let start = current_time();
+        let kind = executor.run_target(fuzzer, state, manager, input)?;
+        let exec_time = current_time().saturating_sub(start);
+        executor
+            .observers_mut()
+            .post_exec_all(state, input, &kind)?;
+
+        let observers = executor.observers();
+        let map = observers[&self.observer_handle].as_ref();
+        Ok((
+            CoverageTrace::from_map(map, self.hitcounts, 0),
+            kind,
+            exec_time,
+        ))
+    }
+
+    /// Minimizes the corpus of `state`, removing all entries not selected.
+    /// The current corpus entry is never removed.
+    pub fn minimize<CS, E, EM, I, S, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        manager: &mut EM,
+        state: &mut S,
+    ) -> Result<(), Error>
+    where
+        E: Executor<EM, I, S, Z> + HasObservers,
+        E::Observers: ObserversTuple<I, S>,
+        CS: RemovableScheduler<I, S>,
+        EM: EventFirer<I, S>,
+        I: Input,
+        S: HasCorpus<I> + HasExecutions,
+        TS: TestcaseScore<I, S>,
+        Z: HasScheduler<I, S, Scheduler = CS>,
+    {
+        let current = *state.corpus().current();
+        let total = state.corpus().count();
+        manager.log(
+            state,
+            LogSeverity::Info,
+            format!("Tracing {total} inputs for minimization..."),
+        )?;
+
+        let mut ids = Vec::with_capacity(total);
+        let mut traces = Vec::with_capacity(total);
+        let mut cur_id = state.corpus().first();
+        while let Some(id) = cur_id {
+            let input = state.corpus().cloned_input_for_id(id)?;
+            let (mut trace, _kind, exec_time) =
+                self.trace(fuzzer, executor, manager, state, &input)?;
+            {
+                let mut testcase = state.corpus().get(id)?.borrow_mut();
+                if testcase.exec_time().is_none() {
+                    testcase.set_exec_time(exec_time);
+                }
+                trace.weight = TS::compute(state, &mut *testcase)?
+                    .to_u64()
+                    .unwrap_or(u64::MAX);
+            }
+            ids.push(id);
+            traces.push(trace);
+            cur_id = state.corpus().next(id);
+        }
+
+        let selected: HashSet<CorpusId> = greedy_select(&traces)
+            .into_iter()
+            .map(|idx| ids[idx])
+            .collect();
+        let mut removed = 0;
+        for id in ids {
+            if selected.contains(&id) || Some(id) == current {
+                continue;
+            }
+            let testcase = state.corpus_mut().remove(id)?;
+            // scheduler needs to know we've removed the input, or it will continue to try
+            // to use now-missing inputs
+            fuzzer
+                .scheduler_mut()
+                .on_remove(state, id, &Some(testcase))?;
+            removed += 1;
+        }
+
+        manager.log(
+            state,
+            LogSeverity::Info,
+            format!("Minimization removed {removed} of {total} inputs"),
+        )?;
+        Ok(())
+    }
+
+    /// Traces the files of `input_dir` belonging to this shard, writing a `<file name>.trace` per input to `trace_dir`.
+    ///
+    /// Files which already have a trace are skipped, so that this can continue after a restart.
+    /// Inputs which crash or time out get an empty trace, they will not be kept by [`distill_dir`].
+    /// Returns the number of inputs traced.
+    #[cfg(feature = "std")]
+    pub fn trace_dir<E, EM, I, S, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        manager: &mut EM,
+        state: &mut S,
+        input_dir: &Path,
+        trace_dir: &Path,
+    ) -> Result<usize, Error>
+    where
+        E: Executor<EM, I, S, Z> + HasObservers,
+        E::Observers: ObserversTuple<I, S>,
+        I: Input,
+        S: HasCorpus<I>,
+        TS: TestcaseScore<I, S>,
+    {
+        fs::create_dir_all(trace_dir)?;
+        let (shard_index, shard_count) = self.shard;
+
+        let mut traced = 0;
+        for (idx, path) in dir_files(input_dir)?.into_iter().enumerate() {
+            if idx % shard_count != shard_index {
+                continue;
+            }
+            let trace_path = trace_path(trace_dir, &path);
+            if trace_path.exists() {
+                continue;
+            }
+            // Leave an empty trace behind in case this input takes down the process
+            fs::write(&trace_path, [])?;
+
+            let input = I::from_file(&path)?;
+            let (mut trace, kind, exec_time) =
+                self.trace(fuzzer, executor, manager, state, &input)?;
+            if kind != ExitKind::Ok {
+                continue;
+            }
+            let mut testcase = Testcase::new(input);
+            testcase.set_exec_time(exec_time);
+            trace.weight = TS::compute(state, &mut testcase)?
+                .to_u64()
+                .unwrap_or(u64::MAX);
+
+            fs::remove_file(&trace_path)?;
+            write_file_atomic(&trace_path, &postcard::to_allocvec(&trace)?)?;
+            traced += 1;
+        }
+        Ok(traced)
+    }
+}
+
+/// The sorted regular, non-hidden files in `dir`
+#[cfg(feature = "std")]
+fn dir_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && !entry.file_name().to_string_lossy().starts_with('.') {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The trace file for the input at `input_path`
+#[cfg(feature = "std")]
+fn trace_path(trace_dir: &Path, input_path: &Path) -> PathBuf {
+    let mut name = input_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(TRACE_FILE_EXTENSION);
+    trace_dir.join(name)
+}
+
+/// Selects the inputs of `input_dir` to keep, using the traces in `trace_dir` written by [`GreedyCorpusMinimizer::trace_dir`],
+/// and copies them to `output_dir`. Inputs without a (non-empty) trace are dropped.
+///
+/// Returns the number of inputs kept.
+#[cfg(feature = "std")]
+pub fn distill_dir(input_dir: &Path, trace_dir: &Path, output_dir: &Path) -> Result<usize, Error> {
+    let mut paths = Vec::new();
+    let mut traces = Vec::new();
+    for path in dir_files(input_dir)? {
+        let Ok(bytes) = fs::read(trace_path(trace_dir, &path)) else {
+            continue;
+        };
+        if bytes.is_empty() {
+            continue;
+        }
+        traces.push(postcard::from_bytes::<CoverageTrace>(&bytes)?);
+        paths.push(path);
+    }
+
+    fs::create_dir_all(output_dir)?;
+    let selected = greedy_select(&traces);
+    for idx in &selected {
+        let path = &paths[*idx];
+        let name = path.file_name().ok_or_else(|| {
+            Error::illegal_argument(format!("Invalid input file {}", path.display()))
+        })?;
+        fs::copy(path, output_dir.join(name))?;
+    }
+    log::info!(
+        "Distilled {} traced inputs of {} to {} inputs in {}",
+        traces.len(),
+        input_dir.display(),
+        selected.len(),
+        output_dir.display()
+    );
+    Ok(selected.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::{CoverageTrace, greedy_select, hitcount_bucket};
+
+    #[test]
+    fn test_greedy_select() {
+        let trace = |features: &[usize], weight| CoverageTrace {
+            features: features.iter().map(|idx| (*idx, 0)).collect(),
+            weight,
+        };
+        let traces = vec![
+            // Covers everything, but heavy
+            trace(&[0, 1, 2, 3], 100),
+            // Light, and together cover everything
+            trace(&[0, 1], 1),
+            trace(&[2, 3], 1),
+            // Redundant
+            trace(&[1, 2], 5),
+        ];
+        assert_eq!(greedy_select(&traces), vec![1, 2]);
+
+        // The only input covering 4 must be kept
+        let mut traces = traces;
+        traces.push(trace(&[3, 4], 50));
+        assert_eq!(greedy_select(&traces), vec![1, 2, 4]);
+
+        assert!(greedy_select(&[]).is_empty());
+        assert_eq!(hitcount_bucket(5), hitcount_bucket(7));
+        assert_ne!(hitcount_bucket(7), hitcount_bucket(8));
+    }
+}
@@ -30,6 +30,9 @@ pub use cached::CachedOnDiskCorpus;
 #[cfg(all(feature = "cmin", unix))]
 pub mod minimizer;
 
+pub mod greedy_minimizer;
+pub use greedy_minimizer::{GreedyCorpusMinimizer, StdGreedyCorpusMinimizer};
+
 pub mod nop;
 #[cfg(all(feature = "cmin", unix))]
 pub use minimizer::*;
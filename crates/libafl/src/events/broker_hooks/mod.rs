@@ -14,7 +14,10 @@ use serde::de::DeserializeOwned;
 use crate::events::llmp::COMPRESS_THRESHOLD;
 use crate::{
     Error,
-    events::{BrokerEventResult, Event, llmp::LLMP_TAG_EVENT_TO_BOTH},
+    events::{
+        BrokerEventResult, CustomEvent, Event,
+        llmp::{LLMP_FLAG_EVENT_CUSTOM, LLMP_TAG_EVENT_TO_BOTH, may_be_event_kind},
+    },
     monitors::{Monitor, stats::ClientStatsManager},
 };
 
@@ -30,6 +33,12 @@ pub mod centralized_multi_machine;
 #[cfg(all(unix, feature = "multi_machine"))]
 pub use centralized_multi_machine::*;
 
+/// Role routing hook
+#[cfg(feature = "std")]
+pub mod role_routing;
+#[cfg(feature = "std")]
+pub use role_routing::*;
+
 use super::EventWithStats;
 
 /// An LLMP-backed event hook for scalable multi-processed fuzzing
@@ -98,6 +107,83 @@ where
     }
 }
 
+/// An LLMP broker hook handling [`CustomEvent`]s of type `C` with a closure.
+///
+/// The closure decides if the event is forwarded to the clients, or handled in the broker.
+/// All other events are passed on to the next hook, so put this hook before the [`StdLlmpEventHook`].
+pub struct LlmpCustomEventHook<I, C, F> {
+    handler: F,
+    #[cfg(feature = "llmp_compression")]
+    compressor: GzipCompressor,
+    phantom: PhantomData<(I, C)>,
+}
+
+impl<I, C, F> core::fmt::Debug for LlmpCustomEventHook<I, C, F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LlmpCustomEventHook")
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I, C, F> LlmpCustomEventHook<I, C, F>
+where
+    C: CustomEvent,
+    F: FnMut(ClientId, &C) -> Result<BrokerEventResult, Error>,
+{
+    /// Create a new [`LlmpCustomEventHook`], calling `handler` for each [`CustomEvent`] of type `C`
+    pub fn new(handler: F) -> Self {
+        Self {
+            handler,
+            #[cfg(feature = "llmp_compression")]
+            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, C, F, SHM, SP> LlmpHook<SHM, SP> for LlmpCustomEventHook<I, C, F>
+where
+    I: DeserializeOwned,
+    C: CustomEvent,
+    F: FnMut(ClientId, &C) -> Result<BrokerEventResult, Error>,
+{
+    fn on_new_message(
+        &mut self,
+        _broker_inner: &mut LlmpBrokerInner<SHM, SP>,
+        client_id: ClientId,
+        msg_tag: &mut Tag,
+        msg_flags: &mut Flags,
+        msg: &mut [u8],
+        _new_msgs: &mut Vec<(Tag, Flags, Vec<u8>)>,
+    ) -> Result<LlmpMsgHookResult, Error> {
+        // Only custom events are of interest, skip the others without deserializing them
+        if *msg_tag != LLMP_TAG_EVENT_TO_BOTH
+            || !may_be_event_kind(*msg_flags, LLMP_FLAG_EVENT_CUSTOM)
+        {
+            return Ok(LlmpMsgHookResult::ForwardToClients);
+        }
+        #[cfg(not(feature = "llmp_compression"))]
+        let event_bytes = msg;
+        #[cfg(feature = "llmp_compression")]
+        let compressed;
+        #[cfg(feature = "llmp_compression")]
+        let event_bytes = if *msg_flags & LLMP_FLAG_COMPRESSED == LLMP_FLAG_COMPRESSED {
+            compressed = self.compressor.decompress(msg)?;
+            &compressed
+        } else {
+            &*msg
+        };
+        let event: EventWithStats<I> = postcard::from_bytes(event_bytes)?;
+        let Some(payload) = event.event().custom_payload::<C>()? else {
+            return Ok(LlmpMsgHookResult::ForwardToClients);
+        };
+        match (self.handler)(client_id, &payload)? {
+            BrokerEventResult::Forward => Ok(LlmpMsgHookResult::ForwardToClients),
+            BrokerEventResult::Handled => Ok(LlmpMsgHookResult::Handled),
+        }
+    }
+}
+
 impl<I, MT> StdLlmpEventHook<I, MT>
 where
     MT: Monitor,
@@ -198,7 +284,7 @@ where
                 log::log!((*severity_level).into(), "{message}");
                 Ok(BrokerEventResult::Handled)
             }
-            Event::Stop => Ok(BrokerEventResult::Forward),
+            Event::Stop | Event::Custom { .. } => Ok(BrokerEventResult::Forward),
             //_ => Ok(BrokerEventResult::Forward),
         }
     }
@@ -0,0 +1,103 @@
+use alloc::vec::Vec;
+use core::{fmt::Debug, marker::PhantomData};
+
+use libafl_bolts::{
+    ClientId, Error,
+    llmp::{Flags, LlmpBrokerInner, LlmpHook, LlmpMsgHookResult, Tag},
+};
+#[cfg(feature = "llmp_compression")]
+use libafl_bolts::{compress::GzipCompressor, llmp::LLMP_FLAG_COMPRESSED};
+use serde::de::DeserializeOwned;
+
+#[cfg(feature = "llmp_compression")]
+use crate::events::COMPRESS_THRESHOLD;
+use crate::events::{
+    Event, EventWithStats, LLMP_FLAG_EVENT_NEW_TESTCASE, LLMP_TAG_EVENT_TO_BOTH, may_be_event_kind,
+    roles::{LLMP_TAG_ROUTED_EVENT, RoleRoutes, route},
+};
+
+/// An LLMP broker hook routing new testcases to the [`crate::events::ClientRole`]s receiving them.
+///
+/// Testcases received by all roles are forwarded as-is. Others are re-sent with a header
+/// naming the receiving roles, so clients of other roles skip them without deserializing.
+/// Put this hook after the [`crate::events::StdLlmpEventHook`], so the monitor sees all testcases.
+pub struct LlmpRoleRoutingHook<I> {
+    routes: Option<RoleRoutes>,
+    #[cfg(feature = "llmp_compression")]
+    compressor: GzipCompressor,
+    phantom: PhantomData<I>,
+}
+
+impl<I> Debug for LlmpRoleRoutingHook<I> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LlmpRoleRoutingHook")
+            .field("routes", &self.routes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I> LlmpRoleRoutingHook<I> {
+    /// Create a new [`LlmpRoleRoutingHook`] for the given [`RoleRoutes`].
+    ///
+    /// With `None`, all messages are forwarded unchanged.
+    #[must_use]
+    pub fn new(routes: Option<RoleRoutes>) -> Self {
+        Self {
+            routes,
+            #[cfg(feature = "llmp_compression")]
+            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, SHM, SP> LlmpHook<SHM, SP> for LlmpRoleRoutingHook<I>
+where
+    I: DeserializeOwned,
+{
+    fn on_new_message(
+        &mut self,
+        _broker_inner: &mut LlmpBrokerInner<SHM, SP>,
+        client_id: ClientId,
+        msg_tag: &mut Tag,
+        msg_flags: &mut Flags,
+        msg: &mut [u8],
+        new_msgs: &mut Vec<(Tag, Flags, Vec<u8>)>,
+    ) -> Result<LlmpMsgHookResult, Error> {
+        let Some(routes) = &self.routes else {
+            return Ok(LlmpMsgHookResult::ForwardToClients);
+        };
+        // Only testcases are routed, skip other events without deserializing them
+        if *msg_tag != LLMP_TAG_EVENT_TO_BOTH
+            || !may_be_event_kind(*msg_flags, LLMP_FLAG_EVENT_NEW_TESTCASE)
+        {
+            return Ok(LlmpMsgHookResult::ForwardToClients);
+        }
+        #[cfg(not(feature = "llmp_compression"))]
+        let event_bytes = &*msg;
+        #[cfg(feature = "llmp_compression")]
+        let compressed;
+        #[cfg(feature = "llmp_compression")]
+        let event_bytes = if *msg_flags & LLMP_FLAG_COMPRESSED == LLMP_FLAG_COMPRESSED {
+            compressed = self.compressor.decompress(msg)?;
+            &compressed
+        } else {
+            &*msg
+        };
+        let event: EventWithStats<I> = postcard::from_bytes(event_bytes)?;
+        let Event::NewTestcase { client_config, .. } = event.event() else {
+            return Ok(LlmpMsgHookResult::ForwardToClients);
+        };
+        let receivers = routes.receivers(*client_config);
+        if receivers == routes.all() {
+            return Ok(LlmpMsgHookResult::ForwardToClients);
+        }
+        log::trace!("Routing a testcase of {client_id:?} to the roles {receivers:#b}");
+        new_msgs.push((
+            LLMP_TAG_ROUTED_EVENT,
+            *msg_flags,
+            route(receivers, client_id, msg),
+        ));
+        Ok(LlmpMsgHookResult::Handled)
+    }
+}
@@ -0,0 +1,375 @@
+//! Corpus-level deduplication and culling across all clients, in the broker.
+//!
+//! An [`LlmpCorpusCullingHook`] in the broker keeps a global coverage map of the campaign.
+//! It drops new testcases that neither add coverage to this map nor are smaller than the inputs covering it,
+//! so the other clients don't have to evaluate them,
+//! and periodically broadcasts the inputs that became redundant as [`CulledCorpus`] event.
+//! Clients disable these inputs in their corpus with the [`CorpusCullingHook`], see [`EnableDisableCorpus`].
+//!
+//! The coverage of a testcase is its [`MapIndexesMetadata`], so clients need a map feedback that tracks indexes,
+//! and have to share this metadata with `MetadataSharing::new().share::<MapIndexesMetadata>(..)`.
+//! Testcases without it are forwarded as usual.
+//! For each map index, the smallest input covering it is kept, inputs that are the smallest for no index are culled.
+//! Inputs are identified by their hash, so the same input is only kept once.
+//!
+//! The `CentralizedLauncher` adds the broker hook with its `corpus_culling` option,
+//! the inner managers of its `launch` come with the [`CorpusCullingHook`].
+//! For other setups, put the hook before the [`crate::events::StdLlmpEventHook`] of the broker,
+//! and add the [`CorpusCullingHook`] to the event managers of the clients.
+
+use alloc::vec::Vec;
+use core::{fmt, marker::PhantomData, mem, time::Duration};
+
+use hashbrown::{HashMap, HashSet, hash_map::Entry};
+use libafl_bolts::{
+    ClientId, current_time, generic_hash_std,
+    llmp::{Flags, LLMP_FLAG_INITIALIZED, LlmpBrokerInner, LlmpHook, LlmpMsgHookResult, Tag},
+};
+#[cfg(feature = "llmp_compression")]
+use libafl_bolts::{compress::GzipCompressor, llmp::LLMP_FLAG_COMPRESSED};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "llmp_compression")]
+use crate::events::llmp::COMPRESS_THRESHOLD;
+use crate::{
+    Error,
+    corpus::{Corpus, CorpusId, EnableDisableCorpus, HasCurrentCorpusId},
+    events::{
+        CustomEvent, Event, EventManagerHook, EventWithStats, SharedMetadata,
+        llmp::{
+            LLMP_FLAG_EVENT_CUSTOM, LLMP_FLAG_EVENT_NEW_TESTCASE, LLMP_TAG_EVENT_TO_BOTH,
+            may_be_event_kind,
+        },
+    },
+    feedbacks::map::MapIndexesMetadata,
+    inputs::Input,
+    state::HasCorpus,
+};
+
+/// The default interval between two broadcasts of a [`LlmpCorpusCullingHook`]
+pub const DEFAULT_CULLING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The inputs culled by the broker since its last broadcast, as hashes of the inputs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CulledCorpus {
+    /// The hashes of the culled inputs
+    pub inputs: Vec<u64>,
+}
+
+impl CustomEvent for CulledCorpus {
+    const NAME: &'static str = "libafl::CulledCorpus";
+}
+
+/// The global coverage map of all clients
+#[derive(Debug, Default)]
+struct GlobalCoverage {
+    /// The smallest input for each map index, as (input hash, size)
+    best: HashMap<usize, (u64, usize)>,
+    /// The number of map indexes each kept input is the smallest for
+    favored: HashMap<u64, usize>,
+    /// The hashes of all inputs seen so far
+    seen: HashSet<u64>,
+    /// The inputs culled since the last broadcast
+    culled: Vec<u64>,
+}
+
+impl GlobalCoverage {
+    /// Add the input with this hash, size, and map indexes.
+    ///
+    /// Returns `false` if it is neither the first nor the smallest input for any of its indexes,
+    /// or was seen before.
+    fn add(&mut self, hash: u64, size: usize, indexes: &[usize]) -> bool {
+        if !self.seen.insert(hash) {
+            return false;
+        }
+        let improves = indexes.iter().any(|idx| {
+            self.best
+                .get(idx)
+                .is_none_or(|(_, best_size)| size < *best_size)
+        });
+        if !improves {
+            self.culled.push(hash);
+            return false;
+        }
+        for &idx in indexes {
+            let old = match self.best.entry(idx) {
+                Entry::Vacant(entry) => {
+                    entry.insert((hash, size));
+                    None
+                }
+                Entry::Occupied(mut entry) => {
+                    let (old, old_size) = *entry.get();
+                    if size >= old_size {
+                        continue;
+                    }
+                    entry.insert((hash, size));
+                    Some(old)
+                }
+            };
+            *self.favored.entry(hash).or_default() += 1;
+            if let Some(old) = old {
+                if let Entry::Occupied(mut entry) = self.favored.entry(old) {
+                    *entry.get_mut() -= 1;
+                    if *entry.get() == 0 {
+                        entry.remove();
+                        self.culled.push(old);
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Get the map indexes from the [`MapIndexesMetadata`] in a serialized [`SharedMetadata`], if any
+fn shared_indexes(metadata_buf: &[u8]) -> Option<Vec<usize>> {
+    let shared: SharedMetadata = postcard::from_bytes(metadata_buf).ok()?;
+    let buf = shared.entry::<MapIndexesMetadata>()?;
+    Some(postcard::from_bytes::<MapIndexesMetadata>(buf).ok()?.list)
+}
+
+/// An LLMP broker hook keeping a global coverage map, dropping new testcases without new coverage,
+/// and broadcasting the culled inputs, see the [module docs](self).
+///
+/// Dropped testcases don't reach later hooks, so put it before the [`crate::events::StdLlmpEventHook`].
+pub struct LlmpCorpusCullingHook<I> {
+    coverage: GlobalCoverage,
+    interval: Duration,
+    last_broadcast: Duration,
+    #[cfg(feature = "llmp_compression")]
+    compressor: GzipCompressor,
+    phantom: PhantomData<I>,
+}
+
+impl<I> fmt::Debug for LlmpCorpusCullingHook<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LlmpCorpusCullingHook")
+            .field("indexes", &self.coverage.best.len())
+            .field("kept", &self.coverage.favored.len())
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I> Default for LlmpCorpusCullingHook<I> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CULLING_INTERVAL)
+    }
+}
+
+impl<I> LlmpCorpusCullingHook<I> {
+    /// Create a new [`LlmpCorpusCullingHook`], broadcasting the culled inputs at most once per `interval`
+    #[must_use]
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            coverage: GlobalCoverage::default(),
+            interval,
+            last_broadcast: current_time(),
+            #[cfg(feature = "llmp_compression")]
+            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            phantom: PhantomData,
+        }
+    }
+
+    /// The number of map indexes covered by all clients
+    #[must_use]
+    pub fn covered(&self) -> usize {
+        self.coverage.best.len()
+    }
+
+    /// Queue a [`CulledCorpus`] event for all clients, if the interval passed and inputs were culled
+    fn maybe_broadcast(&mut self, new_msgs: &mut Vec<(Tag, Flags, Vec<u8>)>) -> Result<(), Error>
+    where
+        I: Input,
+    {
+        let cur_time = current_time();
+        if self.coverage.culled.is_empty()
+            || cur_time.saturating_sub(self.last_broadcast) < self.interval
+        {
+            return Ok(());
+        }
+        self.last_broadcast = cur_time;
+
+        let culled = CulledCorpus {
+            inputs: mem::take(&mut self.coverage.culled),
+        };
+        log::info!("Broadcasting {} culled inputs", culled.inputs.len());
+        let event = EventWithStats::<I>::with_current_time(Event::custom(&culled)?, 0);
+        let serialized = postcard::to_allocvec(&event)?;
+        #[cfg(feature = "llmp_compression")]
+        if let Some(compressed) = self.compressor.maybe_compress(&serialized) {
+            new_msgs.push((
+                LLMP_TAG_EVENT_TO_BOTH,
+                LLMP_FLAG_INITIALIZED | LLMP_FLAG_EVENT_CUSTOM | LLMP_FLAG_COMPRESSED,
+                compressed,
+            ));
+            return Ok(());
+        }
+        new_msgs.push((
+            LLMP_TAG_EVENT_TO_BOTH,
+            LLMP_FLAG_INITIALIZED | LLMP_FLAG_EVENT_CUSTOM,
+            serialized,
+        ));
+        Ok(())
+    }
+}
+
+impl<I, SHM, SP> LlmpHook<SHM, SP> for LlmpCorpusCullingHook<I>
+where
+    I: Input,
+{
+    fn on_new_message(
+        &mut self,
+        _broker_inner: &mut LlmpBrokerInner<SHM, SP>,
+        client_id: ClientId,
+        msg_tag: &mut Tag,
+        msg_flags: &mut Flags,
+        msg: &mut [u8],
+        new_msgs: &mut Vec<(Tag, Flags, Vec<u8>)>,
+    ) -> Result<LlmpMsgHookResult, Error> {
+        if *msg_tag != LLMP_TAG_EVENT_TO_BOTH {
+            return Ok(LlmpMsgHookResult::ForwardToClients);
+        }
+        // Only testcases add coverage, skip other events without deserializing them
+        if !may_be_event_kind(*msg_flags, LLMP_FLAG_EVENT_NEW_TESTCASE) {
+            self.maybe_broadcast(new_msgs)?;
+            return Ok(LlmpMsgHookResult::ForwardToClients);
+        }
+        #[cfg(not(feature = "llmp_compression"))]
+        let event_bytes = msg;
+        #[cfg(feature = "llmp_compression")]
+        let compressed;
+        #[cfg(feature = "llmp_compression")]
+        let event_bytes = if *msg_flags & LLMP_FLAG_COMPRESSED == LLMP_FLAG_COMPRESSED {
+            compressed = self.compressor.decompress(msg)?;
+            &compressed
+        } else {
+            &*msg
+        };
+        let event: EventWithStats<I> = postcard::from_bytes(event_bytes)?;
+
+        let mut result = LlmpMsgHookResult::ForwardToClients;
+        if let Event::NewTestcase {
+            input,
+            metadata_buf: Some(metadata_buf),
+            ..
+        } = event.event()
+        {
+            if let Some(indexes) = shared_indexes(metadata_buf) {
+                let size = postcard::to_allocvec(input)?.len();
+                if !self.coverage.add(generic_hash_std(input), size, &indexes) {
+                    log::debug!("Dropping a testcase of {client_id:?} without new global coverage");
+                    result = LlmpMsgHookResult::Handled;
+                }
+            }
+        }
+
+        self.maybe_broadcast(new_msgs)?;
+        Ok(result)
+    }
+}
+
+/// An [`EventManagerHook`] disabling the inputs of a [`CulledCorpus`] event in the corpus of this client.
+///
+/// The testcase currently fuzzed is never disabled. Scheduler metadata is not updated,
+/// so use a scheduler that only picks enabled testcases, such as the [`crate::schedulers::QueueScheduler`].
+///
+/// Each corpus entry is hashed once, when the first event after its addition arrives.
+/// Entries whose input is replaced afterwards, for example by a minimization stage, are found by their old input.
+#[derive(Debug, Clone, Default)]
+pub struct CorpusCullingHook {
+    /// The corpus entry of each input hash
+    index: HashMap<u64, CorpusId>,
+    /// The last corpus entry in the index
+    indexed_up_to: Option<CorpusId>,
+}
+
+impl CorpusCullingHook {
+    /// Create a new [`CorpusCullingHook`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the corpus entries added since the last call to the index
+    fn update_index<I, S>(&mut self, state: &S) -> Result<(), Error>
+    where
+        I: Input,
+        S: HasCorpus<I>,
+    {
+        let new_ids: Vec<CorpusId> = state
+            .corpus()
+            .ids()
+            .filter(|id| self.indexed_up_to.is_none_or(|up_to| *id > up_to))
+            .collect();
+        for id in new_ids {
+            let hash = generic_hash_std(&state.corpus().cloned_input_for_id(id)?);
+            self.index.insert(hash, id);
+            self.indexed_up_to = self.indexed_up_to.max(Some(id));
+        }
+        Ok(())
+    }
+}
+
+impl<I, S> EventManagerHook<I, S> for CorpusCullingHook
+where
+    I: Input,
+    S: HasCorpus<I> + HasCurrentCorpusId,
+    S::Corpus: EnableDisableCorpus,
+{
+    fn pre_receive(
+        &mut self,
+        state: &mut S,
+        _client_id: ClientId,
+        event: &EventWithStats<I>,
+    ) -> Result<bool, Error> {
+        let Some(culled) = event.event().custom_payload::<CulledCorpus>()? else {
+            return Ok(true);
+        };
+        self.update_index(state)?;
+        let current = state.current_corpus_id()?;
+        let mut disabled = 0_usize;
+        for hash in culled.inputs {
+            let Some(&id) = self.index.get(&hash) else {
+                continue;
+            };
+            if Some(id) == current {
+                continue;
+            }
+            self.index.remove(&hash);
+            state.corpus_mut().disable(id)?;
+            disabled += 1;
+        }
+        log::debug!("Disabled {disabled} culled testcases");
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GlobalCoverage;
+
+    #[test]
+    fn test_global_coverage() {
+        let mut coverage = GlobalCoverage::default();
+        assert!(coverage.add(1, 10, &[0, 1]));
+        // No new coverage and not smaller, or seen before
+        assert!(!coverage.add(2, 10, &[0]));
+        assert!(!coverage.add(1, 10, &[0, 1, 2]));
+        assert_eq!(coverage.culled, [2]);
+
+        // No new coverage, but smaller for one index
+        assert!(coverage.add(5, 5, &[0]));
+        assert_eq!(coverage.best[&0], (5, 5));
+        assert_eq!(coverage.culled, [2]);
+
+        // Smaller for all indexes of the other inputs
+        assert!(coverage.add(3, 4, &[0, 1, 2]));
+        assert_eq!(coverage.culled, [2, 5, 1]);
+        // Larger, but with a new index
+        assert!(coverage.add(4, 20, &[1, 3]));
+        assert_eq!(coverage.culled, [2, 5, 1]);
+        assert_eq!(coverage.best[&1], (3, 4));
+        assert_eq!(coverage.best[&3], (4, 20));
+    }
+}
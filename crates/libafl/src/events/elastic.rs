@@ -0,0 +1,580 @@
+//! Scaling the clients of a [`Launcher`](crate::events::Launcher) up and down at runtime.
+//!
+//! If a [`Launcher`](crate::events::Launcher) is given a `control_socket`, the broker runs in its own process,
+//! and the launcher stays around as supervisor of the clients.
+//! It accepts [`ElasticCommand`]s on this unix socket, one per line, and answers each with one line.
+//! For example, `echo "scale 4" | socat - UNIX-CONNECT:/tmp/libafl.sock`.
+//!
+//! Drained clients stop at the next safe point, and store their state to disk.
+//! The next client added to the campaign resumes from this state.
+
+use alloc::{
+    collections::VecDeque,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    ptr,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use std::{
+    fs::{self, Permissions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+};
+
+use libafl_bolts::{core_affinity::CoreId, fs::write_file_atomic};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{Error, events::ClientDescription};
+
+/// How long to wait for a controller to send its commands
+const CONTROL_READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A command for an elastic [`Launcher`](crate::events::Launcher), sent over its control socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElasticCommand {
+    /// `list`: list the clients
+    List,
+    /// `scale <n>`: add or drain clients, until `n` clients are running
+    Scale(usize),
+    /// `add [core]`: add a client, on the given core, or else on the least busy one
+    Add(Option<CoreId>),
+    /// `drain <id>`: stop the client with this id, keeping its state
+    Drain(usize),
+    /// `move <id> <core>`: drain the client with this id, and resume it on another core
+    Move(usize, CoreId),
+}
+
+impl FromStr for ElasticCommand {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        fn arg<T: FromStr>(arg: Option<&str>, line: &str) -> Result<T, Error> {
+            arg.and_then(|arg| arg.parse().ok())
+                .ok_or_else(|| Error::illegal_argument(format!("Invalid command: {line}")))
+        }
+
+        let mut words = line.split_whitespace();
+        let cmd = match words.next() {
+            Some("list") => Self::List,
+            Some("scale") => Self::Scale(arg(words.next(), line)?),
+            Some("add") => match words.next() {
+                Some(core) => Self::Add(Some(CoreId(arg(Some(core), line)?))),
+                None => Self::Add(None),
+            },
+            Some("drain") => Self::Drain(arg(words.next(), line)?),
+            Some("move") => {
+                let id = arg(words.next(), line)?;
+                Self::Move(id, CoreId(arg(words.next(), line)?))
+            }
+            _ => return Err(Error::illegal_argument(format!("Unknown command: {line}"))),
+        };
+        if words.next().is_some() {
+            return Err(Error::illegal_argument(format!(
+                "Too many arguments: {line}"
+            )));
+        }
+        Ok(cmd)
+    }
+}
+
+/// The unix socket an elastic [`Launcher`](crate::events::Launcher) receives [`ElasticCommand`]s on
+#[derive(Debug)]
+pub(crate) struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Listen on `path`, replacing a stale socket from an earlier run.
+    ///
+    /// Only the owner may connect, since anyone connected controls the campaign.
+    /// Fails if `path` is not a socket, or another launcher is listening on it.
+    pub(crate) fn bind<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let listener = match UnixListener::bind(&path) {
+            Err(err)
+                if err.kind() == ErrorKind::AddrInUse
+                    && fs::symlink_metadata(&path)
+                        .is_ok_and(|meta| meta.file_type().is_socket())
+                    && UnixStream::connect(&path).is_err() =>
+            {
+                log::info!("Removing stale control socket {}", path.display());
+                fs::remove_file(&path)?;
+                UnixListener::bind(&path)
+            }
+            res => res,
+        }
+        .map_err(|err| {
+            Error::os_error(
+                err,
+                format!("Could not bind control socket {}", path.display()),
+            )
+        })?;
+        fs::set_permissions(&path, Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, path })
+    }
+
+    /// Handle the commands of all waiting controllers, without blocking if there are none
+    pub(crate) fn poll<F>(&self, mut handler: F) -> Result<(), Error>
+    where
+        F: FnMut(ElasticCommand) -> Result<String, Error>,
+    {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err.into()),
+            };
+            if let Err(err) = Self::serve(stream, &mut handler) {
+                log::warn!("Error on the control socket: {err}");
+            }
+        }
+    }
+
+    fn serve<F>(stream: UnixStream, handler: &mut F) -> Result<(), Error>
+    where
+        F: FnMut(ElasticCommand) -> Result<String, Error>,
+    {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(CONTROL_READ_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match line.parse().and_then(&mut *handler) {
+                Ok(reply) => format!("ok {reply}"),
+                Err(err) => format!("error {err}"),
+            };
+            writeln!(writer, "{}", reply.trim_end())?;
+        }
+        Ok(())
+    }
+
+    /// Stop listening, and remove the socket
+    pub(crate) fn remove(self) {
+        drop(fs::remove_file(&self.path));
+    }
+}
+
+/// Asks a client to drain: to stop fuzzing at the next safe point, and store its state to disk.
+///
+/// The flag lives on a shared mapping, so it is visible to all processes forked off the launcher.
+#[derive(Debug)]
+pub struct ClientDrain {
+    flag: *mut AtomicBool,
+    state_file: PathBuf,
+}
+
+// The flag is only ever accessed atomically
+unsafe impl Send for ClientDrain {}
+unsafe impl Sync for ClientDrain {}
+
+impl ClientDrain {
+    /// Create a new [`ClientDrain`], storing the state of the drained client to `state_file`
+    pub fn new(state_file: PathBuf) -> Result<Self, Error> {
+        // # Safety
+        // An anonymous mapping, the result is checked below
+        let flag = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size_of::<AtomicBool>(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if flag == libc::MAP_FAILED {
+            return Err(Error::last_os_error("Could not map the drain flag"));
+        }
+        // The mapping is zeroed, i.e., `false`
+        Ok(Self {
+            flag: flag.cast(),
+            state_file,
+        })
+    }
+
+    /// Ask the client to drain
+    pub fn request(&self) {
+        // # Safety
+        // The flag stays mapped for the lifetime of `self`
+        unsafe { &*self.flag }.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if the client should drain
+    #[must_use]
+    pub fn is_requested(&self) -> bool {
+        // # Safety
+        // The flag stays mapped for the lifetime of `self`
+        unsafe { &*self.flag }.load(Ordering::Acquire)
+    }
+
+    /// The file the state of the drained client is stored to
+    #[must_use]
+    pub fn state_file(&self) -> &Path {
+        &self.state_file
+    }
+
+    /// Store the state of the draining client
+    pub fn save_state<S>(&self, state: &S) -> Result<(), Error>
+    where
+        S: Serialize,
+    {
+        log::info!("Draining, storing state to {}", self.state_file.display());
+        write_file_atomic(&self.state_file, &postcard::to_allocvec(state)?)
+    }
+}
+
+impl Drop for ClientDrain {
+    fn drop(&mut self) {
+        // # Safety
+        // We mapped the flag in `new`
+        unsafe {
+            libc::munmap(self.flag.cast(), size_of::<AtomicBool>());
+        }
+    }
+}
+
+/// Load, and remove, the state a drained client stored to `state_file`
+pub fn load_drained_state<S>(state_file: &Path) -> Result<S, Error>
+where
+    S: DeserializeOwned,
+{
+    let state = postcard::from_bytes(&fs::read(state_file)?)?;
+    fs::remove_file(state_file)?;
+    Ok(state)
+}
+
+/// What a supervised client is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClientStatus {
+    Running,
+    /// Draining, to be resumed on the given core once it exited
+    Draining(Option<CoreId>),
+}
+
+/// A client spawned by an elastic [`Launcher`](crate::events::Launcher)
+#[derive(Debug)]
+struct ElasticClient {
+    description: ClientDescription,
+    pid: libc::pid_t,
+    drain: ClientDrain,
+    status: ClientStatus,
+}
+
+/// A client the supervisor should spawn
+#[derive(Debug)]
+pub(crate) struct ClientSpawn {
+    /// The description of the new client
+    pub(crate) description: ClientDescription,
+    /// The state to resume from, stored by a drained client
+    pub(crate) resume_from: Option<PathBuf>,
+    /// Asks the new client to drain
+    pub(crate) drain: ClientDrain,
+}
+
+/// The directory for the states of drained clients of this campaign, if the user gives none.
+///
+/// Created in the temp dir, only accessible by the owner, and unique to this launcher.
+pub(crate) fn default_drain_dir() -> Result<PathBuf, Error> {
+    let dir = std::env::temp_dir().join(format!("libafl_drained_{}", std::process::id()));
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)?;
+    Ok(dir)
+}
+
+/// The book-keeping of an elastic [`Launcher`](crate::events::Launcher)
+#[derive(Debug)]
+pub(crate) struct ElasticClients {
+    cores: Vec<CoreId>,
+    drain_dir: PathBuf,
+    clients: Vec<ElasticClient>,
+    /// Clients to spawn: on which core, `None` for the least busy one, and the state to resume from
+    pending: VecDeque<(Option<CoreId>, Option<PathBuf>)>,
+    /// States of drained clients, not yet resumed
+    drained_states: Vec<PathBuf>,
+    next_id: usize,
+}
+
+impl ElasticClients {
+    /// Keep track of the clients spawned on `cores`, storing the states of drained clients in `drain_dir`
+    pub(crate) fn new(cores: Vec<CoreId>, drain_dir: PathBuf) -> Self {
+        Self {
+            cores,
+            drain_dir,
+            clients: vec![],
+            pending: VecDeque::new(),
+            drained_states: vec![],
+            next_id: 1,
+        }
+    }
+
+    /// The number of clients that are running, or about to be spawned
+    pub(crate) fn running(&self) -> usize {
+        self.clients
+            .iter()
+            .filter(|client| client.status == ClientStatus::Running)
+            .count()
+            + self.pending.len()
+    }
+
+    /// The pids of all clients
+    pub(crate) fn pids(&self) -> impl Iterator<Item = libc::pid_t> + '_ {
+        self.clients.iter().map(|client| client.pid)
+    }
+
+    /// Add a client on `core`, or on the least busy core.
+    /// The new client resumes from the state of a drained client, if there is one.
+    pub(crate) fn add(&mut self, core: Option<CoreId>) {
+        self.pending.push_back((core, None));
+    }
+
+    /// Add or drain clients, until `n` are running
+    pub(crate) fn scale(&mut self, n: usize) -> Result<(), Error> {
+        while self.running() < n {
+            self.add(None);
+        }
+        while self.running() > n {
+            if self.pending.pop_back().is_some() {
+                continue;
+            }
+            // Drain the most recently added client
+            let id = self
+                .clients
+                .iter()
+                .filter(|client| client.status == ClientStatus::Running)
+                .map(|client| client.description.id())
+                .max()
+                .unwrap();
+            self.drain(id, None)?;
+        }
+        Ok(())
+    }
+
+    /// The next client to spawn, if any
+    pub(crate) fn next_spawn(&mut self) -> Result<Option<ClientSpawn>, Error> {
+        let Some((core, resume_from)) = self.pending.pop_front() else {
+            return Ok(None);
+        };
+        let core = match core {
+            Some(core) => core,
+            None => *self
+                .cores
+                .iter()
+                .min_by_key(|core| self.on_core(**core))
+                .ok_or_else(|| Error::illegal_argument("No cores to spawn on"))?,
+        };
+        let resume_from = resume_from.or_else(|| self.drained_states.pop());
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let drain = ClientDrain::new(self.drain_dir.join(format!("client_{id}.state")))?;
+        Ok(Some(ClientSpawn {
+            description: ClientDescription::new(id, self.on_core(core), core),
+            resume_from,
+            drain,
+        }))
+    }
+
+    /// The number of clients on this core
+    fn on_core(&self, core: CoreId) -> usize {
+        self.clients
+            .iter()
+            .filter(|client| client.description.core_id() == core)
+            .count()
+    }
+
+    /// Keep track of a client forked off as `pid`
+    pub(crate) fn spawned(&mut self, spawn: ClientSpawn, pid: libc::pid_t) {
+        log::info!(
+            "Client {} spawned as pid {pid} on core {:?}",
+            spawn.description.id(),
+            spawn.description.core_id()
+        );
+        self.clients.push(ElasticClient {
+            description: spawn.description,
+            pid,
+            drain: spawn.drain,
+            status: ClientStatus::Running,
+        });
+    }
+
+    /// Ask the client `id` to drain, and resume it on `resume_on` once it exited
+    pub(crate) fn drain(&mut self, id: usize, resume_on: Option<CoreId>) -> Result<(), Error> {
+        let client = self
+            .clients
+            .iter_mut()
+            .find(|client| client.description.id() == id)
+            .ok_or_else(|| Error::key_not_found(format!("No client with id {id}")))?;
+        if client.status != ClientStatus::Running {
+            return Err(Error::illegal_state(format!(
+                "Client {id} is already draining"
+            )));
+        }
+        client.drain.request();
+        client.status = ClientStatus::Draining(resume_on);
+        Ok(())
+    }
+
+    /// A client process exited
+    pub(crate) fn exited(&mut self, pid: libc::pid_t) {
+        let Some(idx) = self.clients.iter().position(|client| client.pid == pid) else {
+            return;
+        };
+        let client = self.clients.remove(idx);
+        let state_file = client.drain.state_file();
+        let drained = state_file.exists().then(|| state_file.to_path_buf());
+        log::info!(
+            "Client {} (pid {pid}) exited, state stored: {}",
+            client.description.id(),
+            drained.is_some()
+        );
+        match (client.status, drained) {
+            (ClientStatus::Draining(Some(core)), drained) => {
+                self.pending.push_back((Some(core), drained));
+            }
+            (_, Some(drained)) => self.drained_states.push(drained),
+            (_, None) => {}
+        }
+    }
+
+    /// A summary, and a description of each client, for [`ElasticCommand::List`]
+    pub(crate) fn list(&self) -> String {
+        let summary = format!(
+            "{} running, {} pending, {} drained states",
+            self.running() - self.pending.len(),
+            self.pending.len(),
+            self.drained_states.len()
+        );
+        let clients = self.clients.iter().map(|client| {
+            let status = match client.status {
+                ClientStatus::Running => "running".to_string(),
+                ClientStatus::Draining(None) => "draining".to_string(),
+                ClientStatus::Draining(Some(core)) => format!("moving to core {}", core.0),
+            };
+            format!(
+                "{} core {} pid {} {status}",
+                client.description.id(),
+                client.description.core_id().0,
+                client.pid
+            )
+        });
+        core::iter::once(summary)
+            .chain(clients)
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use libafl_bolts::core_affinity::CoreId;
+
+    use super::{ControlSocket, ElasticClients, ElasticCommand};
+
+    #[test]
+    fn test_parse_elastic_command() {
+        assert_eq!(
+            "list".parse::<ElasticCommand>().unwrap(),
+            ElasticCommand::List
+        );
+        assert_eq!(
+            " scale  4 ".parse::<ElasticCommand>().unwrap(),
+            ElasticCommand::Scale(4)
+        );
+        assert_eq!(
+            "add".parse::<ElasticCommand>().unwrap(),
+            ElasticCommand::Add(None)
+        );
+        assert_eq!(
+            "add 3".parse::<ElasticCommand>().unwrap(),
+            ElasticCommand::Add(Some(CoreId(3)))
+        );
+        assert_eq!(
+            "move 2 5".parse::<ElasticCommand>().unwrap(),
+            ElasticCommand::Move(2, CoreId(5))
+        );
+        assert!("scale".parse::<ElasticCommand>().is_err());
+        assert!("drain x".parse::<ElasticCommand>().is_err());
+        assert!("drain 1 2".parse::<ElasticCommand>().is_err());
+        assert!("grow 2".parse::<ElasticCommand>().is_err());
+    }
+
+    #[test]
+    fn test_elastic_clients() {
+        let dir = std::env::temp_dir().join(format!("libafl_elastic_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut clients = ElasticClients::new(vec![CoreId(0), CoreId(1)], dir.clone());
+
+        clients.scale(3).unwrap();
+        for pid in 100..103 {
+            let spawn = clients.next_spawn().unwrap().unwrap();
+            assert!(spawn.resume_from.is_none());
+            clients.spawned(spawn, pid);
+        }
+        assert!(clients.next_spawn().unwrap().is_none());
+        // spread over the cores
+        assert!(clients.list().contains("3 core 0 pid 102"));
+
+        clients.scale(2).unwrap();
+        assert_eq!(clients.running(), 2);
+        assert!(clients.drain(3, None).is_err());
+
+        // the drained client stores its state, and exits
+        std::fs::write(dir.join("client_3.state"), [0]).unwrap();
+        clients.exited(102);
+
+        // the next client resumes from it
+        clients.add(Some(CoreId(1)));
+        let spawn = clients.next_spawn().unwrap().unwrap();
+        assert_eq!(spawn.resume_from, Some(dir.join("client_3.state")));
+        clients.spawned(spawn, 103);
+
+        // moving resumes on the new core
+        clients.drain(1, Some(CoreId(1))).unwrap();
+        std::fs::write(dir.join("client_1.state"), [0]).unwrap();
+        clients.exited(100);
+        let spawn = clients.next_spawn().unwrap().unwrap();
+        assert_eq!(spawn.description.core_id(), CoreId(1));
+        assert_eq!(spawn.resume_from, Some(dir.join("client_1.state")));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_control_socket_bind() {
+        let path = std::env::temp_dir().join(format!("libafl_control_{}.sock", std::process::id()));
+
+        // Never replace a file that is not a socket
+        std::fs::write(&path, [0]).unwrap();
+        assert!(ControlSocket::bind(&path).is_err());
+        assert!(path.is_file());
+        std::fs::remove_file(&path).unwrap();
+
+        let control = ControlSocket::bind(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Another launcher is listening
+        assert!(ControlSocket::bind(&path).is_err());
+
+        // A stale socket is replaced
+        drop(control);
+        ControlSocket::bind(&path).unwrap().remove();
+        assert!(!path.exists());
+    }
+}
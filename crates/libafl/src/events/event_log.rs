@@ -0,0 +1,657 @@
+//! Recording of all events of a campaign into an append-only [`EventLogWriter`] log, and offline
+//! analysis of such a log with [`EventLogReplay`].
+//!
+//! Put an [`LlmpEventLogHook`] before the [`crate::events::StdLlmpEventHook`] of the broker to record
+//! new testcases, objectives and user stats of all clients. Inputs are not stored, only their hash.
+//! To follow the lineage of testcases, add a [`crate::feedbacks::lineage::LineageFeedback`] to the
+//! clients and share its metadata with
+//! `MetadataSharing::new().share::<LineageMetadata>(..)`.
+//!
+//! The log is a header followed by length-prefixed, `postcard`-serialized [`EventLogRecord`]s.
+//! A log cut short by a crash of the broker is read up to the last complete record.
+//! The `event_log_replay` example of this crate prints a post-mortem report of a recorded log.
+
+use alloc::{borrow::Cow, collections::BTreeMap, string::String, vec::Vec};
+use core::{fmt, marker::PhantomData, time::Duration};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Read, Write},
+    path::Path,
+};
+
+use libafl_bolts::{
+    ClientId, generic_hash_std,
+    llmp::{Flags, LlmpBrokerInner, LlmpHook, LlmpMsgHookResult, Tag},
+};
+#[cfg(feature = "llmp_compression")]
+use libafl_bolts::{compress::GzipCompressor, llmp::LLMP_FLAG_COMPRESSED};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "llmp_compression")]
+use crate::events::llmp::COMPRESS_THRESHOLD;
+use crate::{
+    Error,
+    events::{
+        Event, EventWithStats, SharedMetadata,
+        llmp::{
+            LLMP_FLAG_EVENT_NEW_TESTCASE, LLMP_FLAG_EVENT_OBJECTIVE, LLMP_FLAG_EVENT_USER_STATS,
+            LLMP_TAG_EVENT_TO_BOTH, may_be_event_kind,
+        },
+    },
+    executors::ExitKind,
+    feedbacks::lineage::LineageMetadata,
+    inputs::Input,
+    monitors::stats::UserStatsValue,
+};
+
+/// The magic bytes at the start of each event log, including the format version
+pub const EVENT_LOG_MAGIC: &[u8; 8] = b"LAFLEVL1";
+
+/// What happened, for an [`EventLogRecord`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EventLogKind {
+    /// A client found a new testcase
+    NewTestcase {
+        /// The hash of the input
+        input_hash: u64,
+        /// The hash of the input this testcase was derived from, if known
+        parent: Option<u64>,
+        /// The exit kind
+        exit_kind: ExitKind,
+        /// The new corpus size of this client
+        corpus_size: usize,
+    },
+    /// A client found a new objective
+    Objective {
+        /// The hash of the input, if it was sent along
+        input_hash: Option<u64>,
+        /// The new objective corpus size of this client
+        objective_size: usize,
+    },
+    /// A client reported a user stat
+    UserStats {
+        /// The name of the stat
+        name: Cow<'static, str>,
+        /// The value
+        value: UserStatsValue,
+    },
+}
+
+/// A single event in the log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventLogRecord {
+    /// The time the client sent the event
+    pub time: Duration,
+    /// The client the event is from
+    pub client_id: ClientId,
+    /// The executions of this client so far
+    pub executions: u64,
+    /// What happened
+    pub kind: EventLogKind,
+}
+
+impl EventLogRecord {
+    /// Create a record for this event, or `None` for events that are not recorded.
+    ///
+    /// For forwarded testcases, the original sender is used as client.
+    pub fn from_event<I>(client_id: ClientId, event: &EventWithStats<I>) -> Option<Self>
+    where
+        I: Input,
+    {
+        let stats = event.stats();
+        let (client_id, kind) = match event.event() {
+            Event::NewTestcase {
+                input,
+                metadata_buf,
+                exit_kind,
+                corpus_size,
+                forward_id,
+                ..
+            } => (
+                forward_id.unwrap_or(client_id),
+                EventLogKind::NewTestcase {
+                    input_hash: generic_hash_std(input),
+                    parent: metadata_buf.as_deref().and_then(shared_parent),
+                    exit_kind: *exit_kind,
+                    corpus_size: *corpus_size,
+                },
+            ),
+            Event::Objective {
+                input,
+                objective_size,
+            } => (
+                client_id,
+                EventLogKind::Objective {
+                    input_hash: input.as_ref().map(generic_hash_std),
+                    objective_size: *objective_size,
+                },
+            ),
+            Event::UpdateUserStats { name, value, .. } => (
+                client_id,
+                EventLogKind::UserStats {
+                    name: name.clone(),
+                    value: value.value().clone(),
+                },
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            time: stats.time,
+            client_id,
+            executions: stats.executions,
+            kind,
+        })
+    }
+}
+
+/// Get the parent from the [`LineageMetadata`] in a serialized [`SharedMetadata`], if any
+fn shared_parent(metadata_buf: &[u8]) -> Option<u64> {
+    let shared: SharedMetadata = postcard::from_bytes(metadata_buf).ok()?;
+    postcard::from_bytes::<LineageMetadata>(shared.entry::<LineageMetadata>()?)
+        .ok()
+        .map(|metadata| metadata.parent)
+}
+
+/// Appends [`EventLogRecord`]s to an event log file
+#[derive(Debug)]
+pub struct EventLogWriter {
+    writer: BufWriter<File>,
+}
+
+impl EventLogWriter {
+    /// Open the event log at `path` for appending, creating it if it does not exist
+    pub fn open<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let mut writer = BufWriter::new(file);
+        if writer.get_ref().metadata()?.len() == 0 {
+            writer.write_all(EVENT_LOG_MAGIC)?;
+        } else {
+            // Make sure we don't append to some other file
+            let mut magic = [0; EVENT_LOG_MAGIC.len()];
+            writer.get_mut().read_exact(&mut magic)?;
+            check_magic(&magic)?;
+        }
+        Ok(Self { writer })
+    }
+
+    /// Append a record. It may stay buffered until the next [`Self::flush`].
+    pub fn write(&mut self, record: &EventLogRecord) -> Result<(), Error> {
+        let buf = postcard::to_allocvec(record)?;
+        let len = u32::try_from(buf.len())
+            .map_err(|_| Error::illegal_argument("Event log record too large"))?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Write all buffered records to the file
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn check_magic(bytes: &[u8]) -> Result<(), Error> {
+    if bytes.starts_with(EVENT_LOG_MAGIC) {
+        Ok(())
+    } else {
+        Err(Error::illegal_argument(
+            "Not an event log, or an unsupported version",
+        ))
+    }
+}
+
+/// Read all complete records of the event log at `path`.
+///
+/// An incomplete or corrupted record at the end, as left by a crash, is ignored with a warning.
+pub fn read_event_log<P>(path: P) -> Result<Vec<EventLogRecord>, Error>
+where
+    P: AsRef<Path>,
+{
+    parse_event_log(&fs::read(path)?)
+}
+
+/// Parse all complete records of an event log, see [`read_event_log`]
+pub fn parse_event_log(bytes: &[u8]) -> Result<Vec<EventLogRecord>, Error> {
+    check_magic(bytes)?;
+    let mut records = vec![];
+    let mut rest = &bytes[EVENT_LOG_MAGIC.len()..];
+    while !rest.is_empty() {
+        let record = rest
+            .split_first_chunk::<4>()
+            .map(|(len, tail)| (u32::from_le_bytes(*len) as usize, tail))
+            .filter(|(len, tail)| *len <= tail.len())
+            .and_then(|(len, tail)| {
+                let record = postcard::from_bytes(&tail[..len]).ok()?;
+                Some((record, &tail[len..]))
+            });
+        let Some((record, tail)) = record else {
+            log::warn!(
+                "Ignoring {} trailing bytes of the event log, the log may be truncated",
+                rest.len()
+            );
+            break;
+        };
+        records.push(record);
+        rest = tail;
+    }
+    Ok(records)
+}
+
+/// An LLMP broker hook recording all events into an event log, see the [module docs](self).
+///
+/// It never handles events itself, so put it before the [`crate::events::StdLlmpEventHook`].
+pub struct LlmpEventLogHook<I> {
+    writer: EventLogWriter,
+    #[cfg(feature = "llmp_compression")]
+    compressor: GzipCompressor,
+    phantom: PhantomData<I>,
+}
+
+impl<I> fmt::Debug for LlmpEventLogHook<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LlmpEventLogHook")
+            .field("writer", &self.writer)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I> LlmpEventLogHook<I> {
+    /// Create a new [`LlmpEventLogHook`], appending to the event log at `path`
+    pub fn new<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self {
+            writer: EventLogWriter::open(path)?,
+            #[cfg(feature = "llmp_compression")]
+            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<I, SHM, SP> LlmpHook<SHM, SP> for LlmpEventLogHook<I>
+where
+    I: Input,
+{
+    fn on_new_message(
+        &mut self,
+        _broker_inner: &mut LlmpBrokerInner<SHM, SP>,
+        client_id: ClientId,
+        msg_tag: &mut Tag,
+        msg_flags: &mut Flags,
+        msg: &mut [u8],
+        _new_msgs: &mut Vec<(Tag, Flags, Vec<u8>)>,
+    ) -> Result<LlmpMsgHookResult, Error> {
+        // Heartbeats and other events are not logged, skip them without deserializing them
+        if *msg_tag != LLMP_TAG_EVENT_TO_BOTH
+            || !may_be_event_kind(
+                *msg_flags,
+                LLMP_FLAG_EVENT_NEW_TESTCASE
+                    | LLMP_FLAG_EVENT_OBJECTIVE
+                    | LLMP_FLAG_EVENT_USER_STATS,
+            )
+        {
+            return Ok(LlmpMsgHookResult::ForwardToClients);
+        }
+        #[cfg(not(feature = "llmp_compression"))]
+        let event_bytes = msg;
+        #[cfg(feature = "llmp_compression")]
+        let compressed;
+        #[cfg(feature = "llmp_compression")]
+        let event_bytes = if *msg_flags & LLMP_FLAG_COMPRESSED == LLMP_FLAG_COMPRESSED {
+            compressed = self.compressor.decompress(msg)?;
+            &compressed
+        } else {
+            &*msg
+        };
+        let event: EventWithStats<I> = postcard::from_bytes(event_bytes)?;
+        if let Some(record) = EventLogRecord::from_event(client_id, &event) {
+            self.writer.write(&record)?;
+        }
+        Ok(LlmpMsgHookResult::ForwardToClients)
+    }
+
+    fn on_timeout(&mut self) -> Result<(), Error> {
+        self.writer.flush()
+    }
+}
+
+impl<I> Drop for LlmpEventLogHook<I> {
+    fn drop(&mut self) {
+        if let Err(err) = self.writer.flush() {
+            log::error!("Failed to flush the event log: {err}");
+        }
+    }
+}
+
+/// The contribution of a single client to the campaign, see [`EventLogReplay::contributions`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientContribution {
+    /// The number of new testcases found by this client
+    pub testcases: usize,
+    /// The number of objectives found by this client
+    pub objectives: usize,
+    /// The executions of this client, as last reported
+    pub executions: u64,
+    /// The time of the last testcase found by this client, relative to the start
+    pub last_testcase: Option<Duration>,
+}
+
+/// A testcase, as reconstructed by [`EventLogReplay`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoggedTestcase {
+    /// The hash of the input
+    pub input_hash: u64,
+    /// The client that found it
+    pub client_id: ClientId,
+    /// When it was found, relative to the start
+    pub time: Duration,
+    /// The hash of the input it was derived from, if known
+    pub parent: Option<u64>,
+}
+
+/// Offline analysis of the records of an event log.
+///
+/// All times are relative to the first record.
+#[derive(Debug, Clone)]
+pub struct EventLogReplay {
+    records: Vec<EventLogRecord>,
+    start: Duration,
+}
+
+impl EventLogReplay {
+    /// Analyze the given records. They are sorted by time, as clients don't send in lockstep.
+    #[must_use]
+    pub fn new(mut records: Vec<EventLogRecord>) -> Self {
+        records.sort_by_key(|record| record.time);
+        let start = records.first().map_or(Duration::ZERO, |record| record.time);
+        Self { records, start }
+    }
+
+    /// Read and analyze the event log at `path`
+    pub fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::new(read_event_log(path)?))
+    }
+
+    fn since_start(&self, time: Duration) -> Duration {
+        time.saturating_sub(self.start)
+    }
+
+    /// The records, sorted by time
+    #[must_use]
+    pub fn records(&self) -> &[EventLogRecord] {
+        &self.records
+    }
+
+    /// The duration from the first to the last record
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        self.records
+            .last()
+            .map_or(Duration::ZERO, |record| self.since_start(record.time))
+    }
+
+    /// The value of the user stat `name` over time, such as the `edges` of a map feedback.
+    ///
+    /// Each point is the maximum of the last value reported by each client,
+    /// since the corpora of all clients are synced. Ratios count their numerator.
+    /// Only points where the maximum grows are returned.
+    #[must_use]
+    pub fn user_stat_over_time(&self, name: &str) -> Vec<(Duration, u64)> {
+        let mut latest = BTreeMap::new();
+        let mut points: Vec<(Duration, u64)> = vec![];
+        for record in &self.records {
+            let EventLogKind::UserStats {
+                name: stat_name,
+                value,
+            } = &record.kind
+            else {
+                continue;
+            };
+            if stat_name != name {
+                continue;
+            }
+            let value = match value {
+                UserStatsValue::Number(n) | UserStatsValue::Ratio(n, _) => *n,
+                #[expect(clippy::cast_sign_loss)]
+                UserStatsValue::Float(f) | UserStatsValue::Percent(f) => *f as u64,
+                UserStatsValue::String(_) => continue,
+            };
+            latest.insert(record.client_id, value);
+            let max = latest.values().copied().max().unwrap_or_default();
+            if points.last().is_none_or(|(_, last)| *last < max) {
+                points.push((self.since_start(record.time), max));
+            }
+        }
+        points
+    }
+
+    /// The number of testcases found by all clients over time
+    #[must_use]
+    pub fn corpus_over_time(&self) -> Vec<(Duration, usize)> {
+        self.testcases()
+            .iter()
+            .enumerate()
+            .map(|(i, testcase)| (testcase.time, i + 1))
+            .collect()
+    }
+
+    /// All testcases, in the order they were found
+    #[must_use]
+    pub fn testcases(&self) -> Vec<LoggedTestcase> {
+        self.records
+            .iter()
+            .filter_map(|record| match record.kind {
+                EventLogKind::NewTestcase {
+                    input_hash, parent, ..
+                } => Some(LoggedTestcase {
+                    input_hash,
+                    client_id: record.client_id,
+                    time: self.since_start(record.time),
+                    parent,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The contribution of each client
+    #[must_use]
+    pub fn contributions(&self) -> BTreeMap<ClientId, ClientContribution> {
+        let mut contributions: BTreeMap<ClientId, ClientContribution> = BTreeMap::new();
+        for record in &self.records {
+            let contribution = contributions.entry(record.client_id).or_default();
+            contribution.executions = contribution.executions.max(record.executions);
+            match record.kind {
+                EventLogKind::NewTestcase { .. } => {
+                    contribution.testcases += 1;
+                    contribution.last_testcase = Some(self.since_start(record.time));
+                }
+                EventLogKind::Objective { .. } => contribution.objectives += 1,
+                EventLogKind::UserStats { .. } => {}
+            }
+        }
+        contributions
+    }
+
+    /// The ancestors of the testcase with the given input hash, starting with its parent.
+    ///
+    /// Stops at the first testcase without a known parent, such as a seed.
+    #[must_use]
+    pub fn lineage(&self, input_hash: u64) -> Vec<LoggedTestcase> {
+        let testcases: BTreeMap<u64, LoggedTestcase> = self
+            .testcases()
+            .into_iter()
+            .map(|testcase| (testcase.input_hash, testcase))
+            .collect();
+        let mut ancestors: Vec<LoggedTestcase> = vec![];
+        let mut current = testcases.get(&input_hash).and_then(|tc| tc.parent);
+        while let Some(parent) = current {
+            // Guard against hash collisions forming a cycle
+            if ancestors.iter().any(|tc| tc.input_hash == parent) {
+                break;
+            }
+            let Some(testcase) = testcases.get(&parent) else {
+                break;
+            };
+            ancestors.push(*testcase);
+            current = testcase.parent;
+        }
+        ancestors
+    }
+
+    /// The testcases directly derived from the testcase with the given input hash
+    #[must_use]
+    pub fn children(&self, input_hash: u64) -> Vec<LoggedTestcase> {
+        self.testcases()
+            .into_iter()
+            .filter(|testcase| testcase.parent == Some(input_hash))
+            .collect()
+    }
+}
+
+impl fmt::Display for EventLogReplay {
+    /// A short post-mortem report of the campaign
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let testcases = self.testcases();
+        writeln!(
+            f,
+            "{} records over {}s, {} testcases",
+            self.records.len(),
+            self.duration().as_secs(),
+            testcases.len()
+        )?;
+        for (client_id, contribution) in self.contributions() {
+            let share = if testcases.is_empty() {
+                0.0
+            } else {
+                #[expect(clippy::cast_precision_loss)]
+                let share = contribution.testcases as f64 / testcases.len() as f64;
+                share * 100.0
+            };
+            let last: String = contribution
+                .last_testcase
+                .map_or_else(|| "never".into(), |last| format!("{}s", last.as_secs()));
+            writeln!(
+                f,
+                "client {}: {} testcases ({share:.1}%), {} objectives, {} executions, last testcase: {last}",
+                client_id.0,
+                contribution.testcases,
+                contribution.objectives,
+                contribution.executions
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::Cow, vec::Vec};
+    use core::time::Duration;
+
+    use libafl_bolts::ClientId;
+
+    use super::{
+        EVENT_LOG_MAGIC, EventLogKind, EventLogRecord, EventLogReplay, EventLogWriter,
+        parse_event_log, read_event_log,
+    };
+    use crate::{executors::ExitKind, monitors::stats::UserStatsValue};
+
+    fn testcase(secs: u64, client: u32, input_hash: u64, parent: Option<u64>) -> EventLogRecord {
+        EventLogRecord {
+            time: Duration::from_secs(secs),
+            client_id: ClientId(client),
+            executions: secs * 100,
+            kind: EventLogKind::NewTestcase {
+                input_hash,
+                parent,
+                exit_kind: ExitKind::Ok,
+                corpus_size: 1,
+            },
+        }
+    }
+
+    fn edges(secs: u64, client: u32, edges: u64) -> EventLogRecord {
+        EventLogRecord {
+            time: Duration::from_secs(secs),
+            client_id: ClientId(client),
+            executions: secs * 100,
+            kind: EventLogKind::UserStats {
+                name: Cow::Borrowed("edges"),
+                value: UserStatsValue::Ratio(edges, 100),
+            },
+        }
+    }
+
+    #[test]
+    fn test_event_log_roundtrip() {
+        let path = std::env::temp_dir().join(format!("event_log_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let records = vec![testcase(10, 1, 1, None), edges(11, 1, 5)];
+
+        let mut writer = EventLogWriter::open(&path).unwrap();
+        writer.write(&records[0]).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        // Reopening appends
+        let mut writer = EventLogWriter::open(&path).unwrap();
+        writer.write(&records[1]).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        assert_eq!(read_event_log(&path).unwrap(), records);
+
+        // A truncated record at the end is ignored
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        assert_eq!(parse_event_log(&bytes).unwrap(), records[..1]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(parse_event_log(b"not a log").is_err());
+        assert!(parse_event_log(EVENT_LOG_MAGIC).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_event_log_replay() {
+        let replay = EventLogReplay::new(vec![
+            edges(13, 2, 7),
+            testcase(10, 1, 1, None),
+            edges(11, 1, 5),
+            testcase(12, 2, 2, Some(1)),
+            edges(12, 2, 3),
+            testcase(14, 1, 3, Some(2)),
+            testcase(15, 1, 4, Some(1)),
+        ]);
+        assert_eq!(replay.duration(), Duration::from_secs(5));
+        assert_eq!(
+            replay.user_stat_over_time("edges"),
+            vec![(Duration::from_secs(1), 5), (Duration::from_secs(3), 7)]
+        );
+        assert_eq!(
+            replay.corpus_over_time().last(),
+            Some(&(Duration::from_secs(5), 4))
+        );
+
+        let contributions = replay.contributions();
+        assert_eq!(contributions[&ClientId(1)].testcases, 3);
+        assert_eq!(contributions[&ClientId(2)].testcases, 1);
+        assert_eq!(contributions[&ClientId(2)].executions, 1300);
+
+        let lineage: Vec<u64> = replay.lineage(3).iter().map(|tc| tc.input_hash).collect();
+        assert_eq!(lineage, vec![2, 1]);
+        let children: Vec<u64> = replay.children(1).iter().map(|tc| tc.input_hash).collect();
+        assert_eq!(children, vec![2, 4]);
+    }
+}
@@ -2,9 +2,14 @@
 //!
 //! This will allow user to define pre/post-processing code when the event manager receives any message from
 //! other clients
+use core::{fmt, marker::PhantomData};
+
 use libafl_bolts::ClientId;
 
-use crate::{Error, events::EventWithStats};
+use crate::{
+    Error,
+    events::{CustomEvent, EventWithStats},
+};
 
 /// The `broker_hooks` that are run before and after the event manager calls `try_receive`
 pub trait EventManagerHook<I, S> {
@@ -58,3 +63,46 @@ where
         Ok(first & second)
     }
 }
+
+/// An [`EventManagerHook`] handling [`CustomEvent`]s of type `C` with a closure.
+///
+/// The closure gets the state, the sender, and the deserialized payload.
+/// It returns `false` to cancel the subsequent event handling.
+pub struct CustomEventHook<C, F> {
+    handler: F,
+    phantom: PhantomData<C>,
+}
+
+impl<C, F> fmt::Debug for CustomEventHook<C, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomEventHook").finish_non_exhaustive()
+    }
+}
+
+impl<C, F> CustomEventHook<C, F> {
+    /// Create a new [`CustomEventHook`], calling `handler` for each [`CustomEvent`] of type `C`
+    pub fn new(handler: F) -> Self {
+        Self {
+            handler,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<C, F, I, S> EventManagerHook<I, S> for CustomEventHook<C, F>
+where
+    C: CustomEvent,
+    F: FnMut(&mut S, ClientId, C) -> Result<bool, Error>,
+{
+    fn pre_receive(
+        &mut self,
+        state: &mut S,
+        client_id: ClientId,
+        event: &EventWithStats<I>,
+    ) -> Result<bool, Error> {
+        match event.event().custom_payload::<C>()? {
+            Some(payload) => (self.handler)(state, client_id, payload),
+            None => Ok(true),
+        }
+    }
+}
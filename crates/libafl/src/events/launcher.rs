@@ -8,9 +8,15 @@
 //!
 //! To connect multiple nodes together via TCP, we can use the `remote_broker_addr`.
 //! (this requires the `llmp_bind_public` compile-time feature for `LibAFL`).
+//! On `Unix`, local clients can instead connect over a unix domain socket, the `broker_socket`.
 //!
 //! On `Unix` systems, the [`Launcher`] will use `fork` if the `fork` feature is used for `LibAFL`.
 //! Else, it will start subsequent nodes with the same commandline, and will set special `env` variables accordingly.
+//!
+//! On `Unix`, with the `fork` feature, a `control_socket` allows to add, drain, and move clients at runtime (see the `elastic` module).
+//!
+//! With [`ClientRoles`], different clients, such as `cmplog`, `concolic`, and `havoc` builds,
+//! run under one launcher, each with their own closure and [`EventConfig`] (see the `roles` module).
 
 use alloc::string::String;
 use core::{
@@ -20,6 +26,8 @@ use core::{
     time::Duration,
 };
 
+#[cfg(feature = "secure_channel")]
+use libafl_bolts::secure_channel::SecureChannelConfig;
 use libafl_bolts::{
     core_affinity::{CoreId, Cores},
     shmem::ShMemProvider,
@@ -30,16 +38,27 @@ use typed_builder::TypedBuilder;
 #[cfg(all(unix, feature = "fork"))]
 use {
     crate::{
-        events::{CentralizedLlmpHook, StdLlmpEventHook, centralized::CentralizedEventManager},
+        corpus::{EnableDisableCorpus, HasCurrentCorpusId},
+        events::{
+            CentralizedLlmpHook, LlmpRoleRoutingHook, StdLlmpEventHook,
+            centralized::CentralizedEventManager,
+            corpus_culling::LlmpCorpusCullingHook,
+            elastic::{
+                ClientDrain, ClientSpawn, ControlSocket, ElasticClients, ElasticCommand,
+                default_drain_dir, load_drained_state,
+            },
+        },
         inputs::Input,
+        state::HasCorpus,
     },
     alloc::boxed::Box,
     alloc::string::ToString,
     libafl_bolts::{
         core_affinity::get_core_ids,
-        llmp::{Broker, Brokers, LlmpBroker},
+        llmp::{Broker, Brokers, LlmpBroker, LlmpHookTuple},
         os::{ForkResult, fork},
     },
+    std::path::PathBuf,
 };
 #[cfg(unix)]
 use {
@@ -55,7 +74,9 @@ use crate::{
     Error,
     events::{
         EventConfig, EventManagerHooksTuple,
+        corpus_culling::CorpusCullingHook,
         llmp::{LlmpRestartingEventManager, LlmpShouldSaveState, ManagerKind, RestartingMgr},
+        roles::{ClientRoles, check_roles, configuration_for, take_run_client},
     },
     monitors::Monitor,
 };
@@ -67,6 +88,10 @@ const _AFL_LAUNCHER_CLIENT: &str = "AFL_LAUNCHER_CLIENT";
 #[cfg(all(feature = "fork", unix))]
 const LIBAFL_DEBUG_OUTPUT: &str = "LIBAFL_DEBUG_OUTPUT";
 
+/// How often an elastic launcher checks for commands and exited clients
+#[cfg(all(feature = "fork", unix))]
+const ELASTIC_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 /// Information about this client from the launcher
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientDescription {
@@ -139,6 +164,10 @@ pub struct Launcher<'a, CF, MT, SP> {
     /// The 'main' function to run for each client forked. This probably shouldn't return
     #[builder(default, setter(strip_option))]
     run_client: Option<CF>,
+    /// The [`ClientRoles`], running a different client closure and configuration on some of the cores.
+    /// Cores without a role run [`Self::run_client`] with [`Self::configuration`].
+    #[builder(default, setter(strip_option))]
+    roles: Option<ClientRoles<CF>>,
     /// The broker port to use (or to attach to, in case [`Self::spawn_broker`] is `false`)
     #[builder(default = 1337_u16)]
     broker_port: u16,
@@ -180,6 +209,26 @@ pub struct Launcher<'a, CF, MT, SP> {
     /// Tell the manager to serialize or not the state on restart
     #[builder(default = LlmpShouldSaveState::OnRestart)]
     serialize_state: LlmpShouldSaveState,
+    /// If set, the broker only accepts tcp peers authenticated with this [`SecureChannelConfig`],
+    /// and the clients authenticate to the broker with it.
+    #[cfg(feature = "secure_channel")]
+    #[builder(default = None)]
+    secure_channel: Option<SecureChannelConfig>,
+    /// If set, the broker runs in its own process, and the launcher supervises the clients:
+    /// it adds, drains, and moves clients at runtime, on [`ElasticCommand`]s received on this unix socket.
+    #[cfg(all(unix, feature = "fork"))]
+    #[builder(default = None)]
+    control_socket: Option<&'a str>,
+    /// The directory drained clients store their state to, until another client resumes from it.
+    /// Defaults to a new directory for this campaign in the temp dir.
+    #[cfg(all(unix, feature = "fork"))]
+    #[builder(default = None)]
+    drain_dir: Option<&'a str>,
+    /// If set, the broker listens on a unix domain socket at this path, instead of [`Self::broker_port`].
+    /// Use it with a `ServedShMemProvider`, to run clients without network access, for example in containers.
+    #[cfg(unix)]
+    #[builder(default = None)]
+    broker_socket: Option<&'a str>,
 }
 
 impl<CF, MT, SP> Debug for Launcher<'_, CF, MT, SP> {
@@ -187,6 +236,7 @@ impl<CF, MT, SP> Debug for Launcher<'_, CF, MT, SP> {
         let mut dbg_struct = f.debug_struct("Launcher");
         dbg_struct
             .field("configuration", &self.configuration)
+            .field("roles", &self.roles)
             .field("broker_port", &self.broker_port)
             .field("core", &self.cores)
             .field("spawn_broker", &self.spawn_broker)
@@ -195,7 +245,14 @@ impl<CF, MT, SP> Debug for Launcher<'_, CF, MT, SP> {
         {
             dbg_struct
                 .field("stdout_file", &self.stdout_file)
-                .field("stderr_file", &self.stderr_file);
+                .field("stderr_file", &self.stderr_file)
+                .field("broker_socket", &self.broker_socket);
+        }
+        #[cfg(all(unix, feature = "fork"))]
+        {
+            dbg_struct
+                .field("control_socket", &self.control_socket)
+                .field("drain_dir", &self.drain_dir);
         }
 
         dbg_struct.finish_non_exhaustive()
@@ -247,11 +304,7 @@ where
             ));
         }
 
-        if self.run_client.is_none() {
-            return Err(Error::illegal_argument(
-                "No client callback provided".to_string(),
-            ));
-        }
+        check_roles(self.roles.as_ref(), self.run_client.is_some(), self.cores)?;
 
         let core_ids = get_core_ids()?;
         let mut handles = vec![];
@@ -267,6 +320,10 @@ where
 
         let debug_output = std::env::var(LIBAFL_DEBUG_OUTPUT).is_ok();
 
+        if let Some(control_socket) = self.control_socket {
+            return self.launch_elastic(hooks, control_socket, debug_output);
+        }
+
         // Spawn clients
         let mut index = 0_usize;
         for bind_to in core_ids {
@@ -294,43 +351,14 @@ where
                                 index as u64 * self.launch_delay,
                             ));
 
-                            if !debug_output {
-                                if let Some(file) = &self.opened_stdout_file {
-                                    // # Safety
-                                    // We assume the file descriptors are valid here
-                                    unsafe {
-                                        dup2(file.as_raw_fd(), libc::STDOUT_FILENO)?;
-                                        match &self.opened_stderr_file {
-                                            Some(stderr) => {
-                                                dup2(stderr.as_raw_fd(), libc::STDERR_FILENO)?;
-                                            }
-                                            _ => {
-                                                dup2(file.as_raw_fd(), libc::STDERR_FILENO)?;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-
                             let client_description =
                                 ClientDescription::new(index, overcommit_id, bind_to);
-
-                            // Fuzzer client. keeps retrying the connection to broker till the broker starts
-                            let builder = RestartingMgr::<EMH, I, MT, S, SP>::builder()
-                                .shmem_provider(self.shmem_provider.clone())
-                                .broker_port(self.broker_port)
-                                .kind(ManagerKind::Client {
-                                    client_description: client_description.clone(),
-                                })
-                                .configuration(self.configuration)
-                                .serialize_state(self.serialize_state)
-                                .hooks(hooks);
-                            let (state, mgr) = builder.build().launch()?;
-
-                            return (self.run_client.take().unwrap())(
-                                state,
-                                mgr,
+                            return self.run_forked_client(
+                                hooks,
                                 client_description,
+                                debug_output,
+                                None,
+                                None,
                             );
                         }
                     }
@@ -351,7 +379,12 @@ where
                 .exit_cleanly_after(Some(NonZeroUsize::try_from(self.cores.ids.len()).unwrap()))
                 .configuration(self.configuration)
                 .serialize_state(self.serialize_state)
+                .role_routes(self.roles.as_ref().and_then(ClientRoles::routes))
                 .hooks(hooks);
+            #[cfg(feature = "secure_channel")]
+            let builder = builder.secure_channel(self.secure_channel.clone());
+            #[cfg(unix)]
+            let builder = builder.broker_socket(self.broker_socket.map(Into::into));
 
             builder.build().launch()?;
 
@@ -381,6 +414,242 @@ where
         Ok(())
     }
 
+    /// Run a client we just forked off
+    #[cfg(all(unix, feature = "fork"))]
+    fn run_forked_client<EMH, I, S>(
+        &mut self,
+        hooks: EMH,
+        client_description: ClientDescription,
+        debug_output: bool,
+        drain: Option<ClientDrain>,
+        resume_from: Option<PathBuf>,
+    ) -> Result<(), Error>
+    where
+        S: DeserializeOwned + Serialize,
+        I: DeserializeOwned,
+        EMH: EventManagerHooksTuple<I, S> + Clone + Copy,
+        CF: FnOnce(
+            Option<S>,
+            LlmpRestartingEventManager<EMH, I, S, SP::ShMem, SP>,
+            ClientDescription,
+        ) -> Result<(), Error>,
+    {
+        if !debug_output {
+            if let Some(file) = &self.opened_stdout_file {
+                // # Safety
+                // We assume the file descriptors are valid here
+                unsafe {
+                    dup2(file.as_raw_fd(), libc::STDOUT_FILENO)?;
+                    match &self.opened_stderr_file {
+                        Some(stderr) => {
+                            dup2(stderr.as_raw_fd(), libc::STDERR_FILENO)?;
+                        }
+                        _ => {
+                            dup2(file.as_raw_fd(), libc::STDERR_FILENO)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Fuzzer client. keeps retrying the connection to broker till the broker starts
+        let builder = RestartingMgr::<EMH, I, MT, S, SP>::builder()
+            .shmem_provider(self.shmem_provider.clone())
+            .broker_port(self.broker_port)
+            .kind(ManagerKind::Client {
+                client_description: client_description.clone(),
+            })
+            .configuration(configuration_for(
+                self.roles.as_ref(),
+                self.configuration,
+                client_description.core_id(),
+            ))
+            .serialize_state(self.serialize_state)
+            .hooks(hooks);
+        #[cfg(feature = "secure_channel")]
+        let builder = builder.secure_channel(self.secure_channel.clone());
+        #[cfg(unix)]
+        let builder = builder.broker_socket(self.broker_socket.map(Into::into));
+        let (state, mut mgr) = builder.build().launch()?;
+
+        let state = match (state, resume_from) {
+            // After a restart, the state of the last run takes precedence
+            (None, Some(resume_from)) if resume_from.exists() => {
+                log::info!(
+                    "Resuming from the state of a drained client, in {}",
+                    resume_from.display()
+                );
+                Some(load_drained_state(&resume_from)?)
+            }
+            (state, _) => state,
+        };
+        if let Some(drain) = drain {
+            mgr.set_drain(drain);
+        }
+        let core_id = client_description.core_id();
+        if let Some(role) = self
+            .roles
+            .as_ref()
+            .and_then(|roles| roles.filter_for(core_id))
+        {
+            mgr.set_role(role);
+        }
+
+        let run_client = take_run_client(self.roles.as_mut(), &mut self.run_client, core_id)?;
+        run_client(state, mgr, client_description)
+    }
+
+    /// Launch the broker in its own process, and supervise the clients,
+    /// scaling them on the commands received on the `control_socket`.
+    ///
+    /// The broker keeps running when all clients are gone, until it is stopped.
+    #[cfg(all(unix, feature = "fork"))]
+    fn launch_elastic<EMH, I, S>(
+        &mut self,
+        hooks: EMH,
+        control_socket: &str,
+        debug_output: bool,
+    ) -> Result<(), Error>
+    where
+        S: DeserializeOwned + Serialize,
+        I: DeserializeOwned,
+        EMH: EventManagerHooksTuple<I, S> + Clone + Copy,
+        CF: FnOnce(
+            Option<S>,
+            LlmpRestartingEventManager<EMH, I, S, SP::ShMem, SP>,
+            ClientDescription,
+        ) -> Result<(), Error>,
+    {
+        let broker_pid = if self.spawn_broker {
+            self.shmem_provider.pre_fork()?;
+            match unsafe { fork() }? {
+                ForkResult::Parent(child) => {
+                    self.shmem_provider.post_fork(false)?;
+                    Some(child.pid)
+                }
+                ForkResult::Child => {
+                    self.shmem_provider.post_fork(true)?;
+                    log::info!("I am broker!!.");
+
+                    let builder = RestartingMgr::<EMH, I, MT, S, SP>::builder()
+                        .shmem_provider(self.shmem_provider.clone())
+                        .monitor(Some(self.monitor.clone()))
+                        .broker_port(self.broker_port)
+                        .kind(ManagerKind::Broker)
+                        .remote_broker_addr(self.remote_broker_addr)
+                        .configuration(self.configuration)
+                        .serialize_state(self.serialize_state)
+                        .role_routes(self.roles.as_ref().and_then(ClientRoles::routes))
+                        .hooks(hooks);
+                    #[cfg(feature = "secure_channel")]
+                    let builder = builder.secure_channel(self.secure_channel.clone());
+                    #[cfg(unix)]
+                    let builder = builder.broker_socket(self.broker_socket.map(Into::into));
+
+                    builder.build().launch()?;
+                    return Ok(());
+                }
+            }
+        } else {
+            None
+        };
+
+        let control = ControlSocket::bind(control_socket)?;
+        log::info!("Listening for elastic commands on {control_socket}");
+        let drain_dir = match self.drain_dir {
+            Some(drain_dir) => PathBuf::from(drain_dir),
+            None => default_drain_dir()?,
+        };
+        let mut clients = ElasticClients::new(self.cores.ids.clone(), drain_dir);
+
+        for core_id in &self.cores.ids {
+            for _ in 0..self.overcommit {
+                clients.add(Some(*core_id));
+            }
+        }
+
+        loop {
+            while let Some(spawn) = clients.next_spawn()? {
+                self.shmem_provider.pre_fork()?;
+                // # Safety
+                // Fork is safe in general, apart from potential side effects to the OS and other threads
+                match unsafe { fork() }? {
+                    ForkResult::Parent(child) => {
+                        self.shmem_provider.post_fork(false)?;
+                        clients.spawned(spawn, child.pid);
+                    }
+                    ForkResult::Child => {
+                        self.shmem_provider.post_fork(true)?;
+                        drop(control);
+                        std::thread::sleep(Duration::from_millis(self.launch_delay));
+
+                        let ClientSpawn {
+                            description,
+                            resume_from,
+                            drain,
+                        } = spawn;
+                        return self.run_forked_client(
+                            hooks,
+                            description,
+                            debug_output,
+                            Some(drain),
+                            resume_from,
+                        );
+                    }
+                }
+            }
+
+            control.poll(|command| {
+                log::info!("Received elastic command {command:?}");
+                match command {
+                    ElasticCommand::List => Ok(clients.list()),
+                    ElasticCommand::Scale(n) => {
+                        let running = clients.running();
+                        clients.scale(n)?;
+                        Ok(format!("scaling from {running} to {n} clients"))
+                    }
+                    ElasticCommand::Add(core_id) => {
+                        clients.add(core_id);
+                        Ok("adding a client".to_string())
+                    }
+                    ElasticCommand::Drain(id) => {
+                        clients.drain(id, None)?;
+                        Ok(format!("draining client {id}"))
+                    }
+                    ElasticCommand::Move(id, core_id) => {
+                        clients.drain(id, Some(core_id))?;
+                        Ok(format!("moving client {id} to core {}", core_id.0))
+                    }
+                }
+            })?;
+
+            loop {
+                let mut status = 0;
+                // # Safety
+                // Normal libc call, no dereferences whatsoever
+                let pid = unsafe { libc::waitpid(-1, &raw mut status, libc::WNOHANG) };
+                if pid <= 0 {
+                    break;
+                }
+                if Some(pid) == broker_pid {
+                    log::info!("The broker exited, stopping all clients.");
+                    for pid in clients.pids() {
+                        // # Safety
+                        // Normal libc call, no dereferences whatsoever
+                        unsafe {
+                            libc::kill(pid, libc::SIGINT);
+                        }
+                    }
+                    control.remove();
+                    return Ok(());
+                }
+                clients.exited(pid);
+            }
+
+            std::thread::sleep(ELASTIC_POLL_INTERVAL);
+        }
+    }
+
     /// Launch the broker and the clients and fuzz
     #[cfg(any(windows, not(feature = "fork")))]
     #[expect(clippy::too_many_lines, clippy::match_wild_err_arm)]
@@ -410,13 +679,31 @@ where
                     .kind(ManagerKind::Client {
                         client_description: client_description.clone(),
                     })
-                    .configuration(self.configuration)
+                    .configuration(configuration_for(
+                        self.roles.as_ref(),
+                        self.configuration,
+                        client_description.core_id(),
+                    ))
                     .serialize_state(self.serialize_state)
                     .hooks(hooks);
+                #[cfg(feature = "secure_channel")]
+                let builder = builder.secure_channel(self.secure_channel.clone());
+                #[cfg(unix)]
+                let builder = builder.broker_socket(self.broker_socket.map(Into::into));
 
-                let (state, mgr) = builder.build().launch()?;
+                let (state, mut mgr) = builder.build().launch()?;
 
-                return (self.run_client.take().unwrap())(state, mgr, client_description);
+                let core_id = client_description.core_id();
+                if let Some(role) = self
+                    .roles
+                    .as_ref()
+                    .and_then(|roles| roles.filter_for(core_id))
+                {
+                    mgr.set_role(role);
+                }
+                let run_client =
+                    take_run_client(self.roles.as_mut(), &mut self.run_client, core_id)?;
+                return run_client(state, mgr, client_description);
             }
             Err(std::env::VarError::NotPresent) => {
                 // I am a broker
@@ -507,6 +794,7 @@ where
                 "No cores to spawn on given, cannot launch anything.",
             ));
         }
+        check_roles(self.roles.as_ref(), self.run_client.is_some(), self.cores)?;
 
         if self.spawn_broker {
             log::info!("I am broker!!.");
@@ -520,7 +808,12 @@ where
                 .exit_cleanly_after(Some(NonZeroUsize::try_from(self.cores.ids.len()).unwrap()))
                 .configuration(self.configuration)
                 .serialize_state(self.serialize_state)
+                .role_routes(self.roles.as_ref().and_then(ClientRoles::routes))
                 .hooks(hooks);
+            #[cfg(feature = "secure_channel")]
+            let builder = builder.secure_channel(self.secure_channel.clone());
+            #[cfg(unix)]
+            let builder = builder.broker_socket(self.broker_socket.map(Into::into));
 
             builder.build().launch()?;
 
@@ -563,6 +856,11 @@ pub struct CentralizedLauncher<'a, CF, MF, MT, SP> {
     /// The 'main' function to run for the main evaluator node.
     #[builder(default, setter(strip_option))]
     main_run_client: Option<MF>,
+    /// The [`ClientRoles`], running a different configuration on some of the cores,
+    /// and a different client closure on the secondary nodes there.
+    /// Cores without a role use [`Self::secondary_run_client`] and [`Self::configuration`].
+    #[builder(default, setter(strip_option))]
+    roles: Option<ClientRoles<CF>>,
     /// The broker port to use (or to attach to, in case [`Self::spawn_broker`] is `false`)
     #[builder(default = 1337_u16)]
     broker_port: u16,
@@ -608,6 +906,12 @@ pub struct CentralizedLauncher<'a, CF, MF, MT, SP> {
     /// Tell the manager to serialize or not the state on restart
     #[builder(default = LlmpShouldSaveState::OnRestart)]
     serialize_state: LlmpShouldSaveState,
+    /// Keep a global coverage map in the main broker, drop new testcases without new coverage,
+    /// and broadcast the culled inputs at this interval (see the `corpus_culling` module).
+    /// The clients disable culled inputs with the [`CorpusCullingHook`] of their inner managers,
+    /// which [`Self::launch`] adds. With [`Self::launch_generic`], add it to the inner managers yourself.
+    #[builder(default = None)]
+    corpus_culling: Option<Duration>,
 }
 
 #[cfg(all(unix, feature = "fork"))]
@@ -615,6 +919,7 @@ impl<CF, MF, MT, SP> Debug for CentralizedLauncher<'_, CF, MF, MT, SP> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("Launcher")
             .field("configuration", &self.configuration)
+            .field("roles", &self.roles)
             .field("broker_port", &self.broker_port)
             .field("cores", &self.cores)
             .field("overcommit", &self.overcommit)
@@ -622,12 +927,14 @@ impl<CF, MF, MT, SP> Debug for CentralizedLauncher<'_, CF, MF, MT, SP> {
             .field("remote_broker_addr", &self.remote_broker_addr)
             .field("stdout_file", &self.stdout_file)
             .field("stderr_file", &self.stderr_file)
+            .field("corpus_culling", &self.corpus_culling)
             .finish_non_exhaustive()
     }
 }
 
-/// The standard inner manager of centralized
-pub type StdCentralizedInnerMgr<I, S, SHM, SP> = LlmpRestartingEventManager<(), I, S, SHM, SP>;
+/// The standard inner manager of centralized, disabling the inputs culled by the `corpus_culling` option
+pub type StdCentralizedInnerMgr<I, S, SHM, SP> =
+    LlmpRestartingEventManager<(CorpusCullingHook, ()), I, S, SHM, SP>;
 
 #[cfg(all(unix, feature = "fork"))]
 impl<CF, MF, MT, SP> CentralizedLauncher<'_, CF, MF, MT, SP>
@@ -638,7 +945,8 @@ where
     /// Launch a standard Centralized-based fuzzer
     pub fn launch<I, S>(&mut self) -> Result<(), Error>
     where
-        S: DeserializeOwned + Serialize,
+        S: DeserializeOwned + Serialize + HasCorpus<I> + HasCurrentCorpusId,
+        S::Corpus: EnableDisableCorpus,
         I: DeserializeOwned + Input + Send + Sync + 'static,
         CF: FnOnce(
             Option<S>,
@@ -666,15 +974,25 @@ where
         let restarting_mgr_builder =
             |centralized_launcher: &Self, client_description: ClientDescription| {
                 // Fuzzer client. keeps retrying the connection to broker till the broker starts
-                let builder = RestartingMgr::<(), I, MT, S, SP>::builder()
+                let core_id = client_description.core_id();
+                let roles = centralized_launcher.roles.as_ref();
+                let builder = RestartingMgr::<(CorpusCullingHook, ()), I, MT, S, SP>::builder()
                     .shmem_provider(centralized_launcher.shmem_provider.clone())
                     .broker_port(centralized_launcher.broker_port)
                     .kind(ManagerKind::Client { client_description })
-                    .configuration(centralized_launcher.configuration)
+                    .configuration(configuration_for(
+                        roles,
+                        centralized_launcher.configuration,
+                        core_id,
+                    ))
                     .serialize_state(centralized_launcher.serialize_state)
-                    .hooks(tuple_list!());
+                    .hooks(tuple_list!(CorpusCullingHook::new()));
 
-                builder.build().launch()
+                let (state, mut mgr) = builder.build().launch()?;
+                if let Some(role) = roles.and_then(|roles| roles.filter_for(core_id)) {
+                    mgr.set_role(role);
+                }
+                Ok::<_, Error>((state, mgr))
             };
 
         self.launch_generic(restarting_mgr_builder, restarting_mgr_builder)
@@ -718,11 +1036,11 @@ where
             ));
         }
 
-        if self.secondary_run_client.is_none() {
-            return Err(Error::illegal_argument(
-                "No client callback provided".to_string(),
-            ));
-        }
+        check_roles(
+            self.roles.as_ref(),
+            self.secondary_run_client.is_some(),
+            self.cores,
+        )?;
 
         let core_ids = get_core_ids().unwrap();
         let mut handles = vec![];
@@ -827,11 +1145,12 @@ where
                                     self.centralized_broker_port,
                                 )?;
 
-                                self.secondary_run_client.take().unwrap()(
-                                    state,
-                                    c_mgr,
-                                    client_description,
+                                let run_client = take_run_client(
+                                    self.roles.as_mut(),
+                                    &mut self.secondary_run_client,
+                                    bind_to,
                                 )?;
+                                run_client(state, c_mgr, client_description)?;
                                 Err(Error::shutting_down())
                             }
                         }?,
@@ -890,29 +1209,46 @@ where
         if self.spawn_broker {
             log::info!("I am broker!!.");
 
+            let std_hook = StdLlmpEventHook::<I, MT>::new(self.monitor.clone())?;
+            // Route testcases last, after the other hooks saw them
+            let routing_hook =
+                LlmpRoleRoutingHook::<I>::new(self.roles.as_ref().and_then(ClientRoles::routes));
+
             #[cfg(not(feature = "multi_machine"))]
-            let llmp_hook = tuple_list!(StdLlmpEventHook::<I, MT>::new(self.monitor.clone())?);
+            let broker: Box<dyn Broker> =
+                match self.corpus_culling {
+                    Some(interval) => Box::new(self.create_main_broker(
+                        tuple_list!(
+                            LlmpCorpusCullingHook::<I>::new(interval),
+                            std_hook,
+                            routing_hook
+                        ),
+                        exit_cleanly_after,
+                    )?),
+                    None => Box::new(self.create_main_broker(
+                        tuple_list!(std_hook, routing_hook),
+                        exit_cleanly_after,
+                    )?),
+                };
 
             #[cfg(feature = "multi_machine")]
-            let llmp_hook = tuple_list!(
-                StdLlmpEventHook::<I, MT>::new(self.monitor.clone())?,
-                multi_machine_sender_hook,
-            );
-
-            let mut broker = LlmpBroker::create_attach_to_tcp(
-                self.shmem_provider.clone(),
-                llmp_hook,
-                self.broker_port,
-            )?;
-
-            if let Some(remote_broker_addr) = self.remote_broker_addr {
-                log::info!("B2b: Connecting to {:?}", &remote_broker_addr);
-                broker.inner_mut().connect_b2b(remote_broker_addr)?;
-            }
-
-            broker.set_exit_after(exit_cleanly_after);
+            let broker: Box<dyn Broker> = match self.corpus_culling {
+                Some(interval) => Box::new(self.create_main_broker(
+                    tuple_list!(
+                        LlmpCorpusCullingHook::<I>::new(interval),
+                        std_hook,
+                        multi_machine_sender_hook,
+                        routing_hook,
+                    ),
+                    exit_cleanly_after,
+                )?),
+                None => Box::new(self.create_main_broker(
+                    tuple_list!(std_hook, multi_machine_sender_hook, routing_hook),
+                    exit_cleanly_after,
+                )?),
+            };
 
-            brokers.add(Box::new(broker));
+            brokers.add(broker);
         }
         log::debug!("Broker has been initialized; pid {}.", std::process::id());
 
@@ -931,4 +1267,25 @@ where
 
         Err(Error::shutting_down())
     }
+
+    /// Create the main broker, on which all clients share their testcases, with the given hooks
+    fn create_main_broker<HT>(
+        &self,
+        hooks: HT,
+        exit_cleanly_after: NonZeroUsize,
+    ) -> Result<LlmpBroker<HT, SP::ShMem, SP>, Error>
+    where
+        HT: LlmpHookTuple<SP::ShMem, SP>,
+    {
+        let mut broker =
+            LlmpBroker::create_attach_to_tcp(self.shmem_provider.clone(), hooks, self.broker_port)?;
+
+        if let Some(remote_broker_addr) = self.remote_broker_addr {
+            log::info!("B2b: Connecting to {:?}", &remote_broker_addr);
+            broker.inner_mut().connect_b2b(remote_broker_addr)?;
+        }
+
+        broker.set_exit_after(exit_cleanly_after);
+        Ok(broker)
+    }
 }
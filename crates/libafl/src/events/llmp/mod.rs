@@ -4,7 +4,7 @@ use core::{fmt::Debug, marker::PhantomData, time::Duration};
 
 use libafl_bolts::{
     ClientId,
-    llmp::{LlmpClient, LlmpClientDescription, Tag},
+    llmp::{Flags, LlmpClient, LlmpClientDescription, Tag},
     shmem::{NopShMem, NopShMemProvider, ShMem, ShMemProvider},
 };
 #[cfg(feature = "llmp_compression")]
@@ -14,6 +14,8 @@ use libafl_bolts::{
 };
 use serde::{Serialize, de::DeserializeOwned};
 
+#[cfg(feature = "std")]
+use crate::events::roles::{LLMP_TAG_ROUTED_EVENT, unroute};
 use crate::{
     Error,
     events::{Event, EventFirer, EventWithStats},
@@ -37,6 +39,43 @@ pub(crate) const LLMP_TAG_EVENT_TO_BOTH: Tag = Tag(0x2B0741);
 pub(crate) const _LLMP_TAG_RESTART: Tag = Tag(0x8357A87);
 pub(crate) const _LLMP_TAG_NO_RESTART: Tag = Tag(0x57A7EE71);
 
+/// The message is an [`Event::NewTestcase`].
+/// Broker hooks check the event kind flags to skip other events without deserializing them.
+pub(crate) const LLMP_FLAG_EVENT_NEW_TESTCASE: Flags = Flags(0x100);
+/// The message is an [`Event::Objective`]
+#[cfg(feature = "std")]
+pub(crate) const LLMP_FLAG_EVENT_OBJECTIVE: Flags = Flags(0x200);
+/// The message is an [`Event::UpdateUserStats`]
+#[cfg(feature = "std")]
+pub(crate) const LLMP_FLAG_EVENT_USER_STATS: Flags = Flags(0x400);
+/// The message is an [`Event::Custom`]
+pub(crate) const LLMP_FLAG_EVENT_CUSTOM: Flags = Flags(0x800);
+/// The message is any other [`Event`]
+#[cfg(feature = "std")]
+pub(crate) const LLMP_FLAG_EVENT_OTHER: Flags = Flags(0x1000);
+/// All event kind flags
+const LLMP_FLAGS_EVENT_KIND: Flags = Flags(0x1f00);
+
+/// The event kind flag of an [`Event`]
+#[cfg(feature = "std")]
+pub(crate) fn event_kind_flag<I>(event: &Event<I>) -> Flags {
+    match event {
+        Event::NewTestcase { .. } => LLMP_FLAG_EVENT_NEW_TESTCASE,
+        Event::Objective { .. } => LLMP_FLAG_EVENT_OBJECTIVE,
+        Event::UpdateUserStats { .. } => LLMP_FLAG_EVENT_USER_STATS,
+        Event::Custom { .. } => LLMP_FLAG_EVENT_CUSTOM,
+        _ => LLMP_FLAG_EVENT_OTHER,
+    }
+}
+
+/// Returns `false` if the event kind flags show that a message is none of the event `kinds`.
+///
+/// Messages without event kind flags, for example from other machines, may be of any kind.
+pub(crate) fn may_be_event_kind(flags: Flags, kinds: Flags) -> bool {
+    let kind = flags & LLMP_FLAGS_EVENT_KIND;
+    kind == Flags(0) || kind & kinds != Flags(0)
+}
+
 /// The minimum buffer size at which to compress LLMP IPC messages.
 #[cfg(feature = "llmp_compression")]
 pub const COMPRESS_THRESHOLD: usize = 1024;
@@ -305,7 +344,7 @@ where
                 }
                 Ok(())
             }
-            Event::Stop => Ok(()),
+            Event::Stop | Event::Custom { .. } => Ok(()),
             _ => Err(Error::unknown(format!(
                 "Received illegal message that message should not have arrived: {:?}.",
                 event.name()
@@ -336,6 +375,15 @@ where
                 "EVENT_TO_BROKER parcel should not have arrived in the client!"
             );
 
+            // A converter has no role, so it receives all testcases routed by the broker
+            #[cfg(feature = "std")]
+            let (client_id, msg) = if tag == LLMP_TAG_ROUTED_EVENT {
+                let (_, sender, msg) = unroute(msg)?;
+                (sender, msg)
+            } else {
+                (client_id, msg)
+            };
+
             if client_id == self_id {
                 continue;
             }
@@ -390,6 +438,7 @@ where
                     exit_kind,
                     corpus_size,
                     observers_buf,
+                    metadata_buf,
                     forward_id,
                     #[cfg(all(unix, feature = "std", feature = "multi_machine"))]
                     node_id,
@@ -399,6 +448,7 @@ where
                     exit_kind,
                     corpus_size,
                     observers_buf,
+                    metadata_buf,
                     forward_id,
                     #[cfg(all(unix, feature = "std", feature = "multi_machine"))]
                     node_id,
@@ -411,7 +461,7 @@ where
         );
 
         let serialized = postcard::to_allocvec(&converted_event)?;
-        let flags = LLMP_FLAG_INITIALIZED;
+        let flags = LLMP_FLAG_INITIALIZED | LLMP_FLAG_EVENT_NEW_TESTCASE;
 
         match self.compressor.maybe_compress(&serialized) {
             Some(comp_buf) => {
@@ -422,7 +472,8 @@ where
                 )?;
             }
             None => {
-                self.llmp.send_buf(LLMP_TAG_EVENT_TO_BOTH, &serialized)?;
+                self.llmp
+                    .send_buf_with_flags(LLMP_TAG_EVENT_TO_BOTH, flags, &serialized)?;
             }
         }
         self.last_sent = libafl_bolts::current_time();
@@ -444,6 +495,7 @@ where
                     exit_kind,
                     corpus_size,
                     observers_buf,
+                    metadata_buf,
                     forward_id,
                     #[cfg(all(unix, feature = "std", feature = "multi_machine"))]
                     node_id,
@@ -453,6 +505,7 @@ where
                     exit_kind,
                     corpus_size,
                     observers_buf,
+                    metadata_buf,
                     forward_id,
                     #[cfg(all(unix, feature = "std", feature = "multi_machine"))]
                     node_id,
@@ -465,7 +518,11 @@ where
         );
 
         let serialized = postcard::to_allocvec(&converted_event)?;
-        self.llmp.send_buf(LLMP_TAG_EVENT_TO_BOTH, &serialized)?;
+        self.llmp.send_buf_with_flags(
+            LLMP_TAG_EVENT_TO_BOTH,
+            LLMP_FLAG_EVENT_NEW_TESTCASE,
+            &serialized,
+        )?;
         Ok(())
     }
 }
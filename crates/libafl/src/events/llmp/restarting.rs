@@ -3,9 +3,9 @@
 //! When the target crashes, a watch process (the parent) will
 //! restart/refork it.
 
-#[cfg(feature = "std")]
-use alloc::string::ToString;
 use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use alloc::{borrow::Cow, string::ToString};
 use core::{
     marker::PhantomData,
     net::SocketAddr,
@@ -14,7 +14,15 @@ use core::{
     time::Duration,
 };
 #[cfg(feature = "std")]
-use std::net::TcpStream;
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+};
+#[cfg(unix)]
+use std::{
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+};
 
 #[cfg(any(windows, not(feature = "fork")))]
 use libafl_bolts::os::startable_self;
@@ -22,21 +30,21 @@ use libafl_bolts::os::startable_self;
 use libafl_bolts::os::unix_signals::setup_signal_handler;
 #[cfg(all(feature = "fork", unix))]
 use libafl_bolts::os::{ForkResult, fork};
+#[cfg(feature = "secure_channel")]
+use libafl_bolts::secure_channel::SecureChannelConfig;
 #[cfg(feature = "std")]
 use libafl_bolts::{
     IP_LOCALHOST,
-    llmp::{TcpRequest, TcpResponse, recv_tcp_msg, send_tcp_msg},
+    llmp::{LlmpTcpStream, TcpRequest, TcpResponse, recv_tcp_msg, send_tcp_msg},
 };
 #[cfg(feature = "llmp_compression")]
-use libafl_bolts::{
-    compress::GzipCompressor,
-    llmp::{LLMP_FLAG_COMPRESSED, LLMP_FLAG_INITIALIZED},
-};
+use libafl_bolts::{compress::GzipCompressor, llmp::LLMP_FLAG_COMPRESSED};
 use libafl_bolts::{
     core_affinity::CoreId,
     current_time,
     llmp::{
-        Broker, LLMP_FLAG_FROM_MM, LlmpBroker, LlmpClient, LlmpClientDescription, LlmpConnection,
+        Broker, LLMP_FLAG_FROM_MM, LLMP_FLAG_INITIALIZED, LlmpBroker, LlmpClient,
+        LlmpClientDescription, LlmpConnection, LlmpHookTuple,
     },
     os::CTRL_C_EXIT,
     shmem::{ShMem, ShMemProvider, StdShMem, StdShMemProvider},
@@ -50,14 +58,17 @@ use typed_builder::TypedBuilder;
 use crate::events::COMPRESS_THRESHOLD;
 #[cfg(all(unix, not(miri)))]
 use crate::events::EVENTMGR_SIGHANDLER_STATE;
+#[cfg(all(unix, feature = "std", feature = "fork"))]
+use crate::events::elastic::ClientDrain;
 use crate::{
     Error,
     common::HasMetadata,
     events::{
         _LLMP_TAG_EVENT_TO_BROKER, AwaitRestartSafe, Event, EventConfig, EventFirer,
         EventManagerHooksTuple, EventManagerId, EventReceiver, EventRestarter, EventWithStats,
-        HasEventManagerId, LLMP_TAG_EVENT_TO_BOTH, LlmpShouldSaveState, ProgressReporter,
-        SendExiting, StdLlmpEventHook, launcher::ClientDescription, std_maybe_report_progress,
+        HasEventManagerId, LLMP_TAG_EVENT_TO_BOTH, LlmpRoleRoutingHook, LlmpShouldSaveState,
+        ProgressReporter, SendExiting, StdLlmpEventHook, event_kind_flag,
+        launcher::ClientDescription, roles::RoleRoutes, std_maybe_report_progress,
         std_report_progress,
     },
     inputs::Input,
@@ -67,6 +78,11 @@ use crate::{
         HasSolutions, MaybeHasClientPerfMonitor, Stoppable,
     },
 };
+#[cfg(feature = "std")]
+use crate::{
+    events::roles::{LLMP_TAG_ROUTED_EVENT, ROLE_USER_STAT, RoleFilter, unroute},
+    monitors::stats::{AggregatorOps, UserStats, UserStatsValue},
+};
 
 const INITIAL_EVENT_BUFFER_SIZE: usize = 1024 * 4;
 /// A manager that can restart on the fly, storing states in-between (in `on_restart`)
@@ -91,6 +107,15 @@ pub struct LlmpRestartingEventManager<EMH, I, S, SHM, SP> {
     staterestorer: Option<StateRestorer<SHM, SP>>,
     /// Decide if the state restorer must save the serialized state
     save_state: LlmpShouldSaveState,
+    /// Set by an elastic [`crate::events::Launcher`], to stop this client at runtime
+    #[cfg(all(unix, feature = "std", feature = "fork"))]
+    drain: Option<ClientDrain>,
+    /// Set by a [`crate::events::Launcher`] with roles, to skip testcases routed to other roles
+    #[cfg(feature = "std")]
+    role: Option<RoleFilter>,
+    /// If the role was announced to the broker already
+    #[cfg(feature = "std")]
+    role_announced: bool,
     phantom: PhantomData<(I, S)>,
 }
 
@@ -122,17 +147,66 @@ where
     SP: ShMemProvider<ShMem = SHM>,
 {
     fn fire(&mut self, _state: &mut S, event: EventWithStats<I>) -> Result<(), Error> {
+        #[cfg(feature = "std")]
+        if !self.role_announced {
+            if let Some(role) = &self.role {
+                let announcement = EventWithStats::new(
+                    Event::UpdateUserStats {
+                        name: Cow::Borrowed(ROLE_USER_STAT),
+                        value: UserStats::new(
+                            UserStatsValue::String(Cow::Owned(role.role().to_string())),
+                            AggregatorOps::None,
+                        ),
+                        phantom: PhantomData,
+                    },
+                    event.stats().clone(),
+                );
+                self.send_event(&announcement)?;
+                self.role_announced = true;
+            }
+        }
+
+        self.send_event(&event)?;
+
+        self.last_sent = current_time();
+
+        if self.staterestorer.is_some() {
+            self.intermediate_save()?;
+        }
+        Ok(())
+    }
+
+    fn configuration(&self) -> EventConfig {
+        self.configuration
+    }
+
+    fn should_send(&self) -> bool {
+        if let Some(throttle) = self.throttle {
+            current_time() - self.last_sent > throttle
+        } else {
+            true
+        }
+    }
+}
+
+impl<EMH, I, S, SHM, SP> LlmpRestartingEventManager<EMH, I, S, SHM, SP>
+where
+    I: Serialize,
+    SHM: ShMem,
+    SP: ShMemProvider<ShMem = SHM>,
+{
+    /// Serialize, maybe compress, and send an event to the broker
+    fn send_event(&mut self, event: &EventWithStats<I>) -> Result<(), Error> {
         // Check if we are going to crash in the event, in which case we store our current state for the next runner
-        #[cfg(feature = "llmp_compression")]
-        let flags = LLMP_FLAG_INITIALIZED;
+        let flags = LLMP_FLAG_INITIALIZED | event_kind_flag(event.event());
 
         self.event_buffer.resize(self.event_buffer.capacity(), 0);
 
         // Serialize the event, reallocating event_buffer if needed
-        let written_len = match postcard::to_slice(&event, &mut self.event_buffer) {
+        let written_len = match postcard::to_slice(event, &mut self.event_buffer) {
             Ok(written) => written.len(),
             Err(postcard::Error::SerializeBufferFull) => {
-                let serialized = postcard::to_allocvec(&event)?;
+                let serialized = postcard::to_allocvec(event)?;
                 self.event_buffer = serialized;
                 self.event_buffer.len()
             }
@@ -153,37 +227,25 @@ where
                     )?;
                 }
                 None => {
-                    self.llmp
-                        .send_buf(LLMP_TAG_EVENT_TO_BOTH, &self.event_buffer[..written_len])?;
+                    self.llmp.send_buf_with_flags(
+                        LLMP_TAG_EVENT_TO_BOTH,
+                        flags,
+                        &self.event_buffer[..written_len],
+                    )?;
                 }
             }
         }
 
         #[cfg(not(feature = "llmp_compression"))]
         {
-            self.llmp
-                .send_buf(LLMP_TAG_EVENT_TO_BOTH, &self.event_buffer[..written_len])?;
-        }
-
-        self.last_sent = current_time();
-
-        if self.staterestorer.is_some() {
-            self.intermediate_save()?;
+            self.llmp.send_buf_with_flags(
+                LLMP_TAG_EVENT_TO_BOTH,
+                flags,
+                &self.event_buffer[..written_len],
+            )?;
         }
         Ok(())
     }
-
-    fn configuration(&self) -> EventConfig {
-        self.configuration
-    }
-
-    fn should_send(&self) -> bool {
-        if let Some(throttle) = self.throttle {
-            current_time() - self.last_sent > throttle
-        } else {
-            true
-        }
-    }
 }
 
 impl<EMH, I, S, SHM, SP> EventRestarter<S> for LlmpRestartingEventManager<EMH, I, S, SHM, SP>
@@ -256,6 +318,14 @@ where
     SP: ShMemProvider<ShMem = SHM>,
 {
     fn try_receive(&mut self, state: &mut S) -> Result<Option<(EventWithStats<I>, bool)>, Error> {
+        #[cfg(all(unix, feature = "std", feature = "fork"))]
+        if let Some(drain) = &self.drain {
+            if drain.is_requested() && !state.stop_requested() {
+                drain.save_state(state)?;
+                state.request_stop();
+            }
+        }
+
         // TODO: Get around local event copy by moving handle_in_client
         let self_id = self.llmp.sender().id();
         while let Some((client_id, tag, flags, msg)) = self.llmp.recv_buf_with_flags()? {
@@ -264,6 +334,21 @@ where
                 "EVENT_TO_BROKER parcel should not have arrived in the client!"
             );
 
+            // Testcases routed by the broker to some roles only, see the `roles` module
+            #[cfg(feature = "std")]
+            let (client_id, msg) = if tag == LLMP_TAG_ROUTED_EVENT {
+                let (receivers, sender, msg) = unroute(msg)?;
+                if let Some(role) = &self.role {
+                    if !role.receives(receivers) {
+                        log::trace!("Skipping a testcase not routed to role {}", role.role());
+                        continue;
+                    }
+                }
+                (sender, msg)
+            } else {
+                (client_id, msg)
+            };
+
             if client_id == self_id {
                 continue;
             }
@@ -330,6 +415,9 @@ where
                 Event::Stop => {
                     state.request_stop();
                 }
+                Event::Custom { name, .. } => {
+                    log::debug!("Received unhandled custom event {name}");
+                }
                 _ => {
                     return Err(Error::unknown(format!(
                         "Received illegal message that message should not have arrived: {:?}.",
@@ -438,6 +526,12 @@ impl<EMH> LlmpEventManagerBuilder<EMH> {
             event_buffer: Vec::with_capacity(INITIAL_EVENT_BUFFER_SIZE),
             staterestorer,
             save_state: LlmpShouldSaveState::OnRestart,
+            #[cfg(all(unix, feature = "std", feature = "fork"))]
+            drain: None,
+            #[cfg(feature = "std")]
+            role: None,
+            #[cfg(feature = "std")]
+            role_announced: false,
             phantom: PhantomData,
         })
     }
@@ -460,6 +554,43 @@ impl<EMH> LlmpEventManagerBuilder<EMH> {
         Self::build_from_client(self, llmp, configuration, staterestorer)
     }
 
+    /// Create an LLMP event manager on the unix domain socket at `path`.
+    /// It expects a broker to listen on this socket.
+    #[cfg(all(unix, feature = "std"))]
+    pub fn build_on_unix_socket<I, S, SHM, SP>(
+        self,
+        shmem_provider: SP,
+        path: &Path,
+        configuration: EventConfig,
+        staterestorer: Option<StateRestorer<SHM, SP>>,
+    ) -> Result<LlmpRestartingEventManager<EMH, I, S, SHM, SP>, Error>
+    where
+        SHM: ShMem,
+        SP: ShMemProvider<ShMem = SHM>,
+    {
+        let llmp = LlmpClient::create_attach_to_unix(shmem_provider, path)?;
+        Self::build_from_client(self, llmp, configuration, staterestorer)
+    }
+
+    /// Create an LLMP event manager on a port, authenticating to the broker with the given [`SecureChannelConfig`].
+    /// It expects a broker to exist on this port.
+    #[cfg(feature = "secure_channel")]
+    pub fn build_on_port_secure<I, S, SHM, SP>(
+        self,
+        shmem_provider: SP,
+        port: u16,
+        configuration: EventConfig,
+        staterestorer: Option<StateRestorer<SHM, SP>>,
+        secure_channel: &SecureChannelConfig,
+    ) -> Result<LlmpRestartingEventManager<EMH, I, S, SHM, SP>, Error>
+    where
+        SHM: ShMem,
+        SP: ShMemProvider<ShMem = SHM>,
+    {
+        let llmp = LlmpClient::create_attach_to_tcp_secure(shmem_provider, port, secure_channel)?;
+        Self::build_from_client(self, llmp, configuration, staterestorer)
+    }
+
     /// If a client respawns, it may reuse the existing connection, previously
     /// stored by [`LlmpClient::to_env()`].
     #[cfg(feature = "std")]
@@ -513,6 +644,20 @@ where
         }
     }
 
+    /// Stop this client, storing its state, once the [`ClientDrain`] is requested
+    #[cfg(all(unix, feature = "std", feature = "fork"))]
+    pub fn set_drain(&mut self, drain: ClientDrain) {
+        self.drain = Some(drain);
+    }
+
+    /// Only receive the testcases the broker routes to this [`RoleFilter`],
+    /// and announce the role to the broker with the first event.
+    #[cfg(feature = "std")]
+    pub fn set_role(&mut self, role: RoleFilter) {
+        self.role = Some(role);
+        self.role_announced = false;
+    }
+
     /// Get the staterestorer
     pub fn staterestorer(&self) -> &Option<StateRestorer<SHM, SP>> {
         &self.staterestorer
@@ -553,11 +698,44 @@ where
     /// `send_exiting()` is exclusive to the fuzzer client.
     #[cfg(feature = "std")]
     pub fn detach_from_broker(&self, broker_port: u16) -> Result<(), Error> {
-        let client_id = self.llmp.sender().id();
-        let Ok(mut stream) = TcpStream::connect((IP_LOCALHOST, broker_port)) else {
+        let Ok(stream) = TcpStream::connect((IP_LOCALHOST, broker_port)) else {
+            log::error!("Connection refused.");
+            return Ok(());
+        };
+        self.detach_from_broker_stream(LlmpTcpStream::Plain(stream))
+    }
+
+    /// Like [`Self::detach_from_broker`], for brokers only accepting peers authenticated
+    /// with the given [`SecureChannelConfig`].
+    #[cfg(feature = "secure_channel")]
+    pub fn detach_from_broker_secure(
+        &self,
+        broker_port: u16,
+        secure_channel: &SecureChannelConfig,
+    ) -> Result<(), Error> {
+        let Ok(stream) = TcpStream::connect((IP_LOCALHOST, broker_port)) else {
+            log::error!("Connection refused.");
+            return Ok(());
+        };
+        self.detach_from_broker_stream(LlmpTcpStream::connect(stream, Some(secure_channel))?)
+    }
+
+    /// Like [`Self::detach_from_broker`], for brokers listening on the unix domain socket at `path`
+    #[cfg(all(unix, feature = "std"))]
+    pub fn detach_from_broker_unix(&self, path: &Path) -> Result<(), Error> {
+        let Ok(stream) = UnixStream::connect(path) else {
             log::error!("Connection refused.");
             return Ok(());
         };
+        self.detach_from_broker_stream(stream)
+    }
+
+    #[cfg(feature = "std")]
+    fn detach_from_broker_stream<T>(&self, mut stream: T) -> Result<(), Error>
+    where
+        T: Read + Write,
+    {
+        let client_id = self.llmp.sender().id();
         // The broker tells us hello we don't care we just tell it our client died
         let TcpResponse::BrokerConnectHello {
             broker_shmem_description: _,
@@ -693,6 +871,20 @@ pub struct RestartingMgr<EMH, I, MT, S, SP> {
     serialize_state: LlmpShouldSaveState,
     /// The hooks passed to event manager:
     hooks: EMH,
+    /// If set, all tcp connections of the broker are authenticated with this [`SecureChannelConfig`],
+    /// and clients authenticate to the broker with it.
+    #[cfg(feature = "secure_channel")]
+    #[builder(default = None)]
+    secure_channel: Option<SecureChannelConfig>,
+    /// If set, the broker listens on a unix domain socket at this path, instead of [`Self::broker_port`],
+    /// and clients connect to it there.
+    /// Connections on the socket are not authenticated, restrict access with file permissions instead.
+    #[cfg(unix)]
+    #[builder(default = None)]
+    broker_socket: Option<PathBuf>,
+    /// If set, the broker routes new testcases to the [`crate::events::ClientRole`]s receiving them
+    #[builder(default = None)]
+    role_routes: Option<RoleRoutes>,
     #[builder(setter(skip), default = PhantomData)]
     phantom_data: PhantomData<(EMH, I, S)>,
 }
@@ -740,12 +932,42 @@ where
             // We get here if we are on Unix, or we are a broker on Windows (or without forks).
             let (mgr, core_id) = match &self.kind {
                 ManagerKind::Any => {
-                    let connection =
-                        LlmpConnection::on_port(self.shmem_provider.clone(), self.broker_port)?;
+                    #[cfg(unix)]
+                    let unix_connection = match &self.broker_socket {
+                        Some(path) => Some(LlmpConnection::on_unix_path(
+                            self.shmem_provider.clone(),
+                            path,
+                        )?),
+                        None => None,
+                    };
+                    #[cfg(not(unix))]
+                    let unix_connection = None;
+
+                    #[cfg(feature = "secure_channel")]
+                    let connection = match (unix_connection, &self.secure_channel) {
+                        (Some(connection), _) => connection,
+                        (None, Some(secure_channel)) => LlmpConnection::on_port_secure(
+                            self.shmem_provider.clone(),
+                            self.broker_port,
+                            secure_channel,
+                        )?,
+                        (None, None) => {
+                            LlmpConnection::on_port(self.shmem_provider.clone(), self.broker_port)?
+                        }
+                    };
+                    #[cfg(not(feature = "secure_channel"))]
+                    let connection = match unix_connection {
+                        Some(connection) => connection,
+                        None => {
+                            LlmpConnection::on_port(self.shmem_provider.clone(), self.broker_port)?
+                        }
+                    };
                     match connection {
                         LlmpConnection::IsBroker { broker } => {
                             let llmp_hook =
                                 StdLlmpEventHook::<I, MT>::new(self.monitor.take().unwrap())?;
+                            let routing_hook =
+                                LlmpRoleRoutingHook::<I>::new(self.role_routes.take());
 
                             // Yep, broker. Just loop here.
                             log::info!(
@@ -753,7 +975,7 @@ where
                             );
 
                             broker_things(
-                                broker.add_hooks(tuple_list!(llmp_hook)),
+                                broker.add_hooks(tuple_list!(llmp_hook, routing_hook)),
                                 self.remote_broker_addr,
                             )?;
 
@@ -770,12 +992,9 @@ where
                 }
                 ManagerKind::Broker => {
                     let llmp_hook = StdLlmpEventHook::new(self.monitor.take().unwrap())?;
+                    let routing_hook = LlmpRoleRoutingHook::<I>::new(self.role_routes.take());
 
-                    let broker = LlmpBroker::create_attach_to_tcp(
-                        self.shmem_provider.clone(),
-                        tuple_list!(llmp_hook),
-                        self.broker_port,
-                    )?;
+                    let broker = self.create_broker(tuple_list!(llmp_hook, routing_hook))?;
 
                     broker_things(broker, self.remote_broker_addr)?;
                     unreachable!(
@@ -784,14 +1003,7 @@ where
                 }
                 ManagerKind::Client { client_description } => {
                     // We are a client
-                    let mgr = LlmpEventManagerBuilder::builder()
-                        .hooks(self.hooks)
-                        .build_on_port(
-                            self.shmem_provider.clone(),
-                            self.broker_port,
-                            self.configuration,
-                            None,
-                        )?;
+                    let mgr = self.connect_client()?;
 
                     (mgr, Some(client_description.core_id()))
                 }
@@ -878,14 +1090,14 @@ where
 
                 if child_status == CTRL_C_EXIT || staterestorer.wants_to_exit() {
                     // if ctrl-c is pressed, we end up in this branch
-                    if let Err(err) = mgr.detach_from_broker(self.broker_port) {
+                    if let Err(err) = self.detach_from_broker(&mgr) {
                         log::error!("Failed to detach from broker: {err}");
                     }
                     return Err(Error::shutting_down());
                 }
 
                 if !staterestorer.has_content() && !self.serialize_state.oom_safe() {
-                    if let Err(err) = mgr.detach_from_broker(self.broker_port) {
+                    if let Err(err) = self.detach_from_broker(&mgr) {
                         log::error!("Failed to detach from broker: {err}");
                     }
                     #[cfg(unix)]
@@ -971,6 +1183,77 @@ where
 
         Ok((state, mgr))
     }
+
+    /// Create the broker, listening on the unix socket, or the (maybe authenticated) tcp port
+    fn create_broker<HT>(&self, hooks: HT) -> Result<LlmpBroker<HT, SP::ShMem, SP>, Error>
+    where
+        HT: LlmpHookTuple<SP::ShMem, SP>,
+    {
+        #[cfg(unix)]
+        if let Some(path) = &self.broker_socket {
+            return LlmpBroker::create_attach_to_unix(self.shmem_provider.clone(), hooks, path);
+        }
+        #[cfg(feature = "secure_channel")]
+        if let Some(secure_channel) = &self.secure_channel {
+            let mut broker = LlmpBroker::new(self.shmem_provider.clone(), hooks)?;
+            broker
+                .inner_mut()
+                .set_secure_channel(Some(secure_channel.clone()));
+            broker
+                .inner_mut()
+                .launch_tcp_listener_on(self.broker_port)?;
+            return Ok(broker);
+        }
+        LlmpBroker::create_attach_to_tcp(self.shmem_provider.clone(), hooks, self.broker_port)
+    }
+
+    /// Connect a new client to the broker, on the unix socket, or the (maybe authenticated) tcp port
+    fn connect_client(
+        &self,
+    ) -> Result<LlmpRestartingEventManager<EMH, I, S, SP::ShMem, SP>, Error> {
+        let builder = LlmpEventManagerBuilder::builder().hooks(self.hooks);
+        #[cfg(unix)]
+        if let Some(path) = &self.broker_socket {
+            return builder.build_on_unix_socket(
+                self.shmem_provider.clone(),
+                path,
+                self.configuration,
+                None,
+            );
+        }
+        #[cfg(feature = "secure_channel")]
+        if let Some(secure_channel) = &self.secure_channel {
+            return builder.build_on_port_secure(
+                self.shmem_provider.clone(),
+                self.broker_port,
+                self.configuration,
+                None,
+                secure_channel,
+            );
+        }
+        builder.build_on_port(
+            self.shmem_provider.clone(),
+            self.broker_port,
+            self.configuration,
+            None,
+        )
+    }
+
+    /// Tell the broker that the client of this `mgr` exited
+    fn detach_from_broker(
+        &self,
+        mgr: &LlmpRestartingEventManager<EMH, I, S, SP::ShMem, SP>,
+    ) -> Result<(), Error> {
+        #[cfg(unix)]
+        if let Some(path) = &self.broker_socket {
+            return mgr.detach_from_broker_unix(path);
+        }
+        #[cfg(feature = "secure_channel")]
+        if let Some(secure_channel) = &self.secure_channel {
+            return mgr.detach_from_broker_secure(self.broker_port, secure_channel);
+        }
+        mgr.detach_from_broker(self.broker_port)
+    }
 }
 
 #[cfg(test)]
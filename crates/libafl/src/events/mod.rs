@@ -11,14 +11,26 @@ pub mod centralized;
 #[cfg(all(unix, feature = "std"))]
 pub use centralized::*;
 #[cfg(feature = "std")]
+pub mod corpus_culling;
+#[cfg(all(unix, feature = "std", feature = "fork"))]
+pub mod elastic;
+#[cfg(feature = "std")]
+pub mod event_log;
+#[cfg(feature = "std")]
 pub mod launcher;
+#[cfg(feature = "std")]
+pub mod roles;
 
 pub mod llmp;
 pub use llmp::*;
 #[cfg(feature = "tcp_manager")]
 pub mod tcp;
+#[cfg(any(feature = "tcp_manager", all(unix, feature = "multi_machine")))]
+mod tcp_channel;
 
 pub mod broker_hooks;
+
+pub mod shared_metadata;
 #[cfg(feature = "introspection")]
 use alloc::boxed::Box;
 use alloc::{borrow::Cow, string::String, vec::Vec};
@@ -28,6 +40,7 @@ use core::{
     marker::PhantomData,
     time::Duration,
 };
+pub use shared_metadata::*;
 
 use ahash::RandomState;
 pub use broker_hooks::*;
@@ -38,7 +51,9 @@ use libafl_bolts::current_time;
 use libafl_bolts::os::CTRL_C_EXIT;
 #[cfg(all(unix, feature = "std"))]
 use libafl_bolts::os::unix_signals::{Signal, SignalHandler, siginfo_t, ucontext_t};
-use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+pub use roles::{ClientRole, ClientRoles, RoleFilter, RoleRoutes};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 #[cfg(feature = "std")]
 use uuid::Uuid;
 
@@ -223,20 +238,16 @@ impl From<String> for EventConfig {
     }
 }
 
-/*
-/// A custom event, for own messages, with own handler.
-pub trait CustomEvent<I>: SerdeAny
-where
-    I: Input,
-{
-    /// Returns the name of this event
-    fn name(&self) -> &str;
-    /// This method will be called in the broker
-    fn handle_in_broker(&self) -> Result<BrokerEventResult, Error>;
-    /// This method will be called in the clients after handle_in_broker (unless BrokerEventResult::Handled) was returned in handle_in_broker
-    fn handle_in_client(&self) -> Result<(), Error>;
+/// A custom event payload, for own messages between clients.
+///
+/// Payloads are sent as [`Event::Custom`], serialized with `postcard` and tagged with [`CustomEvent::NAME`].
+/// Fire them with [`EventFirer::fire_custom`], receive them in an [`EventManagerHook`], for example the [`CustomEventHook`],
+/// and handle them in the broker with the [`LlmpCustomEventHook`].
+/// A [`SimpleEventManager`] has no other clients, it drops them with a warning.
+pub trait CustomEvent: Serialize + DeserializeOwned {
+    /// The unique name of this event type
+    const NAME: &'static str;
 }
-*/
 
 /// Basic statistics
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -300,14 +311,14 @@ impl<I> EventWithStats<I> {
 /// Events sent around in the library
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Event<I> {
-    // TODO use an ID to keep track of the original index in the sender Corpus
-    // The sender can then use it to send Testcase metadata with CustomEvent
     /// A fuzzer found a new testcase. Rejoice!
     NewTestcase {
         /// The input for the new testcase
         input: I,
         /// The state of the observers when this testcase was found
         observers_buf: Option<Vec<u8>>,
+        /// The serialized [`SharedMetadata`] of the testcase, see [`MetadataSharing`]
+        metadata_buf: Option<Vec<u8>>,
         /// The exit kind
         exit_kind: ExitKind,
         /// The new corpus size of this client
@@ -358,11 +369,15 @@ pub enum Event<I> {
     },
     /// Exit gracefully
     Stop,
-    /*/// A custom type
+    /// A user-defined [`CustomEvent`]
     Custom {
-        // TODO: Allow custom events
-        // custom_event: Box<dyn CustomEvent<I, OT>>,
-    },*/
+        /// The [`CustomEvent::NAME`] of the payload
+        name: Cow<'static, str>,
+        /// The serialized payload
+        buf: Vec<u8>,
+        /// `PhantomData`
+        phantom: PhantomData<I>,
+    },
 }
 
 impl<I> Event<I> {
@@ -376,10 +391,8 @@ impl<I> Event<I> {
             Event::UpdatePerfMonitor { .. } => "PerfMonitor",
             Event::Objective { .. } => "Objective",
             Event::Log { .. } => "Log",
-            /*Event::Custom {
-                sender_id: _, /*custom_event} => custom_event.name()*/
-            } => "todo",*/
             Event::Stop => "Stop",
+            Event::Custom { name, .. } => name,
         }
     }
 
@@ -399,9 +412,32 @@ impl<I> Event<I> {
             Event::Objective { .. } => Cow::Borrowed("Objective"),
             Event::Log { .. } => Cow::Borrowed("Log"),
             Event::Stop => Cow::Borrowed("Stop"),
-            /*Event::Custom {
-                sender_id: _, /*custom_event} => custom_event.name()*/
-            } => "todo",*/
+            Event::Custom { name, .. } => Cow::Owned(format!("Custom {name}")),
+        }
+    }
+
+    /// Create a new [`Event::Custom`] from the given [`CustomEvent`] payload
+    pub fn custom<C>(payload: &C) -> Result<Self, Error>
+    where
+        C: CustomEvent,
+    {
+        Ok(Event::Custom {
+            name: Cow::Borrowed(C::NAME),
+            buf: postcard::to_allocvec(payload)?,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Deserialize the payload, if this is an [`Event::Custom`] of type `C`
+    pub fn custom_payload<C>(&self) -> Result<Option<C>, Error>
+    where
+        C: CustomEvent,
+    {
+        match self {
+            Event::Custom { name, buf, .. } if name == C::NAME => {
+                Ok(Some(postcard::from_bytes(buf)?))
+            }
+            _ => Ok(None),
         }
     }
 
@@ -455,6 +491,20 @@ pub trait EventFirer<I, S> {
         )
     }
 
+    /// Send off a [`CustomEvent`] to the broker.
+    /// This is a shortcut for [`EventFirer::fire`] with [`Event::custom`] as argument.
+    fn fire_custom<C>(&mut self, state: &mut S, payload: &C) -> Result<(), Error>
+    where
+        C: CustomEvent,
+        S: HasExecutions,
+    {
+        let executions = *state.executions();
+        self.fire(
+            state,
+            EventWithStats::with_current_time(Event::custom(payload)?, executions),
+        )
+    }
+
     /// Get the configuration
     fn configuration(&self) -> EventConfig {
         EventConfig::AlwaysUnique
@@ -700,11 +750,14 @@ impl HasEventManagerId for NopEventManager {
 #[cfg(test)]
 mod tests {
 
+    use alloc::{string::String, vec::Vec};
+
     use libafl_bolts::{Named, tuples::tuple_list};
+    use serde::{Deserialize, Serialize};
     use tuple_list::tuple_list_type;
 
     use crate::{
-        events::{Event, EventConfig},
+        events::{CustomEvent, Event, EventConfig},
         executors::ExitKind,
         inputs::bytes::BytesInput,
         observers::StdMapObserver,
@@ -726,6 +779,7 @@ mod tests {
         let e = Event::NewTestcase {
             input: i,
             observers_buf: Some(observers_buf),
+            metadata_buf: None,
             exit_kind: ExitKind::Ok,
             corpus_size: 123,
             client_config: EventConfig::AlwaysUnique,
@@ -746,4 +800,54 @@ mod tests {
             _ => panic!("mistmatch"),
         }
     }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct NewTokens(Vec<Vec<u8>>);
+
+    impl CustomEvent for NewTokens {
+        const NAME: &'static str = "NewTokens";
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct GrammarChunk(String);
+
+    impl CustomEvent for GrammarChunk {
+        const NAME: &'static str = "GrammarChunk";
+    }
+
+    #[test]
+    fn test_custom_event_serde() {
+        let tokens = NewTokens(vec![b"GET".to_vec(), b"POST".to_vec()]);
+        let e = Event::<BytesInput>::custom(&tokens).unwrap();
+        assert_eq!(e.name(), "NewTokens");
+
+        let serialized = postcard::to_allocvec(&e).unwrap();
+        let d = postcard::from_bytes::<Event<BytesInput>>(&serialized).unwrap();
+        assert_eq!(d.custom_payload::<NewTokens>().unwrap(), Some(tokens));
+        assert!(d.custom_payload::<GrammarChunk>().unwrap().is_none());
+        assert!(
+            Event::<BytesInput>::Heartbeat
+                .custom_payload::<NewTokens>()
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_event_kind_flags() {
+        use libafl_bolts::llmp::{LLMP_FLAG_COMPRESSED, LLMP_FLAG_FROM_MM};
+
+        use crate::events::{LLMP_FLAG_EVENT_CUSTOM, event_kind_flag, may_be_event_kind};
+
+        let custom = event_kind_flag(&Event::<BytesInput>::custom(&NewTokens(vec![])).unwrap());
+        assert!(may_be_event_kind(custom, LLMP_FLAG_EVENT_CUSTOM));
+        let heartbeat = event_kind_flag(&Event::<BytesInput>::Heartbeat);
+        assert!(!may_be_event_kind(
+            heartbeat | LLMP_FLAG_COMPRESSED,
+            LLMP_FLAG_EVENT_CUSTOM
+        ));
+        // Messages without event kind flags may be of any kind
+        assert!(may_be_event_kind(LLMP_FLAG_FROM_MM, LLMP_FLAG_EVENT_CUSTOM));
+    }
 }
@@ -4,25 +4,34 @@ use core::{
     sync::atomic::{AtomicU64, Ordering},
     time::Duration,
 };
-use std::{collections::HashMap, io::ErrorKind, process, sync::OnceLock};
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    process,
+    sync::OnceLock,
+};
 
 use enumflags2::{BitFlags, bitflags};
 #[cfg(feature = "llmp_compression")]
 use libafl_bolts::compress::GzipCompressor;
-use libafl_bolts::{Error, current_time, ownedref::OwnedRef};
+#[cfg(feature = "secure_channel")]
+use libafl_bolts::secure_channel::SecureChannelConfig;
+use libafl_bolts::{Error, current_nanos, current_time, ownedref::OwnedRef};
 use serde::{Deserialize, Serialize};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream, ToSocketAddrs},
     runtime::Runtime,
     sync::RwLock,
-    task::JoinHandle,
+    task::{JoinHandle, spawn},
     time,
 };
 use typed_builder::TypedBuilder;
 
 use crate::{
-    events::{EventWithStats, TcpMultiMachineLlmpReceiverHook, TcpMultiMachineLlmpSenderHook},
+    events::{
+        EventWithStats, TcpMultiMachineLlmpReceiverHook, TcpMultiMachineLlmpSenderHook,
+        tcp_channel::TcpChannel,
+    },
     inputs::{Input, NopInput},
 };
 
@@ -40,6 +49,14 @@ pub enum NodePolicy {
 }
 
 const DUMMY_BYTE: u8 = 0x14;
+/// Marks a heartbeat, without payload
+const HEARTBEAT_BYTE: u8 = 0x15;
+/// Marks a sync message, exchanged once on connect: the parent sends its session id,
+/// the child answers with the number of messages it already received from this session.
+const SYNC_BYTE: u8 = 0x16;
+
+/// The first wait between attempts to reconnect to a parent
+const INITIAL_RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
 
 /// Use `OwnedRef` as much as possible here to avoid useless copies.
 /// An owned TCP message for multi machine
@@ -113,14 +130,44 @@ impl NodeId {
 pub struct TcpMultiMachineState<A> {
     node_descriptor: NodeDescriptor<A>,
     /// the parent to which the testcases should be forwarded when deemed interesting
-    parent: Option<TcpStream>,
+    parent: Option<NodeConnection>,
     /// The children who connected during the fuzzing session.
-    children: HashMap<NodeId, TcpStream>, // The children who connected during the fuzzing session.
+    children: HashMap<NodeId, NodeConnection>, // The children who connected during the fuzzing session.
     old_msgs: Vec<Vec<u8>>,
+    /// The id of this session, so that reconnecting children know if they missed messages
+    session_id: u64,
+    /// The session id of the last parent, and how many of its messages we received
+    parent_sync: Option<(u64, u64)>,
+    /// How many of the `old_msgs` the parent received from us
+    sent_to_parent: usize,
+    /// Messages received in the background, not yet forwarded to the broker
+    pending_msgs: Vec<Box<[u8]>>,
+    parent_reconnect: ParentReconnect,
     #[cfg(feature = "llmp_compression")]
     compressor: GzipCompressor,
 }
 
+/// The state of the reconnection to a parent
+#[derive(Debug, Clone, Copy)]
+struct ParentReconnect {
+    /// The index of the next parent candidate to try
+    candidate: usize,
+    /// The wait after the last failed attempt
+    backoff: Duration,
+    /// When to try again
+    next_attempt: Duration,
+}
+
+impl Default for ParentReconnect {
+    fn default() -> Self {
+        Self {
+            candidate: 0,
+            backoff: INITIAL_RECONNECT_BACKOFF,
+            next_attempt: Duration::ZERO,
+        }
+    }
+}
+
 /// The tree descriptor for the
 #[derive(Debug, Clone, TypedBuilder)]
 pub struct NodeDescriptor<A> {
@@ -138,6 +185,28 @@ pub struct NodeDescriptor<A> {
     /// Node flags
     #[builder(default_code = "BitFlags::default()")]
     pub flags: BitFlags<NodePolicy>, // The policy for shared messages between nodes.
+
+    /// The nodes to reparent to, in order, if the parent is lost and can not be reached anymore
+    #[builder(default = vec![])]
+    pub fallback_parent_addrs: Vec<A>,
+
+    /// How often to send heartbeats to the parent and the children. Defaults to 5 seconds
+    #[builder(default = Duration::from_secs(5))]
+    pub heartbeat_interval: Duration,
+
+    /// After how long without any message a node is considered dead. Defaults to 30 seconds
+    #[builder(default = Duration::from_secs(30))]
+    pub heartbeat_timeout: Duration,
+
+    /// The longest wait between attempts to reconnect to a parent. Defaults to 60 seconds
+    #[builder(default = Duration::from_secs(60))]
+    pub max_reconnect_backoff: Duration,
+
+    /// If set, the connections to the parent and the children are authenticated with this [`SecureChannelConfig`].
+    /// Children that fail to authenticate are rejected.
+    #[cfg(feature = "secure_channel")]
+    #[builder(default = None)]
+    pub secure_channel: Option<SecureChannelConfig>,
 }
 
 /// A set of multi-machine `broker_hooks`.
@@ -219,20 +288,13 @@ where
 
             // Create the state of the hook. This will be shared with the background server, so we wrap
             // it with concurrent-safe objects
-            let state = Arc::new(RwLock::new(TcpMultiMachineState {
-                node_descriptor,
-                parent: None,
-                children: HashMap::default(),
-                old_msgs: Vec::new(),
-                #[cfg(feature = "llmp_compression")]
-                compressor: GzipCompressor::new(),
-            }));
+            let state = Arc::new(RwLock::new(TcpMultiMachineState::new(node_descriptor)));
 
             let rt = Arc::new(
                 Runtime::new().map_err(|_| Error::unknown("Tokio runtime spawning failed"))?,
             );
 
-            TcpMultiMachineState::init::<I>(&state.clone(), &rt.clone())?;
+            TcpMultiMachineState::init(&state.clone(), &rt.clone())?;
 
             Ok(TcpMultiMachineHooks {
                 sender: TcpMultiMachineLlmpSenderHook::new(state.clone(), rt.clone()),
@@ -242,6 +304,32 @@ where
     }
 }
 
+impl<A> NodeDescriptor<A> {
+    /// The parents to connect to, in order: the [`NodeDescriptor::parent_addr`], then the [`NodeDescriptor::fallback_parent_addrs`]
+    fn parent_candidates(&self) -> impl Iterator<Item = &A> {
+        self.parent_addr.iter().chain(&self.fallback_parent_addrs)
+    }
+}
+
+impl<A> TcpMultiMachineState<A> {
+    /// Create the state of a node, not connected to other nodes yet
+    fn new(node_descriptor: NodeDescriptor<A>) -> Self {
+        Self {
+            node_descriptor,
+            parent: None,
+            children: HashMap::default(),
+            old_msgs: Vec::new(),
+            session_id: current_nanos() ^ u64::from(process::id()).rotate_left(32),
+            parent_sync: None,
+            sent_to_parent: 0,
+            pending_msgs: Vec::new(),
+            parent_reconnect: ParentReconnect::default(),
+            #[cfg(feature = "llmp_compression")]
+            compressor: GzipCompressor::new(),
+        }
+    }
+}
+
 impl<A> TcpMultiMachineState<A>
 where
     A: Clone + Display + ToSocketAddrs + Send + Sync + 'static,
@@ -252,38 +340,48 @@ where
     ///
     /// This should be run **only once**, in the same process as the llmp hooks, and before the hooks
     /// are effectively used.
-    unsafe fn init<I: Input>(
-        self_mutex: &Arc<RwLock<Self>>,
-        rt: &Arc<Runtime>,
-    ) -> Result<(), Error> {
-        let node_descriptor =
-            rt.block_on(async { self_mutex.read().await.node_descriptor.clone() });
+    unsafe fn init(self_mutex: &Arc<RwLock<Self>>, rt: &Arc<Runtime>) -> Result<(), Error> {
+        let (node_descriptor, session_id) = rt.block_on(async {
+            let state = self_mutex.read().await;
+            (state.node_descriptor.clone(), state.session_id)
+        });
 
         // Try to connect to the parent if we should
         rt.block_on(async {
-            let parent_mutex = self_mutex.clone();
-            let mut parent_lock = parent_mutex.write().await;
-
-            if let Some(parent_addr) = &parent_lock.node_descriptor.parent_addr {
-                let timeout = current_time() + parent_lock.node_descriptor.timeout;
-
-                parent_lock.parent = loop {
-                    log::debug!("Trying to connect to parent @ {parent_addr}..");
-                    match TcpStream::connect(parent_addr).await {
-                        Ok(stream) => {
-                            log::debug!("Connected to parent @ {parent_addr}");
-
-                            break Some(stream);
-                        }
-                        Err(e) => {
-                            if current_time() > timeout {
-                                return Err(Error::os_error(e, "Unable to connect to parent"));
+            let mut state = self_mutex.write().await;
+
+            // If the parent is not up, one of the fallback parents will do
+            let candidates: Vec<&A> = node_descriptor.parent_candidates().collect();
+            if !candidates.is_empty() {
+                let timeout = current_time() + node_descriptor.timeout;
+
+                'connect: loop {
+                    for parent_addr in &candidates {
+                        log::debug!("Trying to connect to parent @ {parent_addr}..");
+                        let parent = time::timeout(
+                            node_descriptor.heartbeat_timeout,
+                            Self::open_parent(parent_addr, &node_descriptor),
+                        )
+                        .await
+                        .unwrap_or_else(|_| Err(Error::illegal_state("Timed out")));
+                        match parent {
+                            Ok((channel, parent_session_id)) => {
+                                log::debug!("Connected to parent @ {parent_addr}");
+                                state.attach_parent(channel, parent_session_id).await?;
+                                break 'connect;
+                            }
+                            Err(e) => {
+                                if current_time() > timeout {
+                                    log::error!("Unable to connect to any parent");
+                                    return Err(e);
+                                }
+                                log::debug!("Unable to connect to parent @ {parent_addr}: {e:?}");
                             }
                         }
                     }
 
                     time::sleep(Duration::from_secs(1)).await;
-                };
+                }
             }
 
             Ok(())
@@ -292,6 +390,7 @@ where
         // Now, setup the background tasks for the children to connect to
         if let Some(listening_port) = node_descriptor.node_listening_port {
             let bg_state = self_mutex.clone();
+            let node_descriptor = node_descriptor.clone();
             let _handle: JoinHandle<Result<(), Error>> = rt.spawn(async move {
                 let addr = format!("0.0.0.0:{listening_port}");
                 log::debug!("Starting background child task on {addr}...");
@@ -301,28 +400,43 @@ where
                 let state = bg_state;
 
                 // The main listening loop. Should never fail.
-                'listening: loop {
+                loop {
                     log::debug!("listening for children on {listener:?}...");
                     match listener.accept().await {
-                        Ok((mut stream, addr)) => {
-                            log::debug!("{addr} joined the children.");
-                            let mut state_guard = state.write().await;
-
-                            if let Err(e) = state_guard
-                                .send_old_events_to_stream::<I>(&mut stream)
-                                .await
-                            {
-                                log::error!("Error while send old messages: {e:?}.");
-                                log::error!("The loop will resume");
-                                continue 'listening;
-                            }
-
-                            state_guard.children.insert(NodeId::new(), stream);
-                            log::debug!(
-                                "[pid {}]{addr} added the child. nb children: {}",
-                                process::id(),
-                                state_guard.children.len()
-                            );
+                        Ok((stream, addr)) => {
+                            let state = state.clone();
+                            let node_descriptor = node_descriptor.clone();
+                            // Handshake in a task per child, a slow child should not block the others
+                            spawn(async move {
+                                let (mut child, received) =
+                                    match Self::accept_child(stream, &node_descriptor, session_id)
+                                        .await
+                                    {
+                                        Ok(child) => child,
+                                        Err(e) => {
+                                            log::warn!("Rejected child {addr}: {e:?}");
+                                            return;
+                                        }
+                                    };
+                                log::debug!("{addr} joined the children.");
+                                // Only lock the state once the handshake is done
+                                let mut state_guard = state.write().await;
+
+                                if let Err(e) = state_guard
+                                    .send_old_events_to_stream(&mut child, received)
+                                    .await
+                                {
+                                    log::error!("Error while send old messages: {e:?}.");
+                                    return;
+                                }
+
+                                state_guard.children.insert(NodeId::new(), child);
+                                log::debug!(
+                                    "[pid {}]{addr} added the child. nb children: {}",
+                                    process::id(),
+                                    state_guard.children.len()
+                                );
+                            });
                         }
                         Err(e) => {
                             log::error!("Error while accepting child {e:?}.");
@@ -332,115 +446,236 @@ where
             });
         }
 
-        Ok(())
-    }
+        // Keep the connections alive, and find a new parent if we lost ours
+        let bg_state = self_mutex.clone();
+        let _handle: JoinHandle<()> = rt.spawn(async move {
+            loop {
+                time::sleep(node_descriptor.heartbeat_interval).await;
 
-    /// Add an event as past event.
-    pub fn add_past_msg(&mut self, msg: &[u8]) {
-        self.old_msgs.push(msg.to_vec());
-    }
+                let parent_addr = {
+                    let mut state = bg_state.write().await;
+                    if let Err(e) = state.maintain_connections().await {
+                        log::error!("Error while checking the connections to other nodes: {e:?}");
+                    }
+                    state.next_parent_candidate()
+                };
 
-    /// The compressor
-    #[cfg(feature = "llmp_compression")]
-    pub fn compressor(&mut self) -> &GzipCompressor {
-        &self.compressor
+                // Connect without holding the lock, an unreachable node should not block us
+                if let Some(parent_addr) = parent_addr {
+                    log::info!("Trying to reconnect to parent @ {parent_addr}..");
+                    let parent = time::timeout(
+                        node_descriptor.heartbeat_timeout,
+                        Self::open_parent(&parent_addr, &node_descriptor),
+                    )
+                    .await
+                    .unwrap_or_else(|_| Err(Error::illegal_state("Timed out")));
+
+                    let mut state = bg_state.write().await;
+                    let res = match parent {
+                        Ok((channel, parent_session_id)) => {
+                            state.attach_parent(channel, parent_session_id).await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    match res {
+                        Ok(()) => log::info!("Reconnected to parent @ {parent_addr}"),
+                        Err(e) => {
+                            log::warn!("Unable to reconnect to parent @ {parent_addr}: {e:?}");
+                            state.parent_reconnect_failed();
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(())
     }
 
-    /// Read a [`TcpMultiMachineMsg`] from a stream.
-    /// Expects a message written by [`TcpMultiMachineState::write_msg`].
-    /// If there is nothing to read from the stream, return asap with Ok(None).
-    #[expect(clippy::uninit_vec)]
-    async fn read_msg<'a, I: Input + 'a>(
-        stream: &mut TcpStream,
-    ) -> Result<Option<MultiMachineMsg<'a, I>>, Error> {
-        // 0. Check if we should try to fetch something from the stream
-        let mut dummy_byte: [u8; 1] = [0u8];
-        log::debug!("Starting read msg...");
+    /// Connect to a (new) parent, and receive its session id
+    async fn open_parent(
+        parent_addr: &A,
+        node_descriptor: &NodeDescriptor<A>,
+    ) -> Result<(TcpChannel, u64), Error> {
+        let stream = TcpStream::connect(parent_addr)
+            .await
+            .map_err(|e| Error::os_error(e, "Unable to connect to parent"))?;
+        let mut channel = TcpChannel::connect(
+            stream,
+            #[cfg(feature = "secure_channel")]
+            node_descriptor.secure_channel.as_ref(),
+        )
+        .await?;
+        let session_id = read_sync(&mut channel, node_descriptor.heartbeat_timeout).await?;
+        Ok((channel, session_id))
+    }
 
-        let n_read = match stream.try_read(&mut dummy_byte) {
-            Ok(n) => n,
-            Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                return Ok(None);
+    /// Finish the connection to a parent opened with [`Self::open_parent`].
+    ///
+    /// We tell the parent how many of its messages we already received, and send it the messages it missed from us.
+    async fn attach_parent(
+        &mut self,
+        mut channel: TcpChannel,
+        session_id: u64,
+    ) -> Result<(), Error> {
+        let (received, sent) = match self.parent_sync {
+            Some((parent_session_id, received)) if parent_session_id == session_id => {
+                (received, self.sent_to_parent)
             }
-            Err(e) => return Err(Error::os_error(e, "try read failed")),
+            // A new parent, or the old one restarted: it has none of our messages
+            _ => (0, 0),
         };
+        write_frame(&mut channel, SYNC_BYTE, &received.to_le_bytes()).await?;
+        self.parent_sync = Some((session_id, received));
 
-        log::debug!("msg read.");
-
-        if n_read == 0 {
-            log::debug!("No dummy byte received...");
-            return Ok(None); // Nothing to read from this stream
+        let mut parent = NodeConnection::new(channel);
+        if self
+            .node_descriptor
+            .flags
+            .intersects(NodePolicy::SendToParent)
+        {
+            let missed = &self.old_msgs[sent.min(self.old_msgs.len())..];
+            log::debug!("Sending {} missed messages to parent...", missed.len());
+            for msg in missed {
+                parent.write_frame(DUMMY_BYTE, msg).await?;
+            }
         }
+        self.sent_to_parent = self.old_msgs.len();
+        self.parent = Some(parent);
+        self.parent_reconnect = ParentReconnect::default();
+        Ok(())
+    }
 
-        log::debug!("Received dummy byte!");
-
-        // we should always read the dummy byte at this point.
-        assert_eq!(u8::from_le_bytes(dummy_byte), DUMMY_BYTE);
-
-        // 1. Read msg size
-        let mut node_msg_len: [u8; 4] = [0; 4];
-        log::debug!("Receiving msg len...");
-        stream.read_exact(&mut node_msg_len).await?;
-        log::debug!("msg len received.");
-        let node_msg_len = u32::from_le_bytes(node_msg_len) as usize;
+    /// Accept a new child: send it our session id, and receive how many of our messages it already has
+    async fn accept_child(
+        stream: TcpStream,
+        node_descriptor: &NodeDescriptor<A>,
+        session_id: u64,
+    ) -> Result<(NodeConnection, usize), Error> {
+        let mut channel = TcpChannel::accept(
+            stream,
+            #[cfg(feature = "secure_channel")]
+            node_descriptor.secure_channel.as_ref(),
+        )
+        .await?;
+        write_frame(&mut channel, SYNC_BYTE, &session_id.to_le_bytes()).await?;
+        let received = read_sync(&mut channel, node_descriptor.heartbeat_timeout).await?;
+        Ok((NodeConnection::new(channel), usize::try_from(received)?))
+    }
 
-        // 2. Read msg
-        // do not store msg on the stack to avoid overflow issues
-        // TODO: optimize with less allocations...
-        let mut node_msg: Vec<u8> = Vec::with_capacity(node_msg_len);
-        unsafe {
-            node_msg.set_len(node_msg_len);
+    /// The parent to try to reconnect to next, if we have none and the backoff elapsed.
+    ///
+    /// The candidates are tried in turn, starting over with the [`NodeDescriptor::parent_addr`].
+    fn next_parent_candidate(&self) -> Option<A> {
+        if self.parent.is_some() || current_time() < self.parent_reconnect.next_attempt {
+            return None;
         }
-        log::debug!("Receiving msg...");
-        stream.read_exact(node_msg.as_mut_slice()).await?;
-        log::debug!("msg received.");
-        let node_msg = node_msg.into_boxed_slice();
+        let candidates: Vec<&A> = self.node_descriptor.parent_candidates().collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(candidates[self.parent_reconnect.candidate % candidates.len()].clone())
+    }
 
-        Ok(Some(MultiMachineMsg::from_llmp_msg(node_msg)))
+    /// Move on to the next parent candidate, and back off
+    fn parent_reconnect_failed(&mut self) {
+        let reconnect = &mut self.parent_reconnect;
+        reconnect.candidate += 1;
+        reconnect.backoff = (reconnect.backoff * 2).min(self.node_descriptor.max_reconnect_backoff);
+        reconnect.next_attempt = current_time() + reconnect.backoff;
     }
 
-    /// Write an [`OwnedTcpMultiMachineMsg`] to a stream.
-    /// Can be read back using [`TcpMultiMachineState::read_msg`].
-    async fn write_msg<I: Input>(
-        stream: &mut TcpStream,
-        msg: &MultiMachineMsg<'_, I>,
-    ) -> Result<(), Error> {
-        let serialized_msg = msg.serialize_as_ref();
-        let msg_len = u32::to_le_bytes(serialized_msg.len() as u32);
+    /// Check if the parent and the children are still alive, and send them heartbeats.
+    ///
+    /// Messages received in the meantime are kept for [`Self::receive_new_messages_from_nodes`].
+    async fn maintain_connections(&mut self) -> Result<(), Error> {
+        // Reading keeps track of the liveness
+        self.receive_pending_msgs().await?;
 
-        // 0. Write the dummy byte
-        log::debug!("Sending dummy byte...");
-        stream.write_all(&[DUMMY_BYTE]).await?;
-        log::debug!("dummy byte sent.");
+        let now = current_time();
+        let heartbeat_interval = self.node_descriptor.heartbeat_interval;
+        let heartbeat_timeout = self.node_descriptor.heartbeat_timeout;
 
-        // 1. Write msg size
-        log::debug!("Sending msg len...");
-        stream.write_all(&msg_len).await?;
-        log::debug!("msg len sent.");
+        if let Some(parent) = &mut self.parent {
+            if now.saturating_sub(parent.last_seen) > heartbeat_timeout {
+                log::warn!("The parent did not answer for {heartbeat_timeout:?}, dropping it.");
+                self.parent = None;
+            } else if now.saturating_sub(parent.last_sent) >= heartbeat_interval
+                && parent.write_frame(HEARTBEAT_BYTE, &[]).await.is_err()
+            {
+                log::warn!("The parent disconnected.");
+                self.parent = None;
+            }
+        }
 
-        // 2. Write msg
-        log::debug!("Sending msg...");
-        stream.write_all(serialized_msg).await?;
-        log::debug!("msg sent.");
+        let mut ids_to_remove: Vec<NodeId> = Vec::new();
+        for (child_id, child) in &mut self.children {
+            if now.saturating_sub(child.last_seen) > heartbeat_timeout {
+                log::warn!(
+                    "The child {child_id:?} did not answer for {heartbeat_timeout:?}, dropping it."
+                );
+                ids_to_remove.push(*child_id);
+            } else if now.saturating_sub(child.last_sent) >= heartbeat_interval
+                && child.write_frame(HEARTBEAT_BYTE, &[]).await.is_err()
+            {
+                log::debug!("The child {child_id:?} disconnected.");
+                ids_to_remove.push(*child_id);
+            }
+        }
+        for id_to_remove in &ids_to_remove {
+            self.children.remove(id_to_remove);
+        }
 
         Ok(())
     }
 
-    pub(crate) async fn send_old_events_to_stream<I: Input>(
-        &mut self,
-        stream: &mut TcpStream,
+    /// Add an event as past event.
+    pub fn add_past_msg(&mut self, msg: &[u8]) {
+        self.old_msgs.push(msg.to_vec());
+    }
+
+    /// The compressor
+    #[cfg(feature = "llmp_compression")]
+    pub fn compressor(&mut self) -> &GzipCompressor {
+        &self.compressor
+    }
+
+    /// Read the next message from a node, written by [`NodeConnection::write_frame`].
+    /// Heartbeats are skipped.
+    /// If there is nothing to read from the stream, return asap with Ok(None).
+    async fn read_msg(node: &mut NodeConnection) -> Result<Option<Box<[u8]>>, Error> {
+        loop {
+            let Some((kind, payload)) = node.try_read_frame().await? else {
+                return Ok(None);
+            };
+            match kind {
+                DUMMY_BYTE => return Ok(Some(payload)),
+                HEARTBEAT_BYTE => log::trace!("Received heartbeat"),
+                _ => {
+                    return Err(Error::os_error(
+                        io::Error::new(ErrorKind::InvalidData, format!("kind {kind:#x}")),
+                        "Received unexpected message from node",
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Send our old messages to a new child, skipping the first `from` ones it already received.
+    pub(crate) async fn send_old_events_to_stream(
+        &self,
+        child: &mut NodeConnection,
+        from: usize,
     ) -> Result<(), Error> {
         log::debug!("Send old events to new child...");
 
-        for old_msg in &self.old_msgs {
-            let event_ref: MultiMachineMsg<I> =
-                MultiMachineMsg::llmp_msg(OwnedRef::Ref(old_msg.as_slice()));
+        let old_msgs = &self.old_msgs[from.min(self.old_msgs.len())..];
+        for old_msg in old_msgs {
             log::debug!("Sending an old message...");
-            Self::write_msg(stream, &event_ref).await?;
+            child.write_frame(DUMMY_BYTE, old_msg).await?;
             log::debug!("Old message sent.");
         }
 
-        log::debug!("Sent {} old messages.", self.old_msgs.len());
+        log::debug!("Sent {} old messages.", old_msgs.len());
 
         Ok(())
     }
@@ -458,12 +693,13 @@ where
         {
             if let Some(parent) = &mut self.parent {
                 log::debug!("Sending to parent...");
-                if let Err(e) = Self::write_msg(parent, msg).await {
-                    log::error!(
-                        "The parent disconnected. We won't try to communicate with it again."
-                    );
-                    log::error!("Error: {e:?}");
-                    self.parent.take();
+                match parent.write_frame(DUMMY_BYTE, msg.serialize_as_ref()).await {
+                    Ok(()) => self.sent_to_parent = self.old_msgs.len(),
+                    Err(e) => {
+                        log::error!("The parent disconnected. We will try to reconnect later.");
+                        log::error!("Error: {e:?}");
+                        self.parent.take();
+                    }
                 }
             }
         }
@@ -476,7 +712,10 @@ where
             let mut ids_to_remove: Vec<NodeId> = Vec::new();
             for (child_id, child_stream) in &mut self.children {
                 log::debug!("Sending to child {child_id:?}...");
-                if let Err(err) = Self::write_msg(child_stream, msg).await {
+                if let Err(err) = child_stream
+                    .write_frame(DUMMY_BYTE, msg.serialize_as_ref())
+                    .await
+                {
                     // most likely the child disconnected. drop the connection later on and continue.
                     log::debug!(
                         "The child disconnected. We won't try to communicate with it again. Error: {err:?}"
@@ -502,8 +741,17 @@ where
         msgs: &mut Vec<MultiMachineMsg<'_, I>>,
     ) -> Result<(), Error> {
         log::debug!("Checking for new events from other nodes...");
-        // let mut nb_received = 0usize;
+        self.receive_pending_msgs().await?;
+        msgs.extend(
+            self.pending_msgs
+                .drain(..)
+                .map(MultiMachineMsg::from_llmp_msg),
+        );
+        Ok(())
+    }
 
+    /// Read all available messages from the parent and the children into the pending messages.
+    async fn receive_pending_msgs(&mut self) -> Result<(), Error> {
         // Our (potential) parent could have something for us
         if let Some(parent) = &mut self.parent {
             loop {
@@ -519,8 +767,10 @@ where
                     Ok(Some(msg)) => {
                         log::debug!("Received event from parent");
                         // The parent has something for us, we store it
-                        msgs.push(msg);
-                        // nb_received += 1;
+                        self.pending_msgs.push(msg);
+                        if let Some((_, received)) = &mut self.parent_sync {
+                            *received += 1;
+                        }
                     }
 
                     Ok(None) => {
@@ -531,9 +781,7 @@ where
 
                     Err(Error::OsError(_, _, _)) => {
                         // most likely the parent disconnected. drop the connection
-                        log::debug!(
-                            "The parent disconnected. We won't try to communicate with it again."
-                        );
+                        log::warn!("The parent disconnected. We will try to reconnect later.");
                         self.parent.take();
                         break;
                     }
@@ -565,8 +813,7 @@ where
                     Ok(Some(msg)) => {
                         // The parent has something for us, we store it
                         log::debug!("Received event from child!");
-                        msgs.push(msg);
-                        // nb_received += 1;
+                        self.pending_msgs.push(msg);
                     }
 
                     Ok(None) => {
@@ -603,3 +850,206 @@ where
         Ok(())
     }
 }
+
+/// A connection to another node, keeping track of its liveness
+#[derive(Debug)]
+pub(crate) struct NodeConnection {
+    channel: TcpChannel,
+    /// When we last received something
+    last_seen: Duration,
+    /// When we last sent something
+    last_sent: Duration,
+}
+
+impl NodeConnection {
+    fn new(channel: TcpChannel) -> Self {
+        let now = current_time();
+        Self {
+            channel,
+            last_seen: now,
+            last_sent: now,
+        }
+    }
+
+    /// Write a frame of the given kind to the node.
+    /// Can be read back using [`NodeConnection::try_read_frame`].
+    async fn write_frame(&mut self, kind: u8, payload: &[u8]) -> Result<(), Error> {
+        write_frame(&mut self.channel, kind, payload).await?;
+        self.last_sent = current_time();
+        Ok(())
+    }
+
+    /// Read a frame, if the node sent one.
+    /// If there is nothing to read from the stream, return asap with Ok(None).
+    async fn try_read_frame(&mut self) -> Result<Option<(u8, Box<[u8]>)>, Error> {
+        // 0. Check if we should try to fetch something from the stream
+        let mut kind: [u8; 1] = [0u8];
+        log::debug!("Starting read msg...");
+
+        let n_read = match self.channel.reader.try_read(&mut kind).await {
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                return Ok(None);
+            }
+            Err(e) => return Err(Error::os_error(e, "try read failed")),
+        };
+
+        if n_read == 0 {
+            log::debug!("No message kind received...");
+            return Ok(None); // Nothing to read from this stream
+        }
+
+        let payload = read_frame_payload(&mut self.channel).await?;
+        self.last_seen = current_time();
+        Ok(Some((kind[0], payload)))
+    }
+}
+
+/// Write a frame: the kind, the payload length, then the payload
+async fn write_frame(channel: &mut TcpChannel, kind: u8, payload: &[u8]) -> Result<(), Error> {
+    let payload_len = u32::try_from(payload.len())?.to_le_bytes();
+
+    // 0. Write the kind byte
+    channel.writer.write_all(&[kind]).await?;
+    // 1. Write msg size
+    channel.writer.write_all(&payload_len).await?;
+    // 2. Write msg
+    channel.writer.write_all(payload).await?;
+    channel.writer.flush().await?;
+    Ok(())
+}
+
+/// Read the length and the payload of a frame, after its kind
+#[expect(clippy::uninit_vec)]
+async fn read_frame_payload(channel: &mut TcpChannel) -> Result<Box<[u8]>, Error> {
+    // 1. Read msg size
+    let mut payload_len: [u8; 4] = [0; 4];
+    channel.reader.read_exact(&mut payload_len).await?;
+    let payload_len = u32::from_le_bytes(payload_len) as usize;
+
+    // 2. Read msg
+    // do not store msg on the stack to avoid overflow issues
+    // TODO: optimize with less allocations...
+    let mut payload: Vec<u8> = Vec::with_capacity(payload_len);
+    unsafe {
+        payload.set_len(payload_len);
+    }
+    channel.reader.read_exact(payload.as_mut_slice()).await?;
+    Ok(payload.into_boxed_slice())
+}
+
+/// Wait for the sync frame of a newly connected node, and return its value
+async fn read_sync(channel: &mut TcpChannel, timeout: Duration) -> Result<u64, Error> {
+    let sync = time::timeout(timeout, async {
+        let mut kind: [u8; 1] = [0u8];
+        channel.reader.read_exact(&mut kind).await?;
+        let payload = read_frame_payload(channel).await?;
+        match (kind[0], <[u8; 8]>::try_from(payload.as_ref())) {
+            (SYNC_BYTE, Ok(sync)) => Ok(u64::from_le_bytes(sync)),
+            _ => Err(Error::illegal_state(
+                "Expected a sync message from the node",
+            )),
+        }
+    })
+    .await;
+    sync.map_err(|_| Error::illegal_state("Timed out waiting for the node to sync"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, sync::Arc};
+    use core::time::Duration;
+    use std::{thread, time::Instant};
+
+    use tokio::{runtime::Runtime, sync::RwLock};
+
+    use super::{NodeDescriptor, TcpMultiMachineState};
+
+    type Node = (Arc<RwLock<TcpMultiMachineState<String>>>, Arc<Runtime>);
+
+    /// Start a node, the way [`super::TcpMultiMachineHooksBuilder::build`] does
+    fn start_node(node_descriptor: NodeDescriptor<String>) -> Node {
+        let state = Arc::new(RwLock::new(TcpMultiMachineState::new(node_descriptor)));
+        let rt = Arc::new(Runtime::new().unwrap());
+        unsafe {
+            TcpMultiMachineState::init(&state, &rt).unwrap();
+        }
+        (state, rt)
+    }
+
+    /// Wait until the condition holds on the state of the node
+    fn wait_for(
+        (state, rt): &Node,
+        what: &str,
+        cond: impl Fn(&TcpMultiMachineState<String>) -> bool,
+    ) {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while !rt.block_on(async { cond(&*state.read().await) }) {
+            assert!(Instant::now() < deadline, "Timed out waiting for {what}");
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn test_parent_failover() {
+        let root = |port| {
+            NodeDescriptor::builder()
+                .parent_addr(None)
+                .node_listening_port(Some(port))
+                .heartbeat_interval(Duration::from_millis(100))
+                .heartbeat_timeout(Duration::from_secs(1))
+                .build()
+        };
+        let parent = start_node(root(50311));
+        let fallback = start_node(root(50312));
+        fallback
+            .1
+            .block_on(async { fallback.0.write().await.add_past_msg(b"from fallback") });
+
+        let child = start_node(
+            NodeDescriptor::builder()
+                .parent_addr(Some("127.0.0.1:50311".into()))
+                .fallback_parent_addrs(vec!["127.0.0.1:50312".into()])
+                .node_listening_port(None)
+                .heartbeat_interval(Duration::from_millis(100))
+                .heartbeat_timeout(Duration::from_secs(1))
+                .max_reconnect_backoff(Duration::from_millis(100))
+                .build(),
+        );
+        wait_for(&parent, "the child to connect", |state| {
+            state.children.len() == 1
+        });
+
+        // Kill the parent, the child should move on to the fallback
+        let (parent_state, parent_rt) = parent;
+        drop(parent_rt);
+        drop(parent_state);
+        child
+            .1
+            .block_on(async { child.0.write().await.add_past_msg(b"while disconnected") });
+
+        let fallback_session_id = fallback
+            .1
+            .block_on(async { fallback.0.read().await.session_id });
+        wait_for(&child, "the child to reparent", |state| {
+            state.parent.is_some()
+                && state
+                    .parent_sync
+                    .is_some_and(|(session_id, _)| session_id == fallback_session_id)
+        });
+
+        // Both sides catch up on the messages they missed
+        wait_for(&child, "the fallback messages", |state| {
+            state
+                .pending_msgs
+                .iter()
+                .any(|msg| &**msg == b"from fallback")
+        });
+        wait_for(&fallback, "the child messages", |state| {
+            state
+                .pending_msgs
+                .iter()
+                .any(|msg| &**msg == b"while disconnected")
+        });
+    }
+}
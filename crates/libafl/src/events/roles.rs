@@ -0,0 +1,451 @@
+//! Heterogeneous clients under one [`crate::events::Launcher`], each running in a [`ClientRole`].
+//!
+//! A role, such as `cmplog`, `concolic`, or `havoc`, has its own cores, client closure, and [`EventConfig`].
+//! Since all clients of a launcher share the closure type, use boxed closures for different builds.
+//!
+//! Clients announce their role in the [`ROLE_USER_STAT`] user stat, so monitors can report
+//! per-role stats, see [`crate::monitors::RoleMonitor`].
+//! With [`ClientRole::receive_from`], a role only receives the testcases found by the given roles.
+//! The broker routes the testcases with the [`crate::events::LlmpRoleRoutingHook`], by the [`EventConfig`] of the finder:
+//! LLMP broadcasts each message to all clients, so testcases not received by all roles are re-sent
+//! with a header naming the receiving roles, and the other clients skip them without deserializing.
+//! In a `CentralizedLauncher`, the main node forwards the testcases with the [`EventConfig`] of the
+//! secondary node that found them, so they are routed the same way.
+
+use alloc::{borrow::Cow, string::ToString, vec::Vec};
+use core::fmt::{self, Debug, Formatter};
+
+use libafl_bolts::{
+    ClientId,
+    core_affinity::{CoreId, Cores},
+    llmp::Tag,
+};
+
+pub use crate::monitors::stats::ROLE_USER_STAT;
+use crate::{Error, events::EventConfig};
+
+/// The most roles a launcher can route testcases between, one bit each in the routing header
+pub const MAX_ROLES: usize = 64;
+
+/// Tag of the testcases the broker only routes to some roles.
+/// The message starts with the bitmask of the receiving roles and the [`ClientId`] of the sender.
+pub(crate) const LLMP_TAG_ROUTED_EVENT: Tag = Tag(0x2B0752);
+
+/// The length of the routing header of [`LLMP_TAG_ROUTED_EVENT`] messages
+const ROUTING_HEADER_LEN: usize = 12;
+
+/// A role of clients, running the same closure with the same [`EventConfig`] on the given cores
+pub struct ClientRole<CF> {
+    name: Cow<'static, str>,
+    cores: Cores,
+    configuration: EventConfig,
+    receive_from: Option<Vec<Cow<'static, str>>>,
+    run_client: Option<CF>,
+}
+
+impl<CF> Debug for ClientRole<CF> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientRole")
+            .field("name", &self.name)
+            .field("cores", &self.cores)
+            .field("configuration", &self.configuration)
+            .field("receive_from", &self.receive_from)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<CF> ClientRole<CF> {
+    /// Create a new [`ClientRole`], running `run_client` on each of the `cores`.
+    ///
+    /// The [`EventConfig`] defaults to one derived from the role name.
+    pub fn new<N>(name: N, cores: Cores, run_client: CF) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        let name = name.into();
+        Self {
+            configuration: EventConfig::from_name(&name),
+            name,
+            cores,
+            receive_from: None,
+            run_client: Some(run_client),
+        }
+    }
+
+    /// Set the [`EventConfig`] of this role
+    #[must_use]
+    pub fn with_configuration(mut self, configuration: EventConfig) -> Self {
+        self.configuration = configuration;
+        self
+    }
+
+    /// Only receive the testcases found by clients of the given roles
+    #[must_use]
+    pub fn receive_from<R, N>(mut self, roles: R) -> Self
+    where
+        R: IntoIterator<Item = N>,
+        N: Into<Cow<'static, str>>,
+    {
+        self.receive_from = Some(roles.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// The name of this role
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The cores clients of this role run on
+    #[must_use]
+    pub fn cores(&self) -> &Cores {
+        &self.cores
+    }
+
+    /// The [`EventConfig`] of this role
+    #[must_use]
+    pub fn configuration(&self) -> EventConfig {
+        self.configuration
+    }
+}
+
+/// The map of cores to [`ClientRole`]s for a [`crate::events::Launcher`].
+///
+/// Cores of the launcher without a role run the default client closure and configuration.
+pub struct ClientRoles<CF> {
+    roles: Vec<ClientRole<CF>>,
+}
+
+impl<CF> Debug for ClientRoles<CF> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.roles).finish()
+    }
+}
+
+impl<CF> Default for ClientRoles<CF> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<CF> ClientRoles<CF> {
+    /// Create an empty map of [`ClientRole`]s
+    #[must_use]
+    pub fn new() -> Self {
+        Self { roles: vec![] }
+    }
+
+    /// Add a [`ClientRole`]
+    #[must_use]
+    pub fn with_role(mut self, role: ClientRole<CF>) -> Self {
+        self.roles.push(role);
+        self
+    }
+
+    /// The [`ClientRole`] of the client on this core, if any
+    #[must_use]
+    pub fn role_for(&self, core_id: CoreId) -> Option<&ClientRole<CF>> {
+        self.roles.iter().find(|role| role.cores.contains(core_id))
+    }
+
+    /// Check that all roles run on the launcher's `cores`, no core has two roles,
+    /// the roles to receive from exist, and there are at most [`MAX_ROLES`] roles
+    pub fn check(&self, cores: &Cores) -> Result<(), Error> {
+        if self.roles.len() > MAX_ROLES {
+            return Err(Error::illegal_argument(format!(
+                "At most {MAX_ROLES} roles are supported, got {}",
+                self.roles.len()
+            )));
+        }
+        for (i, role) in self.roles.iter().enumerate() {
+            if self.roles[..i].iter().any(|other| other.name == role.name) {
+                return Err(Error::illegal_argument(format!(
+                    "Role {} is defined twice",
+                    role.name
+                )));
+            }
+            for core_id in &role.cores.ids {
+                if !cores.contains(*core_id) {
+                    return Err(Error::illegal_argument(format!(
+                        "Core {} of role {} is not one of the launcher's cores",
+                        core_id.0, role.name
+                    )));
+                }
+                if let Some(other) = self.role_for(*core_id) {
+                    if other.name != role.name {
+                        return Err(Error::illegal_argument(format!(
+                            "Core {} has the roles {} and {}",
+                            core_id.0, other.name, role.name
+                        )));
+                    }
+                }
+            }
+            for sender in role.receive_from.iter().flatten() {
+                if !self.roles.iter().any(|other| other.name == *sender) {
+                    return Err(Error::illegal_argument(format!(
+                        "Role {} receives from the unknown role {sender}",
+                        role.name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The [`RoleRoutes`] for the broker, or `None` if all roles receive all testcases
+    #[must_use]
+    pub fn routes(&self) -> Option<RoleRoutes> {
+        if self.roles.iter().all(|role| role.receive_from.is_none()) {
+            return None;
+        }
+        let accepted = self
+            .roles
+            .iter()
+            .map(|role| {
+                role.receive_from.as_ref().map(|senders| {
+                    self.roles
+                        .iter()
+                        .filter(|other| senders.contains(&other.name))
+                        .map(|other| other.configuration)
+                        .collect()
+                })
+            })
+            .collect();
+        Some(RoleRoutes { accepted })
+    }
+
+    /// Take the client closure of the role on this core
+    pub(crate) fn take_run_client(&mut self, core_id: CoreId) -> RoleClient<CF> {
+        match self
+            .roles
+            .iter_mut()
+            .find(|role| role.cores.contains(core_id))
+        {
+            Some(role) => match role.run_client.take() {
+                Some(run_client) => RoleClient::Run(run_client),
+                None => RoleClient::Taken,
+            },
+            None => RoleClient::NoRole,
+        }
+    }
+
+    /// The [`RoleFilter`] for the client on this core, if it has a role
+    #[must_use]
+    pub fn filter_for(&self, core_id: CoreId) -> Option<RoleFilter> {
+        let index = self
+            .roles
+            .iter()
+            .position(|role| role.cores.contains(core_id))?;
+        Some(RoleFilter::new(self.roles[index].name.clone(), index))
+    }
+}
+
+/// The client closure of a core, see [`ClientRoles::take_run_client`]
+pub(crate) enum RoleClient<CF> {
+    /// The core has no role
+    NoRole,
+    /// The closure of the role on this core
+    Run(CF),
+    /// The closure of the role on this core was taken already
+    Taken,
+}
+
+/// Check the `roles` of a launcher on its `cores`,
+/// and that there is a `run_client` for the cores without a role
+pub(crate) fn check_roles<CF>(
+    roles: Option<&ClientRoles<CF>>,
+    has_run_client: bool,
+    cores: &Cores,
+) -> Result<(), Error> {
+    if let Some(roles) = roles {
+        roles.check(cores)?;
+    }
+    let without_role = cores
+        .ids
+        .iter()
+        .any(|core_id| roles.is_none_or(|roles| roles.role_for(*core_id).is_none()));
+    if without_role && !has_run_client {
+        return Err(Error::illegal_argument(
+            "No client callback provided".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The [`EventConfig`] of the role on this core, or the launcher's `configuration`
+pub(crate) fn configuration_for<CF>(
+    roles: Option<&ClientRoles<CF>>,
+    configuration: EventConfig,
+    core_id: CoreId,
+) -> EventConfig {
+    roles
+        .and_then(|roles| roles.role_for(core_id))
+        .map_or(configuration, ClientRole::configuration)
+}
+
+/// Take the client closure of the role on this core, or the launcher's `run_client`
+pub(crate) fn take_run_client<CF>(
+    roles: Option<&mut ClientRoles<CF>>,
+    run_client: &mut Option<CF>,
+    core_id: CoreId,
+) -> Result<CF, Error> {
+    let run_client = match roles.map(|roles| roles.take_run_client(core_id)) {
+        Some(RoleClient::Run(run_client)) => Some(run_client),
+        Some(RoleClient::Taken) => None,
+        None | Some(RoleClient::NoRole) => run_client.take(),
+    };
+    run_client.ok_or_else(|| {
+        Error::illegal_argument(format!("No client callback left for core {}", core_id.0))
+    })
+}
+
+/// The role of a client, deciding which testcases routed by the broker it receives
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleFilter {
+    role: Cow<'static, str>,
+    index: usize,
+}
+
+impl RoleFilter {
+    /// Create a new [`RoleFilter`] for the `role` at `index` of the launcher's [`ClientRoles`]
+    #[must_use]
+    pub fn new<N>(role: N, index: usize) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        Self {
+            role: role.into(),
+            index,
+        }
+    }
+
+    /// The name of the role
+    #[must_use]
+    pub fn role(&self) -> &str {
+        &self.role
+    }
+
+    /// Returns `true` if this role is one of the `receivers` of a routed testcase
+    #[must_use]
+    pub fn receives(&self, receivers: u64) -> bool {
+        receivers & (1 << self.index) != 0
+    }
+}
+
+/// The roles receiving the testcases of each sender, used by the broker to route testcases
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoleRoutes {
+    /// For each role, the [`EventConfig`]s it receives testcases from, or `None` for all
+    accepted: Vec<Option<Vec<EventConfig>>>,
+}
+
+impl RoleRoutes {
+    /// The bitmask of the roles receiving the testcases of clients with the `sender` configuration
+    #[must_use]
+    pub fn receivers(&self, sender: EventConfig) -> u64 {
+        self.accepted
+            .iter()
+            .enumerate()
+            .filter(|(_, accepted)| {
+                accepted
+                    .as_ref()
+                    .is_none_or(|accepted| accepted.contains(&sender))
+            })
+            .fold(0, |receivers, (index, _)| receivers | (1 << index))
+    }
+
+    /// The bitmask of all roles
+    #[must_use]
+    pub fn all(&self) -> u64 {
+        (0..self.accepted.len()).fold(0, |all, index| all | (1 << index))
+    }
+}
+
+/// Prefix an LLMP message with the routing header for the `receivers` and the `sender`
+pub(crate) fn route(receivers: u64, sender: ClientId, msg: &[u8]) -> Vec<u8> {
+    let mut routed = Vec::with_capacity(ROUTING_HEADER_LEN + msg.len());
+    routed.extend_from_slice(&receivers.to_le_bytes());
+    routed.extend_from_slice(&sender.0.to_le_bytes());
+    routed.extend_from_slice(msg);
+    routed
+}
+
+/// Split a routed LLMP message into the receiving roles, the sender, and the original message
+pub(crate) fn unroute(routed: &[u8]) -> Result<(u64, ClientId, &[u8]), Error> {
+    if routed.len() < ROUTING_HEADER_LEN {
+        return Err(Error::illegal_state(
+            "Routed message is shorter than its header",
+        ));
+    }
+    let (receivers, rest) = routed.split_at(8);
+    let (sender, msg) = rest.split_at(4);
+    Ok((
+        u64::from_le_bytes(receivers.try_into().unwrap()),
+        ClientId(u32::from_le_bytes(sender.try_into().unwrap())),
+        msg,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::{
+        ClientId,
+        core_affinity::{CoreId, Cores},
+    };
+
+    use super::{ClientRole, ClientRoles, route, unroute};
+    use crate::events::EventConfig;
+
+    #[test]
+    fn test_client_roles() {
+        let cores = Cores::from_cmdline("0-3").unwrap();
+        let roles = ClientRoles::new()
+            .with_role(ClientRole::new("havoc", Cores::from(vec![0, 1]), 0))
+            .with_role(ClientRole::new("cmplog", Cores::from(vec![2]), 1))
+            .with_role(
+                ClientRole::new("concolic", Cores::from(vec![3]), 2).receive_from(["havoc"]),
+            );
+        roles.check(&cores).unwrap();
+        assert_eq!(roles.role_for(CoreId(1)).unwrap().name(), "havoc");
+
+        let havoc = EventConfig::from_name("havoc");
+        let cmplog = EventConfig::from_name("cmplog");
+        let routes = roles.routes().unwrap();
+        assert_eq!(routes.all(), 0b111);
+        assert_eq!(routes.receivers(havoc), 0b111);
+        assert_eq!(routes.receivers(cmplog), 0b011);
+        assert_eq!(routes.receivers(EventConfig::from_name("other")), 0b011);
+
+        let concolic = roles.filter_for(CoreId(3)).unwrap();
+        assert_eq!(concolic.role(), "concolic");
+        assert!(concolic.receives(routes.receivers(havoc)));
+        assert!(!concolic.receives(routes.receivers(cmplog)));
+        assert!(
+            roles
+                .filter_for(CoreId(2))
+                .unwrap()
+                .receives(routes.receivers(cmplog))
+        );
+
+        let routed = route(routes.receivers(cmplog), ClientId(7), b"testcase");
+        assert_eq!(
+            unroute(&routed).unwrap(),
+            (0b011, ClientId(7), &b"testcase"[..])
+        );
+        assert!(unroute(&routed[..4]).is_err());
+
+        // Without `receive_from`, the broker doesn't route
+        let unrouted = ClientRoles::new().with_role(ClientRole::new("havoc", cores.clone(), 0));
+        assert!(unrouted.routes().is_none());
+
+        // Cores outside the launcher's cores, overlapping roles, or unknown senders are rejected
+        assert!(roles.check(&Cores::from(vec![0, 1, 2])).is_err());
+        let overlapping = ClientRoles::new()
+            .with_role(ClientRole::new("havoc", Cores::from(vec![0, 1]), 0))
+            .with_role(ClientRole::new("cmplog", Cores::from(vec![1]), 1));
+        assert!(overlapping.check(&cores).is_err());
+        let unknown = ClientRoles::new()
+            .with_role(ClientRole::new("havoc", Cores::from(vec![0]), 0).receive_from(["grammar"]));
+        assert!(unknown.check(&cores).is_err());
+    }
+}
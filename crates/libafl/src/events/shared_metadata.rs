@@ -0,0 +1,350 @@
+//! Sharing of [`Testcase`] metadata with [`crate::events::Event::NewTestcase`] events.
+//!
+//! By default, only the input of a new testcase is sent to the other clients,
+//! so each receiver has to recompute expensive analyses, such as generalization or colorization.
+//! With a [`MetadataSharing`] allow-list, the chosen metadata types are serialized into the event
+//! and restored on the receiving side.
+//! Each type is sent under the explicit [`ShareableMetadata::SHARED_NAME`], so that clients
+//! built differently, for example with other crate versions, still agree on it.
+
+use alloc::{borrow::Cow, vec::Vec};
+
+use libafl_bolts::serdeany::{SerdeAny, SerdeAnyMap};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{Error, HasMetadata, corpus::Testcase};
+
+/// The default limit for all metadata shared with a single testcase, in bytes
+pub const DEFAULT_SHARED_METADATA_MAX_SIZE: usize = 64 * 1024;
+
+/// A metadata type that can be shared with testcases, see [`MetadataSharing::share`]
+pub trait ShareableMetadata: SerdeAny + Serialize + DeserializeOwned {
+    /// The name this metadata is sent under. It has to be the same for all clients, and unique.
+    const SHARED_NAME: &'static str;
+}
+
+/// The serialized metadata entries sent along with a testcase
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SharedMetadata {
+    /// The entries, as ([`ShareableMetadata::SHARED_NAME`], serialized metadata)
+    pub entries: Vec<(Cow<'static, str>, Vec<u8>)>,
+}
+
+impl SharedMetadata {
+    /// The serialized entry of the metadata type `M`, if any
+    #[must_use]
+    pub fn entry<M>(&self) -> Option<&[u8]>
+    where
+        M: ShareableMetadata,
+    {
+        self.entries
+            .iter()
+            .find(|(name, _)| name == M::SHARED_NAME)
+            .map(|(_, entry)| entry.as_slice())
+    }
+}
+
+/// A single allowed metadata type
+#[derive(Debug, Clone, Copy)]
+struct SharedMetadataType {
+    name: &'static str,
+    max_size: usize,
+    export: fn(&SerdeAnyMap) -> Result<Option<Vec<u8>>, Error>,
+    import: fn(&[u8], &mut SerdeAnyMap) -> Result<(), Error>,
+}
+
+fn export_metadata<M>(map: &SerdeAnyMap) -> Result<Option<Vec<u8>>, Error>
+where
+    M: SerdeAny + Serialize,
+{
+    Ok(map.get::<M>().map(postcard::to_allocvec).transpose()?)
+}
+
+fn import_metadata<M>(buf: &[u8], map: &mut SerdeAnyMap) -> Result<(), Error>
+where
+    M: SerdeAny + DeserializeOwned,
+{
+    // Metadata computed locally takes precedence
+    if !map.contains::<M>() {
+        map.insert(postcard::from_bytes::<M>(buf)?);
+    }
+    Ok(())
+}
+
+/// The allow-list of testcase metadata types to share with other clients.
+///
+/// The same allow-list is applied to outgoing and incoming testcases:
+/// types that are not allowed, or exceed their size limit, are neither sent nor restored.
+#[derive(Debug, Clone)]
+pub struct MetadataSharing {
+    types: Vec<SharedMetadataType>,
+    max_total_size: usize,
+}
+
+impl Default for MetadataSharing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetadataSharing {
+    /// Creates a new, empty, [`MetadataSharing`] allow-list
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            types: vec![],
+            max_total_size: DEFAULT_SHARED_METADATA_MAX_SIZE,
+        }
+    }
+
+    /// Allow sharing the metadata type `M`, if its serialized form is at most `max_size` bytes
+    #[must_use]
+    pub fn share<M>(mut self, max_size: usize) -> Self
+    where
+        M: ShareableMetadata,
+    {
+        let name = M::SHARED_NAME;
+        self.types.retain(|ty| ty.name != name);
+        self.types.push(SharedMetadataType {
+            name,
+            max_size,
+            export: export_metadata::<M>,
+            import: import_metadata::<M>,
+        });
+        self
+    }
+
+    /// Set the limit for all metadata shared with a single testcase, in bytes
+    #[must_use]
+    pub fn with_max_total_size(mut self, max_total_size: usize) -> Self {
+        self.max_total_size = max_total_size;
+        self
+    }
+
+    /// Returns `true` if no metadata type is shared
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// Collect the allowed metadata of this testcase, in the order of the allow-list.
+    ///
+    /// Returns the serialized [`SharedMetadata`], or `None` if there is nothing to share.
+    pub fn collect<I>(&self, testcase: &Testcase<I>) -> Result<Option<Vec<u8>>, Error> {
+        let mut shared = SharedMetadata::default();
+        let mut total_size = 0;
+        for ty in &self.types {
+            let Some(buf) = (ty.export)(testcase.metadata_map())? else {
+                continue;
+            };
+            if buf.len() > ty.max_size || total_size + buf.len() > self.max_total_size {
+                log::debug!(
+                    "Not sharing metadata {} of {} bytes, too large",
+                    ty.name,
+                    buf.len()
+                );
+                continue;
+            }
+            total_size += buf.len();
+            shared.entries.push((Cow::Borrowed(ty.name), buf));
+        }
+        if shared.entries.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(postcard::to_allocvec(&shared)?))
+        }
+    }
+
+    /// Restore the allowed metadata from a serialized [`SharedMetadata`] into this testcase.
+    ///
+    /// Entries of types not in this allow-list, or above their size limit, are skipped.
+    /// Metadata the testcase has already is kept.
+    pub fn restore<I>(&self, buf: &[u8], testcase: &mut Testcase<I>) -> Result<(), Error> {
+        let shared: SharedMetadata = postcard::from_bytes(buf)?;
+        for (name, entry) in &shared.entries {
+            let Some(ty) = self.types.iter().find(|ty| ty.name == name) else {
+                log::debug!("Ignoring shared metadata {name}, not allowed");
+                continue;
+            };
+            if entry.len() > ty.max_size {
+                log::debug!("Ignoring shared metadata {name}, too large");
+                continue;
+            }
+            (ty.import)(entry, testcase.metadata_map_mut())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::MetadataSharing;
+    use crate::{
+        Error, HasMetadata,
+        corpus::{Corpus, CorpusId, InMemoryCorpus, Testcase},
+        events::{Event, EventConfig, EventFirer, EventReceiver, EventWithStats},
+        executors::{ExitKind, nop::NopExecutor},
+        feedbacks::{ConstFeedback, MapIndexesMetadata, MapNoveltiesMetadata},
+        fuzzer::{EventProcessor, HasScheduler, StdFuzzer},
+        inputs::BytesInput,
+        schedulers::Scheduler,
+        state::{HasCorpus, HasImported, StdState},
+    };
+
+    /// Hands out the given events, as if they were received from other clients
+    #[derive(Debug, Default)]
+    struct ReceivingEventManager {
+        events: Vec<EventWithStats<BytesInput>>,
+    }
+
+    impl<S> EventFirer<BytesInput, S> for ReceivingEventManager {
+        fn should_send(&self) -> bool {
+            true
+        }
+
+        fn fire(
+            &mut self,
+            _state: &mut S,
+            _event: EventWithStats<BytesInput>,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl<S> EventReceiver<BytesInput, S> for ReceivingEventManager {
+        fn try_receive(
+            &mut self,
+            _state: &mut S,
+        ) -> Result<Option<(EventWithStats<BytesInput>, bool)>, Error> {
+            Ok(self.events.pop().map(|event| (event, false)))
+        }
+
+        fn on_interesting(
+            &mut self,
+            _state: &mut S,
+            _event: EventWithStats<BytesInput>,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    /// Records if the shared metadata is there when a testcase is scheduled
+    #[derive(Debug, Default)]
+    struct RecordingScheduler {
+        restored: Vec<bool>,
+    }
+
+    impl<S> Scheduler<BytesInput, S> for RecordingScheduler
+    where
+        S: HasCorpus<BytesInput>,
+    {
+        fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+            let testcase = state.corpus().get(id)?.borrow();
+            self.restored
+                .push(testcase.has_metadata::<MapNoveltiesMetadata>());
+            Ok(())
+        }
+
+        fn next(&mut self, _state: &mut S) -> Result<CorpusId, Error> {
+            Err(Error::illegal_state("Not fuzzing in this test"))
+        }
+
+        fn set_current_scheduled(
+            &mut self,
+            _state: &mut S,
+            _next_id: Option<CorpusId>,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_metadata_sharing() {
+        let mut testcase = Testcase::new(BytesInput::new(vec![1, 2, 3]));
+        testcase.add_metadata(MapNoveltiesMetadata::new(vec![1, 2, 3]));
+        testcase.add_metadata(MapIndexesMetadata::new((0..1000).collect()));
+        testcase.add_metadata(ExitKind::Ok);
+
+        let sharing = MetadataSharing::new()
+            .share::<MapNoveltiesMetadata>(128)
+            .share::<MapIndexesMetadata>(128);
+        let buf = sharing.collect(&testcase).unwrap().unwrap();
+
+        let mut received = Testcase::new(BytesInput::new(vec![1, 2, 3]));
+        sharing.restore(&buf, &mut received).unwrap();
+        assert_eq!(
+            received.metadata::<MapNoveltiesMetadata>().unwrap().list,
+            vec![1, 2, 3]
+        );
+        // too large
+        assert!(!received.has_metadata::<MapIndexesMetadata>());
+        // not allowed
+        assert!(!received.has_metadata::<ExitKind>());
+
+        // only restore what the receiver allows
+        let mut received = Testcase::new(BytesInput::new(vec![1, 2, 3]));
+        MetadataSharing::new().restore(&buf, &mut received).unwrap();
+        assert!(!received.has_metadata::<MapNoveltiesMetadata>());
+
+        // never overwrite metadata computed locally
+        let mut received = Testcase::new(BytesInput::new(vec![1, 2, 3]));
+        received.add_metadata(MapNoveltiesMetadata::new(vec![4]));
+        sharing.restore(&buf, &mut received).unwrap();
+        assert_eq!(
+            received.metadata::<MapNoveltiesMetadata>().unwrap().list,
+            vec![4]
+        );
+    }
+
+    #[test]
+    fn test_metadata_restored_before_scheduling() {
+        let sharing = MetadataSharing::new().share::<MapNoveltiesMetadata>(128);
+        let mut sent = Testcase::new(BytesInput::new(vec![1, 2, 3]));
+        sent.add_metadata(MapNoveltiesMetadata::new(vec![1, 2, 3]));
+        let metadata_buf = sharing.collect(&sent).unwrap();
+
+        let mut feedback = ConstFeedback::new(true);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::<BytesInput>::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let mut fuzzer = StdFuzzer::builder()
+            .scheduler(RecordingScheduler::default())
+            .feedback(feedback)
+            .objective(objective)
+            .metadata_sharing(sharing)
+            .build();
+        let mut manager = ReceivingEventManager {
+            events: vec![EventWithStats::with_current_time(
+                Event::NewTestcase {
+                    input: BytesInput::new(vec![1, 2, 3]),
+                    observers_buf: None,
+                    metadata_buf,
+                    exit_kind: ExitKind::Ok,
+                    corpus_size: 1,
+                    client_config: EventConfig::AlwaysUnique,
+                    forward_id: None,
+                    #[cfg(all(unix, feature = "std", feature = "multi_machine"))]
+                    node_id: None,
+                },
+                0,
+            )],
+        };
+        let mut executor = NopExecutor::ok();
+
+        fuzzer
+            .process_events(&mut state, &mut executor, &mut manager)
+            .unwrap();
+        assert_eq!(*state.imported(), 1);
+        assert_eq!(fuzzer.scheduler().restored, vec![true]);
+    }
+}
@@ -267,6 +267,11 @@ where
                 Ok(BrokerEventResult::Handled)
             }
             Event::Stop => Ok(BrokerEventResult::Forward),
+            Event::Custom { name, .. } => {
+                // Without other clients, nobody receives it
+                log::warn!("Dropping the custom event {name}, a SimpleEventManager has no clients");
+                Ok(BrokerEventResult::Handled)
+            }
         }
     }
 }
@@ -22,9 +22,12 @@ use libafl_bolts::os::startable_self;
 use libafl_bolts::os::unix_signals::setup_signal_handler;
 #[cfg(all(feature = "fork", unix))]
 use libafl_bolts::os::{ForkResult, fork};
+#[cfg(feature = "secure_channel")]
+use libafl_bolts::secure_channel::SecureChannelConfig;
 use libafl_bolts::{
     ClientId,
     core_affinity::CoreId,
+    llmp::LlmpTcpStream,
     os::CTRL_C_EXIT,
     shmem::{ShMem, ShMemProvider, StdShMem, StdShMemProvider},
     staterestore::StateRestorer,
@@ -32,7 +35,6 @@ use libafl_bolts::{
 };
 use serde::{Serialize, de::DeserializeOwned};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
     sync::{broadcast, broadcast::error::RecvError, mpsc},
     task::{JoinHandle, spawn},
 };
@@ -46,7 +48,7 @@ use crate::{
     events::{
         BrokerEventResult, Event, EventConfig, EventFirer, EventManagerHooksTuple, EventManagerId,
         EventReceiver, EventRestarter, EventWithStats, HasEventManagerId, ProgressReporter,
-        std_on_restart,
+        std_on_restart, tcp_channel::TcpChannel,
     },
     inputs::Input,
     monitors::{Monitor, stats::ClientStatsManager},
@@ -70,13 +72,15 @@ pub struct TcpEventBroker<I, MT>
 where
     I: Input,
     MT: Monitor,
-    //CE: CustomEvent<I>,
 {
     monitor: MT,
     /// A `nonblocking` [`TcpListener`] that we will `take` and convert to a Tokio listener in [`Self::broker_loop()`].
     listener: Option<TcpListener>,
     /// Amount of all clients ever, after which (when all are disconnected) this broker should quit.
     exit_cleanly_after: Option<NonZeroUsize>,
+    /// If set, only clients authenticated with this [`SecureChannelConfig`] are accepted
+    #[cfg(feature = "secure_channel")]
+    secure_channel: Option<SecureChannelConfig>,
     client_stats_manager: ClientStatsManager,
     phantom: PhantomData<I>,
 }
@@ -104,6 +108,8 @@ where
             client_stats_manager: ClientStatsManager::default(),
             phantom: PhantomData,
             exit_cleanly_after: None,
+            #[cfg(feature = "secure_channel")]
+            secure_channel: None,
         }
     }
 
@@ -112,6 +118,12 @@ where
         self.exit_cleanly_after = Some(n_clients);
     }
 
+    /// Only accept clients authenticated with the given [`SecureChannelConfig`], or any client for `None`
+    #[cfg(feature = "secure_channel")]
+    pub fn set_secure_channel(&mut self, secure_channel: Option<SecureChannelConfig>) {
+        self.secure_channel = secure_channel;
+    }
+
     /// Run in the broker until all clients exit
     // TODO: remove expect(clippy::needless_return) when clippy is fixed
     #[tokio::main(flavor = "current_thread")]
@@ -121,6 +133,8 @@ where
         let (tx, mut rx_mpsc) = mpsc::channel(65536);
 
         let exit_cleanly_after = self.exit_cleanly_after;
+        #[cfg(feature = "secure_channel")]
+        let secure_channel = self.secure_channel.clone();
 
         let listener = self
             .listener
@@ -131,6 +145,8 @@ where
         let tokio_broker = spawn(async move {
             let mut recv_handles: Vec<JoinHandle<_>> = vec![];
             let mut receivers: Vec<Arc<tokio::sync::Mutex<broadcast::Receiver<_>>>> = vec![];
+            // Handshakes run in a task per connection, so that a slow client does not block the others
+            let (tx_handshake, mut rx_handshake) = mpsc::channel(64);
 
             loop {
                 let mut reached_max = false;
@@ -141,16 +157,51 @@ where
                     }
                 }
 
-                // Asynchronously wait for an inbound socket.
-                let (socket, _) = listener.accept().await.expect("Accept failed");
-                let (mut read, mut write) = tokio::io::split(socket);
+                // Asynchronously wait for an inbound socket, or a finished handshake.
+                let (mut read, mut write, this_client_id) = tokio::select! {
+                    accepted = listener.accept() => {
+                        let (socket, addr) = match accepted {
+                            Ok(accepted) => accepted,
+                            Err(err) => {
+                                log::warn!("Accept failed: {err}");
+                                continue;
+                            }
+                        };
+                        let tx_handshake = tx_handshake.clone();
+                        #[cfg(feature = "secure_channel")]
+                        let secure_channel = secure_channel.clone();
+                        spawn(async move {
+                            let TcpChannel {
+                                reader: mut read,
+                                writer: write,
+                            } = match TcpChannel::accept(
+                                socket,
+                                #[cfg(feature = "secure_channel")]
+                                secure_channel.as_ref(),
+                            )
+                            .await
+                            {
+                                Ok(channel) => channel,
+                                Err(err) => {
+                                    log::warn!("Rejected client {addr}: {err}");
+                                    return;
+                                }
+                            };
 
-                // Protocol: the new client communicate its old ClientId or -1 if new
-                let mut this_client_id = [0; 4];
-                read.read_exact(&mut this_client_id)
-                    .await
-                    .expect("Socket closed?");
-                let this_client_id = ClientId(u32::from_le_bytes(this_client_id));
+                            // Protocol: the new client communicate its old ClientId or -1 if new
+                            let mut this_client_id = [0; 4];
+                            if let Err(err) = read.read_exact(&mut this_client_id).await {
+                                log::warn!("Client {addr} closed the connection during the handshake: {err}");
+                                return;
+                            }
+                            let this_client_id = ClientId(u32::from_le_bytes(this_client_id));
+                            // The receiver only stops with the broker
+                            let _ = tx_handshake.send((read, write, this_client_id)).await;
+                        });
+                        continue;
+                    }
+                    Some(handshake) = rx_handshake.recv() => handshake,
+                };
 
                 let (this_client_id, is_old) = if this_client_id == UNDEFINED_CLIENT_ID {
                     if reached_max {
@@ -165,10 +216,13 @@ where
 
                 let this_client_id_bytes = this_client_id.0.to_le_bytes();
 
-                // Protocol: Send the client id for this node;
-                write.write_all(&this_client_id_bytes).await.unwrap();
-
                 if !is_old && reached_max {
+                    // Protocol: Send the client id for this node;
+                    spawn(async move {
+                        if write.write_all(&this_client_id_bytes).await.is_ok() {
+                            let _ = write.flush().await;
+                        }
+                    });
                     continue;
                 }
 
@@ -226,6 +280,14 @@ where
 
                 // The forwarding end. No need to keep a handle to this (TODO: unless they don't quit/get stuck?)
                 spawn(async move {
+                    // Protocol: Send the client id for this node;
+                    if write.write_all(&this_client_id_bytes).await.is_err()
+                        || write.flush().await.is_err()
+                    {
+                        log::info!("Socket closed, client restarting");
+                        return;
+                    }
+
                     // In a loop, read data from the socket and write the data back.
                     loop {
                         let buf: Vec<u8> = match rx_inner.lock().await.recv().await {
@@ -262,7 +324,7 @@ where
                             return;
                         }
                         // Write the rest
-                        if write.write_all(&buf).await.is_err() {
+                        if write.write_all(&buf).await.is_err() || write.flush().await.is_err() {
                             // The socket is closed, the client is restarting
                             log::info!("Socket closed, client restarting");
                             return;
@@ -401,7 +463,7 @@ where
                 log::log!((*severity_level).into(), "{message}");
                 Ok(BrokerEventResult::Handled)
             }
-            Event::Stop => Ok(BrokerEventResult::Forward),
+            Event::Stop | Event::Custom { .. } => Ok(BrokerEventResult::Forward),
             //_ => Ok(BrokerEventResult::Forward),
         }
     }
@@ -415,7 +477,7 @@ pub struct TcpEventManager<EMH, I, S> {
     last_sent: Duration,
     hooks: EMH,
     /// The TCP stream for inter process communication
-    tcp: TcpStream,
+    tcp: LlmpTcpStream,
     /// Our `CientId`
     client_id: ClientId,
     #[cfg(feature = "tcp_compression")]
@@ -436,10 +498,12 @@ impl<I, S> TcpEventManager<(), I, S> {
 }
 
 /// Builder for `TcpEventManager`
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct TcpEventManagerBuilder<EMH, I, S> {
     throttle: Option<Duration>,
     hooks: EMH,
+    #[cfg(feature = "secure_channel")]
+    secure_channel: Option<SecureChannelConfig>,
     phantom: PhantomData<(I, S)>,
 }
 
@@ -456,6 +520,8 @@ impl<I, S> TcpEventManagerBuilder<(), I, S> {
         Self {
             throttle: None,
             hooks: (),
+            #[cfg(feature = "secure_channel")]
+            secure_channel: None,
             phantom: PhantomData,
         }
     }
@@ -466,6 +532,8 @@ impl<I, S> TcpEventManagerBuilder<(), I, S> {
         TcpEventManagerBuilder {
             throttle: self.throttle,
             hooks,
+            #[cfg(feature = "secure_channel")]
+            secure_channel: self.secure_channel,
             phantom: PhantomData,
         }
     }
@@ -479,6 +547,14 @@ impl<EMH, I, S> TcpEventManagerBuilder<EMH, I, S> {
         self
     }
 
+    /// Authenticate to the broker with the given [`SecureChannelConfig`]
+    #[cfg(feature = "secure_channel")]
+    #[must_use]
+    pub fn secure_channel(mut self, secure_channel: SecureChannelConfig) -> Self {
+        self.secure_channel = Some(secure_channel);
+        self
+    }
+
     /// Create a manager from a raw TCP client with hooks
     pub fn build_from_client<A: ToSocketAddrs>(
         self,
@@ -486,10 +562,15 @@ impl<EMH, I, S> TcpEventManagerBuilder<EMH, I, S> {
         client_id: ClientId,
         configuration: EventConfig,
     ) -> Result<TcpEventManager<EMH, I, S>, Error> {
-        let mut tcp = TcpStream::connect(addr)?;
+        let mut tcp = LlmpTcpStream::connect(
+            TcpStream::connect(addr)?,
+            #[cfg(feature = "secure_channel")]
+            self.secure_channel.as_ref(),
+        )?;
 
         let mut our_client_id_buf = client_id.0.to_le_bytes();
         tcp.write_all(&our_client_id_buf)
+            .and_then(|()| tcp.flush())
             .expect("Cannot write to the broker");
 
         tcp.read_exact(&mut our_client_id_buf)
@@ -610,6 +691,7 @@ where
         self.tcp.write_all(&size.to_le_bytes())?;
         self.tcp.write_all(&self.client_id.0.to_le_bytes())?;
         self.tcp.write_all(&serialized)?;
+        self.tcp.flush()?;
 
         self.last_sent = libafl_bolts::current_time();
         Ok(())
@@ -644,12 +726,18 @@ where
         // TODO: Get around local event copy by moving handle_in_client
         let self_id = self.client_id;
         let mut len_buf = [0_u8; 4];
-        self.tcp.set_nonblocking(true).expect("set to non-blocking");
+        self.tcp
+            .tcp()
+            .set_nonblocking(true)
+            .expect("set to non-blocking");
         // read all pending messages
         loop {
             match self.tcp.read_exact(&mut len_buf) {
                 Ok(()) => {
-                    self.tcp.set_nonblocking(false).expect("set to blocking");
+                    self.tcp
+                        .tcp()
+                        .set_nonblocking(false)
+                        .expect("set to blocking");
                     let len = u32::from_le_bytes(len_buf);
                     let mut buf = vec![0_u8; 4_usize + len as usize];
                     self.tcp.read_exact(&mut buf)?;
@@ -659,7 +747,10 @@ where
 
                     let other_client_id = ClientId(u32::from_le_bytes(client_id_buf));
 
-                    self.tcp.set_nonblocking(true).expect("set to non-blocking");
+                    self.tcp
+                        .tcp()
+                        .set_nonblocking(true)
+                        .expect("set to non-blocking");
                     if self_id == other_client_id {
                         panic!("Own ID should never have been sent by the broker");
                     } else {
@@ -699,6 +790,9 @@ where
                             Event::Stop => {
                                 state.request_stop();
                             }
+                            Event::Custom { name, .. } => {
+                                log::debug!("Received unhandled custom event {name}");
+                            }
                             _ => {
                                 return Err(Error::unknown(format!(
                                     "Received illegal message that message should not have arrived: {:?}.",
@@ -717,7 +811,10 @@ where
                 }
             }
         }
-        self.tcp.set_nonblocking(false).expect("set to blocking");
+        self.tcp
+            .tcp()
+            .set_nonblocking(false)
+            .expect("set to blocking");
         Ok(None)
     }
 
@@ -1034,6 +1131,11 @@ pub struct TcpRestartingMgr<EMH, I, MT, S, SP> {
     serialize_state: bool,
     /// The hooks for `handle_in_client`
     hooks: EMH,
+    /// If set, the broker only accepts clients authenticated with this [`SecureChannelConfig`],
+    /// and the clients authenticate to the broker with it.
+    #[cfg(feature = "secure_channel")]
+    #[builder(default = None)]
+    secure_channel: Option<SecureChannelConfig>,
     #[builder(setter(skip), default = PhantomData)]
     phantom_data: PhantomData<(I, S)>,
 }
@@ -1071,6 +1173,9 @@ where
                     broker.set_exit_cleanly_after(exit_cleanly_after);
                 }
 
+                #[cfg(feature = "secure_channel")]
+                broker.set_secure_channel(self.secure_channel.clone());
+
                 broker.broker_loop()
             };
 
@@ -1096,13 +1201,11 @@ where
                         }
                         Err(Error::OsError(..)) => {
                             // port was likely already bound
-                            let mgr = TcpEventManagerBuilder::new()
-                                .hooks(self.hooks)
-                                .build_from_client(
-                                    &("127.0.0.1", self.broker_port),
-                                    UNDEFINED_CLIENT_ID,
-                                    self.configuration,
-                                )?;
+                            let mgr = self.mgr_builder().build_from_client(
+                                &("127.0.0.1", self.broker_port),
+                                UNDEFINED_CLIENT_ID,
+                                self.configuration,
+                            )?;
                             (mgr, None)
                         }
                         Err(e) => {
@@ -1123,9 +1226,11 @@ where
                 }
                 TcpManagerKind::Client { cpu_core } => {
                     // We are a client
-                    let mgr = TcpEventManagerBuilder::new()
-                        .hooks(self.hooks)
-                        .build_on_port(self.broker_port, UNDEFINED_CLIENT_ID, self.configuration)?;
+                    let mgr = self.mgr_builder().build_on_port(
+                        self.broker_port,
+                        UNDEFINED_CLIENT_ID,
+                        self.configuration,
+                    )?;
 
                     (mgr, cpu_core)
                 }
@@ -1259,9 +1364,11 @@ where
             (
                 state_opt,
                 TcpRestartingEventManager::with_save_state(
-                    TcpEventManagerBuilder::new()
-                        .hooks(self.hooks)
-                        .build_on_port(self.broker_port, this_id, self.configuration)?,
+                    self.mgr_builder().build_on_port(
+                        self.broker_port,
+                        this_id,
+                        self.configuration,
+                    )?,
                     staterestorer,
                     self.serialize_state,
                 ),
@@ -1269,13 +1376,11 @@ where
         } else {
             log::info!("First run. Let's set it all up");
             // Mgr to send and receive msgs from/to all other fuzzer instances
-            let mgr = TcpEventManagerBuilder::new()
-                .hooks(self.hooks)
-                .build_existing_from_env(
-                    &("127.0.0.1", self.broker_port),
-                    _ENV_FUZZER_BROKER_CLIENT_INITIAL,
-                    self.configuration,
-                )?;
+            let mgr = self.mgr_builder().build_existing_from_env(
+                &("127.0.0.1", self.broker_port),
+                _ENV_FUZZER_BROKER_CLIENT_INITIAL,
+                self.configuration,
+            )?;
 
             (
                 None,
@@ -1297,4 +1402,15 @@ where
 
         Ok((state, mgr))
     }
+
+    /// The builder for the client managers
+    fn mgr_builder(&self) -> TcpEventManagerBuilder<EMH, I, S> {
+        let builder = TcpEventManagerBuilder::new().hooks(self.hooks);
+        #[cfg(feature = "secure_channel")]
+        let builder = match &self.secure_channel {
+            Some(secure_channel) => builder.secure_channel(secure_channel.clone()),
+            None => builder,
+        };
+        builder
+    }
 }
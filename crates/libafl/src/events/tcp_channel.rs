@@ -0,0 +1,300 @@
+//! Async tcp connections for the tokio-based event managers and multi-machine nodes.
+//!
+//! With the `secure_channel` feature, a [`TcpChannel`] may be authenticated (and encrypted)
+//! with a [`SecureChannelConfig`], using the same framing as [`libafl_bolts::secure_channel::SecureStream`].
+//! Written bytes are then buffered, and sent as one message on [`TcpChannelWriter::flush`].
+
+#[cfg(feature = "secure_channel")]
+use alloc::{string::ToString, vec::Vec};
+use std::io;
+#[cfg(feature = "secure_channel")]
+use std::io::ErrorKind;
+
+use libafl_bolts::Error;
+#[cfg(feature = "secure_channel")]
+use libafl_bolts::secure_channel::{
+    SECURE_FRAME_MAX_LEN, SECURE_HANDSHAKE_TIMEOUT, SecureChannelConfig, SecureChannelRole,
+    SecureHandshake, SecureReceiver, SecureSender,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+};
+
+/// The largest handshake frame we accept
+#[cfg(feature = "secure_channel")]
+const HANDSHAKE_FRAME_MAX_LEN: usize = 1024;
+
+/// A tcp connection, authenticated with a secure channel if configured
+#[derive(Debug)]
+pub(crate) struct TcpChannel {
+    /// The reading half
+    pub(crate) reader: TcpChannelReader,
+    /// The writing half
+    pub(crate) writer: TcpChannelWriter,
+}
+
+impl TcpChannel {
+    /// Wrap a connection we accepted, running the handshake if a [`SecureChannelConfig`] is given.
+    /// Rejects peers that fail the handshake.
+    pub(crate) async fn accept(
+        stream: TcpStream,
+        #[cfg(feature = "secure_channel")] secure_channel: Option<&SecureChannelConfig>,
+    ) -> Result<Self, Error> {
+        #[cfg(feature = "secure_channel")]
+        if let Some(config) = secure_channel {
+            return Self::handshake(stream, config, SecureChannelRole::Responder).await;
+        }
+        Ok(Self::plain(stream))
+    }
+
+    /// Wrap a connection we opened, running the handshake if a [`SecureChannelConfig`] is given
+    #[cfg(all(unix, feature = "multi_machine"))]
+    pub(crate) async fn connect(
+        stream: TcpStream,
+        #[cfg(feature = "secure_channel")] secure_channel: Option<&SecureChannelConfig>,
+    ) -> Result<Self, Error> {
+        #[cfg(feature = "secure_channel")]
+        if let Some(config) = secure_channel {
+            return Self::handshake(stream, config, SecureChannelRole::Initiator).await;
+        }
+        Ok(Self::plain(stream))
+    }
+
+    fn plain(stream: TcpStream) -> Self {
+        let (read, write) = stream.into_split();
+        Self {
+            reader: TcpChannelReader {
+                inner: read,
+                #[cfg(feature = "secure_channel")]
+                secure: None,
+            },
+            writer: TcpChannelWriter {
+                inner: write,
+                #[cfg(feature = "secure_channel")]
+                secure: None,
+            },
+        }
+    }
+
+    #[cfg(feature = "secure_channel")]
+    async fn handshake(
+        mut stream: TcpStream,
+        config: &SecureChannelConfig,
+        role: SecureChannelRole,
+    ) -> Result<Self, Error> {
+        // Don't let a silent peer stall us
+        let (sender, receiver) = tokio::time::timeout(
+            SECURE_HANDSHAKE_TIMEOUT,
+            run_handshake(&mut stream, config, role),
+        )
+        .await
+        .map_err(|_| Error::illegal_state("Secure channel handshake timed out"))??;
+
+        let (read, write) = stream.into_split();
+        Ok(Self {
+            reader: TcpChannelReader {
+                inner: read,
+                secure: Some(SecureReadState {
+                    receiver,
+                    buf: vec![],
+                    pos: 0,
+                }),
+            },
+            writer: TcpChannelWriter {
+                inner: write,
+                secure: Some(SecureWriteState {
+                    sender,
+                    buf: vec![],
+                }),
+            },
+        })
+    }
+}
+
+/// The reading half of a [`TcpChannel`]
+#[derive(Debug)]
+pub(crate) struct TcpChannelReader {
+    inner: OwnedReadHalf,
+    #[cfg(feature = "secure_channel")]
+    secure: Option<SecureReadState>,
+}
+
+impl TcpChannelReader {
+    /// Read exactly `buf.len()` bytes
+    pub(crate) async fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        #[cfg(feature = "secure_channel")]
+        if let Some(secure) = &mut self.secure {
+            let mut filled = 0;
+            while filled < buf.len() {
+                if secure.is_empty() {
+                    let mut len_buf = [0; 4];
+                    self.inner.read_exact(&mut len_buf).await?;
+                    secure.read_message(&mut self.inner, len_buf).await?;
+                }
+                filled += secure.take(&mut buf[filled..]);
+            }
+            return Ok(());
+        }
+        self.inner.read_exact(buf).await.map(|_| ())
+    }
+
+    /// Try to read without waiting for the peer, like [`OwnedReadHalf::try_read`].
+    ///
+    /// For secure channels, once the peer started sending a message, this waits for all of it.
+    #[cfg(all(unix, feature = "multi_machine"))]
+    pub(crate) async fn try_read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(feature = "secure_channel")]
+        if let Some(secure) = &mut self.secure {
+            if secure.is_empty() {
+                let mut len_buf = [0; 4];
+                if self.inner.try_read(&mut len_buf[..1])? == 0 {
+                    return Ok(0);
+                }
+                self.inner.read_exact(&mut len_buf[1..]).await?;
+                secure.read_message(&mut self.inner, len_buf).await?;
+            }
+            return Ok(secure.take(buf));
+        }
+        self.inner.try_read(buf)
+    }
+}
+
+/// The writing half of a [`TcpChannel`]
+#[derive(Debug)]
+pub(crate) struct TcpChannelWriter {
+    inner: OwnedWriteHalf,
+    #[cfg(feature = "secure_channel")]
+    secure: Option<SecureWriteState>,
+}
+
+impl TcpChannelWriter {
+    /// Write all of `buf`. For secure channels, the bytes are only sent on [`Self::flush`].
+    pub(crate) async fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        #[cfg(feature = "secure_channel")]
+        if let Some(secure) = &mut self.secure {
+            secure.buf.extend_from_slice(buf);
+            return Ok(());
+        }
+        self.inner.write_all(buf).await
+    }
+
+    /// Send all written bytes, as one message for secure channels
+    pub(crate) async fn flush(&mut self) -> io::Result<()> {
+        #[cfg(feature = "secure_channel")]
+        if let Some(secure) = &mut self.secure {
+            if !secure.buf.is_empty() {
+                let sealed = secure
+                    .sender
+                    .seal(&secure.buf)
+                    .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err.to_string()))?;
+                secure.buf.clear();
+                write_frame(&mut self.inner, &sealed).await?;
+            }
+        }
+        self.inner.flush().await
+    }
+}
+
+#[cfg(feature = "secure_channel")]
+#[derive(Debug)]
+struct SecureReadState {
+    receiver: SecureReceiver,
+    /// The last opened message
+    buf: Vec<u8>,
+    /// How much of `buf` was consumed already
+    pos: usize,
+}
+
+#[cfg(feature = "secure_channel")]
+impl SecureReadState {
+    fn is_empty(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    /// Read the frame of the given length, and open it
+    async fn read_message(
+        &mut self,
+        inner: &mut OwnedReadHalf,
+        len_buf: [u8; 4],
+    ) -> io::Result<()> {
+        let len = u32::from_be_bytes(len_buf) as usize;
+        if len > SECURE_FRAME_MAX_LEN {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Secure channel frame too large",
+            ));
+        }
+        let mut sealed = vec![0; len];
+        inner.read_exact(&mut sealed).await?;
+        self.buf = self
+            .receiver
+            .open(&sealed)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err.to_string()))?;
+        self.pos = 0;
+        Ok(())
+    }
+
+    /// Copy as much of the opened message as fits into `buf`
+    fn take(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.buf.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        len
+    }
+}
+
+#[cfg(feature = "secure_channel")]
+#[derive(Debug)]
+struct SecureWriteState {
+    sender: SecureSender,
+    /// The bytes written since the last flush
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "secure_channel")]
+async fn run_handshake(
+    stream: &mut TcpStream,
+    config: &SecureChannelConfig,
+    role: SecureChannelRole,
+) -> Result<(SecureSender, SecureReceiver), Error> {
+    let mut handshake = SecureHandshake::new(config, role)?;
+    write_frame(stream, &handshake.hello()).await?;
+    let peer_hello = read_frame(stream).await?;
+    let proof = handshake.receive_hello(&peer_hello)?;
+    write_frame(stream, &proof).await?;
+    let peer_proof = read_frame(stream).await?;
+    handshake.finish(&peer_proof)
+}
+
+/// Write a frame: the length as big endian `u32`, then the bytes
+#[cfg(feature = "secure_channel")]
+async fn write_frame<W>(stream: &mut W, frame: &[u8]) -> io::Result<()>
+where
+    W: AsyncWriteExt + Unpin,
+{
+    let len = u32::try_from(frame.len())
+        .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "Secure channel frame too large"))?;
+    stream.write_all(&len.to_be_bytes()).await?;
+    stream.write_all(frame).await
+}
+
+/// Read a handshake frame written by [`write_frame`]
+#[cfg(feature = "secure_channel")]
+async fn read_frame(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut len_buf = [0; 4];
+    stream.read_exact(&mut len_buf).await?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > HANDSHAKE_FRAME_MAX_LEN {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "Secure channel handshake frame too large",
+        ));
+    }
+    let mut frame = vec![0; len];
+    stream.read_exact(&mut frame).await?;
+    Ok(frame)
+}
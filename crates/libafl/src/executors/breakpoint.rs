@@ -0,0 +1,345 @@
+//! Binary-only block coverage with one-shot breakpoints, for the [`PTraceCommandConfigurator`], like `UnTracer`.
+//!
+//! An `int3` breakpoint is set at the start of each basic block that was never hit before.
+//! When the child hits one, the block is marked in the coverage map and its breakpoint is removed for good,
+//! so later runs only stop at new blocks, and run at nearly native speed once the coverage saturates.
+//!
+//! There is no built-in disassembler, the block addresses have to be provided,
+//! for example as a listing written by an external disassembler script, see [`BreakpointCoverage::from_file`].
+//! They are the virtual addresses of the ELF, the load address of position-independent executables is added automatically.
+//!
+//! Blocks are only reported the first time they are hit, so running an input again shows no coverage.
+//! Don't calibrate this map, all its entries would be marked as unstable.
+//!
+//! [`PTraceCommandConfigurator`]: crate::executors::command::PTraceCommandConfigurator
+
+use alloc::vec::Vec;
+use core::slice;
+use std::{
+    fs::{self, File},
+    io::Read,
+    os::unix::fs::FileExt,
+    path::Path,
+};
+
+use hashbrown::HashMap;
+#[cfg(target_arch = "x86_64")]
+use nix::sys::ptrace;
+use nix::{
+    sys::{
+        ptrace::cont,
+        signal::Signal,
+        wait::{WaitStatus, waitpid},
+    },
+    unistd::Pid,
+};
+
+use crate::Error;
+
+/// The `int3` instruction
+const INT3: u8 = 0xcc;
+/// The ELF type of position-independent executables
+const ET_DYN: u16 = 3;
+/// The breakpoints are written to the child one page at a time
+const PAGE_SIZE: u64 = 4096;
+
+/// One-shot breakpoint coverage of basic blocks, see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakpointCoverage {
+    /// The block addresses, as in the ELF
+    blocks: Vec<u64>,
+    /// The block index of each breakpoint address, once the load bias is known
+    breakpoints: HashMap<u64, usize>,
+    /// The original byte at the start of each block, read on the first run
+    original: Vec<u8>,
+    /// The pages with breakpoints, once the load bias is known
+    pages: Vec<BreakpointPage>,
+    /// If each block was hit in an earlier run, so its breakpoint is gone
+    hit: Vec<bool>,
+    /// Added to all block addresses, detected on the first run if not set
+    load_bias: Option<u64>,
+    initialized: bool,
+    map_ptr: *mut u8,
+    map_len: usize,
+}
+
+impl BreakpointCoverage {
+    /// Create a new [`BreakpointCoverage`] for the blocks at these addresses.
+    /// A hit of the block `i` sets the entry `i` of the map.
+    ///
+    /// # Safety
+    /// The map must stay valid for writes of `map_len` bytes while this is used.
+    pub unsafe fn new(blocks: Vec<u64>, map_ptr: *mut u8, map_len: usize) -> Result<Self, Error> {
+        if cfg!(not(target_arch = "x86_64")) {
+            return Err(Error::unsupported(
+                "Breakpoint coverage is only supported on x86_64",
+            ));
+        }
+        if map_len < blocks.len() {
+            return Err(Error::illegal_argument(format!(
+                "The map has {map_len} entries, but there are {} blocks",
+                blocks.len()
+            )));
+        }
+        Ok(Self {
+            hit: vec![false; blocks.len()],
+            blocks,
+            breakpoints: HashMap::new(),
+            original: vec![],
+            pages: vec![],
+            load_bias: None,
+            initialized: false,
+            map_ptr,
+            map_len,
+        })
+    }
+
+    /// Create a new [`BreakpointCoverage`] for the blocks listed in this file, one hexadecimal address per line.
+    /// Empty lines, lines starting with `#`, and anything after the address are ignored,
+    /// so listings like `401126: push rbp` work as well.
+    ///
+    /// # Safety
+    /// The map must stay valid for writes of `map_len` bytes while this is used.
+    pub unsafe fn from_file<P>(path: P, map_ptr: *mut u8, map_len: usize) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let blocks = parse_addresses(&fs::read_to_string(path)?)?;
+        unsafe { Self::new(blocks, map_ptr, map_len) }
+    }
+
+    /// Add this load bias to all block addresses, instead of detecting it on the first run
+    #[must_use]
+    pub fn with_load_bias(mut self, load_bias: u64) -> Self {
+        self.load_bias = Some(load_bias);
+        self
+    }
+
+    /// The block addresses, as in the ELF
+    #[must_use]
+    pub fn blocks(&self) -> &[u64] {
+        &self.blocks
+    }
+
+    /// The number of blocks hit so far, in all runs
+    #[must_use]
+    pub fn hit_count(&self) -> usize {
+        self.hit.iter().filter(|hit| **hit).count()
+    }
+
+    /// Trace the child until it terminates, and return its last [`WaitStatus`].
+    ///
+    /// The child must be traced by us, and stopped right after its `exec`.
+    pub fn trace(&mut self, child: Pid) -> Result<WaitStatus, Error> {
+        let mem = File::options()
+            .read(true)
+            .write(true)
+            .open(format!("/proc/{child}/mem"))?;
+        if !self.initialized {
+            self.init(child, &mem)?;
+        }
+
+        // # Safety
+        // The map is valid as long as we are used, see `new`.
+        let map = unsafe { slice::from_raw_parts_mut(self.map_ptr, self.map_len) };
+        map.fill(0);
+        let mut patched = Vec::new();
+        for page in &self.pages {
+            if page.blocks.iter().all(|(idx, _)| self.hit[*idx]) {
+                continue;
+            }
+            patched.clone_from(&page.original);
+            for (idx, offset) in &page.blocks {
+                if !self.hit[*idx] {
+                    patched[*offset] = INT3;
+                }
+            }
+            mem.write_all_at(&patched, page.start)?;
+        }
+
+        let mut signal = None;
+        loop {
+            cont(child, signal)?;
+            signal = match waitpid(child, None)? {
+                WaitStatus::Stopped(_, Signal::SIGTRAP) => {
+                    let addr = trap_address(child)?;
+                    match self.breakpoints.get(&addr) {
+                        Some(&idx) if !self.hit[idx] => {
+                            mem.write_all_at(&[self.original[idx]], addr)?;
+                            set_program_counter(child, addr)?;
+                            self.hit[idx] = true;
+                            map[idx] = 1;
+                            None
+                        }
+                        // Not one of ours
+                        _ => Some(Signal::SIGTRAP),
+                    }
+                }
+                WaitStatus::Stopped(_, sig) => Some(sig),
+                status @ (WaitStatus::Exited(..) | WaitStatus::Signaled(..)) => return Ok(status),
+                _ => None,
+            };
+        }
+    }
+
+    /// Resolve the breakpoint addresses, and read the original bytes, in the child of the first run
+    fn init(&mut self, child: Pid, mem: &File) -> Result<(), Error> {
+        let load_bias = match self.load_bias {
+            Some(load_bias) => load_bias,
+            None => detect_load_bias(child)?,
+        };
+        log::info!(
+            "Breakpoint coverage of {} blocks, load bias {load_bias:#x}",
+            self.blocks.len()
+        );
+
+        let addrs: Vec<u64> = self
+            .blocks
+            .iter()
+            .map(|block| block.wrapping_add(load_bias))
+            .collect();
+        let mut order: Vec<usize> = (0..addrs.len()).collect();
+        order.sort_by_key(|idx| addrs[*idx]);
+
+        self.original = vec![0; addrs.len()];
+        for idxs in order.chunk_by(|a, b| addrs[*a] / PAGE_SIZE == addrs[*b] / PAGE_SIZE) {
+            // Sorted, and on the same page
+            let start = addrs[idxs[0]];
+            let end = addrs[idxs[idxs.len() - 1]];
+            let mut original = vec![0; usize::try_from(end - start)? + 1];
+            mem.read_exact_at(&mut original, start).map_err(|err| {
+                Error::illegal_argument(format!("Cannot read the blocks at {start:#x}: {err}"))
+            })?;
+            let mut blocks = Vec::with_capacity(idxs.len());
+            for idx in idxs {
+                let offset = usize::try_from(addrs[*idx] - start)?;
+                self.original[*idx] = original[offset];
+                self.breakpoints.insert(addrs[*idx], *idx);
+                blocks.push((*idx, offset));
+            }
+            self.pages.push(BreakpointPage {
+                start,
+                original,
+                blocks,
+            });
+        }
+        self.load_bias = Some(load_bias);
+        self.initialized = true;
+        Ok(())
+    }
+}
+
+/// The blocks on one page of the child, to set their breakpoints with a single write
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BreakpointPage {
+    /// The address of the first block
+    start: u64,
+    /// The original bytes, from the first to the last block
+    original: Vec<u8>,
+    /// The index and the offset from `start` of each block
+    blocks: Vec<(usize, usize)>,
+}
+
+/// The address of the breakpoint the child stopped at, if it was a breakpoint
+#[cfg(target_arch = "x86_64")]
+fn trap_address(child: Pid) -> Result<u64, Error> {
+    Ok(ptrace::getregs(child)?.rip.wrapping_sub(1))
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn trap_address(_child: Pid) -> Result<u64, Error> {
+    Err(Error::unsupported(
+        "Breakpoint coverage is only supported on x86_64",
+    ))
+}
+
+/// Continue the child at this address
+#[cfg(target_arch = "x86_64")]
+fn set_program_counter(child: Pid, addr: u64) -> Result<(), Error> {
+    let mut regs = ptrace::getregs(child)?;
+    regs.rip = addr;
+    ptrace::setregs(child, regs)?;
+    Ok(())
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn set_program_counter(_child: Pid, _addr: u64) -> Result<(), Error> {
+    Err(Error::unsupported(
+        "Breakpoint coverage is only supported on x86_64",
+    ))
+}
+
+/// The load bias of the executable of the child:
+/// its lowest mapping for position-independent executables, else 0
+fn detect_load_bias(child: Pid) -> Result<u64, Error> {
+    let exe = fs::read_link(format!("/proc/{child}/exe"))?;
+    let mut header = [0; 18];
+    File::open(&exe)?.read_exact(&mut header)?;
+    if u16::from_le_bytes([header[16], header[17]]) != ET_DYN {
+        return Ok(0);
+    }
+    let maps = fs::read_to_string(format!("/proc/{child}/maps"))?;
+    lowest_mapping(&maps, &exe.to_string_lossy()).ok_or_else(|| {
+        Error::illegal_state(format!("{} is not mapped in the child", exe.display()))
+    })
+}
+
+/// The start of the lowest mapping of this file, in the contents of `/proc/<pid>/maps`
+fn lowest_mapping(maps: &str, path: &str) -> Option<u64> {
+    maps.lines()
+        .filter(|line| line.split_whitespace().nth(5) == Some(path))
+        .filter_map(|line| u64::from_str_radix(line.split('-').next()?, 16).ok())
+        .min()
+}
+
+/// Parse a listing of block addresses, see [`BreakpointCoverage::from_file`]
+fn parse_addresses(listing: &str) -> Result<Vec<u64>, Error> {
+    listing
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let token = line
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches(':');
+            let hex = token
+                .strip_prefix("0x")
+                .or_else(|| token.strip_prefix("0X"))
+                .unwrap_or(token);
+            u64::from_str_radix(hex, 16).map_err(|_| {
+                Error::illegal_argument(format!("Invalid block address in line {line:?}"))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lowest_mapping, parse_addresses};
+
+    #[test]
+    fn test_parse_addresses() {
+        let listing = "# blocks of main\n0x401000\n\n  401126: push rbp\n0X40113A\n";
+        assert_eq!(
+            parse_addresses(listing).unwrap(),
+            [0x40_1000, 0x40_1126, 0x40_113a]
+        );
+        assert!(parse_addresses("main: push rbp").is_err());
+    }
+
+    #[test]
+    fn test_lowest_mapping() {
+        let maps = "\
+555555556000-555555557000 r-xp 00001000 00:1f 42 /usr/bin/target
+555555555000-555555556000 r--p 00000000 00:1f 42 /usr/bin/target
+7ffff7fc3000-7ffff7fc5000 r--p 00000000 00:1f 43 /usr/lib/ld-linux-x86-64.so.2
+7ffffffde000-7ffffffff000 rw-p 00000000 00:00 0 [stack]";
+        assert_eq!(
+            lowest_mapping(maps, "/usr/bin/target"),
+            Some(0x5555_5555_5000)
+        );
+        assert_eq!(lowest_mapping(maps, "/usr/bin/other"), None);
+    }
+}
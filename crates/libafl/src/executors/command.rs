@@ -1,11 +1,11 @@
 //! The command executor executes a sub program for each run
-#[cfg(all(feature = "intel_pt", target_os = "linux"))]
+#[cfg(target_os = "linux")]
 use alloc::ffi::CString;
 #[cfg(not(unix))]
 use alloc::string::{String, ToString};
-#[cfg(all(feature = "intel_pt", target_os = "linux"))]
+#[cfg(target_os = "linux")]
 use alloc::vec::Vec;
-#[cfg(all(feature = "intel_pt", target_os = "linux"))]
+#[cfg(target_os = "linux")]
 use core::ffi::CStr;
 use core::{
     fmt::{self, Debug, Formatter},
@@ -17,7 +17,7 @@ use core::{
 use std::ffi::OsStr;
 #[cfg(not(unix))]
 use std::ffi::OsString;
-#[cfg(all(feature = "intel_pt", target_os = "linux"))]
+#[cfg(target_os = "linux")]
 use std::os::fd::AsRawFd;
 #[cfg(unix)]
 use std::os::{fd::RawFd, unix::ffi::OsStrExt};
@@ -33,9 +33,9 @@ use libafl_bolts::{
     ownedref::OwnedSlice,
     tuples::{Handle, MatchName, RefIndexable},
 };
-#[cfg(all(feature = "intel_pt", target_os = "linux"))]
+#[cfg(target_os = "linux")]
 use libafl_bolts::{core_affinity::CoreId, os::dup2};
-#[cfg(all(feature = "intel_pt", target_os = "linux"))]
+#[cfg(target_os = "linux")]
 use libc::STDIN_FILENO;
 #[cfg(target_os = "linux")]
 use nix::{
@@ -52,14 +52,18 @@ use nix::{
     },
     unistd::Pid,
 };
-#[cfg(all(feature = "intel_pt", target_os = "linux"))]
+#[cfg(target_os = "linux")]
 use typed_builder::TypedBuilder;
 
 #[cfg(all(target_family = "unix", feature = "fork"))]
 use super::forkserver::ConfigTarget;
+#[cfg(unix)]
+use super::limits::ChildLimits;
+#[cfg(target_os = "linux")]
+use super::limits::MemoryCgroup;
 use super::{HasTimeout, StdChildArgs, StdChildArgsInner};
 #[cfg(target_os = "linux")]
-use crate::executors::hooks::ExecutorHooksTuple;
+use crate::executors::{BreakpointCoverage, hooks::ExecutorHooksTuple};
 use crate::{
     Error,
     executors::{Executor, ExitKind, HasObservers},
@@ -128,6 +132,12 @@ pub struct StdCommandConfigurator {
     input_location: InputLocation,
     /// The Command to execute
     command: Command,
+    /// The limits, applied to each new command
+    #[cfg(unix)]
+    limits: ChildLimits,
+    /// The cgroup of the children, if they have a cgroup memory limit
+    #[cfg(target_os = "linux")]
+    cgroup: Option<MemoryCgroup>,
 }
 
 impl CommandConfigurator<Child> for StdCommandConfigurator {
@@ -176,6 +186,12 @@ impl CommandConfigurator<Child> for StdCommandConfigurator {
                 if let Some(cwd) = self.command.get_current_dir() {
                     cmd.current_dir(cwd);
                 }
+                #[cfg(unix)]
+                self.limits.apply_rlimits(&mut cmd);
+                #[cfg(target_os = "linux")]
+                if let Some(cgroup) = &self.cgroup {
+                    cgroup.apply_to(&mut cmd);
+                }
                 Ok(cmd.spawn()?)
             }
             InputLocation::StdIn { input_file: _ } => {
@@ -211,13 +227,31 @@ impl CommandConfigurator<Child> for StdCommandConfigurator {
     fn exec_timeout_mut(&mut self) -> &mut Duration {
         &mut self.timeout
     }
+
+    #[cfg(target_os = "linux")]
+    fn exit_kind_from_status(&self, status: &std::process::ExitStatus) -> ExitKind {
+        use crate::std::os::unix::process::ExitStatusExt;
+        let signal = status.signal();
+        if let (Some(_), Some(cgroup)) = (signal, &self.cgroup) {
+            match cgroup.oom_killed() {
+                Ok(true) => return ExitKind::Oom,
+                Ok(false) => {}
+                Err(err) => log::warn!(
+                    "Could not read the OOM kills of the cgroup {}: {err}",
+                    cgroup.path().display()
+                ),
+            }
+        }
+        exit_kind_from_signal(signal)
+    }
 }
 
 /// Linux specific [`CommandConfigurator`] that leverages `ptrace`
 ///
 /// This configurator was primarly developed to be used in conjunction with
-/// [`crate::executors::hooks::intel_pt::IntelPTHook`]
-#[cfg(all(feature = "intel_pt", target_os = "linux"))]
+/// `crate::executors::hooks::intel_pt::IntelPTHook`.
+/// With a [`BreakpointCoverage`], it also collects block coverage of binary-only targets.
+#[cfg(target_os = "linux")]
 #[derive(Debug, Clone, PartialEq, Eq, TypedBuilder)]
 pub struct PTraceCommandConfigurator {
     #[builder(setter(into))]
@@ -232,9 +266,11 @@ pub struct PTraceCommandConfigurator {
     cpu: Option<CoreId>,
     #[builder(default = 5 * 60, setter(transform = |t: Duration| t.as_secs() as u32))]
     timeout: u32,
+    #[builder(default, setter(strip_option))]
+    breakpoint_coverage: Option<BreakpointCoverage>,
 }
 
-#[cfg(all(feature = "intel_pt", target_os = "linux"))]
+#[cfg(target_os = "linux")]
 impl CommandConfigurator<Pid> for PTraceCommandConfigurator {
     fn spawn_child(&mut self, target_bytes: OwnedSlice<'_, u8>) -> Result<Pid, Error> {
         use nix::{
@@ -293,8 +329,8 @@ impl CommandConfigurator<Pid> for PTraceCommandConfigurator {
                 alarm::set(self.timeout);
 
                 // Just before this returns, hooks pre_execs are called
-                execve(&self.path, &self.args, &self.env).unwrap();
-                unreachable!("execve returns only on error and its result is unwrapped");
+                let Err(err) = execve(&self.path, &self.args, &self.env);
+                panic!("Could not execute the target: {err}");
             }
             Err(e) => Err(Error::unknown(format!("Fork failed: {e}"))),
         }
@@ -308,6 +344,10 @@ impl CommandConfigurator<Pid> for PTraceCommandConfigurator {
     fn exec_timeout_mut(&mut self) -> &mut Duration {
         unimplemented!("Use [`PTraceCommandConfigurator::builder().timeout`] instead")
     }
+
+    fn breakpoint_coverage_mut(&mut self) -> Option<&mut BreakpointCoverage> {
+        self.breakpoint_coverage.as_mut()
+    }
 }
 
 /// A `CommandExecutor` is a wrapper around [`Command`] to execute a target as a child process.
@@ -514,10 +554,15 @@ where
         }
         self.hooks.pre_exec_all(state, input);
 
-        // todo: it might be better to keep the target ptraced in case the target handles sigalarm,
-        // breaking the libafl timeout
-        ptrace::detach(child, None)?;
-        let res = match waitpid(child, None)? {
+        let wait_status = if let Some(coverage) = self.configurator.breakpoint_coverage_mut() {
+            coverage.trace(child)?
+        } else {
+            // todo: it might be better to keep the target ptraced in case the target handles sigalarm,
+            // breaking the libafl timeout
+            ptrace::detach(child, None)?;
+            waitpid(child, None)?
+        };
+        let res = match wait_status {
             Exited(pid, 0) if pid == child => ExitKind::Ok,
             Exited(pid, _) if pid == child => ExitKind::Crash,
             Signaled(pid, Signal::SIGALRM, _has_coredump) if pid == child => ExitKind::Timeout,
@@ -711,6 +756,16 @@ impl CommandExecutorBuilder {
             )));
         }
 
+        self.child_env_inner.limits.check_supported()?;
+        #[cfg(unix)]
+        self.child_env_inner.limits.apply_rlimits(&mut command);
+        #[cfg(target_os = "linux")]
+        let cgroup = self.child_env_inner.limits.create_cgroup()?;
+        #[cfg(target_os = "linux")]
+        if let Some(cgroup) = &cgroup {
+            cgroup.apply_to(&mut command);
+        }
+
         let configurator = StdCommandConfigurator {
             debug_child: self.child_env_inner.debug_child,
             stdout_cap,
@@ -718,6 +773,10 @@ impl CommandExecutorBuilder {
             input_location: self.target_inner.input_location.clone(),
             timeout: self.child_env_inner.timeout,
             command,
+            #[cfg(unix)]
+            limits: self.child_env_inner.limits.clone(),
+            #[cfg(target_os = "linux")]
+            cgroup,
         };
 
         Ok(configurator.into_executor::<I, OT, S>(
@@ -797,17 +856,19 @@ pub trait CommandConfigurator<C>: Sized {
     /// Set the timeout duration for execution of the child process.
     fn exec_timeout_mut(&mut self) -> &mut Duration;
 
+    /// The [`BreakpointCoverage`] to collect while tracing the child, if any.
+    /// Only used by the `ptrace` based [`CommandExecutor`].
+    #[cfg(target_os = "linux")]
+    fn breakpoint_coverage_mut(&mut self) -> Option<&mut BreakpointCoverage> {
+        None
+    }
+
     /// Maps the exit status of the child process to an `ExitKind`.
     #[cfg(unix)]
     #[inline]
     fn exit_kind_from_status(&self, status: &std::process::ExitStatus) -> ExitKind {
         use crate::std::os::unix::process::ExitStatusExt;
-        match status.signal() {
-            // for reference: https://www.man7.org/linux/man-pages/man7/signal.7.html
-            Some(9) => ExitKind::Oom,
-            Some(_) => ExitKind::Crash,
-            None => ExitKind::Ok,
-        }
+        exit_kind_from_signal(status.signal())
     }
 
     /// Maps the exit status of the child process to an `ExitKind`.
@@ -857,6 +918,19 @@ pub trait CommandConfigurator<C>: Sized {
     }
 }
 
+/// Maps the signal that terminated a child process, if any, to an `ExitKind`
+#[cfg(unix)]
+fn exit_kind_from_signal(signal: Option<i32>) -> ExitKind {
+    match signal {
+        // for reference: https://www.man7.org/linux/man-pages/man7/signal.7.html
+        Some(9) => ExitKind::Oom,
+        // The child exceeded its cpu time limit
+        Some(libc::SIGXCPU) => ExitKind::Timeout,
+        Some(_) => ExitKind::Crash,
+        None => ExitKind::Ok,
+    }
+}
+
 /// waitpid wrapper that ignores some signals sent by the ptraced child
 #[cfg(target_os = "linux")]
 fn waitpid_filtered(pid: Pid, options: Option<WaitPidFlag>) -> Result<WaitStatus, Errno> {
@@ -875,6 +949,9 @@ fn waitpid_filtered(pid: Pid, options: Option<WaitPidFlag>) -> Result<WaitStatus
 
 #[cfg(test)]
 mod tests {
+    #[cfg(unix)]
+    use std::{env, fs, process};
+
     use libafl_bolts::StdTargetArgs;
     #[cfg(unix)]
     use libafl_bolts::tuples::Handled;
@@ -893,7 +970,10 @@ mod tests {
         state::NopState,
     };
     #[cfg(unix)]
-    use crate::{executors::StdChildArgs, observers::StdOutObserver};
+    use crate::{
+        executors::{ExitKind, StdChildArgs},
+        observers::StdOutObserver,
+    };
 
     #[test]
     #[cfg_attr(miri, ignore)]
@@ -948,4 +1028,40 @@ mod tests {
 
         assert!(executor.observers.0.output.is_some());
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    #[cfg(unix)]
+    fn test_file_size_limit() {
+        let mut mgr: SimpleEventManager<NopInput, _, NopState<NopInput>> =
+            SimpleEventManager::new(SimpleMonitor::new(|status| {
+                log::info!("{status}");
+            }));
+
+        let path = env::temp_dir().join(format!("libafl_file_size_limit_{}", process::id()));
+        let mut executor = CommandExecutor::builder()
+            .program("dd")
+            .arg("if=/dev/zero")
+            .arg("bs=4096")
+            .arg("count=1")
+            .input(InputLocation::Arg { argnum: 3 })
+            .file_size_limit(1024)
+            .build(())
+            .unwrap();
+
+        let exit_kind = executor
+            .run_target(
+                &mut NopFuzzer::new(),
+                &mut NopState::<NopInput>::new(),
+                &mut mgr,
+                &BytesInput::new(format!("of={}", path.display()).into_bytes()),
+            )
+            .unwrap();
+        let written = fs::metadata(&path).unwrap().len();
+        fs::remove_file(&path).unwrap();
+
+        // `dd` is killed by `SIGXFSZ` when writing past the limit
+        assert_eq!(exit_kind, ExitKind::Crash);
+        assert!(written <= 1024);
+    }
 }
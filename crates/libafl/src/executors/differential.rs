@@ -2,12 +2,15 @@
 //!
 //! It wraps two executors that will be run after each other with the same input.
 //! In comparison to the [`crate::executors::CombinedExecutor`] it also runs the secondary executor in `run_target`.
+//! The [`MultiDiffExecutor`] does the same for any number of executors.
+use alloc::vec::Vec;
 use core::{
     cell::UnsafeCell,
     fmt::Debug,
     marker::PhantomData,
     ops::{Deref, DerefMut},
     ptr,
+    time::Duration,
 };
 
 use libafl_bolts::{
@@ -19,7 +22,7 @@ use serde::{Deserialize, Serialize};
 use super::HasTimeout;
 use crate::{
     Error,
-    executors::{Executor, ExitKind, HasObservers},
+    executors::{DiffExitKind, Executor, ExitKind, HasObservers},
     observers::{DifferentialObserversTuple, ObserversTuple},
 };
 
@@ -261,3 +264,461 @@ where
         }
     }
 }
+
+/// A tuple of executors, run after each other with the same input by a [`MultiDiffExecutor`]
+pub trait DiffExecutorsTuple<EM, I, S, Z> {
+    /// Run all executors, each with its observers, and push their [`ExitKind`]s in order
+    fn run_all(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut S,
+        mgr: &mut EM,
+        input: &I,
+        exit_kinds: &mut Vec<ExitKind>,
+    ) -> Result<(), Error>;
+}
+
+impl<EM, I, S, Z> DiffExecutorsTuple<EM, I, S, Z> for () {
+    fn run_all(
+        &mut self,
+        _fuzzer: &mut Z,
+        _state: &mut S,
+        _mgr: &mut EM,
+        _input: &I,
+        _exit_kinds: &mut Vec<ExitKind>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<EM, Head, I, S, Tail, Z> DiffExecutorsTuple<EM, I, S, Z> for (Head, Tail)
+where
+    Head: Executor<EM, I, S, Z> + HasObservers,
+    Head::Observers: ObserversTuple<I, S>,
+    Tail: DiffExecutorsTuple<EM, I, S, Z>,
+{
+    fn run_all(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut S,
+        mgr: &mut EM,
+        input: &I,
+        exit_kinds: &mut Vec<ExitKind>,
+    ) -> Result<(), Error> {
+        self.0.observers_mut().pre_exec_all(state, input)?;
+        let exit_kind = self.0.run_target(fuzzer, state, mgr, input)?;
+        self.0
+            .observers_mut()
+            .post_exec_all(state, input, &exit_kind)?;
+        exit_kinds.push(exit_kind);
+        self.1.run_all(fuzzer, state, mgr, input, exit_kinds)
+    }
+}
+
+/// A tuple of executors with timeouts, set all at once by a [`MultiDiffExecutor`]
+pub trait HasTimeoutsTuple {
+    /// Set the timeout of all executors
+    fn set_timeout_all(&mut self, timeout: Duration);
+
+    /// The timeout of all executors, or `None` if there are none.
+    ///
+    /// # Panics
+    /// Panics if the executors have different timeouts.
+    fn common_timeout(&self) -> Option<Duration>;
+}
+
+impl HasTimeoutsTuple for () {
+    fn set_timeout_all(&mut self, _timeout: Duration) {}
+
+    fn common_timeout(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl<Head, Tail> HasTimeoutsTuple for (Head, Tail)
+where
+    Head: HasTimeout,
+    Tail: HasTimeoutsTuple,
+{
+    fn set_timeout_all(&mut self, timeout: Duration) {
+        self.0.set_timeout(timeout);
+        self.1.set_timeout_all(timeout);
+    }
+
+    fn common_timeout(&self) -> Option<Duration> {
+        let timeout = self.0.timeout();
+        if let Some(other) = self.1.common_timeout() {
+            assert!(
+                timeout == other,
+                "The executors of the MultiDiffExecutor have different timeouts!"
+            );
+        }
+        Some(timeout)
+    }
+}
+
+/// A tuple of executors with observers, exposed by a [`MultiDiffExecutor`]
+pub trait HasObserversPtrs {
+    /// The pointers to the observers of all executors
+    type ObserversPtrs;
+
+    /// Get pointers to the observers of all executors, valid until the executors are moved or dropped
+    fn observers_ptrs(&self) -> Self::ObserversPtrs;
+}
+
+impl HasObserversPtrs for () {
+    type ObserversPtrs = ();
+
+    fn observers_ptrs(&self) -> Self::ObserversPtrs {}
+}
+
+impl<Head, Tail> HasObserversPtrs for (Head, Tail)
+where
+    Head: HasObservers,
+    Tail: HasObserversPtrs,
+{
+    type ObserversPtrs = ObserversPtrs<Head::Observers, Tail::ObserversPtrs>;
+
+    fn observers_ptrs(&self) -> Self::ObserversPtrs {
+        ObserversPtrs(
+            OwnedMutPtr::Ptr(ptr::from_ref(&*self.0.observers()).cast_mut()),
+            self.1.observers_ptrs(),
+        )
+    }
+}
+
+/// Pointers to the observers of each executor of a [`MultiDiffExecutor`], as a list
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(
+    bound = "OT: serde::Serialize + serde::de::DeserializeOwned, Tail: serde::Serialize + serde::de::DeserializeOwned"
+)]
+pub struct ObserversPtrs<OT, Tail>(OwnedMutPtr<OT>, Tail);
+
+impl<OT, Tail> MatchName for ObserversPtrs<OT, Tail>
+where
+    OT: MatchName,
+    Tail: MatchName,
+{
+    #[expect(deprecated)]
+    fn match_name<T>(&self, name: &str) -> Option<&T> {
+        match self.0.as_ref().match_name::<T>(name) {
+            Some(t) => Some(t),
+            _ => self.1.match_name::<T>(name),
+        }
+    }
+
+    #[expect(deprecated)]
+    fn match_name_mut<T>(&mut self, name: &str) -> Option<&mut T> {
+        match self.0.as_mut().match_name_mut::<T>(name) {
+            Some(t) => Some(t),
+            _ => self.1.match_name_mut::<T>(name),
+        }
+    }
+}
+
+/// A [`MultiDiffExecutor`] runs a tuple of executors after each other with the same input.
+///
+/// If their [`ExitKind`]s differ, it returns an [`ExitKind::Diff`] with the exit kind of the majority as `primary`,
+/// and the first exit kind outvoted as `secondary`, see [`MultiDiffExecutor::last_exit_kinds`] for all of them.
+/// The observers of all executors are exposed, so a [`crate::feedbacks::MultiDiffFeedback`] can compare them.
+#[derive(Debug)]
+pub struct MultiDiffExecutor<DOT, ET, I, PT, S> {
+    executors: ET,
+    exit_kinds: Vec<ExitKind>,
+    observers: UnsafeCell<MultiProxyObserversTuple<PT, DOT>>,
+    phantom: PhantomData<(I, S)>,
+}
+
+impl<DOT, ET, I, PT, S> MultiDiffExecutor<DOT, ET, I, PT, S> {
+    /// Create a new `MultiDiffExecutor`, wrapping the given tuple of executors,
+    /// with its own `observers`, run around all executors.
+    pub fn new(executors: ET, observers: DOT) -> Self {
+        Self {
+            executors,
+            exit_kinds: vec![],
+            observers: UnsafeCell::new(MultiProxyObserversTuple {
+                executors: None,
+                differential: observers,
+            }),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Retrieve the `Executor`s that are wrapped by this `MultiDiffExecutor`.
+    pub fn executors(&mut self) -> &mut ET {
+        &mut self.executors
+    }
+
+    /// The [`ExitKind`]s of all executors in the last run, in order
+    pub fn last_exit_kinds(&self) -> &[ExitKind] {
+        &self.exit_kinds
+    }
+}
+
+impl<DOT, ET, I, PT, S> HasTimeout for MultiDiffExecutor<DOT, ET, I, PT, S>
+where
+    ET: HasTimeoutsTuple,
+{
+    #[inline]
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.executors.set_timeout_all(timeout);
+    }
+
+    #[inline]
+    fn timeout(&self) -> Duration {
+        self.executors.common_timeout().unwrap_or_default()
+    }
+}
+
+/// The [`ExitKind`] of the majority of these exit kinds, and the first one that differs from it, if any
+fn vote_exit_kinds(exit_kinds: &[ExitKind]) -> Option<(ExitKind, Option<ExitKind>)> {
+    let majority = *exit_kinds
+        .iter()
+        .rev()
+        .max_by_key(|kind| exit_kinds.iter().filter(|other| other == kind).count())?;
+    let outvoted = exit_kinds.iter().find(|kind| **kind != majority).copied();
+    Some((majority, outvoted))
+}
+
+impl<DOT, EM, ET, I, PT, S, Z> Executor<EM, I, S, Z> for MultiDiffExecutor<DOT, ET, I, PT, S>
+where
+    ET: DiffExecutorsTuple<EM, I, S, Z>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut S,
+        mgr: &mut EM,
+        input: &I,
+    ) -> Result<ExitKind, Error> {
+        self.exit_kinds.clear();
+        self.executors
+            .run_all(fuzzer, state, mgr, input, &mut self.exit_kinds)?;
+        match vote_exit_kinds(&self.exit_kinds) {
+            None => Err(Error::empty("MultiDiffExecutor has no executors")),
+            Some((majority, None)) => Ok(majority),
+            Some((majority, Some(outvoted))) => {
+                // We found a diff in the exit codes!
+                log::debug!("Executors disagree on the exit kind: {:?}", self.exit_kinds);
+                Ok(ExitKind::Diff {
+                    primary: DiffExitKind::from(majority),
+                    secondary: DiffExitKind::from(outvoted),
+                })
+            }
+        }
+    }
+}
+
+/// Proxy the observers of all executors of a [`MultiDiffExecutor`], followed by its own
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(
+    bound = "PT: serde::Serialize + serde::de::DeserializeOwned, DOT: serde::Serialize + serde::de::DeserializeOwned"
+)]
+pub struct MultiProxyObserversTuple<PT, DOT> {
+    executors: Option<PT>,
+    differential: DOT,
+}
+
+impl<DOT, I, PT, S> ObserversTuple<I, S> for MultiProxyObserversTuple<PT, DOT>
+where
+    PT: MatchName,
+    DOT: ObserversTuple<I, S>,
+{
+    fn pre_exec_all(&mut self, state: &mut S, input: &I) -> Result<(), Error> {
+        self.differential.pre_exec_all(state, input)
+    }
+
+    fn post_exec_all(
+        &mut self,
+        state: &mut S,
+        input: &I,
+        exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.differential.post_exec_all(state, input, exit_kind)
+    }
+
+    fn pre_exec_child_all(&mut self, state: &mut S, input: &I) -> Result<(), Error> {
+        self.differential.pre_exec_child_all(state, input)
+    }
+
+    fn post_exec_child_all(
+        &mut self,
+        state: &mut S,
+        input: &I,
+        exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.differential
+            .post_exec_child_all(state, input, exit_kind)
+    }
+}
+
+impl<DOT, PT> Deref for MultiProxyObserversTuple<PT, DOT> {
+    type Target = DOT;
+
+    fn deref(&self) -> &Self::Target {
+        &self.differential
+    }
+}
+
+impl<DOT, PT> DerefMut for MultiProxyObserversTuple<PT, DOT> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.differential
+    }
+}
+
+impl<DOT, PT> MatchName for MultiProxyObserversTuple<PT, DOT>
+where
+    PT: MatchName,
+    DOT: MatchName,
+{
+    #[expect(deprecated)]
+    fn match_name<T>(&self, name: &str) -> Option<&T> {
+        match self
+            .executors
+            .as_ref()
+            .and_then(|executors| executors.match_name::<T>(name))
+        {
+            Some(t) => Some(t),
+            _ => self.differential.match_name::<T>(name),
+        }
+    }
+
+    #[expect(deprecated)]
+    fn match_name_mut<T>(&mut self, name: &str) -> Option<&mut T> {
+        match self
+            .executors
+            .as_mut()
+            .and_then(|executors| executors.match_name_mut::<T>(name))
+        {
+            Some(t) => Some(t),
+            _ => self.differential.match_name_mut::<T>(name),
+        }
+    }
+}
+
+impl<DOT, ET, I, PT, S> HasObservers for MultiDiffExecutor<DOT, ET, I, PT, S>
+where
+    ET: HasObserversPtrs<ObserversPtrs = PT>,
+    PT: MatchName,
+    DOT: ObserversTuple<I, S>,
+{
+    type Observers = MultiProxyObserversTuple<PT, DOT>;
+
+    #[inline]
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        unsafe {
+            self.observers.get().as_mut().unwrap().executors =
+                Some(self.executors.observers_ptrs());
+            RefIndexable::from(self.observers.get().as_ref().unwrap())
+        }
+    }
+
+    #[inline]
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        let observers = self.observers.get_mut();
+        observers.executors = Some(self.executors.observers_ptrs());
+        RefIndexable::from(observers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use core::time::Duration;
+
+    use libafl_bolts::{
+        ownedref::OwnedRef,
+        tuples::{MatchName, tuple_list},
+    };
+
+    use super::{MultiDiffExecutor, MultiProxyObserversTuple, vote_exit_kinds};
+    use crate::{
+        events::NopEventManager,
+        executors::{
+            DiffExitKind, Executor, ExitKind, HasObservers, HasTimeout, nop::ConstantExecutor,
+        },
+        fuzzer::NopFuzzer,
+        inputs::NopInput,
+        observers::ValueObserver,
+        state::NopState,
+    };
+
+    #[test]
+    fn test_vote_exit_kinds() {
+        assert_eq!(vote_exit_kinds(&[]), None);
+        assert_eq!(
+            vote_exit_kinds(&[ExitKind::Ok, ExitKind::Ok, ExitKind::Ok]),
+            Some((ExitKind::Ok, None))
+        );
+        assert_eq!(
+            vote_exit_kinds(&[ExitKind::Ok, ExitKind::Crash, ExitKind::Ok]),
+            Some((ExitKind::Ok, Some(ExitKind::Crash)))
+        );
+        // Ties go to the first executor
+        assert_eq!(
+            vote_exit_kinds(&[ExitKind::Timeout, ExitKind::Ok]),
+            Some((ExitKind::Timeout, Some(ExitKind::Ok)))
+        );
+    }
+
+    #[test]
+    #[expect(deprecated)]
+    fn test_multi_diff_executor() {
+        let observer = |name| ValueObserver::new(name, OwnedRef::Owned(Box::new(0_u8)));
+        let mut executor = MultiDiffExecutor::new(
+            tuple_list!(
+                ConstantExecutor::new(ExitKind::Ok, Duration::ZERO, tuple_list!(observer("a"))),
+                ConstantExecutor::new(ExitKind::Crash, Duration::ZERO, tuple_list!(observer("b"))),
+                ConstantExecutor::new(ExitKind::Ok, Duration::ZERO, tuple_list!(observer("c"))),
+            ),
+            tuple_list!(observer("diff")),
+        );
+
+        let exit_kind = executor
+            .run_target(
+                &mut NopFuzzer::new(),
+                &mut NopState::<NopInput>::new(),
+                &mut NopEventManager::new(),
+                &NopInput {},
+            )
+            .unwrap();
+        assert_eq!(
+            exit_kind,
+            ExitKind::Diff {
+                primary: DiffExitKind::Ok,
+                secondary: DiffExitKind::Crash,
+            }
+        );
+        assert_eq!(
+            executor.last_exit_kinds(),
+            [ExitKind::Ok, ExitKind::Crash, ExitKind::Ok]
+        );
+
+        let observers = executor.observers();
+        for name in ["a", "b", "c", "diff"] {
+            assert!(
+                observers.match_name::<ValueObserver<u8>>(name).is_some(),
+                "{name} not found"
+            );
+        }
+
+        executor.set_timeout(Duration::from_secs(3));
+        assert_eq!(executor.timeout(), Duration::from_secs(3));
+        assert_eq!(executor.executors().1.1.0.timeout(), Duration::from_secs(3));
+    }
+
+    #[test]
+    #[expect(deprecated)]
+    fn test_multi_proxy_observers_without_executors() {
+        let mut observers = MultiProxyObserversTuple::<(), _> {
+            executors: None,
+            differential: tuple_list!(ValueObserver::new("diff", OwnedRef::Owned(Box::new(0_u8)))),
+        };
+        assert!(observers.match_name::<ValueObserver<u8>>("diff").is_some());
+        assert!(
+            observers
+                .match_name_mut::<ValueObserver<u8>>("diff")
+                .is_some()
+        );
+    }
+}
@@ -39,7 +39,9 @@ use nix::{
     unistd::Pid,
 };
 
-use super::{HasTimeout, StdChildArgs, StdChildArgsInner};
+#[cfg(target_os = "linux")]
+use super::limits::{MemoryCgroup, set_cpu_time_limit};
+use super::{HasTimeout, StdChildArgs, StdChildArgsInner, limits::ChildLimits};
 #[cfg(feature = "regex")]
 use crate::observers::{
     AsanBacktraceObserver, get_asan_runtime_flags, get_asan_runtime_flags_with_log_path,
@@ -346,6 +348,12 @@ pub struct Forkserver {
     last_run_timed_out: i32,
     /// The signal this [`Forkserver`] will use to kill (defaults to [`self.kill_signal`])
     kill_signal: Signal,
+    /// The cgroup of the forkserver and its children, if any
+    #[cfg(target_os = "linux")]
+    cgroup: Option<MemoryCgroup>,
+    /// The cpu time limit, set on each forked child
+    #[cfg(target_os = "linux")]
+    child_cpu_time: Option<Duration>,
 }
 
 impl Drop for Forkserver {
@@ -400,7 +408,7 @@ impl Forkserver {
         envs: Vec<(OsString, OsString)>,
         input_filefd: RawFd,
         use_stdin: bool,
-        memlimit: u64,
+        limits: &ChildLimits,
         is_persistent: bool,
         is_deferred_frksrv: bool,
         is_fsrv_only: bool,
@@ -413,6 +421,7 @@ impl Forkserver {
         cwd: Option<PathBuf>,
         core: Option<CoreId>,
     ) -> Result<Self, Error> {
+        limits.check_supported()?;
         let Some(coverage_map_size) = coverage_map_size else {
             return Err(Error::unknown(
                 "Coverage map size unknown. Use coverage_map_size() to tell the forkserver about the map size.",
@@ -510,13 +519,35 @@ impl Forkserver {
             command.current_dir(cwd);
         }
 
+        // The forkserver would add up the cpu time of all its forks, so the limit is set
+        // on each forked child instead, see `ForkserverExecutor::spawn_child`.
+        // A persistent child runs many inputs, so it is not limited at all.
+        let mut limits = limits.clone();
+        let child_cpu_time = limits.cpu_time.take();
+        if is_persistent && child_cpu_time.is_some() {
+            log::warn!("The cpu time limit is not applied in persistent mode");
+        }
+        #[cfg(not(target_os = "linux"))]
+        if !is_persistent && child_cpu_time.is_some() {
+            return Err(Error::unsupported(
+                "The cpu time limit of forkserver children is only supported on Linux",
+            ));
+        }
+        limits.apply_rlimits(&mut command);
+        // Children inherit the cgroup of the forkserver at fork
+        #[cfg(target_os = "linux")]
+        let cgroup = limits.create_cgroup()?;
+        #[cfg(target_os = "linux")]
+        if let Some(cgroup) = &cgroup {
+            cgroup.apply_to(&mut command);
+        }
+
         // # Saftey
         // The pipe file descriptors used for `setpipe` are valid at this point.
         let fsrv_handle = unsafe {
             match command
                 .env("LD_BIND_NOW", "1")
                 .envs(envs)
-                .setlimit(memlimit)
                 .set_coredump(afl_debug)
                 .setsid()
                 .setpipe(
@@ -548,6 +579,10 @@ impl Forkserver {
             status: 0,
             last_run_timed_out: 0,
             kill_signal,
+            #[cfg(target_os = "linux")]
+            cgroup,
+            #[cfg(target_os = "linux")]
+            child_cpu_time: child_cpu_time.filter(|_| !is_persistent),
         })
     }
 
@@ -602,6 +637,13 @@ impl Forkserver {
         self.child_pid = None;
     }
 
+    /// The cgroup of the forkserver and its children, if it has a cgroup memory limit
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn cgroup(&self) -> Option<&MemoryCgroup> {
+        self.cgroup.as_ref()
+    }
+
     /// Read from the st pipe
     pub fn read_st(&mut self) -> Result<i32, Error> {
         let mut buf: [u8; 4] = [0_u8; 4];
@@ -810,10 +852,6 @@ where
     /// Execute input, but side-step the execution counter.
     #[inline]
     fn execute_input_uncounted(&mut self, input: &[u8]) -> Result<ExitKind, Error> {
-        let mut exit_kind = ExitKind::Ok;
-
-        let last_run_timed_out = self.forkserver.last_run_timed_out_raw();
-
         let mut input_size = input.len();
         if input_size > self.max_input_size {
             // Truncate like AFL++ does
@@ -831,6 +869,15 @@ where
             self.map_input_to_shmem(input, input_size)?;
         }
 
+        self.spawn_child()?;
+        let timeout = self.timeout;
+        self.wait_for_child(&timeout)
+    }
+
+    /// Request a new child from the fork server, and return its pid
+    pub(crate) fn spawn_child(&mut self) -> Result<Pid, Error> {
+        let last_run_timed_out = self.forkserver.last_run_timed_out_raw();
+
         self.forkserver.set_last_run_timed_out(false);
         if let Err(err) = self.forkserver.write_ctl(last_run_timed_out) {
             return Err(Error::unknown(format!(
@@ -850,40 +897,95 @@ where
             ));
         }
 
-        self.forkserver.set_child_pid(Pid::from_raw(pid));
+        let pid = Pid::from_raw(pid);
+        #[cfg(target_os = "linux")]
+        if let Some(cpu_time) = self.forkserver.child_cpu_time {
+            set_cpu_time_limit(pid.as_raw(), cpu_time)?;
+        }
+        self.forkserver.set_child_pid(pid);
+        Ok(pid)
+    }
 
-        if let Some(status) = self.forkserver.read_st_timed(&self.timeout)? {
-            self.forkserver.set_status(status);
-            let exitcode_is_crash = if let Some(crash_exitcode) = self.crash_exitcode {
-                (libc::WEXITSTATUS(self.forkserver().status()) as i8) == crash_exitcode
-            } else {
-                false
-            };
-            if libc::WIFSIGNALED(self.forkserver().status()) || exitcode_is_crash {
-                exit_kind = ExitKind::Crash;
-                #[cfg(feature = "regex")]
-                if let Some(asan_observer) = self.observers.get_mut(&self.asan_obs) {
-                    asan_observer.parse_asan_output_from_asan_log_file(pid)?;
+    /// Wait for the current child to exit, at most for `timeout`, and kill it after.
+    pub(crate) fn wait_for_child(&mut self, timeout: &TimeSpec) -> Result<ExitKind, Error> {
+        match self.try_wait_for_child(timeout)? {
+            Some(exit_kind) => Ok(exit_kind),
+            None => self.kill_child(),
+        }
+    }
+
+    /// Wait for the current child to exit, at most for `timeout`.
+    ///
+    /// Returns `None` if it is still running.
+    pub(crate) fn try_wait_for_child(
+        &mut self,
+        timeout: &TimeSpec,
+    ) -> Result<Option<ExitKind>, Error> {
+        #[cfg(feature = "regex")]
+        let pid = self.forkserver.child_pid().as_raw();
+
+        let Some(status) = self.forkserver.read_st_timed(timeout)? else {
+            return Ok(None);
+        };
+        self.forkserver.set_status(status);
+        let mut exit_kind = ExitKind::Ok;
+        let exitcode_is_crash = if let Some(crash_exitcode) = self.crash_exitcode {
+            (libc::WEXITSTATUS(self.forkserver().status()) as i8) == crash_exitcode
+        } else {
+            false
+        };
+        if libc::WIFSIGNALED(self.forkserver().status()) || exitcode_is_crash {
+            exit_kind = ExitKind::Crash;
+            #[cfg(feature = "regex")]
+            if let Some(asan_observer) = self.observers.get_mut(&self.asan_obs) {
+                asan_observer.parse_asan_output_from_asan_log_file(pid)?;
+                if libc::WIFSIGNALED(status) {
+                    let signal = libc::WTERMSIG(status);
+                    match Signal::try_from(signal) {
+                        Ok(signal) => asan_observer.record_signal(signal.as_str()),
+                        Err(_) => asan_observer.record_signal(&format!("signal {signal}")),
+                    }
                 }
             }
-        } else {
-            self.forkserver.set_last_run_timed_out(true);
+        }
 
-            // We need to kill the child in case he has timed out, or we can't get the correct pid in the next call to self.executor.forkserver_mut().read_st()?
-            let _ = kill(self.forkserver().child_pid(), self.forkserver.kill_signal);
-            if let Err(err) = self.forkserver.read_st() {
-                return Err(Error::unknown(format!(
-                    "Could not kill timed-out child: {err:?}"
-                )));
+        if libc::WIFSIGNALED(status) {
+            if libc::WTERMSIG(status) == libc::SIGXCPU {
+                // The child exceeded its cpu time limit
+                exit_kind = ExitKind::Timeout;
             }
-            exit_kind = ExitKind::Timeout;
+            #[cfg(target_os = "linux")]
+            if let Some(cgroup) = self.forkserver.cgroup() {
+                if cgroup.oom_killed()? {
+                    exit_kind = ExitKind::Oom;
+                }
+            }
+        }
+
+        if !libc::WIFSTOPPED(self.forkserver().status()) {
+            self.forkserver.reset_child_pid();
+        }
+
+        Ok(Some(exit_kind))
+    }
+
+    /// Kill the current child, after it timed out
+    pub(crate) fn kill_child(&mut self) -> Result<ExitKind, Error> {
+        self.forkserver.set_last_run_timed_out(true);
+
+        // We need to kill the child in case he has timed out, or we can't get the correct pid in the next call to self.executor.forkserver_mut().read_st()?
+        let _ = kill(self.forkserver().child_pid(), self.forkserver.kill_signal);
+        if let Err(err) = self.forkserver.read_st() {
+            return Err(Error::unknown(format!(
+                "Could not kill timed-out child: {err:?}"
+            )));
         }
 
         if !libc::WIFSTOPPED(self.forkserver().status()) {
             self.forkserver.reset_child_pid();
         }
 
-        Ok(exit_kind)
+        Ok(ExitKind::Timeout)
     }
 }
 
@@ -1107,7 +1209,7 @@ where
                 self.target_inner.envs.clone(),
                 input_file.as_raw_fd(),
                 self.use_stdin(),
-                0,
+                &self.child_env_inner.limits,
                 self.is_persistent,
                 self.is_deferred_frksrv,
                 self.is_fsrv_only,
@@ -0,0 +1,345 @@
+//! Resource limits for the children of executors spawning processes, such as the `ForkserverExecutor` and `CommandExecutor`.
+//!
+//! [`ChildLimits`] are set with the [`StdChildArgs`](crate::executors::StdChildArgs) of the executor builders.
+//! The rlimits apply to each child separately. On Linux, the children can also share a cgroup v2 with a memory limit.
+//! Children killed by the kernel for exceeding it are reported as [`crate::executors::ExitKind::Oom`],
+//! children exceeding their cpu time as [`crate::executors::ExitKind::Timeout`].
+//!
+//! A forkserver is started in the cgroup, so its children are in it from the fork on,
+//! and the memory of the forkserver itself counts towards the limit.
+//! The cpu time limit is set on each forked child only, once the forkserver reports its pid,
+//! as the forkserver would otherwise add up the cpu time of all its forks. This needs `prlimit`, so it is Linux only.
+//! The cpu time limit does not apply in persistent mode, as one child runs many inputs there.
+//!
+//! The cgroup is created below a parent cgroup that must be writable, and may not contain any processes itself,
+//! for example:
+//!
+//! ```sh
+//! sudo mkdir /sys/fs/cgroup/libafl
+//! sudo chown -R $USER /sys/fs/cgroup/libafl
+//! ```
+
+#[cfg(target_os = "linux")]
+use alloc::string::ToString;
+use core::time::Duration;
+#[cfg(target_os = "linux")]
+use core::{
+    cell::Cell,
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::{
+    fs::{self, File},
+    os::fd::AsRawFd,
+    path::Path,
+    process,
+};
+#[cfg(unix)]
+use std::{io, os::unix::process::CommandExt, process::Command};
+
+use crate::Error;
+
+/// The resource limits of a child process, see the [module docs](self)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChildLimits {
+    /// The maximum size of the address space, in bytes (`RLIMIT_AS`)
+    pub address_space: Option<u64>,
+    /// The maximum cpu time (`RLIMIT_CPU`), rounded to seconds. Not applied to persistent forkservers.
+    pub cpu_time: Option<Duration>,
+    /// The maximum size of files written, in bytes (`RLIMIT_FSIZE`)
+    pub file_size: Option<u64>,
+    /// The maximum number of open files (`RLIMIT_NOFILE`)
+    pub open_files: Option<u64>,
+    /// The parent of the cgroup for all children, and its memory limit in bytes. Linux only.
+    pub cgroup_memory: Option<(PathBuf, u64)>,
+}
+
+impl ChildLimits {
+    /// Check that these limits are supported on this platform
+    pub fn check_supported(&self) -> Result<(), Error> {
+        if cfg!(not(unix)) && *self != Self::default() {
+            return Err(Error::unsupported(
+                "Child resource limits are only supported on unix",
+            ));
+        }
+        if cfg!(not(target_os = "linux")) && self.cgroup_memory.is_some() {
+            return Err(Error::unsupported(
+                "cgroup memory limits are only supported on Linux",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl ChildLimits {
+    /// Apply the rlimits to the children spawned by this command
+    #[expect(trivial_numeric_casts)]
+    pub fn apply_rlimits(&self, command: &mut Command) {
+        let Self {
+            address_space,
+            cpu_time,
+            file_size,
+            open_files,
+            ..
+        } = *self;
+        if address_space.is_none()
+            && cpu_time.is_none()
+            && file_size.is_none()
+            && open_files.is_none()
+        {
+            return;
+        }
+        let cpu_time = cpu_time.map(|cpu_time| cpu_time.as_secs().max(1));
+
+        macro_rules! set_rlimit {
+            ($resource:expr, $limit:expr) => {
+                set_rlimit!($resource, $limit, 0)
+            };
+            ($resource:expr, $limit:expr, $slack:expr) => {
+                if let Some(limit) = $limit {
+                    let r = libc::rlimit {
+                        rlim_cur: limit as libc::rlim_t,
+                        rlim_max: limit.saturating_add($slack) as libc::rlim_t,
+                    };
+                    // # Safety
+                    // A plain libc call, with a valid pointer.
+                    if unsafe { libc::setrlimit($resource, &raw const r) } < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+            };
+        }
+
+        // # Safety
+        // This method does not do shady pointer foo.
+        // It merely call libc functions.
+        let func = move || {
+            #[cfg(target_os = "openbsd")]
+            set_rlimit!(libc::RLIMIT_RSS, address_space);
+            #[cfg(not(target_os = "openbsd"))]
+            set_rlimit!(libc::RLIMIT_AS, address_space);
+            // The kernel sends `SIGKILL` at the hard limit, give it a second to die of `SIGXCPU` first
+            set_rlimit!(libc::RLIMIT_CPU, cpu_time, 1);
+            set_rlimit!(libc::RLIMIT_FSIZE, file_size);
+            set_rlimit!(libc::RLIMIT_NOFILE, open_files);
+            Ok(())
+        };
+        // # Safety
+        // This calls our non-shady function from above.
+        unsafe {
+            command.pre_exec(func);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ChildLimits {
+    /// Create the cgroup for the children, if these limits have a cgroup memory limit
+    pub fn create_cgroup(&self) -> Result<Option<MemoryCgroup>, Error> {
+        self.cgroup_memory
+            .as_ref()
+            .map(|(parent, max_memory)| MemoryCgroup::new(parent, *max_memory))
+            .transpose()
+    }
+}
+
+/// Limit the cpu time of a running process (`RLIMIT_CPU`), e.g. a child reported by a forkserver.
+///
+/// A process that already exited is ignored.
+#[cfg(target_os = "linux")]
+#[expect(trivial_numeric_casts)]
+pub fn set_cpu_time_limit(pid: libc::pid_t, cpu_time: Duration) -> Result<(), Error> {
+    let limit = cpu_time.as_secs().max(1);
+    // The kernel sends `SIGKILL` at the hard limit, give it a second to die of `SIGXCPU` first
+    let r = libc::rlimit {
+        rlim_cur: limit as libc::rlim_t,
+        rlim_max: limit.saturating_add(1) as libc::rlim_t,
+    };
+    // # Safety
+    // A plain libc call, with a valid pointer for the new limit and none for the old one.
+    if unsafe { libc::prlimit(pid, libc::RLIMIT_CPU, &raw const r, ptr::null_mut()) } < 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ESRCH) {
+            return Ok(());
+        }
+        return Err(Error::os_error(
+            err,
+            format!("Could not limit the cpu time of the process {pid}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Counts the cgroups created by this process, for unique names
+#[cfg(target_os = "linux")]
+static CGROUP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A cgroup v2 with a memory limit, removed on drop, see the [module docs](self)
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct MemoryCgroup {
+    path: PathBuf,
+    /// The `cgroup.procs` file, opened in advance for the children
+    procs: File,
+    /// The number of OOM kills seen so far
+    oom_kills: Cell<u64>,
+}
+
+#[cfg(target_os = "linux")]
+impl MemoryCgroup {
+    /// Create a new cgroup below `parent`, limited to `max_memory` bytes, without swap
+    pub fn new(parent: &Path, max_memory: u64) -> Result<Self, Error> {
+        // Fails if the memory controller is already enabled for the children of `parent`, or can't be.
+        // In the latter case, setting `memory.max` fails below.
+        let _ = fs::write(parent.join("cgroup.subtree_control"), "+memory");
+
+        let path = parent.join(format!(
+            "libafl-{}-{}",
+            process::id(),
+            CGROUP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir(&path).map_err(|err| {
+            Error::illegal_argument(format!(
+                "Could not create the cgroup {}, the parent must be a writable cgroup v2: {err}",
+                path.display()
+            ))
+        })?;
+        let cgroup = Self {
+            procs: File::options()
+                .write(true)
+                .open(path.join("cgroup.procs"))?,
+            path,
+            oom_kills: Cell::new(0),
+        };
+        fs::write(cgroup.path.join("memory.max"), max_memory.to_string()).map_err(|err| {
+            Error::illegal_argument(format!(
+                "Could not set the memory limit of the cgroup {}, is the memory controller enabled? {err}",
+                cgroup.path.display()
+            ))
+        })?;
+        // Not all kernels account swap
+        let _ = fs::write(cgroup.path.join("memory.swap.max"), "0");
+        cgroup.oom_kills.set(cgroup.read_oom_kills()?);
+        Ok(cgroup)
+    }
+
+    /// The path of this cgroup
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Move the children spawned by this command into this cgroup.
+    /// The command must not outlive this cgroup.
+    pub fn apply_to(&self, command: &mut Command) {
+        let procs = self.procs.as_raw_fd();
+        let func = move || {
+            // Writing `0` moves the writing process
+            // # Safety
+            // A plain libc call, on a file descriptor that is open as long as the cgroup.
+            if unsafe { libc::write(procs, b"0".as_ptr().cast(), 1) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        };
+        // # Safety
+        // This calls our non-shady function from above.
+        unsafe {
+            command.pre_exec(func);
+        }
+    }
+
+    /// If the kernel killed a process in this cgroup for exceeding the memory limit, since the last call
+    pub fn oom_killed(&self) -> Result<bool, Error> {
+        let oom_kills = self.read_oom_kills()?;
+        Ok(self.oom_kills.replace(oom_kills) < oom_kills)
+    }
+
+    fn read_oom_kills(&self) -> Result<u64, Error> {
+        let events = fs::read_to_string(self.path.join("memory.events"))?;
+        Ok(parse_oom_kills(&events))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for MemoryCgroup {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir(&self.path) {
+            log::warn!("Could not remove the cgroup {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// The `oom_kill` counter of a `memory.events` file
+#[cfg(target_os = "linux")]
+fn parse_oom_kills(events: &str) -> u64 {
+    events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill ")?.trim().parse().ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+    use core::time::Duration;
+    use std::{
+        os::unix::process::ExitStatusExt,
+        process::{Command, Stdio},
+        thread,
+    };
+
+    use super::{parse_oom_kills, set_cpu_time_limit};
+
+    #[test]
+    fn test_parse_oom_kills() {
+        let events = "low 0\nhigh 0\nmax 12\noom 3\noom_kill 2\noom_group_kill 0\n";
+        assert_eq!(parse_oom_kills(events), 2);
+        assert_eq!(parse_oom_kills(""), 0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_cpu_time_limit_per_child() {
+        let own_limit = || {
+            let mut r = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            // # Safety
+            // A plain libc call, with a valid pointer.
+            assert_eq!(unsafe { libc::getrlimit(libc::RLIMIT_CPU, &raw mut r) }, 0);
+            (r.rlim_cur, r.rlim_max)
+        };
+        let before = own_limit();
+        let spin = || {
+            Command::new("sh")
+                .args(["-c", "while :; do :; done"])
+                .stdout(Stdio::null())
+                .spawn()
+                .unwrap()
+        };
+
+        // Like a forkserver, run more children than the limit allows in total
+        for _ in 0..3 {
+            let mut child = spin();
+            set_cpu_time_limit(child.id().try_into().unwrap(), Duration::from_secs(1)).unwrap();
+            thread::sleep(Duration::from_millis(600));
+            child.kill().unwrap();
+            assert_eq!(
+                child.wait().unwrap().signal(),
+                Some(libc::SIGKILL),
+                "The child was stopped before we killed it"
+            );
+        }
+        assert_eq!(own_limit(), before);
+
+        // A single child exceeding the limit is stopped
+        let mut child = spin();
+        set_cpu_time_limit(child.id().try_into().unwrap(), Duration::from_secs(1)).unwrap();
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGXCPU));
+    }
+}
@@ -5,10 +5,12 @@ use core::{fmt::Debug, time::Duration};
 #[cfg(feature = "std")]
 use std::path::PathBuf;
 
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub use breakpoint::BreakpointCoverage;
 pub use combined::CombinedExecutor;
 #[cfg(feature = "std")]
 pub use command::CommandExecutor;
-pub use differential::DiffExecutor;
+pub use differential::{DiffExecutor, MultiDiffExecutor};
 #[cfg(all(feature = "std", feature = "fork", unix))]
 pub use forkserver::{Forkserver, ForkserverExecutor};
 pub use inprocess::InProcessExecutor;
@@ -19,6 +21,10 @@ use libafl_bolts::os::unix_signals::Signal;
 use libafl_bolts::tuples::RefIndexable;
 #[cfg(feature = "std")]
 use libafl_bolts::{core_affinity::CoreId, tuples::Handle};
+#[cfg(feature = "std")]
+pub use limits::ChildLimits;
+#[cfg(all(feature = "std", feature = "fork", feature = "multipart_inputs", unix))]
+pub use network::{NetworkExecutor, NetworkProtocol};
 use serde::{Deserialize, Serialize};
 pub use shadow::ShadowExecutor;
 pub use with_observers::WithObservers;
@@ -27,6 +33,8 @@ use crate::Error;
 #[cfg(feature = "std")]
 use crate::observers::{StdErrObserver, StdOutObserver};
 
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub mod breakpoint;
 pub mod combined;
 #[cfg(feature = "std")]
 pub mod command;
@@ -34,6 +42,10 @@ pub mod differential;
 #[cfg(all(feature = "std", feature = "fork", unix))]
 pub mod forkserver;
 pub mod inprocess;
+#[cfg(feature = "std")]
+pub mod limits;
+#[cfg(all(feature = "std", feature = "fork", feature = "multipart_inputs", unix))]
+pub mod network;
 pub mod nop;
 /// SAND(<https://github.com/wtdcode/sand-aflpp>) implementation
 pub mod sand;
@@ -249,6 +261,8 @@ pub struct StdChildArgsInner {
     pub debug_child: bool,
     /// Core to bind for the children
     pub core: Option<CoreId>,
+    /// The resource limits of the children
+    pub limits: ChildLimits,
 }
 
 #[cfg(feature = "std")]
@@ -261,6 +275,7 @@ impl Default for StdChildArgsInner {
             current_directory: None,
             debug_child: false,
             core: None,
+            limits: ChildLimits::default(),
         }
     }
 }
@@ -322,6 +337,46 @@ pub trait StdChildArgs: Sized {
         self.inner_mut().core = Some(core);
         self
     }
+
+    #[must_use]
+    /// Limits the address space of the children, in bytes.
+    /// Allocations beyond it fail, which usually ends as a crash.
+    fn memory_limit(mut self, bytes: u64) -> Self {
+        self.inner_mut().limits.address_space = Some(bytes);
+        self
+    }
+
+    #[must_use]
+    /// Limits the cpu time of each child, children exceeding it are reported as timeouts.
+    /// For forkservers, the limit is set on each forked child, which is Linux only.
+    /// Not applied to persistent forkservers.
+    fn cpu_time_limit(mut self, cpu_time: Duration) -> Self {
+        self.inner_mut().limits.cpu_time = Some(cpu_time);
+        self
+    }
+
+    #[must_use]
+    /// Limits the size of the files written by the children, in bytes
+    fn file_size_limit(mut self, bytes: u64) -> Self {
+        self.inner_mut().limits.file_size = Some(bytes);
+        self
+    }
+
+    #[must_use]
+    /// Limits the number of files the children can open
+    fn open_files_limit(mut self, open_files: u64) -> Self {
+        self.inner_mut().limits.open_files = Some(open_files);
+        self
+    }
+
+    #[must_use]
+    /// Runs the children in a new cgroup v2 below `parent`, with a memory limit in bytes.
+    /// Children killed for exceeding it are reported as [`ExitKind::Oom`]. Linux only,
+    /// see [`limits`] for the setup of the parent.
+    fn cgroup_memory_limit(mut self, parent: PathBuf, bytes: u64) -> Self {
+        self.inner_mut().limits.cgroup_memory = Some((parent, bytes));
+        self
+    }
 }
 
 #[cfg(test)]
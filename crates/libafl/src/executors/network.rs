@@ -0,0 +1,402 @@
+//! The [`NetworkExecutor`] fuzzes network servers, such as daemons, over a local socket, without desocketing them.
+//!
+//! It starts the server with a [`ForkserverExecutor`], waits until it listens on a local TCP or UDP port,
+//! and sends the parts of a [`ListInput`] as messages, one `write` or packet each.
+//! The responses can be captured with a [`NetworkResponseObserver`].
+//! After the last message, the connection is closed, and the server gets the response timeout to exit on its own,
+//! else it is killed. Crashes are reported as usual, a server that doesn't listen within the timeout as [`ExitKind::Timeout`].
+//!
+//! The server should set `SO_REUSEADDR` on its socket, so each new child can bind the port again.
+//! With a deferred forkserver after `listen`, each child inherits the listening socket.
+
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Debug, Formatter},
+    net::{Ipv4Addr, SocketAddr},
+    ops::IndexMut,
+    time::Duration,
+};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpStream, UdpSocket},
+    time::Instant,
+};
+
+use libafl_bolts::{
+    AsSlice,
+    shmem::ShMem,
+    tuples::{Handle, RefIndexable},
+};
+use nix::sys::time::TimeSpec;
+
+use crate::{
+    Error,
+    executors::{Executor, ExitKind, HasObservers, HasTimeout, forkserver::ForkserverExecutor},
+    inputs::{ListInput, ToTargetBytes},
+    observers::{NetworkResponseObserver, ObserversTuple},
+    state::HasExecutions,
+};
+
+/// The default time to wait for the response to each message
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// How often to check if the server listens
+const LISTEN_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The largest response read at once
+const MAX_RESPONSE_SIZE: usize = 64 * 1024;
+
+/// The transport protocol of a [`NetworkExecutor`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkProtocol {
+    /// One TCP connection per execution
+    Tcp,
+    /// One UDP packet per message
+    Udp,
+}
+
+/// The connection to the server, for one execution
+enum Connection {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+impl Connection {
+    /// Send a message, and read the response, or an empty response after `timeout`.
+    ///
+    /// Returns `None` if the server closed the connection.
+    fn exchange(&mut self, msg: &[u8], timeout: Duration) -> Result<Option<Vec<u8>>, Error> {
+        let mut buf = vec![0; MAX_RESPONSE_SIZE];
+        let read = match self {
+            Self::Tcp(stream) => {
+                if let Err(err) = stream.write_all(msg) {
+                    return closed_or_error(err);
+                }
+                stream.set_read_timeout(Some(timeout))?;
+                stream.read(&mut buf)
+            }
+            Self::Udp(socket) => {
+                if let Err(err) = socket.send(msg) {
+                    return closed_or_error(err);
+                }
+                socket.set_read_timeout(Some(timeout))?;
+                socket.recv(&mut buf)
+            }
+        };
+        match read {
+            // A closed TCP connection, UDP packets can be empty
+            Ok(0) if matches!(self, Self::Tcp(_)) => Ok(None),
+            Ok(len) => {
+                buf.truncate(len);
+                Ok(Some(buf))
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                Ok(Some(vec![]))
+            }
+            Err(err) => closed_or_error(err),
+        }
+    }
+}
+
+/// Map errors of a connection closed by the server to `None`
+fn closed_or_error<T>(err: io::Error) -> Result<Option<T>, Error> {
+    match err.kind() {
+        ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::ConnectionRefused => {
+            Ok(None)
+        }
+        _ => Err(err.into()),
+    }
+}
+
+/// Check if a UDP socket is bound to this local port, in `/proc/net/udp{,6}`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn udp_port_bound(port: u16) -> Result<bool, Error> {
+    let local_port = format!("{port:04X}");
+    for table in ["/proc/net/udp", "/proc/net/udp6"] {
+        let content = match std::fs::read_to_string(table) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        // The second column is the local address, as `ADDR:PORT`
+        if content
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().nth(1))
+            .any(|local| local.rsplit(':').next() == Some(local_port.as_str()))
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Without `/proc`, we can't see if a UDP port is bound, so we assume it is
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+#[expect(clippy::unnecessary_wraps)]
+fn udp_port_bound(_port: u16) -> Result<bool, Error> {
+    Ok(true)
+}
+
+/// An [`Executor`] for network servers, sending each part of a [`ListInput`] as message to a local port.
+/// See the [module docs](self).
+pub struct NetworkExecutor<M, OT, S, SHM> {
+    executor: ForkserverExecutor<ListInput<M>, OT, S, SHM>,
+    protocol: NetworkProtocol,
+    port: u16,
+    response_timeout: Duration,
+    response_observer: Option<Handle<NetworkResponseObserver>>,
+}
+
+impl<M, OT, S, SHM> Debug for NetworkExecutor<M, OT, S, SHM>
+where
+    OT: Debug,
+    SHM: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetworkExecutor")
+            .field("executor", &self.executor)
+            .field("protocol", &self.protocol)
+            .field("port", &self.port)
+            .field("response_timeout", &self.response_timeout)
+            .field("response_observer", &self.response_observer)
+            .finish()
+    }
+}
+
+impl<M, OT, S, SHM> NetworkExecutor<M, OT, S, SHM> {
+    /// Create a new [`NetworkExecutor`], starting the server with the `executor`,
+    /// and sending messages to the local `port`.
+    ///
+    /// The timeout of the `executor` bounds the startup of the server and the whole exchange.
+    pub fn new(
+        executor: ForkserverExecutor<ListInput<M>, OT, S, SHM>,
+        protocol: NetworkProtocol,
+        port: u16,
+    ) -> Self {
+        Self {
+            executor,
+            protocol,
+            port,
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+            response_observer: None,
+        }
+    }
+
+    /// Set the time to wait for the response to each message,
+    /// and for the server to exit after the last message
+    #[must_use]
+    pub fn with_response_timeout(mut self, response_timeout: Duration) -> Self {
+        self.response_timeout = response_timeout;
+        self
+    }
+
+    /// Capture the responses in this [`NetworkResponseObserver`], which must be one of the observers of the `executor`
+    #[must_use]
+    pub fn with_response_observer(mut self, observer: Handle<NetworkResponseObserver>) -> Self {
+        self.response_observer = Some(observer);
+        self
+    }
+
+    /// The inner [`ForkserverExecutor`]
+    pub fn executor(&self) -> &ForkserverExecutor<ListInput<M>, OT, S, SHM> {
+        &self.executor
+    }
+
+    /// The inner [`ForkserverExecutor`] (mutable)
+    pub fn executor_mut(&mut self) -> &mut ForkserverExecutor<ListInput<M>, OT, S, SHM> {
+        &mut self.executor
+    }
+
+    fn server_addr(&self) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::LOCALHOST, self.port))
+    }
+}
+
+impl<M, OT, S, SHM> NetworkExecutor<M, OT, S, SHM>
+where
+    OT: ObserversTuple<ListInput<M>, S>,
+    SHM: ShMem,
+{
+    /// Connect to the freshly started server, until `deadline`.
+    ///
+    /// Returns the [`ExitKind`] instead, if the server exited, or didn't listen in time.
+    fn connect(&mut self, deadline: Instant) -> Result<Result<Connection, ExitKind>, Error> {
+        let addr = self.server_addr();
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(Err(self.executor.kill_child()?));
+            }
+            match self.protocol {
+                NetworkProtocol::Tcp => {
+                    match TcpStream::connect_timeout(&addr, deadline.saturating_duration_since(now))
+                    {
+                        Ok(stream) => {
+                            stream.set_nodelay(true)?;
+                            return Ok(Ok(Connection::Tcp(stream)));
+                        }
+                        Err(err) => log::trace!("Server on {addr} doesn't accept yet: {err}"),
+                    }
+                }
+                NetworkProtocol::Udp => {
+                    if udp_port_bound(self.port)? {
+                        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+                        socket.connect(addr)?;
+                        return Ok(Ok(Connection::Udp(socket)));
+                    }
+                }
+            }
+            // Sleep until the next try, unless the server exited
+            let poll = TimeSpec::from_duration(LISTEN_POLL_INTERVAL);
+            if let Some(exit_kind) = self.executor.try_wait_for_child(&poll)? {
+                log::debug!("The server exited before listening on {addr}");
+                return Ok(Err(exit_kind));
+            }
+        }
+    }
+
+    /// Run the server, and send the messages
+    fn execute<Z>(
+        &mut self,
+        fuzzer: &mut Z,
+        input: &ListInput<M>,
+        responses: &mut Vec<Vec<u8>>,
+    ) -> Result<ExitKind, Error>
+    where
+        Z: ToTargetBytes<M>,
+    {
+        let deadline = Instant::now() + self.executor.timeout();
+        self.executor.spawn_child()?;
+
+        let mut connection = match self.connect(deadline)? {
+            Ok(connection) => connection,
+            Err(exit_kind) => return Ok(exit_kind),
+        };
+        for part in input.parts() {
+            if Instant::now() >= deadline {
+                return self.executor.kill_child();
+            }
+            let msg = fuzzer.to_target_bytes(part);
+            match connection.exchange(msg.as_slice(), self.response_timeout)? {
+                Some(response) => responses.push(response),
+                None => break,
+            }
+        }
+        drop(connection);
+
+        // Give the server the chance to exit, or crash, on its own
+        let grace = TimeSpec::from_duration(self.response_timeout);
+        if let Some(exit_kind) = self.executor.try_wait_for_child(&grace)? {
+            Ok(exit_kind)
+        } else {
+            self.executor.kill_child()?;
+            Ok(ExitKind::Ok)
+        }
+    }
+}
+
+impl<EM, M, OT, S, SHM, Z> Executor<EM, ListInput<M>, S, Z> for NetworkExecutor<M, OT, S, SHM>
+where
+    OT: ObserversTuple<ListInput<M>, S>,
+    S: HasExecutions,
+    SHM: ShMem,
+    Z: ToTargetBytes<M>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut S,
+        _mgr: &mut EM,
+        input: &ListInput<M>,
+    ) -> Result<ExitKind, Error> {
+        *state.executions_mut() += 1;
+        self.observers_mut().pre_exec_child_all(state, input)?;
+
+        let mut responses = vec![];
+        let exit_kind = self.execute(fuzzer, input, &mut responses)?;
+
+        if let Some(handle) = self.response_observer.clone() {
+            self.observers_mut().index_mut(&handle).observe(responses)?;
+        }
+        self.observers_mut()
+            .post_exec_child_all(state, input, &exit_kind)?;
+        Ok(exit_kind)
+    }
+}
+
+impl<M, OT, S, SHM> HasTimeout for NetworkExecutor<M, OT, S, SHM> {
+    #[inline]
+    fn timeout(&self) -> Duration {
+        self.executor.timeout()
+    }
+
+    #[inline]
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.executor.set_timeout(timeout);
+    }
+}
+
+impl<M, OT, S, SHM> HasObservers for NetworkExecutor<M, OT, S, SHM>
+where
+    OT: ObserversTuple<ListInput<M>, S>,
+{
+    type Observers = OT;
+
+    #[inline]
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        self.executor.observers()
+    }
+
+    #[inline]
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        self.executor.observers_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream, UdpSocket},
+        thread,
+    };
+
+    use super::Connection;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_tcp_exchange() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 5];
+            stream.read_exact(&mut buf).unwrap();
+            stream.write_all(b"pong").unwrap();
+            // No response to the second message, then close
+            stream.read_exact(&mut buf).unwrap();
+        });
+
+        let mut connection = Connection::Tcp(TcpStream::connect(addr).unwrap());
+        let timeout = Duration::from_millis(200);
+        assert_eq!(
+            connection.exchange(b"ping1", timeout).unwrap(),
+            Some(b"pong".to_vec())
+        );
+        assert_eq!(connection.exchange(b"ping2", timeout).unwrap(), None);
+        server.join().unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_udp_port_bound() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+        assert!(super::udp_port_bound(port).unwrap());
+        drop(socket);
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        assert!(!super::udp_port_bound(port).unwrap());
+    }
+}
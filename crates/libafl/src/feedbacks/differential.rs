@@ -1,10 +1,12 @@
 //! Diff Feedback, comparing the content of two observers of the same type.
+//!
+//! The [`MultiDiffFeedback`] compares any number of observers, and reports the ones outvoted by the majority.
 
-use alloc::borrow::Cow;
+use alloc::{borrow::Cow, vec::Vec};
 use core::fmt::{self, Debug, Formatter};
 
 use libafl_bolts::{
-    Named,
+    Named, impl_serdeany,
     tuples::{Handle, Handled, MatchName, MatchNameRef},
 };
 use serde::{Deserialize, Serialize};
@@ -12,7 +14,8 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "track_hit_feedbacks")]
 use crate::feedbacks::premature_last_result_err;
 use crate::{
-    Error,
+    Error, HasMetadata,
+    corpus::Testcase,
     executors::ExitKind,
     feedbacks::{Feedback, FeedbackFactory, StateInitializer},
 };
@@ -175,6 +178,200 @@ where
     }
 }
 
+/// The outcome of a vote of a [`MultiDiffFeedback`], as indexes of its observers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultiDiffMetadata {
+    /// The observers agreeing with the majority
+    pub majority: Vec<usize>,
+    /// The observers outvoted by the majority
+    pub outvoted: Vec<usize>,
+}
+
+impl_serdeany!(MultiDiffMetadata);
+
+/// A [`MultiDiffFeedback`] compares the content of any number of observers of the same type,
+/// such as the outputs of different implementations, run by a [`crate::executors::MultiDiffExecutor`].
+///
+/// The observers are grouped by the comparator, which should be transitive, and the largest group is the majority.
+/// On ties, the group of the first observer wins. Inputs where any observer is outvoted are interesting,
+/// and get a [`MultiDiffMetadata`] naming the outvoted observers.
+#[derive(Serialize, Deserialize)]
+pub struct MultiDiffFeedback<C, O> {
+    /// This feedback's name
+    name: Cow<'static, str>,
+    /// The observers to compare
+    o_refs: Vec<Handle<O>>,
+    /// The outcome of the last vote
+    last_vote: MultiDiffMetadata,
+    // The previous run's result of `Self::is_interesting`
+    #[cfg(feature = "track_hit_feedbacks")]
+    last_result: Option<bool>,
+    /// The comparator used to compare two observers
+    comparator: C,
+}
+
+impl<C, O> MultiDiffFeedback<C, O>
+where
+    O: Named,
+{
+    /// Create a new [`MultiDiffFeedback`] using at least two observers and a test function.
+    pub fn new(name: &'static str, observers: &[&O], comparator: C) -> Result<Self, Error> {
+        let o_refs: Vec<Handle<O>> = observers.iter().map(|o| o.handle()).collect();
+        if o_refs.len() < 2 {
+            return Err(Error::illegal_argument(
+                "MultiDiffFeedback: needs at least two observers",
+            ));
+        }
+        for (i, o_ref) in o_refs.iter().enumerate() {
+            if o_refs[..i].iter().any(|other| other.name() == o_ref.name()) {
+                return Err(Error::illegal_argument(format!(
+                    "MultiDiffFeedback: observer names must be different ({} is used twice)",
+                    o_ref.name()
+                )));
+            }
+        }
+        Ok(Self {
+            name: Cow::from(name),
+            o_refs,
+            last_vote: MultiDiffMetadata::default(),
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+            comparator,
+        })
+    }
+}
+
+impl<C, O> MultiDiffFeedback<C, O> {
+    /// The outcome of the last vote
+    #[must_use]
+    pub fn last_vote(&self) -> &MultiDiffMetadata {
+        &self.last_vote
+    }
+}
+
+/// Group the observers by the comparator, and split them into the largest group and the rest
+fn vote<C, O>(comparator: &mut C, observers: &[&O]) -> MultiDiffMetadata
+where
+    C: DiffComparator<O, O>,
+{
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for (idx, observer) in observers.iter().enumerate() {
+        match groups.iter_mut().find(|group| {
+            comparator
+                .compare(observers[group[0]], *observer)
+                .is_equal()
+        }) {
+            Some(group) => group.push(idx),
+            None => groups.push(vec![idx]),
+        }
+    }
+    // The first largest group wins
+    let winner = groups
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, group)| group.len())
+        .map_or(0, |(i, _)| i);
+    let mut vote = MultiDiffMetadata::default();
+    for (i, group) in groups.into_iter().enumerate() {
+        if i == winner {
+            vote.majority = group;
+        } else {
+            vote.outvoted.extend(group);
+        }
+    }
+    vote.outvoted.sort_unstable();
+    vote
+}
+
+impl<C, O, T> FeedbackFactory<MultiDiffFeedback<C, O>, T> for MultiDiffFeedback<C, O>
+where
+    C: Clone,
+{
+    fn create_feedback(&self, _ctx: &T) -> MultiDiffFeedback<C, O> {
+        Self {
+            name: self.name.clone(),
+            o_refs: self.o_refs.clone(),
+            last_vote: MultiDiffMetadata::default(),
+            comparator: self.comparator.clone(),
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+}
+
+impl<C, O> Named for MultiDiffFeedback<C, O> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<C, O> Debug for MultiDiffFeedback<C, O>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiDiffFeedback")
+            .field("name", self.name())
+            .field("observers", &self.o_refs)
+            .field("last_vote", &self.last_vote)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C, O, S> StateInitializer<S> for MultiDiffFeedback<C, O> {}
+
+impl<C, EM, I, O, OT, S> Feedback<EM, I, OT, S> for MultiDiffFeedback<C, O>
+where
+    OT: MatchName,
+    C: DiffComparator<O, O>,
+{
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observers = self
+            .o_refs
+            .iter()
+            .map(|o_ref| {
+                observers.get(o_ref).ok_or_else(|| {
+                    Error::illegal_argument(format!(
+                        "MultiDiffFeedback: observer {} not found",
+                        o_ref.name()
+                    ))
+                })
+            })
+            .collect::<Result<Vec<&O>, Error>>()?;
+        self.last_vote = vote(&mut self.comparator, &observers);
+        let res = !self.last_vote.outvoted.is_empty();
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        testcase.add_metadata(self.last_vote.clone());
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use alloc::borrow::Cow;
@@ -184,7 +381,10 @@ mod tests {
     use crate::{
         events::NopEventManager,
         executors::ExitKind,
-        feedbacks::{DiffFeedback, Feedback, differential::DiffResult},
+        feedbacks::{
+            DiffFeedback, Feedback, MultiDiffFeedback,
+            differential::{DiffResult, MultiDiffMetadata},
+        },
         inputs::BytesInput,
         observers::Observer,
         state::NopState,
@@ -254,4 +454,36 @@ mod tests {
     fn test_diff_neq() {
         test_diff(false);
     }
+
+    #[test]
+    fn test_multi_diff() {
+        let mut nop_state: NopState<BytesInput> = NopState::new();
+
+        let o1 = DummyObserver::new("o1", true);
+        let o2 = DummyObserver::new("o2", true);
+        let o3 = DummyObserver::new("o3", false);
+
+        assert!(MultiDiffFeedback::new("multi_diff", &[&o1, &o1], comparator).is_err());
+        let mut feedback =
+            MultiDiffFeedback::new("multi_diff", &[&o1, &o2, &o3], comparator).unwrap();
+        let observers = tuple_list![o1, o2, o3];
+        assert!(
+            MultiDiffFeedback::<_, _>::is_interesting(
+                &mut feedback,
+                &mut nop_state,
+                &mut NopEventManager::default(),
+                &BytesInput::new(vec![0]),
+                &observers,
+                &ExitKind::Ok
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            feedback.last_vote(),
+            &MultiDiffMetadata {
+                majority: vec![0, 1],
+                outvoted: vec![2],
+            }
+        );
+    }
 }
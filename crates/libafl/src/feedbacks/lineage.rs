@@ -0,0 +1,88 @@
+//! The [`LineageFeedback`] annotates each new testcase with the testcase it was derived from.
+//!
+//! The parent is identified by the hash of its input, so the lineage can be followed across clients,
+//! for example in a recorded event log, if the [`LineageMetadata`] is shared
+//! with a [`crate::events::MetadataSharing`] allow-list.
+//!
+//! Only testcases found while a stage fuzzes a corpus entry have a parent. Seeds, and inputs imported from
+//! other clients or sync directories, get no [`LineageMetadata`], unless it is shared by the client that found them.
+
+use alloc::borrow::Cow;
+use core::hash::Hash;
+
+use libafl_bolts::{Named, generic_hash_std, impl_serdeany};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{Corpus, HasCurrentCorpusId, Testcase},
+    events::ShareableMetadata,
+    feedbacks::{Feedback, StateInitializer},
+    state::{HasCorpus, HasCurrentStageId},
+};
+
+/// The parent of a testcase, as hash of its input, see [`LineageFeedback`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineageMetadata {
+    /// The hash of the input of the testcase this one was derived from
+    pub parent: u64,
+}
+
+impl_serdeany!(LineageMetadata);
+
+impl ShareableMetadata for LineageMetadata {
+    const SHARED_NAME: &'static str = "lineage";
+}
+
+/// Nop feedback that annotates new testcases with their [`LineageMetadata`].
+/// The testcase is never interesting (use with an OR).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LineageFeedback;
+
+impl LineageFeedback {
+    /// Creates a new [`LineageFeedback`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> StateInitializer<S> for LineageFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for LineageFeedback
+where
+    I: Clone + Hash,
+    S: HasCorpus<I> + HasCurrentCorpusId + HasCurrentStageId,
+{
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        // The testcase currently being fuzzed is the parent.
+        // Events are processed outside of the stages, and sync stages hide the current corpus id while importing.
+        if state.current_stage_id()?.is_none() {
+            return Ok(());
+        }
+        if let Some(id) = state.current_corpus_id()? {
+            let parent = generic_hash_std(&state.corpus().cloned_input_for_id(id)?);
+            testcase.add_metadata(LineageMetadata { parent });
+        }
+        Ok(())
+    }
+}
+
+impl Named for LineageFeedback {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("LineageFeedback");
+        &NAME
+    }
+}
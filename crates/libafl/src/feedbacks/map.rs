@@ -28,7 +28,7 @@ use crate::feedbacks::premature_last_result_err;
 use crate::{
     Error, HasMetadata, HasNamedMetadata,
     corpus::Testcase,
-    events::{Event, EventFirer, EventWithStats},
+    events::{Event, EventFirer, EventWithStats, ShareableMetadata},
     executors::ExitKind,
     feedbacks::{Feedback, HasObserverHandle, StateInitializer},
     monitors::stats::{AggregatorOps, UserStats, UserStatsValue},
@@ -166,6 +166,10 @@ pub struct MapIndexesMetadata {
 
 libafl_bolts::impl_serdeany!(MapIndexesMetadata);
 
+impl ShareableMetadata for MapIndexesMetadata {
+    const SHARED_NAME: &'static str = "map_indexes";
+}
+
 impl Deref for MapIndexesMetadata {
     type Target = [usize];
     /// Convert to a slice
@@ -209,6 +213,10 @@ pub struct MapNoveltiesMetadata {
 
 libafl_bolts::impl_serdeany!(MapNoveltiesMetadata);
 
+impl ShareableMetadata for MapNoveltiesMetadata {
+    const SHARED_NAME: &'static str = "map_novelties";
+}
+
 impl Deref for MapNoveltiesMetadata {
     type Target = [usize];
     /// Convert to a slice
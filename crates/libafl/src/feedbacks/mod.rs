@@ -10,7 +10,7 @@ use core::{fmt::Debug, marker::PhantomData};
 
 #[cfg(feature = "std")]
 pub use concolic::ConcolicFeedback;
-pub use differential::DiffFeedback;
+pub use differential::{DiffFeedback, MultiDiffFeedback};
 use libafl_bolts::{
     Named,
     tuples::{Handle, Handled, MatchName, MatchNameRef},
@@ -39,6 +39,7 @@ pub mod concolic;
 /// The module for `CustomFilenameToTestcaseFeedback`
 pub mod custom_filename;
 pub mod differential;
+pub mod lineage;
 /// The module for list feedback
 pub mod list;
 pub mod map;
@@ -46,11 +47,19 @@ pub mod map;
 pub mod nautilus;
 #[cfg(feature = "std")]
 pub mod new_hash_feedback;
+#[cfg(feature = "std")]
+pub mod protocol_state;
+#[cfg(feature = "std")]
+pub use protocol_state::{ProtocolStateFeedback, ProtocolStateGraph, ProtocolStatesMetadata};
 #[cfg(feature = "simd")]
 pub mod simd;
 #[cfg(feature = "std")]
 pub mod stdio;
 pub mod transferred;
+#[cfg(feature = "regex")]
+pub mod triage;
+#[cfg(feature = "regex")]
+pub use triage::{CrashBucketMetadata, CrashTriageFeedback, CrashTriageMetadata};
 
 #[cfg(feature = "std")]
 pub use capture_feedback::CaptureTimeoutFeedback;
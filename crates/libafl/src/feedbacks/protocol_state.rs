@@ -0,0 +1,244 @@
+//! The [`ProtocolStateFeedback`] infers the state machine of a network protocol from the responses of the server,
+//! and keeps testcases that take new state transitions, like `AFLNet`.
+//!
+//! The states come from a [`NetworkResponseObserver`] with a [`crate::observers::ResponseStateExtractor`].
+//! The inferred [`ProtocolStateGraph`] is kept in the state metadata,
+//! and each new testcase gets a [`ProtocolStatesMetadata`] with the states it reaches.
+//! The [`crate::schedulers::ProtocolStateScheduler`] uses both to prioritize rarely visited states.
+
+use alloc::{borrow::Cow, vec::Vec};
+
+use hashbrown::HashMap;
+use libafl_bolts::{
+    Named, impl_serdeany,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "track_hit_feedbacks")]
+use crate::feedbacks::premature_last_result_err;
+use crate::{
+    Error, HasMetadata,
+    corpus::{CorpusId, Testcase},
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle, StateInitializer},
+    observers::NetworkResponseObserver,
+};
+
+/// The state of the server before the first response
+pub const PROTOCOL_STATE_INITIAL: u64 = 0;
+
+/// The inferred state graph of a network protocol, see the [module docs](self)
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ProtocolStateGraph {
+    /// The number of times each state was reached, over all executions
+    visits: HashMap<u64, u64>,
+    /// The number of times each transition was taken, as `from -> to -> count`
+    transitions: HashMap<u64, HashMap<u64, u64>>,
+    /// The testcases reaching each state, maintained by the `ProtocolStateScheduler`
+    testcases: HashMap<u64, Vec<CorpusId>>,
+}
+
+impl_serdeany!(ProtocolStateGraph);
+
+impl ProtocolStateGraph {
+    /// Create a new, empty, [`ProtocolStateGraph`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the states reached by an execution, in order, starting from [`PROTOCOL_STATE_INITIAL`].
+    ///
+    /// Returns `true` if the execution took a transition not seen before.
+    pub fn record(&mut self, states: &[u64]) -> bool {
+        let mut novel = false;
+        let mut from = PROTOCOL_STATE_INITIAL;
+        for &to in states {
+            *self.visits.entry(to).or_default() += 1;
+            let count = self
+                .transitions
+                .entry(from)
+                .or_default()
+                .entry(to)
+                .or_default();
+            novel |= *count == 0;
+            *count += 1;
+            from = to;
+        }
+        novel
+    }
+
+    /// The number of times this state was reached
+    #[must_use]
+    pub fn visits(&self, state: u64) -> u64 {
+        self.visits.get(&state).copied().unwrap_or_default()
+    }
+
+    /// All states reached so far, with their number of visits
+    pub fn states(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.visits.iter().map(|(state, visits)| (*state, *visits))
+    }
+
+    /// All transitions taken so far, as `(from, to, count)`
+    pub fn transitions(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.transitions
+            .iter()
+            .flat_map(|(from, targets)| targets.iter().map(move |(to, count)| (*from, *to, *count)))
+    }
+
+    /// The testcases reaching this state
+    #[must_use]
+    pub fn testcases(&self, state: u64) -> &[CorpusId] {
+        self.testcases.get(&state).map_or(&[], Vec::as_slice)
+    }
+
+    /// Register a testcase reaching these states
+    pub fn add_testcase(&mut self, id: CorpusId, states: &[u64]) {
+        for state in states {
+            let ids = self.testcases.entry(*state).or_default();
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    /// Forget a testcase, when it is removed from the corpus
+    pub fn remove_testcase(&mut self, id: CorpusId) {
+        for ids in self.testcases.values_mut() {
+            ids.retain(|other| *other != id);
+        }
+    }
+}
+
+/// The protocol states a testcase reaches, sorted, see [`ProtocolStateFeedback`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolStatesMetadata {
+    /// The states
+    pub states: Vec<u64>,
+}
+
+impl_serdeany!(ProtocolStatesMetadata);
+
+/// A feedback keeping testcases that take new transitions in the [`ProtocolStateGraph`], see the [module docs](self)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolStateFeedback {
+    o_ref: Handle<NetworkResponseObserver>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    // The previous run's result of `Self::is_interesting`
+    last_result: Option<bool>,
+}
+
+impl ProtocolStateFeedback {
+    /// Creates a new [`ProtocolStateFeedback`] for the states of this observer
+    #[must_use]
+    pub fn new(observer: &NetworkResponseObserver) -> Self {
+        Self {
+            o_ref: observer.handle(),
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+}
+
+impl<S> StateInitializer<S> for ProtocolStateFeedback
+where
+    S: HasMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.metadata_or_insert_with(ProtocolStateGraph::new);
+        Ok(())
+    }
+}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for ProtocolStateFeedback
+where
+    OT: MatchName,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = observers
+            .get(&self.o_ref)
+            .ok_or(Error::illegal_state("NetworkResponseObserver is missing"))?;
+        let res = state
+            .metadata_or_insert_with(ProtocolStateGraph::new)
+            .record(&observer.states);
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let observer = observers
+            .get(&self.o_ref)
+            .ok_or(Error::illegal_state("NetworkResponseObserver is missing"))?;
+        let mut states = observer.states.clone();
+        states.sort_unstable();
+        states.dedup();
+        testcase.add_metadata(ProtocolStatesMetadata { states });
+        Ok(())
+    }
+}
+
+impl Named for ProtocolStateFeedback {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtocolStateFeedback");
+        &NAME
+    }
+}
+
+impl HasObserverHandle for ProtocolStateFeedback {
+    type Observer = NetworkResponseObserver;
+
+    #[inline]
+    fn observer_handle(&self) -> &Handle<NetworkResponseObserver> {
+        &self.o_ref
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProtocolStateGraph;
+    use crate::corpus::CorpusId;
+
+    #[test]
+    fn test_protocol_state_graph() {
+        let mut graph = ProtocolStateGraph::new();
+        assert!(graph.record(&[220, 331, 230]));
+        assert!(!graph.record(&[220, 331]));
+        // A new transition between known states
+        assert!(graph.record(&[220, 230]));
+        assert!(!graph.record(&[]));
+        assert_eq!(graph.visits(220), 3);
+        assert_eq!(graph.visits(500), 0);
+        assert_eq!(graph.transitions().count(), 4);
+
+        graph.add_testcase(CorpusId(0), &[220, 331]);
+        graph.add_testcase(CorpusId(1), &[220]);
+        assert_eq!(graph.testcases(220), [CorpusId(0), CorpusId(1)]);
+        graph.remove_testcase(CorpusId(0));
+        assert_eq!(graph.testcases(220), [CorpusId(1)]);
+        assert!(graph.testcases(331).is_empty());
+    }
+}
@@ -0,0 +1,386 @@
+//! The [`CrashTriageFeedback`] buckets crashes by the top frames of their symbolized stacktrace.
+//!
+//! Every bucket gets its own directory, containing the smallest input seen for it so far
+//! and a `report.json` with the bug class, the frames and the number of hits.
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::time::Duration;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use hashbrown::HashMap;
+use libafl_bolts::{
+    HasLen, Named, current_time, generic_hash_std,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "track_hit_feedbacks")]
+use crate::feedbacks::premature_last_result_err;
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::Testcase,
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle, StateInitializer},
+    inputs::Input,
+    observers::{ObserverWithStacktrace, StackFrame},
+};
+
+/// The prefix of the metadata names
+pub const CRASH_TRIAGE_PREFIX: &str = "crashtriage_metadata_";
+
+/// The default number of frames used to tell buckets apart
+pub const DEFAULT_TRIAGE_FRAMES: usize = 5;
+
+/// The file name of the smallest input seen so far in a bucket directory
+pub const BUCKET_INPUT_FILE: &str = "input";
+
+/// The file name of the report in a bucket directory
+pub const BUCKET_REPORT_FILE: &str = "report.json";
+
+/// A group of crashes sharing the same top stack frames
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrashBucket {
+    /// The id of this bucket, a hash over its frames
+    pub id: u64,
+    /// The bug class of the first crash in this bucket, e.g. `heap-buffer-overflow`
+    pub bug_class: String,
+    /// The top (non-internal) frames shared by all crashes in this bucket
+    pub frames: Vec<StackFrame>,
+    /// How often a crash in this bucket was hit
+    pub hits: u64,
+    /// The length of the smallest input seen so far for this bucket
+    pub input_len: usize,
+    /// When this bucket was first seen
+    pub first_seen: Duration,
+    /// When this bucket was last hit
+    pub last_seen: Duration,
+}
+
+/// The state of [`CrashTriageFeedback`], holding all buckets found so far
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+#[expect(clippy::unsafe_derive_deserialize)]
+pub struct CrashTriageMetadata {
+    buckets: HashMap<u64, CrashBucket>,
+}
+
+libafl_bolts::impl_serdeany!(CrashTriageMetadata);
+
+impl CrashTriageMetadata {
+    /// Create a new [`CrashTriageMetadata`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All buckets found so far, by id
+    #[must_use]
+    pub fn buckets(&self) -> &HashMap<u64, CrashBucket> {
+        &self.buckets
+    }
+}
+
+/// Testcase metadata recording the bucket a solution belongs to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct CrashBucketMetadata {
+    /// The id of the [`CrashBucket`]
+    pub bucket: u64,
+    /// The bug class of the crash
+    pub bug_class: String,
+}
+
+libafl_bolts::impl_serdeany!(CrashBucketMetadata);
+
+/// A [`CrashTriageFeedback`] buckets crashes by the top `N` frames of their stacktrace,
+/// and considers a crash interesting only if it opens a new bucket.
+///
+/// For each bucket, the smallest input seen so far and a JSON report are kept
+/// in `<output_dir>/<bucket id>/`.
+/// Only [`ExitKind::Crash`] is triaged, combine it with a `TimeoutFeedback` to also keep timeouts.
+/// Crashes of unknown bug class are classified as `crash`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrashTriageFeedback<O> {
+    name: Cow<'static, str>,
+    o_ref: Handle<O>,
+    output_dir: PathBuf,
+    top_frames: usize,
+    /// The bucket of the last crash, to be added to the testcase
+    last_bucket: Option<CrashBucketMetadata>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    // The previous run's result of `Self::is_interesting`
+    last_result: Option<bool>,
+}
+
+impl<O> CrashTriageFeedback<O>
+where
+    O: Named,
+{
+    /// Returns a new [`CrashTriageFeedback`], writing buckets to `output_dir`.
+    #[must_use]
+    pub fn new<P>(observer: &O, output_dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self::with_top_frames(observer, output_dir, DEFAULT_TRIAGE_FRAMES)
+    }
+
+    /// Returns a new [`CrashTriageFeedback`] that buckets by the given number of top frames.
+    #[must_use]
+    pub fn with_top_frames<P>(observer: &O, output_dir: P, top_frames: usize) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            name: Cow::from(CRASH_TRIAGE_PREFIX.to_string() + observer.name()),
+            o_ref: observer.handle(),
+            output_dir: output_dir.into(),
+            top_frames,
+            last_bucket: None,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+
+    /// The directory the buckets are written to
+    #[must_use]
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+}
+
+impl<O> CrashTriageFeedback<O> {
+    /// Writes the smallest input seen so far and the report of a bucket
+    fn write_bucket<I>(&self, bucket: &CrashBucket, input: Option<&I>) -> Result<(), Error>
+    where
+        I: Input,
+    {
+        let dir = self.output_dir.join(format!("{:016x}", bucket.id));
+        fs::create_dir_all(&dir)?;
+        if let Some(input) = input {
+            input.to_file(dir.join(BUCKET_INPUT_FILE))?;
+        }
+        let report = serde_json::to_string_pretty(bucket)
+            .map_err(|err| Error::serialize(format!("Failed to json-ify crash report: {err:?}")))?;
+        fs::write(dir.join(BUCKET_REPORT_FILE), report)?;
+        Ok(())
+    }
+}
+
+/// Computes the bucket id from the identities of the given frames.
+/// Symbolized frames are identified by function name, as addresses change with ASLR.
+fn bucket_id(frames: &[StackFrame]) -> u64 {
+    let identities: Vec<Result<&str, u64>> = frames
+        .iter()
+        .map(|frame| frame.function.as_deref().ok_or(frame.address))
+        .collect();
+    generic_hash_std(&identities)
+}
+
+impl<O, S> StateInitializer<S> for CrashTriageFeedback<O>
+where
+    S: HasNamedMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.add_named_metadata_checked(&self.name, CrashTriageMetadata::new())?;
+        Ok(())
+    }
+}
+
+impl<O, EM, I, OT, S> Feedback<EM, I, OT, S> for CrashTriageFeedback<O>
+where
+    O: ObserverWithStacktrace,
+    OT: MatchName,
+    I: Input + HasLen,
+    S: HasNamedMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        input: &I,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        self.last_bucket = None;
+        if *exit_kind != ExitKind::Crash {
+            #[cfg(feature = "track_hit_feedbacks")]
+            {
+                self.last_result = Some(false);
+            }
+            return Ok(false);
+        }
+
+        let observer = observers
+            .get(&self.o_ref)
+            .expect("A CrashTriageFeedback needs an ObserverWithStacktrace");
+        let frames: Vec<StackFrame> = observer
+            .frames()
+            .iter()
+            .filter(|frame| !frame.is_internal())
+            .take(self.top_frames)
+            .cloned()
+            .collect();
+        // Without frames, fall back to the hash of the observer
+        let id = if frames.is_empty() {
+            observer.hash().unwrap_or_default()
+        } else {
+            bucket_id(&frames)
+        };
+        let bug_class = observer
+            .bug_class()
+            .map_or_else(|| "crash".to_string(), ToString::to_string);
+
+        let now = current_time();
+        let triage = state
+            .named_metadata_map_mut()
+            .get_mut::<CrashTriageMetadata>(&self.name)
+            .unwrap();
+        let is_new = !triage.buckets.contains_key(&id);
+        let bucket = triage.buckets.entry(id).or_insert_with(|| CrashBucket {
+            id,
+            bug_class: bug_class.clone(),
+            frames,
+            hits: 0,
+            input_len: usize::MAX,
+            first_seen: now,
+            last_seen: now,
+        });
+        bucket.hits += 1;
+        bucket.last_seen = now;
+        let smaller = input.len() < bucket.input_len;
+        if smaller {
+            bucket.input_len = input.len();
+        }
+        let bucket = bucket.clone();
+
+        self.write_bucket(&bucket, smaller.then_some(input))?;
+        self.last_bucket = Some(CrashBucketMetadata {
+            bucket: id,
+            bug_class,
+        });
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(is_new);
+        }
+        Ok(is_new)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        if let Some(meta) = self.last_bucket.take() {
+            testcase.add_metadata(meta);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+}
+
+impl<O> Named for CrashTriageFeedback<O> {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<O> HasObserverHandle for CrashTriageFeedback<O> {
+    type Observer = O;
+
+    #[inline]
+    fn observer_handle(&self) -> &Handle<O> {
+        &self.o_ref
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::bucket_id;
+    use crate::observers::{
+        AsanBacktraceObserver, ObserverWithStacktrace, StackFrame, bug_class_of_frames,
+    };
+
+    const ASAN_REPORT: &str = "=================================================================
+==4242==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000011 at pc 0x4f8a1c bp 0x7ffc sp 0x7ffc
+READ of size 1 at 0x602000000011 thread T0
+    #0 0x4f8a1c in parse_header /src/parser.c:42:7
+    #1 0x4f8b2d in parse /src/parser.c:97:3
+    #2 0x7f0000001234 in __libc_start_main (/lib/x86_64-linux-gnu/libc.so.6+0x21b96)
+    #3 0x41c3e9 (/out/target+0x41c3e9)
+
+0x602000000011 is located 0 bytes to the right of 1-byte region
+allocated by thread T0 here:
+    #0 0x4c3a8d in __interceptor_malloc (/out/target+0x4c3a8d)
+    #1 0x4f8ccc in main /src/main.c:10:5
+";
+
+    #[test]
+    fn test_asan_report_triage() {
+        let mut observer = AsanBacktraceObserver::new("asan");
+        observer.parse_asan_output(ASAN_REPORT);
+        assert_eq!(observer.bug_class(), Some("heap-buffer-overflow"));
+
+        let frames = observer.frames();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].function.as_deref(), Some("parse_header"));
+        assert_eq!(frames[0].file.as_deref(), Some("/src/parser.c"));
+        assert_eq!(frames[0].line, Some(42));
+        assert_eq!(frames[2].function.as_deref(), Some("__libc_start_main"));
+        assert_eq!(frames[3].function, None);
+
+        // The same functions at different addresses end up in the same bucket
+        let mut moved = frames[..2].to_vec();
+        moved[0].address += 0x1000;
+        assert_eq!(bucket_id(&frames[..2]), bucket_id(&moved));
+        moved[1].function = Some("other".to_string());
+        assert_ne!(bucket_id(&frames[..2]), bucket_id(&moved));
+
+        let internal = StackFrame {
+            address: 0,
+            function: Some("__asan_report_load1".to_string()),
+            file: None,
+            line: None,
+        };
+        assert!(internal.is_internal());
+        assert!(!frames[0].is_internal());
+    }
+
+    #[test]
+    fn test_in_process_bug_class() {
+        let frame = |function: &str| StackFrame {
+            address: 0,
+            function: Some(function.to_string()),
+            file: None,
+            line: None,
+        };
+        // A panic aborts, the panic wins
+        let panic = [
+            frame("abort"),
+            frame("core::panicking::panic_fmt"),
+            frame("parse_header"),
+        ];
+        assert_eq!(bug_class_of_frames(&panic), Some("panic"));
+        assert_eq!(bug_class_of_frames(&panic[..1]), Some("abort"));
+        assert_eq!(bug_class_of_frames(&panic[2..]), None);
+    }
+}
@@ -16,8 +16,8 @@ use crate::{
     Error, HasMetadata,
     corpus::{Corpus, CorpusId, HasCurrentCorpusId, HasTestcase, Testcase},
     events::{
-        Event, EventConfig, EventFirer, EventReceiver, EventWithStats, ProgressReporter,
-        SendExiting,
+        Event, EventConfig, EventFirer, EventReceiver, EventWithStats, MetadataSharing,
+        ProgressReporter, SendExiting,
     },
     executors::{Executor, ExitKind, HasObservers},
     feedbacks::Feedback,
@@ -329,6 +329,12 @@ pub struct StdFuzzer<CS, F, IC, IF, OF> {
     input_filter: IF,
     /// Handles whether to share objective testcases among nodes
     share_objectives: bool,
+    /// The testcase metadata types to share with new testcases among nodes
+    metadata_sharing: MetadataSharing,
+    /// The shared metadata of the last testcase added to the corpus, to be sent with its event
+    shared_metadata_buf: Option<Vec<u8>>,
+    /// The shared metadata of the received testcase being evaluated, restored before it is scheduled
+    received_metadata_buf: Option<Vec<u8>>,
 }
 
 impl<CS, F, I, IC, IF, OF, S> HasScheduler<I, S> for StdFuzzer<CS, F, IC, IF, OF>
@@ -456,10 +462,16 @@ where
                 .append_hit_feedbacks(testcase.hit_feedbacks_mut())?;
             self.feedback_mut()
                 .append_metadata(state, manager, observers, &mut testcase)?;
+            if let Some(metadata_buf) = self.received_metadata_buf.take() {
+                self.metadata_sharing
+                    .restore(&metadata_buf, &mut testcase)?;
+            }
+            self.shared_metadata_buf = self.metadata_sharing.collect(&testcase)?;
             let id = state.corpus_mut().add(testcase)?;
             self.scheduler_mut().on_add(state, id)?;
             Ok(Some(id))
         } else {
+            self.shared_metadata_buf = None;
             Ok(None)
         };
 
@@ -524,6 +536,7 @@ where
                         Event::NewTestcase {
                             input: input.clone(),
                             observers_buf,
+                            metadata_buf: self.shared_metadata_buf.take(),
                             exit_kind: *exit_kind,
                             corpus_size: state.corpus().count(),
                             client_config: manager.configuration(),
@@ -779,6 +792,7 @@ where
         // Add the input to the main corpus
         self.feedback_mut()
             .append_metadata(state, manager, &*observers, &mut testcase)?;
+        let metadata_buf = self.metadata_sharing.collect(&testcase)?;
         let id = state.corpus_mut().add(testcase)?;
         self.scheduler_mut().on_add(state, id)?;
 
@@ -793,6 +807,7 @@ where
                 Event::NewTestcase {
                     input,
                     observers_buf,
+                    metadata_buf,
                     exit_kind,
                     corpus_size: state.corpus().count(),
                     client_config: manager.configuration(),
@@ -843,6 +858,11 @@ where
         // Execute the manager
         while let Some((event, with_observers)) = manager.try_receive(state)? {
             // at this point event is either newtestcase or objectives
+            // The shared metadata is restored before the testcase is scheduled, see `process_execution`
+            self.received_metadata_buf = match event.event() {
+                Event::NewTestcase { metadata_buf, .. } => metadata_buf.clone(),
+                _ => None,
+            };
             let res = if with_observers {
                 match event.event() {
                     Event::NewTestcase {
@@ -884,6 +904,7 @@ where
                     _ => None,
                 }
             };
+            self.received_metadata_buf = None;
             if let Some(item) = res {
                 *state.imported_mut() += 1;
                 log::debug!("Added received input as item #{item}");
@@ -1045,6 +1066,8 @@ pub struct StdFuzzerBuilder<CS, F, IC, IF, OF> {
     input_filter: IF,
     /// Handles whether to share objective testcases among nodes
     share_objectives: bool,
+    /// The testcase metadata types to share with new testcases among nodes
+    metadata_sharing: MetadataSharing,
 }
 
 impl StdFuzzerBuilder<(), (), NopToTargetBytes, NopInputFilter, ()> {
@@ -1058,6 +1081,7 @@ impl StdFuzzerBuilder<(), (), NopToTargetBytes, NopInputFilter, ()> {
             feedback: (),
             objective: (),
             share_objectives: false,
+            metadata_sharing: MetadataSharing::new(),
         }
     }
 }
@@ -1083,6 +1107,7 @@ impl<CS, F, IC, IF, OF> StdFuzzerBuilder<CS, F, IC, IF, OF> {
             feedback: self.feedback,
             objective: self.objective,
             share_objectives: self.share_objectives,
+            metadata_sharing: self.metadata_sharing,
         }
     }
 }
@@ -1099,6 +1124,7 @@ impl<CS, F, IC, IF, OF> StdFuzzerBuilder<CS, F, IC, IF, OF> {
             feedback: self.feedback,
             objective: self.objective,
             share_objectives: self.share_objectives,
+            metadata_sharing: self.metadata_sharing,
         }
     }
 }
@@ -1114,6 +1140,7 @@ impl<CS, F, IC, IF, OF> StdFuzzerBuilder<CS, F, IC, IF, OF> {
             feedback: self.feedback,
             objective: self.objective,
             share_objectives: self.share_objectives,
+            metadata_sharing: self.metadata_sharing,
         }
     }
 }
@@ -1129,6 +1156,7 @@ impl<CS, F, IC, IF, OF> StdFuzzerBuilder<CS, F, IC, IF, OF> {
             feedback,
             objective: self.objective,
             share_objectives: self.share_objectives,
+            metadata_sharing: self.metadata_sharing,
         }
     }
 }
@@ -1144,6 +1172,7 @@ impl<CS, F, IC, IF, OF> StdFuzzerBuilder<CS, F, IC, IF, OF> {
             feedback: self.feedback,
             objective,
             share_objectives: self.share_objectives,
+            metadata_sharing: self.metadata_sharing,
         }
     }
 }
@@ -1159,6 +1188,26 @@ impl<CS, F, IC, IF, OF> StdFuzzerBuilder<CS, F, IC, IF, OF> {
             feedback: self.feedback,
             objective: self.objective,
             share_objectives,
+            metadata_sharing: self.metadata_sharing,
+        }
+    }
+}
+
+impl<CS, F, IC, IF, OF> StdFuzzerBuilder<CS, F, IC, IF, OF> {
+    /// Sets the testcase metadata types to send along with new testcases, and to restore on received testcases
+    #[must_use]
+    pub fn metadata_sharing(
+        self,
+        metadata_sharing: MetadataSharing,
+    ) -> StdFuzzerBuilder<CS, F, IC, IF, OF> {
+        StdFuzzerBuilder {
+            target_bytes_converter: self.target_bytes_converter,
+            input_filter: self.input_filter,
+            scheduler: self.scheduler,
+            feedback: self.feedback,
+            objective: self.objective,
+            share_objectives: self.share_objectives,
+            metadata_sharing,
         }
     }
 }
@@ -1173,6 +1222,9 @@ impl<CS, F, IC, IF, OF> StdFuzzerBuilder<CS, F, IC, IF, OF> {
             feedback: self.feedback,
             objective: self.objective,
             share_objectives: self.share_objectives,
+            metadata_sharing: self.metadata_sharing,
+            shared_metadata_buf: None,
+            received_metadata_buf: None,
         }
     }
 }
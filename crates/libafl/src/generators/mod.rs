@@ -12,6 +12,9 @@ use core::cmp::max;
 
 pub use gramatron::*;
 
+pub mod protobuf;
+pub use protobuf::*;
+
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
 #[cfg(feature = "nautilus")]
@@ -0,0 +1,272 @@
+//! Protobuf generator, creating random messages following a [`ProtobufSchema`]
+use alloc::{string::String, vec::Vec};
+use core::marker::PhantomData;
+
+use libafl_bolts::rands::Rand;
+
+use crate::{
+    Error,
+    generators::Generator,
+    inputs::{
+        ProtobufFieldDescriptor, ProtobufFieldType, ProtobufInput, ProtobufMessage,
+        ProtobufMessageDescriptor, ProtobufSchema, ProtobufValue,
+    },
+    nonzero,
+    state::HasRand,
+};
+
+/// The default maximum nesting depth of generated messages
+pub const DEFAULT_PROTOBUF_MAX_DEPTH: usize = 8;
+
+/// Interesting values for varint fields
+const INTERESTING_VARINTS: [u64; 10] = [
+    0,
+    1,
+    0x7f,
+    0x80,
+    0xff,
+    0xffff,
+    0x7fff_ffff,
+    0xffff_ffff,
+    0x7fff_ffff_ffff_ffff,
+    u64::MAX,
+];
+
+/// Maximum length of generated `bytes` and `string` fields
+const MAX_GENERATED_LEN: usize = 32;
+
+#[derive(Debug, Clone)]
+/// Generates random [`ProtobufInput`]s of a message type in a [`ProtobufSchema`]
+pub struct ProtobufGenerator<'a, S> {
+    schema: &'a ProtobufSchema,
+    root: String,
+    max_depth: usize,
+    phantom: PhantomData<S>,
+}
+
+impl<S> Generator<ProtobufInput, S> for ProtobufGenerator<'_, S>
+where
+    S: HasRand,
+{
+    fn generate(&mut self, state: &mut S) -> Result<ProtobufInput, Error> {
+        let message = self.generate_message(state.rand_mut(), &self.root, 0)?;
+        Ok(ProtobufInput::new(message))
+    }
+}
+
+impl<'a, S> ProtobufGenerator<'a, S> {
+    /// Returns a new [`ProtobufGenerator`] for messages of type `root`
+    pub fn new(schema: &'a ProtobufSchema, root: &str) -> Result<Self, Error> {
+        Self::with_max_depth(schema, root, DEFAULT_PROTOBUF_MAX_DEPTH)
+    }
+
+    /// Returns a new [`ProtobufGenerator`] with a custom maximum nesting depth
+    pub fn with_max_depth(
+        schema: &'a ProtobufSchema,
+        root: &str,
+        max_depth: usize,
+    ) -> Result<Self, Error> {
+        let root = schema
+            .message(root)
+            .ok_or_else(|| Error::key_not_found(format!("Unknown message type {root}")))?
+            .name
+            .clone();
+        Ok(Self {
+            schema,
+            root,
+            max_depth,
+            phantom: PhantomData,
+        })
+    }
+
+    /// The schema used by this generator
+    #[must_use]
+    pub fn schema(&self) -> &'a ProtobufSchema {
+        self.schema
+    }
+
+    /// Generates a random message of type `type_name`, nested at `depth`
+    pub fn generate_message<R>(
+        &self,
+        rand: &mut R,
+        type_name: &str,
+        depth: usize,
+    ) -> Result<ProtobufMessage, Error>
+    where
+        R: Rand,
+    {
+        let descriptor = self
+            .schema
+            .message(type_name)
+            .ok_or_else(|| Error::key_not_found(format!("Unknown message type {type_name}")))?;
+        Ok(self.generate_fields(rand, descriptor, depth))
+    }
+
+    /// Generates random fields for a message of type `descriptor`, nested at `depth`
+    fn generate_fields<R>(
+        &self,
+        rand: &mut R,
+        descriptor: &ProtobufMessageDescriptor,
+        depth: usize,
+    ) -> ProtobufMessage
+    where
+        R: Rand,
+    {
+        let mut message = ProtobufMessage::new(descriptor.name.clone());
+        for field in &descriptor.fields {
+            // Past the maximum depth, no further nested messages are generated
+            if matches!(
+                field.ty,
+                ProtobufFieldType::Message | ProtobufFieldType::Group
+            ) && depth >= self.max_depth
+            {
+                continue;
+            }
+            let count = if field.repeated {
+                rand.below(nonzero!(4))
+            } else {
+                usize::from(rand.coinflip(0.5))
+            };
+            for _ in 0..count {
+                let Some(value) = self.generate_value(rand, field, depth) else {
+                    break;
+                };
+                message.push(field.number, value);
+            }
+        }
+        message
+    }
+
+    /// Generates a random value for `field` of a message nested at `depth`.
+    ///
+    /// Returns `None` for nested messages of a type missing from the schema,
+    /// e.g. if the descriptor set was built without `--include_imports`.
+    pub fn generate_value<R>(
+        &self,
+        rand: &mut R,
+        field: &ProtobufFieldDescriptor,
+        depth: usize,
+    ) -> Option<ProtobufValue>
+    where
+        R: Rand,
+    {
+        Some(match field.ty {
+            ProtobufFieldType::Message | ProtobufFieldType::Group => {
+                let descriptor = field
+                    .type_name
+                    .as_deref()
+                    .and_then(|type_name| self.schema.message(type_name))?;
+                let message = self.generate_fields(rand, descriptor, depth + 1);
+                if field.ty == ProtobufFieldType::Group {
+                    ProtobufValue::Group(message)
+                } else {
+                    ProtobufValue::Message(message)
+                }
+            }
+            ProtobufFieldType::Enum => {
+                let values = field
+                    .type_name
+                    .as_deref()
+                    .and_then(|name| self.schema.enum_values(name))
+                    .unwrap_or_default();
+                // Enum values are encoded as sign-extended varints
+                #[expect(clippy::cast_sign_loss)]
+                let value = match rand.choose(values) {
+                    Some(value) => i64::from(*value) as u64,
+                    None => rand.next(),
+                };
+                ProtobufValue::Varint(value)
+            }
+            ProtobufFieldType::Bool => ProtobufValue::Varint(u64::from(rand.coinflip(0.5))),
+            ProtobufFieldType::String | ProtobufFieldType::Bytes => {
+                let len = rand.below_or_zero(MAX_GENERATED_LEN);
+                let bytes: Vec<u8> = if field.ty == ProtobufFieldType::String {
+                    (0..len)
+                        .map(|_| b' ' + rand.below(nonzero!(95)) as u8)
+                        .collect()
+                } else {
+                    (0..len).map(|_| rand.next() as u8).collect()
+                };
+                ProtobufValue::Bytes(bytes)
+            }
+            _ => match field.ty.wire_type() {
+                0 => ProtobufValue::Varint(if rand.coinflip(0.5) {
+                    *rand.choose(&INTERESTING_VARINTS).unwrap()
+                } else {
+                    rand.next()
+                }),
+                1 => ProtobufValue::Fixed64(rand.next()),
+                _ => ProtobufValue::Fixed32(rand.next() as u32),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec};
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::ProtobufGenerator;
+    use crate::inputs::{
+        ProtobufFieldDescriptor, ProtobufFieldType, ProtobufMessageDescriptor, ProtobufSchema,
+        ProtobufValue,
+    };
+
+    fn field(
+        number: u32,
+        ty: ProtobufFieldType,
+        type_name: Option<&str>,
+    ) -> ProtobufFieldDescriptor {
+        ProtobufFieldDescriptor {
+            name: format!("field{number}"),
+            number,
+            repeated: false,
+            ty,
+            type_name: type_name.map(ToString::to_string),
+        }
+    }
+
+    #[test]
+    fn test_protobuf_generator() {
+        let mut schema = ProtobufSchema::default();
+        schema.add_message(ProtobufMessageDescriptor {
+            name: "test.Outer".to_string(),
+            fields: vec![
+                // Not part of the schema, e.g. defined in a file missing from the descriptor set
+                field(1, ProtobufFieldType::Message, Some("test.Missing")),
+                field(2, ProtobufFieldType::Group, Some("test.Outer.Item")),
+                field(3, ProtobufFieldType::Int32, None),
+            ],
+        });
+        schema.add_message(ProtobufMessageDescriptor {
+            name: "test.Outer.Item".to_string(),
+            fields: vec![field(1, ProtobufFieldType::Uint64, None)],
+        });
+
+        let generator = ProtobufGenerator::<()>::new(&schema, "test.Outer").unwrap();
+        let mut rand = StdRand::with_seed(1337);
+        let mut groups = 0;
+        for _ in 0..100 {
+            let message = generator
+                .generate_message(&mut rand, "test.Outer", 0)
+                .unwrap();
+            assert!(message.fields.iter().all(|field| field.number != 1));
+            groups += message
+                .fields
+                .iter()
+                .filter(|field| matches!(field.value, ProtobufValue::Group(_)))
+                .count();
+
+            // Groups have to be encoded with start and end group tags to decode again
+            let mut bytes = vec![];
+            message.encode(&mut bytes);
+            assert_eq!(
+                *schema.decode("test.Outer", &bytes).unwrap().message(),
+                message
+            );
+        }
+        assert!(groups > 0);
+    }
+}
@@ -8,6 +8,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     Error, HasMetadata,
     corpus::Testcase,
+    events::ShareableMetadata,
     inputs::BytesInput,
     stages::mutational::{MutatedTransform, MutatedTransformPost},
 };
@@ -33,6 +34,10 @@ pub struct GeneralizedInputMetadata {
 
 impl_serdeany!(GeneralizedInputMetadata);
 
+impl ShareableMetadata for GeneralizedInputMetadata {
+    const SHARED_NAME: &'static str = "generalized_input";
+}
+
 impl GeneralizedInputMetadata {
     /// Fill the generalized vector from a slice of option (None -> Gap)
     #[must_use]
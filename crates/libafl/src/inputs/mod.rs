@@ -12,6 +12,9 @@ pub use encoded::*;
 pub mod gramatron;
 pub use gramatron::*;
 
+pub mod protobuf;
+pub use protobuf::*;
+
 pub mod generalized;
 pub use generalized::*;
 
@@ -0,0 +1,1002 @@
+//! Structure-aware inputs for targets consuming [protobuf](https://protobuf.dev/) messages.
+//!
+//! The schema is loaded from a binary `FileDescriptorSet`, as produced by
+//! `protoc --descriptor_set_out=schema.pb --include_imports schema.proto`, and parsed locally.
+//! A [`ProtobufInput`] is a dynamic message tree that serializes to the protobuf wire format.
+
+use alloc::{
+    borrow::ToOwned,
+    string::{String, ToString},
+    vec::Vec,
+};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use hashbrown::HashMap;
+use libafl_bolts::{Error, HasLen, ownedref::OwnedSlice};
+use serde::{Deserialize, Serialize};
+
+use crate::inputs::{HasTargetBytes, Input, ToTargetBytes};
+
+/// Wire type for varints
+const WIRE_VARINT: u8 = 0;
+/// Wire type for 64 bit values
+const WIRE_FIXED64: u8 = 1;
+/// Wire type for length-delimited values
+const WIRE_LEN: u8 = 2;
+/// Wire type for the start of a group
+const WIRE_START_GROUP: u8 = 3;
+/// Wire type for the end of a group
+const WIRE_END_GROUP: u8 = 4;
+/// Wire type for 32 bit values
+const WIRE_FIXED32: u8 = 5;
+
+/// The maximum nesting depth of messages and groups when decoding, deeper inputs are rejected
+pub const PROTOBUF_MAX_DECODE_DEPTH: usize = 100;
+
+/// The type of a field, as in `FieldDescriptorProto.Type`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtobufFieldType {
+    /// `double`
+    Double,
+    /// `float`
+    Float,
+    /// `int64`
+    Int64,
+    /// `uint64`
+    Uint64,
+    /// `int32`
+    Int32,
+    /// `fixed64`
+    Fixed64,
+    /// `fixed32`
+    Fixed32,
+    /// `bool`
+    Bool,
+    /// `string`
+    String,
+    /// `group`, deprecated. A nested message between a start and an end group tag.
+    Group,
+    /// A nested message
+    Message,
+    /// `bytes`
+    Bytes,
+    /// `uint32`
+    Uint32,
+    /// An enum
+    Enum,
+    /// `sfixed32`
+    Sfixed32,
+    /// `sfixed64`
+    Sfixed64,
+    /// `sint32`, zigzag encoded
+    Sint32,
+    /// `sint64`, zigzag encoded
+    Sint64,
+}
+
+impl ProtobufFieldType {
+    /// Converts the numeric type of a `FieldDescriptorProto`
+    fn from_descriptor(ty: u64) -> Result<Self, Error> {
+        Ok(match ty {
+            1 => Self::Double,
+            2 => Self::Float,
+            3 => Self::Int64,
+            4 => Self::Uint64,
+            5 => Self::Int32,
+            6 => Self::Fixed64,
+            7 => Self::Fixed32,
+            8 => Self::Bool,
+            9 => Self::String,
+            10 => Self::Group,
+            11 => Self::Message,
+            12 => Self::Bytes,
+            13 => Self::Uint32,
+            14 => Self::Enum,
+            15 => Self::Sfixed32,
+            16 => Self::Sfixed64,
+            17 => Self::Sint32,
+            18 => Self::Sint64,
+            _ => return Err(Error::illegal_argument(format!("Unknown field type {ty}"))),
+        })
+    }
+
+    /// The wire type used to encode values of this type
+    #[must_use]
+    pub fn wire_type(self) -> u8 {
+        match self {
+            Self::Int64
+            | Self::Uint64
+            | Self::Int32
+            | Self::Bool
+            | Self::Uint32
+            | Self::Enum
+            | Self::Sint32
+            | Self::Sint64 => WIRE_VARINT,
+            Self::Double | Self::Fixed64 | Self::Sfixed64 => WIRE_FIXED64,
+            Self::Float | Self::Fixed32 | Self::Sfixed32 => WIRE_FIXED32,
+            Self::String | Self::Bytes | Self::Message => WIRE_LEN,
+            Self::Group => WIRE_START_GROUP,
+        }
+    }
+}
+
+/// The description of a field of a message
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProtobufFieldDescriptor {
+    /// The name of the field
+    pub name: String,
+    /// The field number
+    pub number: u32,
+    /// If this field is `repeated`
+    pub repeated: bool,
+    /// The type of this field
+    pub ty: ProtobufFieldType,
+    /// The fully qualified name of the message or enum type, without leading `.`
+    pub type_name: Option<String>,
+}
+
+/// The description of a message type
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProtobufMessageDescriptor {
+    /// The fully qualified name of this message, without leading `.`
+    pub name: String,
+    /// The fields of this message
+    pub fields: Vec<ProtobufFieldDescriptor>,
+}
+
+impl ProtobufMessageDescriptor {
+    /// Gets the field with the given number
+    #[must_use]
+    pub fn field(&self, number: u32) -> Option<&ProtobufFieldDescriptor> {
+        self.fields.iter().find(|field| field.number == number)
+    }
+}
+
+/// All message and enum types described by a `FileDescriptorSet`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProtobufSchema {
+    messages: HashMap<String, ProtobufMessageDescriptor>,
+    enums: HashMap<String, Vec<i32>>,
+}
+
+impl ProtobufSchema {
+    /// Parses a binary `FileDescriptorSet`
+    pub fn from_descriptor_set(bytes: &[u8]) -> Result<Self, Error> {
+        let mut schema = Self::default();
+        let mut reader = WireReader::new(bytes);
+        while let Some((number, value)) = reader.read_field()? {
+            // FileDescriptorSet.file
+            if number == 1 {
+                schema.parse_file(value.bytes()?)?;
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Loads a binary `FileDescriptorSet` from a file
+    #[cfg(feature = "std")]
+    pub fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_descriptor_set(&std::fs::read(path)?)
+    }
+
+    /// Adds a message type, e.g. to build a schema without a descriptor set
+    pub fn add_message(&mut self, descriptor: ProtobufMessageDescriptor) {
+        self.messages.insert(descriptor.name.clone(), descriptor);
+    }
+
+    /// Gets the message type with the given fully qualified name
+    #[must_use]
+    pub fn message(&self, name: &str) -> Option<&ProtobufMessageDescriptor> {
+        self.messages.get(name.trim_start_matches('.'))
+    }
+
+    /// All message types of this schema
+    pub fn messages(&self) -> impl Iterator<Item = &ProtobufMessageDescriptor> {
+        self.messages.values()
+    }
+
+    /// The values of the enum with the given fully qualified name
+    #[must_use]
+    pub fn enum_values(&self, name: &str) -> Option<&[i32]> {
+        self.enums
+            .get(name.trim_start_matches('.'))
+            .map(Vec::as_slice)
+    }
+
+    /// Decodes a message of the type `type_name` from the wire format, e.g. to import seeds
+    pub fn decode(&self, type_name: &str, bytes: &[u8]) -> Result<ProtobufInput, Error> {
+        Ok(ProtobufInput::new(
+            self.decode_message(type_name, bytes, 0)?,
+        ))
+    }
+
+    fn decode_message(
+        &self,
+        type_name: &str,
+        bytes: &[u8],
+        depth: usize,
+    ) -> Result<ProtobufMessage, Error> {
+        let descriptor = self
+            .message(type_name)
+            .ok_or_else(|| Error::key_not_found(format!("Unknown message type {type_name}")))?;
+        self.decode_fields(Some(descriptor), descriptor.name.clone(), bytes, depth)
+    }
+
+    /// Decodes the fields of a message nested at `depth`, without a descriptor all fields are unknown
+    fn decode_fields(
+        &self,
+        descriptor: Option<&ProtobufMessageDescriptor>,
+        type_name: String,
+        bytes: &[u8],
+        depth: usize,
+    ) -> Result<ProtobufMessage, Error> {
+        if depth > PROTOBUF_MAX_DECODE_DEPTH {
+            return Err(Error::illegal_argument(format!(
+                "Protobuf message nested deeper than {PROTOBUF_MAX_DECODE_DEPTH}"
+            )));
+        }
+        let mut message = ProtobufMessage::new(type_name);
+        let mut reader = WireReader::new(bytes);
+        while let Some((number, value)) = reader.read_field()? {
+            let field = descriptor.and_then(|descriptor| descriptor.field(number));
+            match (field, value) {
+                (Some(field), WireValue::Bytes(bytes))
+                    if field.ty == ProtobufFieldType::Message =>
+                {
+                    let type_name = field.type_name.as_deref().unwrap_or_default();
+                    let nested = self.decode_message(type_name, bytes, depth + 1)?;
+                    message.push(number, ProtobufValue::Message(nested));
+                }
+                (field, WireValue::Group(bytes)) => {
+                    let nested = match field.and_then(|field| field.type_name.as_deref()) {
+                        Some(type_name) => self.decode_message(type_name, bytes, depth + 1)?,
+                        // Unknown groups are kept as untyped messages
+                        None => self.decode_fields(None, String::new(), bytes, depth + 1)?,
+                    };
+                    message.push(number, ProtobufValue::Group(nested));
+                }
+                (Some(field), WireValue::Bytes(bytes))
+                    if field.repeated
+                        && matches!(
+                            field.ty.wire_type(),
+                            WIRE_VARINT | WIRE_FIXED64 | WIRE_FIXED32
+                        ) =>
+                {
+                    // packed repeated scalars
+                    let mut packed = WireReader::new(bytes);
+                    while !packed.is_empty() {
+                        let value = match field.ty.wire_type() {
+                            WIRE_VARINT => ProtobufValue::Varint(packed.read_varint()?),
+                            WIRE_FIXED64 => ProtobufValue::Fixed64(packed.read_fixed64()?),
+                            _ => ProtobufValue::Fixed32(packed.read_fixed32()?),
+                        };
+                        message.push(number, value);
+                    }
+                }
+                // Unknown fields are kept as they are
+                (_, WireValue::Varint(value)) => message.push(number, ProtobufValue::Varint(value)),
+                (_, WireValue::Fixed64(value)) => {
+                    message.push(number, ProtobufValue::Fixed64(value));
+                }
+                (_, WireValue::Fixed32(value)) => {
+                    message.push(number, ProtobufValue::Fixed32(value));
+                }
+                (_, WireValue::Bytes(bytes)) => {
+                    message.push(number, ProtobufValue::Bytes(bytes.to_vec()));
+                }
+            }
+        }
+        Ok(message)
+    }
+
+    fn parse_file(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let mut package = String::new();
+        let mut messages = Vec::new();
+        let mut enums = Vec::new();
+        let mut reader = WireReader::new(bytes);
+        while let Some((number, value)) = reader.read_field()? {
+            match number {
+                // FileDescriptorProto.package
+                2 => package = value.string()?,
+                // FileDescriptorProto.message_type
+                4 => messages.push(value.bytes()?),
+                // FileDescriptorProto.enum_type
+                5 => enums.push(value.bytes()?),
+                _ => {}
+            }
+        }
+        for message in messages {
+            self.parse_message(&package, message)?;
+        }
+        for enum_type in enums {
+            self.parse_enum(&package, enum_type)?;
+        }
+        Ok(())
+    }
+
+    fn parse_message(&mut self, scope: &str, bytes: &[u8]) -> Result<(), Error> {
+        let mut name = String::new();
+        let mut fields = Vec::new();
+        let mut nested = Vec::new();
+        let mut enums = Vec::new();
+        let mut reader = WireReader::new(bytes);
+        while let Some((number, value)) = reader.read_field()? {
+            match number {
+                // DescriptorProto.name
+                1 => name = value.string()?,
+                // DescriptorProto.field
+                2 => fields.push(Self::parse_field(value.bytes()?)?),
+                // DescriptorProto.nested_type
+                3 => nested.push(value.bytes()?),
+                // DescriptorProto.enum_type
+                4 => enums.push(value.bytes()?),
+                _ => {}
+            }
+        }
+        let full_name = qualify(scope, &name);
+        for message in nested {
+            self.parse_message(&full_name, message)?;
+        }
+        for enum_type in enums {
+            self.parse_enum(&full_name, enum_type)?;
+        }
+        self.add_message(ProtobufMessageDescriptor {
+            name: full_name,
+            fields,
+        });
+        Ok(())
+    }
+
+    fn parse_field(bytes: &[u8]) -> Result<ProtobufFieldDescriptor, Error> {
+        let mut field = ProtobufFieldDescriptor {
+            name: String::new(),
+            number: 0,
+            repeated: false,
+            ty: ProtobufFieldType::Int32,
+            type_name: None,
+        };
+        let mut reader = WireReader::new(bytes);
+        while let Some((number, value)) = reader.read_field()? {
+            match number {
+                // FieldDescriptorProto.name
+                1 => field.name = value.string()?,
+                // FieldDescriptorProto.number
+                3 => field.number = u32::try_from(value.varint()?)?,
+                // FieldDescriptorProto.label, 3 is LABEL_REPEATED
+                4 => field.repeated = value.varint()? == 3,
+                // FieldDescriptorProto.type
+                5 => field.ty = ProtobufFieldType::from_descriptor(value.varint()?)?,
+                // FieldDescriptorProto.type_name
+                6 => field.type_name = Some(value.string()?.trim_start_matches('.').to_owned()),
+                _ => {}
+            }
+        }
+        Ok(field)
+    }
+
+    fn parse_enum(&mut self, scope: &str, bytes: &[u8]) -> Result<(), Error> {
+        let mut name = String::new();
+        let mut values = Vec::new();
+        let mut reader = WireReader::new(bytes);
+        while let Some((number, value)) = reader.read_field()? {
+            match number {
+                // EnumDescriptorProto.name
+                1 => name = value.string()?,
+                // EnumDescriptorProto.value
+                2 => {
+                    let mut value_reader = WireReader::new(value.bytes()?);
+                    while let Some((number, value)) = value_reader.read_field()? {
+                        // EnumValueDescriptorProto.number
+                        if number == 2 {
+                            // Negative numbers are encoded as sign-extended varints
+                            #[expect(clippy::cast_possible_wrap)]
+                            let value = value.varint()? as i64 as i32;
+                            values.push(value);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.enums.insert(qualify(scope, &name), values);
+        Ok(())
+    }
+}
+
+/// Builds the fully qualified name of `name` in `scope`
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{scope}.{name}")
+    }
+}
+
+/// A raw value read from the wire
+#[derive(Debug, Clone, Copy)]
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+    /// The encoded fields between a start and an end group tag
+    Group(&'a [u8]),
+}
+
+impl<'a> WireValue<'a> {
+    fn varint(self) -> Result<u64, Error> {
+        match self {
+            Self::Varint(value) => Ok(value),
+            _ => Err(Error::illegal_argument("Expected a varint")),
+        }
+    }
+
+    fn bytes(self) -> Result<&'a [u8], Error> {
+        match self {
+            Self::Bytes(bytes) => Ok(bytes),
+            _ => Err(Error::illegal_argument("Expected a length-delimited value")),
+        }
+    }
+
+    fn string(self) -> Result<String, Error> {
+        Ok(String::from_utf8_lossy(self.bytes()?).into_owned())
+    }
+}
+
+/// A minimal reader for the protobuf wire format
+#[derive(Debug)]
+struct WireReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> WireReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| Error::illegal_argument("Truncated protobuf message"))?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::illegal_argument("Varint too long"))
+    }
+
+    fn read_fixed64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn read_fixed32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    /// Reads a field key, as field number and wire type
+    fn read_key(&mut self) -> Result<(u32, u8), Error> {
+        let key = self.read_varint()?;
+        Ok((u32::try_from(key >> 3)?, (key & 7) as u8))
+    }
+
+    /// Reads a value of the given wire type, nested in `depth` groups.
+    /// Groups are read up to their end tag.
+    fn read_value(
+        &mut self,
+        number: u32,
+        wire_type: u8,
+        depth: usize,
+    ) -> Result<WireValue<'a>, Error> {
+        Ok(match wire_type {
+            WIRE_VARINT => WireValue::Varint(self.read_varint()?),
+            WIRE_FIXED64 => WireValue::Fixed64(self.read_fixed64()?),
+            WIRE_LEN => {
+                let len = usize::try_from(self.read_varint()?)?;
+                WireValue::Bytes(self.take(len)?)
+            }
+            WIRE_START_GROUP => {
+                if depth >= PROTOBUF_MAX_DECODE_DEPTH {
+                    return Err(Error::illegal_argument(format!(
+                        "Protobuf group nested deeper than {PROTOBUF_MAX_DECODE_DEPTH}"
+                    )));
+                }
+                let start = self.pos;
+                loop {
+                    let end = self.pos;
+                    if self.is_empty() {
+                        return Err(Error::illegal_argument("Truncated protobuf group"));
+                    }
+                    let (inner_number, inner_wire_type) = self.read_key()?;
+                    if inner_wire_type == WIRE_END_GROUP {
+                        if inner_number != number {
+                            return Err(Error::illegal_argument(format!(
+                                "End group tag {inner_number} does not match group {number}"
+                            )));
+                        }
+                        break WireValue::Group(&self.buf[start..end]);
+                    }
+                    self.read_value(inner_number, inner_wire_type, depth + 1)?;
+                }
+            }
+            WIRE_FIXED32 => WireValue::Fixed32(self.read_fixed32()?),
+            wire_type => {
+                return Err(Error::illegal_argument(format!(
+                    "Unsupported wire type {wire_type}"
+                )));
+            }
+        })
+    }
+
+    /// Reads the next field, or `None` at the end of the buffer
+    fn read_field(&mut self) -> Result<Option<(u32, WireValue<'a>)>, Error> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let (number, wire_type) = self.read_key()?;
+        Ok(Some((number, self.read_value(number, wire_type, 0)?)))
+    }
+}
+
+/// Appends `value` as varint to `out`
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// The value of a field in a [`ProtobufMessage`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProtobufValue {
+    /// A varint, used for all integer types, `bool` and enums.
+    /// Signed values are stored as encoded on the wire, e.g. zigzag encoded for `sint32`.
+    Varint(u64),
+    /// A 64 bit value, e.g. `fixed64` or the bits of a `double`
+    Fixed64(u64),
+    /// A 32 bit value, e.g. `fixed32` or the bits of a `float`
+    Fixed32(u32),
+    /// `bytes`, `string` or an unknown length-delimited value
+    Bytes(Vec<u8>),
+    /// A nested message
+    Message(ProtobufMessage),
+    /// A nested message of a `group` field, encoded between a start and an end group tag
+    Group(ProtobufMessage),
+}
+
+impl ProtobufValue {
+    /// The wire type of this value
+    #[must_use]
+    pub fn wire_type(&self) -> u8 {
+        match self {
+            Self::Varint(_) => WIRE_VARINT,
+            Self::Fixed64(_) => WIRE_FIXED64,
+            Self::Fixed32(_) => WIRE_FIXED32,
+            Self::Bytes(_) | Self::Message(_) => WIRE_LEN,
+            Self::Group(_) => WIRE_START_GROUP,
+        }
+    }
+
+    /// The nested message of a `Message` or `Group` value
+    #[must_use]
+    pub fn nested(&self) -> Option<&ProtobufMessage> {
+        match self {
+            Self::Message(message) | Self::Group(message) => Some(message),
+            _ => None,
+        }
+    }
+
+    /// The nested message of a `Message` or `Group` value (mutable)
+    pub fn nested_mut(&mut self) -> Option<&mut ProtobufMessage> {
+        match self {
+            Self::Message(message) | Self::Group(message) => Some(message),
+            _ => None,
+        }
+    }
+}
+
+/// A field of a [`ProtobufMessage`]. Repeated fields occur multiple times.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProtobufField {
+    /// The field number
+    pub number: u32,
+    /// The value
+    pub value: ProtobufValue,
+}
+
+/// A dynamic protobuf message, described by a [`ProtobufMessageDescriptor`] in the [`ProtobufSchema`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProtobufMessage {
+    /// The fully qualified name of the message type
+    pub type_name: String,
+    /// The fields, in wire order
+    pub fields: Vec<ProtobufField>,
+}
+
+impl ProtobufMessage {
+    /// Creates a new empty message of the given type
+    #[must_use]
+    pub fn new(type_name: String) -> Self {
+        Self {
+            type_name,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Appends a field
+    pub fn push(&mut self, number: u32, value: ProtobufValue) {
+        self.fields.push(ProtobufField { number, value });
+    }
+
+    /// Serializes this message to the wire format
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        for field in &self.fields {
+            write_varint(
+                out,
+                (u64::from(field.number) << 3) | u64::from(field.value.wire_type()),
+            );
+            match &field.value {
+                ProtobufValue::Varint(value) => write_varint(out, *value),
+                ProtobufValue::Fixed64(value) => out.extend_from_slice(&value.to_le_bytes()),
+                ProtobufValue::Fixed32(value) => out.extend_from_slice(&value.to_le_bytes()),
+                ProtobufValue::Bytes(bytes) => {
+                    write_varint(out, bytes.len() as u64);
+                    out.extend_from_slice(bytes);
+                }
+                ProtobufValue::Message(message) => {
+                    let mut nested = Vec::new();
+                    message.encode(&mut nested);
+                    write_varint(out, nested.len() as u64);
+                    out.extend_from_slice(&nested);
+                }
+                ProtobufValue::Group(message) => {
+                    message.encode(out);
+                    write_varint(
+                        out,
+                        (u64::from(field.number) << 3) | u64::from(WIRE_END_GROUP),
+                    );
+                }
+            }
+        }
+    }
+
+    /// The number of messages in this tree, including this one
+    #[must_use]
+    pub fn message_count(&self) -> usize {
+        1 + self
+            .fields
+            .iter()
+            .filter_map(|field| field.value.nested())
+            .map(ProtobufMessage::message_count)
+            .sum::<usize>()
+    }
+
+    /// The number of fields in this tree
+    #[must_use]
+    pub fn field_count(&self) -> usize {
+        self.fields.len()
+            + self
+                .fields
+                .iter()
+                .filter_map(|field| field.value.nested())
+                .map(ProtobufMessage::field_count)
+                .sum::<usize>()
+    }
+
+    /// Gets the `n`-th message of this tree in pre-order, where `0` is this message
+    #[must_use]
+    pub fn nth_message(&self, n: usize) -> Option<&ProtobufMessage> {
+        if n == 0 {
+            return Some(self);
+        }
+        let mut n = n - 1;
+        for message in self.fields.iter().filter_map(|field| field.value.nested()) {
+            let count = message.message_count();
+            if n < count {
+                return message.nth_message(n);
+            }
+            n -= count;
+        }
+        None
+    }
+
+    /// Gets the `n`-th message of this tree in pre-order (mutable), where `0` is this message
+    pub fn nth_message_mut(&mut self, n: usize) -> Option<&mut ProtobufMessage> {
+        if n == 0 {
+            return Some(self);
+        }
+        let mut n = n - 1;
+        for message in self
+            .fields
+            .iter_mut()
+            .filter_map(|field| field.value.nested_mut())
+        {
+            let count = message.message_count();
+            if n < count {
+                return message.nth_message_mut(n);
+            }
+            n -= count;
+        }
+        None
+    }
+
+    /// All messages of the given type in this tree, in pre-order
+    #[must_use]
+    pub fn messages_of_type(&self, type_name: &str) -> Vec<&ProtobufMessage> {
+        let mut found = Vec::new();
+        self.collect_messages_of_type(type_name, &mut found);
+        found
+    }
+
+    fn collect_messages_of_type<'a>(&'a self, type_name: &str, found: &mut Vec<&'a Self>) {
+        if self.type_name == type_name {
+            found.push(self);
+        }
+        for message in self.fields.iter().filter_map(|field| field.value.nested()) {
+            message.collect_messages_of_type(type_name, found);
+        }
+    }
+}
+
+/// An input for targets consuming protobuf messages
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProtobufInput {
+    message: ProtobufMessage,
+}
+
+impl Input for ProtobufInput {}
+
+impl HasLen for ProtobufInput {
+    #[inline]
+    fn len(&self) -> usize {
+        self.message.field_count()
+    }
+}
+
+impl HasTargetBytes for ProtobufInput {
+    fn target_bytes(&self) -> OwnedSlice<'_, u8> {
+        OwnedSlice::from(self.to_bytes())
+    }
+}
+
+impl ProtobufInput {
+    /// Creates a new input from the root message
+    #[must_use]
+    pub fn new(message: ProtobufMessage) -> Self {
+        Self { message }
+    }
+
+    /// The root message
+    #[must_use]
+    pub fn message(&self) -> &ProtobufMessage {
+        &self.message
+    }
+
+    /// The root message (mutable)
+    pub fn message_mut(&mut self) -> &mut ProtobufMessage {
+        &mut self.message
+    }
+
+    /// Serializes this input to the protobuf wire format
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.message.encode(&mut bytes);
+        bytes
+    }
+}
+
+/// A [`ToTargetBytes`] converter serializing [`ProtobufInput`]s to the wire format
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProtobufBytesConverter;
+
+impl ProtobufBytesConverter {
+    /// Creates a new [`ProtobufBytesConverter`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl ToTargetBytes<ProtobufInput> for ProtobufBytesConverter {
+    fn to_target_bytes<'a>(&mut self, input: &'a ProtobufInput) -> OwnedSlice<'a, u8> {
+        OwnedSlice::from(input.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{
+        PROTOBUF_MAX_DECODE_DEPTH, ProtobufFieldType, ProtobufSchema, ProtobufValue,
+        WIRE_END_GROUP, WIRE_START_GROUP, write_varint,
+    };
+
+    /// Appends a length-delimited field
+    fn len_field(out: &mut Vec<u8>, number: u64, bytes: &[u8]) {
+        write_varint(out, (number << 3) | 2);
+        write_varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    /// Appends a varint field
+    fn varint_field(out: &mut Vec<u8>, number: u64, value: u64) {
+        write_varint(out, number << 3);
+        write_varint(out, value);
+    }
+
+    /// `package test; message Inner { int32 a = 1; } message Outer { repeated Inner inner = 1; string name = 2; }`
+    fn descriptor_set() -> Vec<u8> {
+        let mut inner_field = Vec::new();
+        len_field(&mut inner_field, 1, b"a");
+        varint_field(&mut inner_field, 3, 1);
+        varint_field(&mut inner_field, 4, 1);
+        varint_field(&mut inner_field, 5, 5);
+        let mut inner = Vec::new();
+        len_field(&mut inner, 1, b"Inner");
+        len_field(&mut inner, 2, &inner_field);
+
+        let mut outer_inner_field = Vec::new();
+        len_field(&mut outer_inner_field, 1, b"inner");
+        varint_field(&mut outer_inner_field, 3, 1);
+        varint_field(&mut outer_inner_field, 4, 3);
+        varint_field(&mut outer_inner_field, 5, 11);
+        len_field(&mut outer_inner_field, 6, b".test.Inner");
+        let mut outer_name_field = Vec::new();
+        len_field(&mut outer_name_field, 1, b"name");
+        varint_field(&mut outer_name_field, 3, 2);
+        varint_field(&mut outer_name_field, 4, 1);
+        varint_field(&mut outer_name_field, 5, 9);
+        let mut outer = Vec::new();
+        len_field(&mut outer, 1, b"Outer");
+        len_field(&mut outer, 2, &outer_inner_field);
+        len_field(&mut outer, 2, &outer_name_field);
+
+        let mut file = Vec::new();
+        len_field(&mut file, 1, b"test.proto");
+        len_field(&mut file, 2, b"test");
+        len_field(&mut file, 4, &inner);
+        len_field(&mut file, 4, &outer);
+
+        let mut set = Vec::new();
+        len_field(&mut set, 1, &file);
+        set
+    }
+
+    #[test]
+    fn test_protobuf_roundtrip() {
+        let schema = ProtobufSchema::from_descriptor_set(&descriptor_set()).unwrap();
+        let outer = schema.message("test.Outer").unwrap();
+        assert_eq!(outer.fields.len(), 2);
+        assert!(outer.field(1).unwrap().repeated);
+        assert_eq!(
+            outer.field(1).unwrap().type_name.as_deref(),
+            Some("test.Inner")
+        );
+
+        let mut inner = Vec::new();
+        varint_field(&mut inner, 1, 42);
+        let mut bytes = Vec::new();
+        len_field(&mut bytes, 1, &inner);
+        len_field(&mut bytes, 1, &inner);
+        len_field(&mut bytes, 2, b"hello");
+
+        let input = schema.decode("test.Outer", &bytes).unwrap();
+        assert_eq!(input.message().message_count(), 3);
+        assert_eq!(input.message().messages_of_type("test.Inner").len(), 2);
+        assert_eq!(input.to_bytes(), bytes);
+    }
+
+    /// `package test; message WithGroup { optional group Item = 1 { optional int32 id = 2; } }`
+    fn group_descriptor_set() -> Vec<u8> {
+        let mut id_field = Vec::new();
+        len_field(&mut id_field, 1, b"id");
+        varint_field(&mut id_field, 3, 2);
+        varint_field(&mut id_field, 4, 1);
+        varint_field(&mut id_field, 5, 5);
+        let mut item = Vec::new();
+        len_field(&mut item, 1, b"Item");
+        len_field(&mut item, 2, &id_field);
+
+        let mut item_field = Vec::new();
+        len_field(&mut item_field, 1, b"item");
+        varint_field(&mut item_field, 3, 1);
+        varint_field(&mut item_field, 4, 1);
+        varint_field(&mut item_field, 5, 10);
+        len_field(&mut item_field, 6, b".test.WithGroup.Item");
+        let mut with_group = Vec::new();
+        len_field(&mut with_group, 1, b"WithGroup");
+        len_field(&mut with_group, 2, &item_field);
+        len_field(&mut with_group, 3, &item);
+
+        let mut file = Vec::new();
+        len_field(&mut file, 2, b"test");
+        len_field(&mut file, 4, &with_group);
+
+        let mut set = Vec::new();
+        len_field(&mut set, 1, &file);
+        set
+    }
+
+    #[test]
+    fn test_protobuf_group_roundtrip() {
+        let schema = ProtobufSchema::from_descriptor_set(&group_descriptor_set()).unwrap();
+        let field = schema.message("test.WithGroup").unwrap().field(1).unwrap();
+        assert_eq!(field.ty, ProtobufFieldType::Group);
+        assert_eq!(field.ty.wire_type(), WIRE_START_GROUP);
+
+        // start group 1, id = 42, end group 1
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, (1 << 3) | u64::from(WIRE_START_GROUP));
+        varint_field(&mut bytes, 2, 42);
+        write_varint(&mut bytes, (1 << 3) | u64::from(WIRE_END_GROUP));
+
+        let input = schema.decode("test.WithGroup", &bytes).unwrap();
+        let ProtobufValue::Group(item) = &input.message().fields[0].value else {
+            panic!(
+                "Expected a group, got {:?}",
+                input.message().fields[0].value
+            );
+        };
+        assert_eq!(item.type_name, "test.WithGroup.Item");
+        assert_eq!(item.fields[0].value, ProtobufValue::Varint(42));
+        assert_eq!(input.to_bytes(), bytes);
+
+        // The end tag has to close the same group
+        let mut mismatched = Vec::new();
+        write_varint(&mut mismatched, (1 << 3) | u64::from(WIRE_START_GROUP));
+        write_varint(&mut mismatched, (3 << 3) | u64::from(WIRE_END_GROUP));
+        assert!(schema.decode("test.WithGroup", &mismatched).is_err());
+    }
+
+    #[test]
+    fn test_protobuf_decode_depth() {
+        let schema = ProtobufSchema::from_descriptor_set(&group_descriptor_set()).unwrap();
+
+        // Unknown groups nested in each other, closed by the matching end tags
+        let nested_groups = |depth: usize| {
+            let mut bytes = Vec::new();
+            for _ in 0..depth {
+                write_varint(&mut bytes, (7 << 3) | u64::from(WIRE_START_GROUP));
+            }
+            for _ in 0..depth {
+                write_varint(&mut bytes, (7 << 3) | u64::from(WIRE_END_GROUP));
+            }
+            bytes
+        };
+
+        let bytes = nested_groups(PROTOBUF_MAX_DECODE_DEPTH);
+        let input = schema.decode("test.WithGroup", &bytes).unwrap();
+        assert_eq!(
+            input.message().message_count(),
+            PROTOBUF_MAX_DECODE_DEPTH + 1
+        );
+        assert_eq!(input.to_bytes(), bytes);
+
+        // Deeper nesting is rejected instead of overflowing the stack
+        let bytes = nested_groups(100_000);
+        assert!(schema.decode("test.WithGroup", &bytes).is_err());
+    }
+}
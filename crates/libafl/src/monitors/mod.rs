@@ -8,6 +8,9 @@ pub mod stats;
 pub mod logics;
 pub use logics::{IfElseMonitor, IfMonitor, OptionalMonitor, WhileMonitor};
 
+pub mod roles;
+pub use roles::RoleMonitor;
+
 #[cfg(feature = "std")]
 pub mod disk;
 #[cfg(feature = "std")]
@@ -18,6 +21,11 @@ pub mod disk_aggregate;
 #[cfg(feature = "std")]
 pub use disk_aggregate::OnDiskJsonAggregateMonitor;
 
+#[cfg(feature = "std")]
+pub mod web;
+#[cfg(feature = "std")]
+pub use web::WebMonitor;
+
 #[cfg(all(feature = "tui_monitor", feature = "std"))]
 pub mod tui;
 #[cfg(all(feature = "tui_monitor", feature = "std"))]
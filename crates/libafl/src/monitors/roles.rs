@@ -0,0 +1,91 @@
+//! The [`RoleMonitor`] prints the stats of each client role, as announced by clients of a `Launcher` with roles.
+
+use core::{
+    fmt::{self, Debug},
+    time::Duration,
+};
+
+use libafl_bolts::{ClientId, Error, current_time};
+
+use crate::monitors::{
+    Monitor,
+    stats::{ClientStatsManager, prettify_float},
+};
+
+/// The default interval between two reports of a [`RoleMonitor`]
+pub const DEFAULT_ROLE_MONITOR_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A monitor printing one line per client role, at most once per interval.
+///
+/// Combine it with another monitor in a tuple, such as `(SimpleMonitor::new(..), RoleMonitor::new(..))`.
+#[derive(Clone)]
+pub struct RoleMonitor<F>
+where
+    F: FnMut(&str),
+{
+    print_fn: F,
+    interval: Duration,
+    last_report: Duration,
+}
+
+impl<F> Debug for RoleMonitor<F>
+where
+    F: FnMut(&str),
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoleMonitor")
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F> RoleMonitor<F>
+where
+    F: FnMut(&str),
+{
+    /// Creates the monitor, reporting every [`DEFAULT_ROLE_MONITOR_INTERVAL`]
+    pub fn new(print_fn: F) -> Self {
+        Self::with_interval(print_fn, DEFAULT_ROLE_MONITOR_INTERVAL)
+    }
+
+    /// Creates the monitor, reporting at most once per `interval`
+    pub fn with_interval(print_fn: F, interval: Duration) -> Self {
+        Self {
+            print_fn,
+            interval,
+            last_report: Duration::ZERO,
+        }
+    }
+}
+
+impl<F> Monitor for RoleMonitor<F>
+where
+    F: FnMut(&str),
+{
+    fn display(
+        &mut self,
+        client_stats_manager: &mut ClientStatsManager,
+        _event_msg: &str,
+        _sender_id: ClientId,
+    ) -> Result<(), Error> {
+        let cur_time = current_time();
+        if cur_time.saturating_sub(self.last_report) < self.interval {
+            return Ok(());
+        }
+        self.last_report = cur_time;
+
+        for stats in client_stats_manager.role_stats() {
+            let fmt = format!(
+                "[Role {}] clients: {}, corpus: {}, objectives: {}, executions: {}, exec/sec: {}",
+                stats.role,
+                stats.clients,
+                stats.corpus_size,
+                stats.objective_size,
+                stats.executions,
+                prettify_float(stats.execs_per_sec)
+            );
+            (self.print_fn)(&fmt);
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,10 @@
 //! Client statistics manager
 
-#[cfg(feature = "std")]
-use alloc::string::ToString;
-use alloc::{borrow::Cow, string::String};
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
 use core::time::Duration;
 
 use hashbrown::HashMap;
@@ -10,7 +12,9 @@ use libafl_bolts::{ClientId, Error, current_time, format_duration};
 #[cfg(feature = "std")]
 use serde_json::Value;
 
-use super::{ClientStats, EdgeCoverage, ProcessTiming, user_stats::UserStatsValue};
+use super::{
+    ClientStats, EdgeCoverage, ProcessTiming, ROLE_USER_STAT, RoleStats, user_stats::UserStatsValue,
+};
 #[cfg(feature = "std")]
 use super::{
     ItemGeometry,
@@ -181,6 +185,40 @@ impl ClientStatsManager {
         global_stats
     }
 
+    /// Get the stats of each client role, sorted by name.
+    ///
+    /// Clients announce their role in the [`ROLE_USER_STAT`] user stat, clients without a role are skipped.
+    pub fn role_stats(&mut self) -> Vec<RoleStats> {
+        let cur_time = current_time();
+        let mut roles: Vec<RoleStats> = vec![];
+        for client in self
+            .client_stats
+            .values_mut()
+            .filter(|client| client.enabled)
+        {
+            let Some(role) = client.get_user_stats(ROLE_USER_STAT) else {
+                continue;
+            };
+            let role = role.to_string();
+            let stats = if let Some(i) = roles.iter().position(|stats| stats.role == role) {
+                &mut roles[i]
+            } else {
+                roles.push(RoleStats {
+                    role,
+                    ..RoleStats::default()
+                });
+                roles.last_mut().unwrap()
+            };
+            stats.clients += 1;
+            stats.corpus_size += client.corpus_size;
+            stats.objective_size += client.objective_size;
+            stats.executions += client.executions;
+            stats.execs_per_sec += client.execs_per_sec(cur_time);
+        }
+        roles.sort_by(|a, b| a.role.cmp(&b.role));
+        roles
+    }
+
     /// Get process timing. `execs_per_sec_pretty` could be retrieved from `GlobalStats`.
     #[must_use]
     pub fn process_timing(&self, execs_per_sec_pretty: String, total_execs: u64) -> ProcessTiming {
@@ -21,6 +21,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 pub use user_stats::{AggregatorOps, UserStats, UserStatsValue};
 
+/// The user stat in which a client announces its role, see [`ClientStatsManager::role_stats`]
+pub const ROLE_USER_STAT: &str = "role";
+
 #[cfg(feature = "afl_exec_sec")]
 const CLIENT_STATS_TIME_WINDOW_SECS: u64 = 5; // 5 seconds
 
@@ -109,6 +112,23 @@ impl ProcessTiming {
     }
 }
 
+/// The aggregated stats of all clients of a role
+#[derive(Debug, Default, Clone)]
+pub struct RoleStats {
+    /// The name of the role
+    pub role: String,
+    /// The number of clients
+    pub clients: u64,
+    /// The sum of the corpus sizes
+    pub corpus_size: u64,
+    /// The sum of the objective sizes
+    pub objective_size: u64,
+    /// The sum of the executions
+    pub executions: u64,
+    /// The sum of the executions per second
+    pub execs_per_sec: f64,
+}
+
 /// The geometry of a single data point
 #[derive(Debug, Default, Clone)]
 pub struct ItemGeometry {
@@ -414,7 +434,7 @@ impl ClientStats {
 }
 
 /// Prettifies float values for human-readable output
-fn prettify_float(value: f64) -> String {
+pub(crate) fn prettify_float(value: f64) -> String {
     let (value, suffix) = match value {
         value if value >= 1_000_000.0 => (value / 1_000_000.0, "M"),
         value if value >= 1_000.0 => (value / 1_000.0, "k"),
@@ -0,0 +1,363 @@
+//! The [`WebMonitor`] serves a live dashboard and a JSON API over HTTP, straight from the broker.
+//!
+//! No external services are needed, point a browser to the listening address:
+//! - `/` is the dashboard, with coverage over time, corpus and objective counts and per-client stats
+//! - `/api/stats` returns the current global and per-client stats as JSON
+//! - `/api/history` returns the recorded global stats over time as JSON
+//!
+//! ```rust,no_run
+//! use libafl::monitors::WebMonitor;
+//!
+//! let mon = WebMonitor::new("127.0.0.1:8090").unwrap();
+//! // let mgr = SimpleEventManager::new(mon);
+//! ```
+
+use alloc::{
+    collections::VecDeque,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{net::SocketAddr, time::Duration};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::RwLock,
+    thread,
+};
+
+use libafl_bolts::{ClientId, Error, current_time};
+use serde_json::{Value, json};
+
+use crate::monitors::{Monitor, stats::ClientStatsManager};
+
+/// The maximum number of history entries kept, older entries get dropped.
+pub const WEB_MONITOR_MAX_HISTORY: usize = 4096;
+
+/// The dashboard, it polls the JSON API and renders everything client-side.
+const DASHBOARD_HTML: &str = r##"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>LibAFL</title>
+<style>
+body { font-family: monospace; margin: 1em 2em; background: #fafafa; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+td, th { border: 1px solid #ccc; padding: 2px 8px; text-align: right; }
+canvas { border: 1px solid #ccc; background: #fff; margin-right: 1em; }
+pre { background: #fff; border: 1px solid #ccc; padding: 4px; }
+</style></head>
+<body>
+<h1>LibAFL</h1>
+<table id="global"></table>
+<canvas id="edges" width="600" height="250"></canvas>
+<canvas id="corpus" width="600" height="250"></canvas>
+<h2>Clients</h2>
+<table id="clients"></table>
+<div id="introspection"></div>
+<script>
+function esc(s) {
+  return String(s).replace(/[&<>"]/g, c => ({"&": "&amp;", "<": "&lt;", ">": "&gt;", '"': "&quot;"})[c]);
+}
+function row(cells, tag) {
+  return "<tr>" + cells.map(c => "<" + tag + ">" + esc(c) + "</" + tag + ">").join("") + "</tr>";
+}
+function chart(id, title, history, series) {
+  const canvas = document.getElementById(id);
+  const ctx = canvas.getContext("2d");
+  ctx.clearRect(0, 0, canvas.width, canvas.height);
+  ctx.fillStyle = "#000";
+  ctx.fillText(title, 5, 12);
+  if (history.length < 2) { return; }
+  const maxX = Math.max(1, history[history.length - 1].run_time);
+  const maxY = Math.max(1, ...series.flatMap(s => history.map(h => h[s.key] || 0)));
+  ctx.fillText(String(maxY), 5, 26);
+  series.forEach((s, idx) => {
+    ctx.strokeStyle = s.color;
+    ctx.fillStyle = s.color;
+    ctx.fillText(s.key, 100 + idx * 100, 12);
+    ctx.beginPath();
+    history.forEach((h, i) => {
+      const x = h.run_time / maxX * (canvas.width - 10) + 5;
+      const y = canvas.height - 5 - (h[s.key] || 0) / maxY * (canvas.height - 35);
+      if (i === 0) { ctx.moveTo(x, y); } else { ctx.lineTo(x, y); }
+    });
+    ctx.stroke();
+  });
+}
+async function refresh() {
+  const stats = await (await fetch("/api/stats")).json();
+  const history = await (await fetch("/api/history")).json();
+  const g = stats.global;
+  document.getElementById("global").innerHTML =
+    row(["run time", "clients", "corpus", "objectives", "executions", "exec/sec", "edges"], "th") +
+    row([g.run_time_pretty, g.clients, g.corpus, g.objectives, g.executions, g.exec_sec_pretty,
+         g.edges_total ? g.edges_hit + "/" + g.edges_total : "-"], "td");
+  let clients = row(["client", "corpus", "objectives", "executions", "exec/sec", "stats"], "th");
+  let introspection = "";
+  for (const c of stats.clients) {
+    const user = Object.entries(c.user_stats).map(([k, v]) => k + ": " + v).join(", ");
+    clients += row([c.id, c.corpus, c.objectives, c.executions, c.exec_sec_pretty, user], "td");
+    if (c.introspection) {
+      introspection += "<h3>Client " + esc(c.id) + "</h3><pre>" + esc(c.introspection) + "</pre>";
+    }
+  }
+  document.getElementById("clients").innerHTML = clients;
+  document.getElementById("introspection").innerHTML = introspection;
+  chart("edges", "coverage", history, [{key: "edges_hit", color: "#c00"}]);
+  chart("corpus", "corpus / objectives", history,
+        [{key: "corpus", color: "#00c"}, {key: "objectives", color: "#c00"}]);
+}
+refresh();
+setInterval(refresh, 2000);
+</script>
+</body></html>
+"##;
+
+/// The stats shared between the monitor and the HTTP server thread
+#[derive(Debug, Default)]
+struct WebStats {
+    current: Value,
+    history: VecDeque<Value>,
+}
+
+/// A monitor serving an HTML dashboard and a JSON API over HTTP.
+///
+/// Clones share the stats served by the same HTTP server.
+#[derive(Debug, Clone)]
+pub struct WebMonitor {
+    stats: Arc<RwLock<WebStats>>,
+    local_addr: SocketAddr,
+    history_interval: Duration,
+    last_update: Option<Duration>,
+}
+
+impl WebMonitor {
+    /// Creates a new [`WebMonitor`], listening on `addr`.
+    /// The stats are refreshed, and a history entry for the charts recorded, at most every 10 seconds.
+    pub fn new<A>(addr: A) -> Result<Self, Error>
+    where
+        A: ToSocketAddrs,
+    {
+        Self::with_history_interval(addr, Duration::from_secs(10))
+    }
+
+    /// Creates a new [`WebMonitor`], listening on `addr` and refreshing the stats at most every `history_interval`.
+    pub fn with_history_interval<A>(addr: A, history_interval: Duration) -> Result<Self, Error>
+    where
+        A: ToSocketAddrs,
+    {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let stats = Arc::new(RwLock::new(WebStats::default()));
+
+        let server_stats = stats.clone();
+        // Serve from a separate thread, so that the broker never blocks on slow browsers
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                if let Err(err) = serve(stream, &server_stats) {
+                    log::debug!("WebMonitor failed to serve a request: {err:?}");
+                }
+            }
+        });
+        log::info!("WebMonitor listening on http://{local_addr}");
+
+        Ok(Self {
+            stats,
+            local_addr,
+            history_interval,
+            last_update: None,
+        })
+    }
+
+    /// The address the dashboard is served on
+    #[must_use]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Monitor for WebMonitor {
+    fn display(
+        &mut self,
+        client_stats_manager: &mut ClientStatsManager,
+        _event_msg: &str,
+        _sender_id: ClientId,
+    ) -> Result<(), Error> {
+        let cur_time = current_time();
+        // Rebuilding the snapshot is expensive with many clients, don't do it for every event
+        if self
+            .last_update
+            .is_some_and(|last| cur_time.saturating_sub(last) < self.history_interval)
+        {
+            return Ok(());
+        }
+        self.last_update = Some(cur_time);
+
+        let edges = client_stats_manager.edges_coverage();
+        let global_stats = client_stats_manager.global_stats();
+        let global = json!({
+            "run_time": global_stats.run_time.as_secs(),
+            "run_time_pretty": global_stats.run_time_pretty,
+            "clients": global_stats.client_stats_count,
+            "corpus": global_stats.corpus_size,
+            "objectives": global_stats.objective_size,
+            "executions": global_stats.total_execs,
+            "exec_sec": global_stats.execs_per_sec,
+            "exec_sec_pretty": global_stats.execs_per_sec_pretty,
+            "edges_hit": edges.as_ref().map(|edges| edges.edges_hit),
+            "edges_total": edges.as_ref().map(|edges| edges.edges_total),
+        });
+
+        let mut client_ids: Vec<ClientId> = client_stats_manager
+            .client_stats()
+            .iter()
+            .filter(|(_, client)| client.enabled())
+            .map(|(id, _)| *id)
+            .collect();
+        client_ids.sort_by_key(|id| id.0);
+
+        let mut clients = Vec::with_capacity(client_ids.len());
+        for client_id in client_ids {
+            let exec_sec_pretty = client_stats_manager
+                .update_client_stats_for(client_id, |client| {
+                    client.execs_per_sec_pretty(cur_time)
+                })?;
+            let client = client_stats_manager.client_stats_for(client_id)?;
+            let user_stats: serde_json::Map<String, Value> = client
+                .user_stats()
+                .iter()
+                .map(|(key, value)| (key.to_string(), Value::from(value.to_string())))
+                .collect();
+            #[cfg_attr(not(feature = "introspection"), expect(unused_mut))]
+            let mut entry = json!({
+                "id": client_id.0,
+                "corpus": client.corpus_size(),
+                "objectives": client.objective_size(),
+                "executions": client.executions(),
+                "exec_sec_pretty": exec_sec_pretty,
+                "user_stats": user_stats,
+            });
+            #[cfg(feature = "introspection")]
+            {
+                entry["introspection"] = Value::from(client.introspection_stats.to_string());
+            }
+            clients.push(entry);
+        }
+
+        let mut stats = self
+            .stats
+            .write()
+            .map_err(|_| Error::illegal_state("WebMonitor stats lock is poisoned"))?;
+        if stats.history.len() >= WEB_MONITOR_MAX_HISTORY {
+            stats.history.pop_front();
+        }
+        stats.history.push_back(global.clone());
+        stats.current = json!({
+            "global": global,
+            "clients": clients,
+        });
+        Ok(())
+    }
+}
+
+/// Answers a single HTTP request
+fn serve(stream: TcpStream, stats: &RwLock<WebStats>) -> Result<(), Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, we don't care about them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+
+    let (status, content_type, body) = if method == "GET" {
+        let stats = stats
+            .read()
+            .map_err(|_| Error::illegal_state("WebMonitor stats lock is poisoned"))?;
+        match path {
+            "/" | "/index.html" => ("200 OK", "text/html", String::from(DASHBOARD_HTML)),
+            "/api/stats" => ("200 OK", "application/json", stats.current.to_string()),
+            "/api/history" => (
+                "200 OK",
+                "application/json",
+                stats.history.iter().cloned().collect::<Value>().to_string(),
+            ),
+            _ => ("404 Not Found", "text/plain", String::from("not found")),
+        }
+    } else {
+        (
+            "405 Method Not Allowed",
+            "text/plain",
+            String::from("GET only"),
+        )
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+    };
+
+    use libafl_bolts::ClientId;
+
+    use super::WebMonitor;
+    use crate::monitors::{Monitor, stats::ClientStatsManager};
+
+    fn get(monitor: &WebMonitor, path: &str) -> String {
+        let mut stream = TcpStream::connect(monitor.local_addr()).unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_web_monitor() {
+        let mut monitor = WebMonitor::new("127.0.0.1:0").unwrap();
+        let mut manager = ClientStatsManager::new();
+        manager.client_stats_insert(ClientId(1)).unwrap();
+        manager
+            .update_client_stats_for(ClientId(1), |client| client.update_corpus_size(42))
+            .unwrap();
+        monitor
+            .display(&mut manager, "Testcase", ClientId(1))
+            .unwrap();
+
+        assert!(get(&monitor, "/").contains("<canvas"));
+        let stats = get(&monitor, "/api/stats");
+        assert!(stats.starts_with("HTTP/1.1 200 OK"));
+        assert!(stats.contains("\"corpus\":42"));
+        assert!(get(&monitor, "/api/history").contains("\"corpus\":42"));
+        assert!(get(&monitor, "/nope").starts_with("HTTP/1.1 404"));
+
+        // Within the history interval, the snapshot is not rebuilt
+        let mut cloned = monitor.clone();
+        manager
+            .update_client_stats_for(ClientId(1), |client| client.update_corpus_size(43))
+            .unwrap();
+        cloned
+            .display(&mut manager, "Testcase", ClientId(1))
+            .unwrap();
+        assert!(get(&cloned, "/api/stats").contains("\"corpus\":42"));
+    }
+}
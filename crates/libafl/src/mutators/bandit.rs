@@ -0,0 +1,496 @@
+//! A [`ScheduledMutator`] learning which mutations to apply online, using multi-armed bandits.
+//!
+//! Each mutation of the [`MutatorsTuple`] is an arm, and so is each stacking depth.
+//! An execution is rewarded if it added the input to the corpus or to the solutions,
+//! and all mutations applied to this input, as well as the chosen depth, receive this reward.
+//! The learned state lives in the named metadata of the state, so it survives restarts.
+
+use alloc::{borrow::Cow, vec::Vec};
+
+use libafl_bolts::{
+    Named,
+    rands::{Rand, StdRand},
+    tuples::NamedTuple,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasNamedMetadata,
+    corpus::CorpusId,
+    mutators::{
+        ComposedByMutations, MutationId, MutationResult, Mutator, MutatorsTuple, ScheduledMutator,
+        mutation_stats::current_objectives_found,
+    },
+    state::{HasCurrentTestcase, HasRand},
+};
+
+/// The default maximum stacking power, as for the [`crate::mutators::HavocScheduledMutator`]
+pub const BANDIT_DEFAULT_MAX_STACK_POW: usize = 7;
+
+/// The bandit algorithm used to pick arms
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BanditPolicy {
+    /// Thompson sampling with a Beta-Bernoulli model per arm
+    #[default]
+    ThompsonSampling,
+    /// UCB1, with the given exploration factor (`sqrt(2)` in the original paper)
+    Ucb1 {
+        /// The exploration factor
+        exploration: f64,
+    },
+    /// EXP3 for adversarial bandits, with the given exploration rate `gamma` in `(0, 1]`
+    Exp3 {
+        /// The exploration rate
+        gamma: f64,
+    },
+}
+
+/// The learned state of a single arm
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BanditArm {
+    /// How often this arm was pulled
+    pub pulls: u64,
+    /// The sum of all rewards of this arm, each in `[0, 1]`
+    pub rewards: f64,
+    /// The weight of this arm, for EXP3
+    pub weight: f64,
+}
+
+impl Default for BanditArm {
+    fn default() -> Self {
+        Self {
+            pulls: 0,
+            rewards: 0.0,
+            weight: 1.0,
+        }
+    }
+}
+
+impl BanditArm {
+    /// The mean reward of this arm
+    #[must_use]
+    #[expect(clippy::cast_precision_loss)]
+    pub fn mean(&self) -> f64 {
+        if self.pulls == 0 {
+            0.0
+        } else {
+            self.rewards / self.pulls as f64
+        }
+    }
+}
+
+/// The learned state of a [`BanditScheduledMutator`], stored as named metadata under the name of the mutator
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct BanditScheduledMutatorMetadata {
+    /// One arm per mutation, indexed by [`MutationId`]
+    pub mutation_arms: Vec<BanditArm>,
+    /// One arm per stacking depth, arm `n` stacks `2^(n + 1)` mutations
+    pub depth_arms: Vec<BanditArm>,
+}
+
+libafl_bolts::impl_serdeany!(BanditScheduledMutatorMetadata);
+
+impl BanditScheduledMutatorMetadata {
+    /// Creates new [`struct@BanditScheduledMutatorMetadata`] with untrained arms.
+    #[must_use]
+    pub fn new(mutations: usize, depths: usize) -> Self {
+        Self {
+            mutation_arms: vec![BanditArm::default(); mutations],
+            depth_arms: vec![BanditArm::default(); depths],
+        }
+    }
+}
+
+/// A standard normal sample, using Box-Muller
+fn sample_normal<R: Rand>(rand: &mut R) -> f64 {
+    let u1 = 1.0 - rand.next_float();
+    let u2 = rand.next_float();
+    libm::sqrt(-2.0 * libm::log(u1)) * libm::cos(2.0 * core::f64::consts::PI * u2)
+}
+
+/// A `Gamma(shape, 1)` sample for `shape >= 1`, using Marsaglia and Tsang's method
+fn sample_gamma<R: Rand>(rand: &mut R, shape: f64) -> f64 {
+    let shifted = shape - 1.0 / 3.0;
+    let scale = 1.0 / libm::sqrt(9.0 * shifted);
+    loop {
+        let normal = sample_normal(rand);
+        let base = 1.0 + scale * normal;
+        if base <= 0.0 {
+            continue;
+        }
+        let cube = base * base * base;
+        let uniform = 1.0 - rand.next_float();
+        if libm::log(uniform)
+            < 0.5 * normal * normal + shifted - shifted * cube + shifted * libm::log(cube)
+        {
+            return shifted * cube;
+        }
+    }
+}
+
+/// A `Beta(alpha, beta)` sample for `alpha, beta >= 1`
+fn sample_beta<R: Rand>(rand: &mut R, alpha: f64, beta: f64) -> f64 {
+    let x = sample_gamma(rand, alpha);
+    let y = sample_gamma(rand, beta);
+    x / (x + y)
+}
+
+/// Picks an index with a probability proportional to its (non-negative) weight
+fn choose_weighted<R: Rand>(rand: &mut R, weights: &[f64]) -> usize {
+    let total: f64 = weights.iter().sum();
+    let mut target = rand.next_float() * total;
+    for (idx, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return idx;
+        }
+        target -= weight;
+    }
+    weights.len() - 1
+}
+
+/// One Thompson sample of the mean reward of each arm
+#[expect(clippy::cast_precision_loss)]
+fn thompson_samples<R: Rand>(rand: &mut R, arms: &[BanditArm]) -> Vec<f64> {
+    arms.iter()
+        .map(|arm| {
+            let failures = (arm.pulls as f64 - arm.rewards).max(0.0);
+            sample_beta(rand, 1.0 + arm.rewards, 1.0 + failures)
+        })
+        .collect()
+}
+
+/// The EXP3 probabilities of all arms
+#[expect(clippy::cast_precision_loss)]
+fn exp3_probabilities(arms: &[BanditArm], gamma: f64) -> Vec<f64> {
+    let total: f64 = arms.iter().map(|arm| arm.weight).sum();
+    let uniform = gamma / arms.len() as f64;
+    arms.iter()
+        .map(|arm| (1.0 - gamma) * arm.weight / total + uniform)
+        .collect()
+}
+
+impl BanditPolicy {
+    /// Picks one of the (non-empty) `arms`
+    #[expect(clippy::cast_precision_loss)]
+    fn choose<R: Rand>(&self, rand: &mut R, arms: &[BanditArm]) -> usize {
+        debug_assert!(!arms.is_empty());
+        match self {
+            Self::ThompsonSampling => {
+                let mut best = (0, f64::MIN);
+                for (idx, sample) in thompson_samples(rand, arms).into_iter().enumerate() {
+                    if sample > best.1 {
+                        best = (idx, sample);
+                    }
+                }
+                best.0
+            }
+            Self::Ucb1 { exploration } => {
+                let untried: Vec<usize> = (0..arms.len())
+                    .filter(|idx| arms[*idx].pulls == 0)
+                    .collect();
+                if !untried.is_empty() {
+                    return *rand.choose(&untried).unwrap();
+                }
+                let total: u64 = arms.iter().map(|arm| arm.pulls).sum();
+                let log_total = libm::log(total as f64);
+                let mut best = (0, f64::MIN);
+                for (idx, arm) in arms.iter().enumerate() {
+                    let score = arm.mean() + exploration * libm::sqrt(log_total / arm.pulls as f64);
+                    if score > best.1 {
+                        best = (idx, score);
+                    }
+                }
+                best.0
+            }
+            Self::Exp3 { gamma } => choose_weighted(rand, &exp3_probabilities(arms, *gamma)),
+        }
+    }
+
+    /// Rewards the arm `idx` with `reward` in `[0, 1]`
+    #[expect(clippy::cast_precision_loss)]
+    fn update(&self, arms: &mut [BanditArm], idx: usize, reward: f64) {
+        if let Self::Exp3 { gamma } = self {
+            let probability = exp3_probabilities(arms, *gamma)[idx];
+            let estimate = reward / probability;
+            arms[idx].weight *= libm::exp(gamma * estimate / arms.len() as f64);
+            // Keep the weights in range, only their ratios matter
+            let max = arms.iter().map(|arm| arm.weight).fold(0.0, f64::max);
+            if max > 1e100 {
+                for arm in arms.iter_mut() {
+                    arm.weight = (arm.weight / max).max(f64::MIN_POSITIVE);
+                }
+            }
+        }
+        arms[idx].pulls += 1;
+        arms[idx].rewards += reward;
+    }
+}
+
+/// A [`ScheduledMutator`] picking mutations and the stacking depth using a [`BanditPolicy`].
+///
+/// With Thompson sampling, the arms are sampled once per stack of mutations,
+/// and each mutation of the stack is picked proportionally to these samples.
+#[derive(Debug)]
+pub struct BanditScheduledMutator<MT> {
+    name: Cow<'static, str>,
+    mutations: MT,
+    policy: BanditPolicy,
+    max_stack_pow: usize,
+    mutation_log: Vec<MutationId>,
+    last_depth: Option<usize>,
+    /// The Thompson samples of the mutation arms, for the current stack
+    mutation_samples: Vec<f64>,
+    objectives_before: usize,
+}
+
+impl<MT> Named for BanditScheduledMutator<MT> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<MT> BanditScheduledMutator<MT>
+where
+    MT: NamedTuple,
+{
+    /// Create a new [`BanditScheduledMutator`] using Thompson sampling
+    pub fn new(mutations: MT) -> Self {
+        Self::with_policy(mutations, BanditPolicy::default())
+    }
+
+    /// Create a new [`BanditScheduledMutator`] using the given [`BanditPolicy`]
+    pub fn with_policy(mutations: MT, policy: BanditPolicy) -> Self {
+        Self {
+            name: Cow::from(format!(
+                "BanditScheduledMutator[{}]",
+                mutations.names().join(", ")
+            )),
+            mutations,
+            policy,
+            max_stack_pow: BANDIT_DEFAULT_MAX_STACK_POW,
+            mutation_log: vec![],
+            last_depth: None,
+            mutation_samples: vec![],
+            objectives_before: 0,
+        }
+    }
+
+    /// Sets the maximum stacking power, stacking at most `2^max_stack_pow` mutations
+    #[must_use]
+    pub fn with_max_stack_pow(mut self, max_stack_pow: usize) -> Self {
+        assert!(max_stack_pow > 0, "max_stack_pow must be at least 1");
+        self.max_stack_pow = max_stack_pow;
+        self
+    }
+
+    /// The [`BanditPolicy`] of this mutator
+    #[must_use]
+    pub fn policy(&self) -> BanditPolicy {
+        self.policy
+    }
+
+    /// Gets the learned state, initializing it if needed
+    fn metadata_mut<'a, S>(&self, state: &'a mut S) -> &'a mut BanditScheduledMutatorMetadata
+    where
+        S: HasNamedMetadata,
+    {
+        state.named_metadata_or_insert_with(&self.name, || {
+            BanditScheduledMutatorMetadata::new(MT::LEN, self.max_stack_pow)
+        })
+    }
+
+    /// Picks an arm, seeding a local rand from the state to read the metadata alongside
+    fn choose_arm<S, F>(&self, state: &mut S, arms: F) -> usize
+    where
+        S: HasRand + HasNamedMetadata,
+        F: FnOnce(&BanditScheduledMutatorMetadata) -> &[BanditArm],
+    {
+        let mut rand = StdRand::with_seed(state.rand_mut().next());
+        let meta = self.metadata_mut(state);
+        self.policy.choose(&mut rand, arms(meta))
+    }
+
+    /// Sample the mutation arms once for the next stack, if using Thompson sampling
+    fn sample_mutation_arms<S>(&mut self, state: &mut S)
+    where
+        S: HasRand + HasNamedMetadata,
+    {
+        self.mutation_samples.clear();
+        if self.policy == BanditPolicy::ThompsonSampling {
+            let mut rand = StdRand::with_seed(state.rand_mut().next());
+            let samples = thompson_samples(&mut rand, &self.metadata_mut(state).mutation_arms);
+            self.mutation_samples = samples;
+        }
+    }
+}
+
+impl<I, MT, S> Mutator<I, S> for BanditScheduledMutator<MT>
+where
+    MT: MutatorsTuple<I, S> + NamedTuple,
+    S: HasRand + HasNamedMetadata + HasCurrentTestcase<I>,
+{
+    #[inline]
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        self.objectives_before = current_objectives_found(state);
+        self.scheduled_mutate(state, input)
+    }
+
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        let found =
+            new_corpus_id.is_some() || current_objectives_found(state) > self.objectives_before;
+        let reward = if found { 1.0 } else { 0.0 };
+        let meta = self.metadata_mut(state);
+        if let Some(depth) = self.last_depth.take() {
+            self.policy.update(&mut meta.depth_arms, depth, reward);
+        }
+        for id in self.mutation_log.drain(..) {
+            if id.0 >= meta.mutation_arms.len() {
+                return Err(Error::key_not_found(format!("No mutation with {id}")));
+            }
+            self.policy.update(&mut meta.mutation_arms, id.0, reward);
+        }
+        Ok(())
+    }
+}
+
+impl<MT> ComposedByMutations for BanditScheduledMutator<MT> {
+    type Mutations = MT;
+    /// Get the mutations
+    #[inline]
+    fn mutations(&self) -> &MT {
+        &self.mutations
+    }
+
+    // Get the mutations (mutable)
+    #[inline]
+    fn mutations_mut(&mut self) -> &mut MT {
+        &mut self.mutations
+    }
+}
+
+impl<I, MT, S> ScheduledMutator<I, S> for BanditScheduledMutator<MT>
+where
+    MT: MutatorsTuple<I, S> + NamedTuple,
+    S: HasRand + HasNamedMetadata + HasCurrentTestcase<I>,
+{
+    /// Compute the number of iterations used to apply stacked mutations
+    fn iterations(&self, state: &mut S, _: &I) -> u64 {
+        1 << (1 + self.choose_arm(state, |meta| &meta.depth_arms))
+    }
+
+    /// Get the next mutation to apply
+    fn schedule(&self, state: &mut S, _: &I) -> MutationId {
+        debug_assert_ne!(MT::LEN, 0);
+        if self.mutation_samples.is_empty() {
+            self.choose_arm(state, |meta| &meta.mutation_arms).into()
+        } else {
+            choose_weighted(state.rand_mut(), &self.mutation_samples).into()
+        }
+    }
+
+    fn scheduled_mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let mut r = MutationResult::Skipped;
+        let depth = self.choose_arm(state, |meta| &meta.depth_arms);
+        self.last_depth = Some(depth);
+        self.mutation_log.clear();
+        self.sample_mutation_arms(state);
+        for _ in 0..(1_u64 << (1 + depth)) {
+            let idx = self.schedule(state, input);
+            let outcome = self.mutations_mut().get_and_mutate(idx, state, input)?;
+            if outcome == MutationResult::Mutated {
+                self.mutation_log.push(idx);
+                r = MutationResult::Mutated;
+            }
+        }
+        self.mutation_samples.clear();
+        Ok(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::{rands::StdRand, tuples::tuple_list};
+
+    use super::{BanditArm, BanditPolicy, BanditScheduledMutator, BanditScheduledMutatorMetadata};
+    use crate::{
+        HasNamedMetadata,
+        corpus::{Corpus, HasCurrentCorpusId, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        mutators::{
+            Mutator,
+            mutations::{BitFlipMutator, ByteIncMutator},
+        },
+        state::{HasCorpus, HasCurrentTestcase, StdState},
+    };
+
+    /// Arm 1 always pays off, the others never do; every policy should figure that out
+    #[test]
+    fn test_bandit_policies() {
+        for policy in [
+            BanditPolicy::ThompsonSampling,
+            BanditPolicy::Ucb1 {
+                exploration: core::f64::consts::SQRT_2,
+            },
+            BanditPolicy::Exp3 { gamma: 0.1 },
+        ] {
+            let mut rand = StdRand::with_seed(1337);
+            let mut arms = [BanditArm::default(); 4];
+            for _ in 0..2000 {
+                let idx = policy.choose(&mut rand, &arms);
+                policy.update(&mut arms, idx, if idx == 1 { 1.0 } else { 0.0 });
+            }
+            let best = arms.iter().map(|arm| arm.pulls).max().unwrap();
+            assert_eq!(arms[1].pulls, best, "{policy:?} did not learn: {arms:?}");
+            assert!(arms[1].pulls > 1000, "{policy:?} did not exploit: {arms:?}");
+        }
+    }
+
+    #[test]
+    fn test_bandit_scheduled_mutator() {
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let current = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![1, 2, 3, 4])))
+            .unwrap();
+        state.set_corpus_id(current).unwrap();
+
+        let mut mutator =
+            BanditScheduledMutator::new(tuple_list!(BitFlipMutator::new(), ByteIncMutator::new()))
+                .with_max_stack_pow(2);
+        let mut input = BytesInput::new(vec![1, 2, 3, 4]);
+        for i in 0..10 {
+            mutator.mutate(&mut state, &mut input).unwrap();
+            if i == 9 {
+                // As the fuzzer does when adding a solution
+                state.current_testcase_mut().unwrap().found_objective();
+            }
+            mutator.post_exec(&mut state, None).unwrap();
+        }
+
+        let meta = state
+            .named_metadata::<BanditScheduledMutatorMetadata>(&mutator.name)
+            .unwrap();
+        assert_eq!(meta.depth_arms.iter().map(|arm| arm.pulls).sum::<u64>(), 10);
+        // Only the stack finding the objective is rewarded
+        let rewards: f64 = meta.depth_arms.iter().map(|arm| arm.rewards).sum();
+        assert!((rewards - 1.0).abs() < f64::EPSILON);
+        assert!(
+            meta.mutation_arms.iter().map(|arm| arm.pulls).sum::<u64>() >= 20,
+            "Each stack applies at least two mutations"
+        );
+    }
+}
@@ -21,12 +21,18 @@ pub mod mopt_mutator;
 pub use mopt_mutator::*;
 pub mod gramatron;
 pub use gramatron::*;
+pub mod protobuf;
+pub use protobuf::*;
 pub mod grimoire;
 pub use grimoire::*;
 pub mod mapping;
 pub use mapping::*;
 pub mod tuneable;
 pub use tuneable::*;
+pub mod mutation_stats;
+pub use mutation_stats::*;
+pub mod bandit;
+pub use bandit::*;
 
 #[cfg(feature = "lua_mutator")]
 pub mod lua;
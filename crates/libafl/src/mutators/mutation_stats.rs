@@ -0,0 +1,277 @@
+//! Per-mutation effectiveness accounting.
+//!
+//! The [`StatsScheduledMutator`] wraps a [`ScheduledMutator`] and counts, for each of its mutations,
+//! how often it was applied and how often an input it contributed to was added to the corpus or the solutions.
+//! The counters are kept in the [`MutationStatsMetadata`] of the state, grouped by stage,
+//! and can be reported to the monitors with the [`crate::stages::MutationStatsStage`].
+
+use alloc::{borrow::Cow, collections::BTreeMap, vec::Vec};
+
+use libafl_bolts::{
+    Named,
+    tuples::{HasConstLen, NamedTuple},
+};
+use serde::{Deserialize, Serialize};
+
+use super::MutationId;
+use crate::{
+    Error, HasMetadata,
+    corpus::CorpusId,
+    mutators::{ComposedByMutations, MutationResult, Mutator, MutatorsTuple, ScheduledMutator},
+    state::HasCurrentTestcase,
+};
+
+/// The counters of a single mutation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MutationStats {
+    /// The name of the mutation
+    pub name: Cow<'static, str>,
+    /// How often this mutation was applied
+    pub applied: u64,
+    /// How often an input this mutation was applied to was added to the corpus
+    pub corpus_finds: u64,
+    /// How often an input this mutation was applied to was added to the solutions
+    pub objective_finds: u64,
+}
+
+/// The per-mutation counters of all [`StatsScheduledMutator`]s, by stage name
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct MutationStatsMetadata {
+    /// The counters, by stage name, indexed by [`MutationId`]
+    pub stages: BTreeMap<Cow<'static, str>, Vec<MutationStats>>,
+}
+
+libafl_bolts::impl_serdeany!(MutationStatsMetadata);
+
+impl MutationStatsMetadata {
+    /// Creates new [`struct@MutationStatsMetadata`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The counters of the mutations of the given stage, indexed by [`MutationId`]
+    #[must_use]
+    pub fn stage(&self, stage: &str) -> Option<&[MutationStats]> {
+        self.stages.get(stage).map(Vec::as_slice)
+    }
+
+    /// The counters of a single mutation of the given stage
+    #[must_use]
+    pub fn get(&self, stage: &str, id: MutationId) -> Option<&MutationStats> {
+        self.stage(stage)?.get(id.0)
+    }
+}
+
+/// A [`ScheduledMutator`] wrapper counting the applications and finds of each of its mutations.
+///
+/// Inputs are attributed to all mutations that were applied to them.
+/// Solutions are noticed by the [`crate::corpus::Testcase::objectives_found`] of the current testcase,
+/// counted by the fuzzer and the executors when they add a solution.
+#[derive(Debug)]
+pub struct StatsScheduledMutator<SM> {
+    name: Cow<'static, str>,
+    stage_name: Cow<'static, str>,
+    scheduled: SM,
+    mutation_log: Vec<MutationId>,
+    objectives_before: usize,
+}
+
+impl<SM> Named for StatsScheduledMutator<SM> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<SM> StatsScheduledMutator<SM>
+where
+    SM: Named,
+{
+    /// Create a new [`StatsScheduledMutator`], accounting the mutations of `scheduled` under `stage_name`
+    pub fn new<N>(stage_name: N, scheduled: SM) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        Self {
+            name: Cow::from(format!("StatsScheduledMutator[{}]", scheduled.name())),
+            stage_name: stage_name.into(),
+            scheduled,
+            mutation_log: vec![],
+            objectives_before: 0,
+        }
+    }
+
+    /// The name under which the mutations are accounted
+    #[must_use]
+    pub fn stage_name(&self) -> &str {
+        &self.stage_name
+    }
+}
+
+/// The objectives found by mutating the current testcase so far
+pub(crate) fn current_objectives_found<I, S>(state: &S) -> usize
+where
+    S: HasCurrentTestcase<I>,
+{
+    state
+        .current_testcase()
+        .map_or(0, |testcase| testcase.objectives_found())
+}
+
+impl<I, S, SM> Mutator<I, S> for StatsScheduledMutator<SM>
+where
+    S: HasMetadata + HasCurrentTestcase<I>,
+    SM: ScheduledMutator<I, S>,
+    SM::Mutations: MutatorsTuple<I, S> + NamedTuple,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        self.objectives_before = current_objectives_found(state);
+        self.scheduled_mutate(state, input)
+    }
+
+    fn post_exec(&mut self, state: &mut S, corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        let objective = current_objectives_found(state) > self.objectives_before;
+        let meta = state.metadata_or_insert_with(MutationStatsMetadata::new);
+        let stats = meta.stages.entry(self.stage_name.clone()).or_default();
+        if stats.is_empty() {
+            let mutations = self.scheduled.mutations();
+            *stats = (0..<SM::Mutations as HasConstLen>::LEN)
+                .map(|idx| MutationStats {
+                    name: mutations.name(idx).cloned().unwrap_or_default(),
+                    ..MutationStats::default()
+                })
+                .collect();
+        }
+        for id in self.mutation_log.drain(..) {
+            let Some(entry) = stats.get_mut(id.0) else {
+                return Err(Error::key_not_found(format!("No mutation with {id}")));
+            };
+            entry.applied += 1;
+            if corpus_id.is_some() {
+                entry.corpus_finds += 1;
+            }
+            if objective {
+                entry.objective_finds += 1;
+            }
+        }
+        self.scheduled.post_exec(state, corpus_id)
+    }
+}
+
+impl<SM> ComposedByMutations for StatsScheduledMutator<SM>
+where
+    SM: ComposedByMutations,
+{
+    type Mutations = SM::Mutations;
+    #[inline]
+    fn mutations(&self) -> &SM::Mutations {
+        self.scheduled.mutations()
+    }
+
+    #[inline]
+    fn mutations_mut(&mut self) -> &mut SM::Mutations {
+        self.scheduled.mutations_mut()
+    }
+}
+
+impl<I, S, SM> ScheduledMutator<I, S> for StatsScheduledMutator<SM>
+where
+    S: HasMetadata + HasCurrentTestcase<I>,
+    SM: ScheduledMutator<I, S>,
+    SM::Mutations: MutatorsTuple<I, S> + NamedTuple,
+{
+    /// Compute the number of iterations used to apply stacked mutations
+    #[inline]
+    fn iterations(&self, state: &mut S, input: &I) -> u64 {
+        self.scheduled.iterations(state, input)
+    }
+
+    /// Get the next mutation to apply
+    #[inline]
+    fn schedule(&self, state: &mut S, input: &I) -> MutationId {
+        self.scheduled.schedule(state, input)
+    }
+
+    fn scheduled_mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let mut r = MutationResult::Skipped;
+        let num = self.iterations(state, input);
+        self.mutation_log.clear();
+        for _ in 0..num {
+            let idx = self.schedule(state, input);
+            let outcome = self.mutations_mut().get_and_mutate(idx, state, input)?;
+            if outcome == MutationResult::Mutated {
+                self.mutation_log.push(idx);
+                r = MutationResult::Mutated;
+            }
+        }
+        Ok(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::{rands::StdRand, tuples::tuple_list};
+
+    use super::{MutationStatsMetadata, StatsScheduledMutator};
+    use crate::{
+        HasMetadata,
+        corpus::{Corpus, CorpusId, HasCurrentCorpusId, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        mutators::{
+            Mutator,
+            mutations::{BitFlipMutator, ByteIncMutator},
+            scheduled::SingleChoiceScheduledMutator,
+        },
+        state::{HasCorpus, HasCurrentTestcase, StdState},
+    };
+
+    #[test]
+    fn test_mutation_stats() {
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let current = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![1, 2, 3, 4])))
+            .unwrap();
+        state.set_corpus_id(current).unwrap();
+
+        let mut mutator = StatsScheduledMutator::new(
+            "havoc",
+            SingleChoiceScheduledMutator::new(tuple_list!(
+                BitFlipMutator::new(),
+                ByteIncMutator::new()
+            )),
+        );
+        let mut input = BytesInput::new(vec![1, 2, 3, 4]);
+        for i in 0..10 {
+            mutator.mutate(&mut state, &mut input).unwrap();
+            let corpus_id = (i % 2 == 0).then_some(CorpusId(0));
+            if i == 9 {
+                // As the fuzzer does when adding a solution
+                state.current_testcase_mut().unwrap().found_objective();
+            }
+            mutator.post_exec(&mut state, corpus_id).unwrap();
+        }
+
+        let meta = state.metadata::<MutationStatsMetadata>().unwrap();
+        let stats = meta.stage("havoc").unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].name, "BitFlipMutator");
+        assert_eq!(stats.iter().map(|s| s.applied).sum::<u64>(), 10);
+        assert_eq!(stats.iter().map(|s| s.corpus_finds).sum::<u64>(), 5);
+        assert_eq!(stats.iter().map(|s| s.objective_finds).sum::<u64>(), 1);
+    }
+}
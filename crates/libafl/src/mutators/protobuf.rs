@@ -0,0 +1,701 @@
+//! Schema-aware mutators for [`ProtobufInput`]s.
+//!
+//! Unlike byte-level mutations, these mutators keep the input a well-formed message of the
+//! [`ProtobufSchema`], so that the target's deserialization succeeds and deeper logic is reached.
+use alloc::{borrow::Cow, vec::Vec};
+use core::mem;
+
+use libafl_bolts::{
+    Named,
+    rands::{Rand, choose},
+};
+use tuple_list::{tuple_list, tuple_list_type};
+
+use crate::{
+    Error,
+    corpus::{Corpus, CorpusId},
+    generators::ProtobufGenerator,
+    inputs::{
+        ProtobufField, ProtobufFieldDescriptor, ProtobufFieldType, ProtobufInput, ProtobufMessage,
+        ProtobufSchema, ProtobufValue,
+    },
+    mutators::{MutationResult, Mutator},
+    nonzero, random_corpus_id,
+    state::{HasCorpus, HasMaxSize, HasRand},
+};
+
+/// Picks the index of a random message of the input's message tree, see [`ProtobufMessage::nth_message_mut`]
+fn random_message_idx<R>(rand: &mut R, input: &ProtobufInput) -> usize
+where
+    R: Rand,
+{
+    rand.below_or_zero(input.message().message_count())
+}
+
+/// Picks a random message of the input's message tree
+fn random_message_mut<'a, R>(rand: &mut R, input: &'a mut ProtobufInput) -> &'a mut ProtobufMessage
+where
+    R: Rand,
+{
+    let idx = random_message_idx(rand, input);
+    input.message_mut().nth_message_mut(idx).unwrap()
+}
+
+/// Returns `true` if the input, serialized to the wire format, is larger than `max_size`
+fn exceeds_max_size(input: &ProtobufInput, max_size: usize) -> bool {
+    input.to_bytes().len() > max_size
+}
+
+/// Applies a random byte-level mutation to the contents of a `bytes` or `string` field,
+/// growing it by at most `max_growth` bytes
+fn mutate_bytes<R>(rand: &mut R, bytes: &mut Vec<u8>, max_growth: usize) -> MutationResult
+where
+    R: Rand,
+{
+    if bytes.is_empty() {
+        if max_growth == 0 {
+            return MutationResult::Skipped;
+        }
+        bytes.push(rand.next() as u8);
+        return MutationResult::Mutated;
+    }
+    let idx = rand.below_or_zero(bytes.len());
+    // Without room to grow, only flip, set or remove bytes
+    let ops = if max_growth > 0 {
+        nonzero!(5)
+    } else {
+        nonzero!(3)
+    };
+    match rand.below(ops) {
+        0 => bytes[idx] ^= 1 << rand.below(nonzero!(8)),
+        1 => bytes[idx] = rand.next() as u8,
+        2 => {
+            bytes.remove(idx);
+        }
+        3 => bytes.insert(idx, rand.next() as u8),
+        _ => {
+            // Duplicate a random chunk, to grow lengths quickly
+            let end = rand.between(idx, bytes.len() - 1) + 1;
+            let end = end.min(idx + max_growth);
+            let chunk = bytes[idx..end].to_vec();
+            bytes.splice(idx..idx, chunk);
+        }
+    }
+    MutationResult::Mutated
+}
+
+/// Applies a random numeric mutation to an integer
+fn mutate_int<R>(rand: &mut R, value: u64, bits: usize) -> u64
+where
+    R: Rand,
+{
+    let delta = rand.between(1, 16) as u64;
+    match rand.below(nonzero!(4)) {
+        0 => value ^ (1 << rand.below_or_zero(bits)),
+        1 => value.wrapping_add(delta),
+        2 => value.wrapping_sub(delta),
+        _ => value.wrapping_neg(),
+    }
+}
+
+/// A [`Mutator`] adding a field, generated following the schema, to a random message of a [`ProtobufInput`].
+/// For non-repeated fields that are already present, the value is replaced instead.
+/// Skips if the serialized input would exceed the [`HasMaxSize::max_size`].
+#[derive(Debug)]
+pub struct ProtobufAddFieldMutator<'a, S> {
+    generator: &'a ProtobufGenerator<'a, S>,
+}
+
+impl<S> Mutator<ProtobufInput, S> for ProtobufAddFieldMutator<'_, S>
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput,
+    ) -> Result<MutationResult, Error> {
+        let max_size = state.max_size();
+        let rand = state.rand_mut();
+        let message_idx = random_message_idx(rand, input);
+        let message = input.message_mut().nth_message_mut(message_idx).unwrap();
+        let Some(descriptor) = self.generator.schema().message(&message.type_name) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some(field) = rand.choose(&descriptor.fields) else {
+            return Ok(MutationResult::Skipped);
+        };
+        // The message sits somewhere in the tree, generate shallow values only
+        let Some(value) = self.generator.generate_value(rand, field, 1) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let existing = message
+            .fields
+            .iter()
+            .position(|present| present.number == field.number);
+        let (idx, replaced) = match existing {
+            Some(idx) if !field.repeated => (
+                idx,
+                Some(mem::replace(&mut message.fields[idx].value, value)),
+            ),
+            _ => {
+                let idx = rand.below_or_zero(message.fields.len() + 1);
+                message.fields.insert(
+                    idx,
+                    ProtobufField {
+                        number: field.number,
+                        value,
+                    },
+                );
+                (idx, None)
+            }
+        };
+
+        if exceeds_max_size(input, max_size) {
+            // Fields are only added to this message, so it keeps its index in the tree
+            let message = input.message_mut().nth_message_mut(message_idx).unwrap();
+            match replaced {
+                Some(value) => message.fields[idx].value = value,
+                None => {
+                    message.fields.remove(idx);
+                }
+            }
+            return Ok(MutationResult::Skipped);
+        }
+        Ok(MutationResult::Mutated)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<S> Named for ProtobufAddFieldMutator<'_, S> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufAddFieldMutator");
+        &NAME
+    }
+}
+
+impl<'a, S> ProtobufAddFieldMutator<'a, S> {
+    /// Creates a new [`ProtobufAddFieldMutator`].
+    #[must_use]
+    pub fn new(generator: &'a ProtobufGenerator<'a, S>) -> Self {
+        Self { generator }
+    }
+}
+
+/// A [`Mutator`] removing a random field from a random message of a [`ProtobufInput`]
+#[derive(Debug, Default)]
+pub struct ProtobufRemoveFieldMutator;
+
+impl<S> Mutator<ProtobufInput, S> for ProtobufRemoveFieldMutator
+where
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput,
+    ) -> Result<MutationResult, Error> {
+        let rand = state.rand_mut();
+        let message = random_message_mut(rand, input);
+        if message.fields.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let idx = rand.below_or_zero(message.fields.len());
+        message.fields.remove(idx);
+        Ok(MutationResult::Mutated)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for ProtobufRemoveFieldMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufRemoveFieldMutator");
+        &NAME
+    }
+}
+
+impl ProtobufRemoveFieldMutator {
+    /// Creates a new [`ProtobufRemoveFieldMutator`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// A [`Mutator`] changing the value of a random field of a [`ProtobufInput`], depending on its type.
+/// Enums take valid values, nested messages are regenerated.
+/// Skips if the serialized input would exceed the [`HasMaxSize::max_size`].
+#[derive(Debug)]
+pub struct ProtobufChangeFieldMutator<'a, S> {
+    generator: &'a ProtobufGenerator<'a, S>,
+}
+
+impl<S> Mutator<ProtobufInput, S> for ProtobufChangeFieldMutator<'_, S>
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput,
+    ) -> Result<MutationResult, Error> {
+        let max_size = state.max_size();
+        let size = input.to_bytes().len();
+        let rand = state.rand_mut();
+        let message_idx = random_message_idx(rand, input);
+        let message = input.message_mut().nth_message_mut(message_idx).unwrap();
+        if message.fields.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let idx = rand.below_or_zero(message.fields.len());
+        let descriptor: Option<&ProtobufFieldDescriptor> = self
+            .generator
+            .schema()
+            .message(&message.type_name)
+            .and_then(|descriptor| descriptor.field(message.fields[idx].number));
+
+        let original = message.fields[idx].value.clone();
+        let value = &mut message.fields[idx].value;
+        match (descriptor.map(|field| field.ty), value) {
+            (Some(ProtobufFieldType::Bool), ProtobufValue::Varint(value)) => *value ^= 1,
+            (
+                Some(
+                    ProtobufFieldType::Enum | ProtobufFieldType::Message | ProtobufFieldType::Group,
+                ),
+                value,
+            ) => {
+                let Some(generated) = self.generator.generate_value(rand, descriptor.unwrap(), 1)
+                else {
+                    return Ok(MutationResult::Skipped);
+                };
+                *value = generated;
+            }
+            (_, ProtobufValue::Varint(value)) => {
+                // Sometimes pick a fresh (interesting) value instead of a small change
+                let generated = match descriptor {
+                    Some(field) if rand.coinflip(0.25) => {
+                        match self.generator.generate_value(rand, field, 1) {
+                            Some(ProtobufValue::Varint(generated)) => Some(generated),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                *value = match generated {
+                    Some(generated) => generated,
+                    None => mutate_int(rand, *value, 64),
+                };
+            }
+            (_, ProtobufValue::Fixed64(value)) => *value = mutate_int(rand, *value, 64),
+            (_, ProtobufValue::Fixed32(value)) => {
+                *value = mutate_int(rand, u64::from(*value), 32) as u32;
+            }
+            (_, ProtobufValue::Bytes(bytes)) => {
+                if mutate_bytes(rand, bytes, max_size.saturating_sub(size))
+                    == MutationResult::Skipped
+                {
+                    return Ok(MutationResult::Skipped);
+                }
+            }
+            (_, ProtobufValue::Message(_) | ProtobufValue::Group(_)) => {
+                return Ok(MutationResult::Skipped);
+            }
+        }
+
+        // Longer values and regenerated messages may still grow the length prefixes
+        if exceeds_max_size(input, max_size) {
+            let message = input.message_mut().nth_message_mut(message_idx).unwrap();
+            message.fields[idx].value = original;
+            return Ok(MutationResult::Skipped);
+        }
+        Ok(MutationResult::Mutated)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<S> Named for ProtobufChangeFieldMutator<'_, S> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufChangeFieldMutator");
+        &NAME
+    }
+}
+
+impl<'a, S> ProtobufChangeFieldMutator<'a, S> {
+    /// Creates a new [`ProtobufChangeFieldMutator`].
+    #[must_use]
+    pub fn new(generator: &'a ProtobufGenerator<'a, S>) -> Self {
+        Self { generator }
+    }
+}
+
+/// A [`Mutator`] splicing the values of a repeated field with the values of the same field
+/// in a message of the same type, taken from another corpus entry.
+/// Skips if the serialized input would exceed the [`HasMaxSize::max_size`].
+#[derive(Debug)]
+pub struct ProtobufRepeatedSpliceMutator<'a> {
+    schema: &'a ProtobufSchema,
+}
+
+impl<S> Mutator<ProtobufInput, S> for ProtobufRepeatedSpliceMutator<'_>
+where
+    S: HasRand + HasCorpus<ProtobufInput> + HasMaxSize,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput,
+    ) -> Result<MutationResult, Error> {
+        if state.corpus().count() == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+        let max_size = state.max_size();
+        let message_idx = random_message_idx(state.rand_mut(), input);
+        let message = input.message_mut().nth_message_mut(message_idx).unwrap();
+        let Some(descriptor) = self.schema.message(&message.type_name) else {
+            return Ok(MutationResult::Skipped);
+        };
+        // Only repeated fields may occur more than once
+        let repeated = message
+            .fields
+            .iter()
+            .map(|field| field.number)
+            .filter(|number| {
+                descriptor
+                    .field(*number)
+                    .is_some_and(|field| field.repeated)
+            });
+        let Some(number) = state.rand_mut().choose(repeated) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let id = random_corpus_id!(state.corpus(), state.rand_mut());
+        let rand_num = state.rand_mut().next();
+        let others: Vec<ProtobufField> = {
+            let mut other_testcase = state.corpus().get(id)?.borrow_mut();
+            let other = other_testcase.load_input(state.corpus())?;
+            let Some(other_message) = choose(
+                other.message().messages_of_type(&message.type_name),
+                rand_num,
+            ) else {
+                return Ok(MutationResult::Skipped);
+            };
+            other_message
+                .fields
+                .iter()
+                .filter(|field| field.number == number)
+                .cloned()
+                .collect()
+        };
+        if others.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        // Replace a random range of our values with a random range of theirs
+        let ours: Vec<usize> = message
+            .fields
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| field.number == number)
+            .map(|(idx, _)| idx)
+            .collect();
+        let rand = state.rand_mut();
+        let start = rand.below_or_zero(ours.len());
+        let end = rand.between(start, ours.len() - 1) + 1;
+        let other_start = rand.below_or_zero(others.len());
+        let other_end = rand.between(other_start, others.len() - 1) + 1;
+
+        let original = message.fields.clone();
+        for idx in ours[start..end].iter().rev() {
+            message.fields.remove(*idx);
+        }
+        let insert_at = ours[start];
+        message.fields.splice(
+            insert_at..insert_at,
+            others[other_start..other_end].iter().cloned(),
+        );
+
+        if exceeds_max_size(input, max_size) {
+            input
+                .message_mut()
+                .nth_message_mut(message_idx)
+                .unwrap()
+                .fields = original;
+            return Ok(MutationResult::Skipped);
+        }
+        Ok(MutationResult::Mutated)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for ProtobufRepeatedSpliceMutator<'_> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufRepeatedSpliceMutator");
+        &NAME
+    }
+}
+
+impl<'a> ProtobufRepeatedSpliceMutator<'a> {
+    /// Creates a new [`ProtobufRepeatedSpliceMutator`] for messages of the given schema.
+    #[must_use]
+    pub fn new(schema: &'a ProtobufSchema) -> Self {
+        Self { schema }
+    }
+}
+
+/// A [`Mutator`] replacing a random nested message with a message of the same type
+/// taken from another corpus entry, i.e., a structure-preserving crossover.
+/// Skips if the serialized input would exceed the [`HasMaxSize::max_size`].
+#[derive(Debug, Default)]
+pub struct ProtobufCrossoverMutator;
+
+impl<S> Mutator<ProtobufInput, S> for ProtobufCrossoverMutator
+where
+    S: HasRand + HasCorpus<ProtobufInput> + HasMaxSize,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput,
+    ) -> Result<MutationResult, Error> {
+        if state.corpus().count() == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+        let max_size = state.max_size();
+        let message_idx = random_message_idx(state.rand_mut(), input);
+        let message = input.message_mut().nth_message_mut(message_idx).unwrap();
+
+        let id = random_corpus_id!(state.corpus(), state.rand_mut());
+        let rand_num = state.rand_mut().next();
+        let mut other_testcase = state.corpus().get(id)?.borrow_mut();
+        let other = other_testcase.load_input(state.corpus())?;
+        let Some(other_message) = choose(
+            other.message().messages_of_type(&message.type_name),
+            rand_num,
+        ) else {
+            return Ok(MutationResult::Skipped);
+        };
+        if *other_message == *message {
+            return Ok(MutationResult::Skipped);
+        }
+        let original = mem::replace(message, other_message.clone());
+
+        if exceeds_max_size(input, max_size) {
+            *input.message_mut().nth_message_mut(message_idx).unwrap() = original;
+            return Ok(MutationResult::Skipped);
+        }
+        Ok(MutationResult::Mutated)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for ProtobufCrossoverMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufCrossoverMutator");
+        &NAME
+    }
+}
+
+impl ProtobufCrossoverMutator {
+    /// Creates a new [`ProtobufCrossoverMutator`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Tuple type of the mutations that compose the protobuf mutator
+pub type ProtobufMutationsType<'a, S> = tuple_list_type!(
+    ProtobufAddFieldMutator<'a, S>,
+    ProtobufRemoveFieldMutator,
+    ProtobufChangeFieldMutator<'a, S>,
+    ProtobufRepeatedSpliceMutator<'a>,
+    ProtobufCrossoverMutator,
+);
+
+/// Get the mutations that compose the protobuf mutator
+#[must_use]
+pub fn protobuf_mutations<'a, S>(
+    generator: &'a ProtobufGenerator<'a, S>,
+) -> ProtobufMutationsType<'a, S> {
+    tuple_list!(
+        ProtobufAddFieldMutator::new(generator),
+        ProtobufRemoveFieldMutator::new(),
+        ProtobufChangeFieldMutator::new(generator),
+        ProtobufRepeatedSpliceMutator::new(generator.schema()),
+        ProtobufCrossoverMutator::new(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec, vec::Vec};
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::{
+        ProtobufAddFieldMutator, ProtobufChangeFieldMutator, ProtobufRepeatedSpliceMutator,
+    };
+    use crate::{
+        corpus::{Corpus, InMemoryCorpus},
+        feedbacks::ConstFeedback,
+        generators::ProtobufGenerator,
+        inputs::{
+            ProtobufFieldDescriptor, ProtobufFieldType, ProtobufInput, ProtobufMessage,
+            ProtobufMessageDescriptor, ProtobufSchema, ProtobufValue,
+        },
+        mutators::{MutationResult, Mutator},
+        state::{HasMaxSize, StdState},
+    };
+
+    fn field(
+        number: u32,
+        repeated: bool,
+        ty: ProtobufFieldType,
+        type_name: Option<&str>,
+    ) -> ProtobufFieldDescriptor {
+        ProtobufFieldDescriptor {
+            name: format!("field{number}"),
+            number,
+            repeated,
+            ty,
+            type_name: type_name.map(ToString::to_string),
+        }
+    }
+
+    fn outer(values: &[u64], name: &[u8]) -> ProtobufInput {
+        let mut message = ProtobufMessage::new("test.Outer".to_string());
+        message.push(2, ProtobufValue::Bytes(name.to_vec()));
+        for value in values {
+            message.push(1, ProtobufValue::Varint(*value));
+        }
+        ProtobufInput::new(message)
+    }
+
+    #[test]
+    fn test_protobuf_repeated_splice_mutator() {
+        let mut schema = ProtobufSchema::default();
+        schema.add_message(ProtobufMessageDescriptor {
+            name: "test.Outer".to_string(),
+            fields: vec![
+                field(1, true, ProtobufFieldType::Uint64, None),
+                field(2, false, ProtobufFieldType::String, None),
+            ],
+        });
+        let mut corpus = InMemoryCorpus::new();
+        corpus.add(outer(&[3, 4], b"theirs").into()).unwrap();
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            corpus,
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+
+        let mut mutator = ProtobufRepeatedSpliceMutator::new(&schema);
+        let mut mutated = false;
+        for _ in 0..100 {
+            let mut input = outer(&[1, 2], b"ours");
+            mutated |= mutator.mutate(&mut state, &mut input).unwrap() == MutationResult::Mutated;
+            // The non-repeated name is never spliced
+            let names: Vec<_> = input
+                .message()
+                .fields
+                .iter()
+                .filter(|field| field.number == 2)
+                .collect();
+            assert_eq!(names.len(), 1);
+            assert_eq!(names[0].value, ProtobufValue::Bytes(b"ours".to_vec()));
+        }
+        assert!(mutated);
+    }
+
+    #[test]
+    fn test_protobuf_add_field_mutator_missing_type() {
+        // `Missing` is not part of the schema, e.g. defined in a file missing from the descriptor set
+        let mut schema = ProtobufSchema::default();
+        schema.add_message(ProtobufMessageDescriptor {
+            name: "test.Outer".to_string(),
+            fields: vec![field(
+                1,
+                false,
+                ProtobufFieldType::Message,
+                Some("test.Missing"),
+            )],
+        });
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<ProtobufInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+
+        let generator = ProtobufGenerator::new(&schema, "test.Outer").unwrap();
+        let mut mutator = ProtobufAddFieldMutator::new(&generator);
+        let mut input = ProtobufInput::new(ProtobufMessage::new("test.Outer".to_string()));
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Skipped
+        );
+        assert!(input.message().fields.is_empty());
+    }
+
+    #[test]
+    fn test_protobuf_mutators_max_size() {
+        let mut schema = ProtobufSchema::default();
+        schema.add_message(ProtobufMessageDescriptor {
+            name: "test.Outer".to_string(),
+            fields: vec![
+                field(1, true, ProtobufFieldType::Uint64, None),
+                field(2, false, ProtobufFieldType::String, None),
+            ],
+        });
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<ProtobufInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        let max_size = outer(&[1, 2], b"ours").to_bytes().len();
+        state.set_max_size(max_size);
+
+        let generator = ProtobufGenerator::new(&schema, "test.Outer").unwrap();
+        let mut add_field = ProtobufAddFieldMutator::new(&generator);
+        let mut change_field = ProtobufChangeFieldMutator::new(&generator);
+        let mut input = outer(&[1, 2], b"ours");
+        let mut mutated = false;
+        for _ in 0..1000 {
+            add_field.mutate(&mut state, &mut input).unwrap();
+            assert!(input.to_bytes().len() <= max_size);
+            mutated |=
+                change_field.mutate(&mut state, &mut input).unwrap() == MutationResult::Mutated;
+            assert!(input.to_bytes().len() <= max_size);
+        }
+        assert!(mutated);
+    }
+}
@@ -9,6 +9,11 @@ pub mod stdio;
 #[cfg(feature = "std")]
 pub use stdio::{StdErrObserver, StdOutObserver};
 
+#[cfg(feature = "std")]
+pub mod network;
+#[cfg(feature = "std")]
+pub use network::{NetworkResponseObserver, ResponseStateExtractor};
+
 #[cfg(feature = "regex")]
 pub mod stacktrace;
 #[cfg(feature = "regex")]
@@ -0,0 +1,175 @@
+//! The [`NetworkResponseObserver`] captures the responses of a network target, one per message sent.
+//!
+//! The executor must explicitly support this observer, such as the `NetworkExecutor`.
+//! With a [`ResponseStateExtractor`], the observer also derives a protocol state from each response,
+//! for the `ProtocolStateFeedback`.
+
+use alloc::{borrow::Cow, boxed::Box, vec::Vec};
+use core::fmt::{self, Debug, Formatter};
+
+use libafl_bolts::{Named, hash_std};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, observers::Observer};
+
+/// Derives the protocol state of a server from a response, such as the status code.
+///
+/// States are identified by the hash of the extracted bytes.
+pub enum ResponseStateExtractor {
+    /// The first `n` bytes of each response, such as the status codes of FTP, SMTP, or RTSP.
+    /// Shorter responses have no state.
+    Prefix(usize),
+    /// The first capture group of this regex, or the whole match if it has no groups
+    #[cfg(feature = "regex")]
+    Regex(regex::bytes::Regex),
+    /// A function or closure returning the state of a response, if any
+    Fn(Box<dyn Fn(&[u8]) -> Option<u64>>),
+}
+
+impl Debug for ResponseStateExtractor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Prefix(len) => f.debug_tuple("Prefix").field(len).finish(),
+            #[cfg(feature = "regex")]
+            Self::Regex(regex) => f.debug_tuple("Regex").field(regex).finish(),
+            Self::Fn(_) => f.debug_tuple("Fn").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl ResponseStateExtractor {
+    /// The state of this response, or `None` if it has no state
+    #[must_use]
+    pub fn extract(&self, response: &[u8]) -> Option<u64> {
+        match self {
+            Self::Prefix(len) => response.get(..*len).map(hash_std),
+            #[cfg(feature = "regex")]
+            Self::Regex(regex) => {
+                let captures = regex.captures(response)?;
+                let state = captures.get(1).or_else(|| captures.get(0))?;
+                Some(hash_std(state.as_bytes()))
+            }
+            Self::Fn(extract) => extract(response),
+        }
+    }
+}
+
+/// An observer that captures the responses of a network target during the last execution.
+/// Only works for supported executors.
+///
+/// The [`ResponseStateExtractor`] is not serialized, it has to be set again on deserialized observers
+/// with [`NetworkResponseObserver::set_state_extractor`] before they observe responses.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NetworkResponseObserver {
+    name: Cow<'static, str>,
+    /// The responses to each message sent during the last execution,
+    /// empty if the target didn't respond to a message.
+    /// Messages after the connection was closed have no response.
+    pub responses: Vec<Vec<u8>>,
+    /// The protocol states reached during the last execution, in order,
+    /// if this observer has a [`ResponseStateExtractor`].
+    /// Responses without state are skipped.
+    pub states: Vec<u64>,
+    #[serde(skip)]
+    extractor: Option<ResponseStateExtractor>,
+    /// If this observer has a [`ResponseStateExtractor`], even if it was not deserialized
+    has_extractor: bool,
+}
+
+impl NetworkResponseObserver {
+    /// Create a new [`NetworkResponseObserver`] with the given name
+    #[must_use]
+    pub fn new(name: Cow<'static, str>) -> Self {
+        Self {
+            name,
+            responses: vec![],
+            states: vec![],
+            extractor: None,
+            has_extractor: false,
+        }
+    }
+
+    /// Derive the protocol states from the responses with this [`ResponseStateExtractor`]
+    #[must_use]
+    pub fn with_state_extractor(mut self, extractor: ResponseStateExtractor) -> Self {
+        self.set_state_extractor(extractor);
+        self
+    }
+
+    /// Derive the protocol states from the responses with this [`ResponseStateExtractor`],
+    /// for example again after this observer was deserialized
+    pub fn set_state_extractor(&mut self, extractor: ResponseStateExtractor) {
+        self.extractor = Some(extractor);
+        self.has_extractor = true;
+    }
+
+    /// Store the responses of the last execution, called by the executor.
+    ///
+    /// Fails if the [`ResponseStateExtractor`] was lost in serialization, instead of silently observing no states.
+    pub fn observe(&mut self, responses: Vec<Vec<u8>>) -> Result<(), Error> {
+        match &self.extractor {
+            Some(extractor) => {
+                self.states = responses
+                    .iter()
+                    .filter_map(|response| extractor.extract(response))
+                    .collect();
+            }
+            None if self.has_extractor => {
+                return Err(Error::illegal_state(format!(
+                    "The state extractor of {} was not deserialized, set it again with `set_state_extractor`",
+                    self.name
+                )));
+            }
+            None => {}
+        }
+        self.responses = responses;
+        Ok(())
+    }
+}
+
+impl Named for NetworkResponseObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Observer<I, S> for NetworkResponseObserver {
+    fn pre_exec_child(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.responses.clear();
+        self.states.clear();
+        Ok(())
+    }
+
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.responses.clear();
+        self.states.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::Cow, boxed::Box, vec};
+
+    use super::{NetworkResponseObserver, ResponseStateExtractor};
+
+    #[test]
+    fn test_state_extractor() {
+        let mut observer = NetworkResponseObserver::new(Cow::Borrowed("responses"))
+            .with_state_extractor(ResponseStateExtractor::Fn(Box::new(|response| {
+                response.first().map(|code| u64::from(*code))
+            })));
+        observer
+            .observe(vec![b"2 ok".to_vec(), vec![], b"5 no".to_vec()])
+            .unwrap();
+        assert_eq!(observer.states, [u64::from(b'2'), u64::from(b'5')]);
+
+        // The extractor is lost in serialization
+        let mut observer: NetworkResponseObserver =
+            postcard::from_bytes(&postcard::to_allocvec(&observer).unwrap()).unwrap();
+        assert!(observer.observe(vec![b"2 ok".to_vec()]).is_err());
+        observer.set_state_extractor(ResponseStateExtractor::Prefix(1));
+        observer.observe(vec![b"2 ok".to_vec()]).unwrap();
+        assert_eq!(observer.states.len(), 1);
+    }
+}
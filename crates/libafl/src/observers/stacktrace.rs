@@ -1,8 +1,10 @@
 //! the ``StacktraceObserver`` looks up the stacktrace on the execution thread and computes a hash for it for dedupe
 
-#[cfg(feature = "casr")]
-use alloc::string::ToString;
-use alloc::{borrow::Cow, string::String, vec::Vec};
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
 use core::fmt::Debug;
 #[cfg(feature = "casr")]
 use core::hash::{Hash, Hasher};
@@ -15,7 +17,7 @@ use std::{
     process::ChildStderr,
 };
 
-use backtrace::Backtrace;
+use backtrace::{Backtrace, BacktraceSymbol};
 use libafl_bolts::{Named, ownedref::OwnedRefMut};
 #[allow(unused_imports)] // expect breaks here for some reason
 #[cfg(feature = "casr")]
@@ -99,6 +101,118 @@ pub fn collect_backtrace() -> u64 {
     s.finish()
 }
 
+/// A single, symbolized frame of a stacktrace
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StackFrame {
+    /// The instruction pointer of this frame
+    pub address: u64,
+    /// The (demangled) function name, if symbolized
+    pub function: Option<String>,
+    /// The source file, if debug info is available
+    pub file: Option<String>,
+    /// The source line, if debug info is available
+    pub line: Option<u32>,
+}
+
+/// Function name prefixes of frames belonging to the fuzzer, the sanitizer runtimes or the
+/// signal handling, rather than to the target.
+const INTERNAL_FRAME_PREFIXES: [&str; 17] = [
+    "libafl",
+    "<libafl",
+    "backtrace::",
+    "<backtrace::",
+    "std::",
+    "<std::",
+    "core::",
+    "<core::",
+    "alloc::",
+    "<alloc::",
+    "__rust",
+    "rust_begin_unwind",
+    "__restore_rt",
+    "_sigtramp",
+    "__asan",
+    "__sanitizer",
+    "__interceptor",
+];
+
+/// Function name prefixes telling the bug class of an in-process crash, if found in its stacktrace.
+/// The first matching entry wins, as e.g. a panic also shows up as abort.
+const BUG_CLASS_FRAME_PREFIXES: [(&str, &str); 7] = [
+    ("__asan_report", "address-sanitizer"),
+    ("__msan_warning", "use-of-uninitialized-value"),
+    ("__ubsan_handle", "undefined-behavior"),
+    ("rust_panic", "panic"),
+    ("core::panicking::", "panic"),
+    ("abort", "abort"),
+    ("__GI_abort", "abort"),
+];
+
+/// The bug class of a crash with the given frames, if one of the [`BUG_CLASS_FRAME_PREFIXES`] is found
+#[must_use]
+pub fn bug_class_of_frames(frames: &[StackFrame]) -> Option<&'static str> {
+    BUG_CLASS_FRAME_PREFIXES
+        .iter()
+        .find(|(prefix, _)| {
+            frames.iter().any(|frame| {
+                frame
+                    .function
+                    .as_deref()
+                    .is_some_and(|function| function.starts_with(prefix))
+            })
+        })
+        .map(|(_, bug_class)| *bug_class)
+}
+
+impl StackFrame {
+    /// Returns `true` if this frame belongs to the fuzzer or a runtime, not to the target.
+    /// Such frames are ignored for crash deduplication.
+    #[must_use]
+    pub fn is_internal(&self) -> bool {
+        self.function.as_deref().is_some_and(|function| {
+            INTERNAL_FRAME_PREFIXES
+                .iter()
+                .any(|prefix| function.starts_with(prefix))
+        })
+    }
+}
+
+/// A trait for [`Observer`]`s` that keep the symbolized stacktrace of the last crash,
+/// next to its hash.
+pub trait ObserverWithStacktrace: ObserverWithHashField {
+    /// The frames of the last crash, innermost first
+    fn frames(&self) -> &[StackFrame];
+
+    /// The class of the last bug, e.g. `heap-buffer-overflow` or `SIGSEGV`, if known
+    fn bug_class(&self) -> Option<&str>;
+}
+
+/// Collects the current backtrace via [`Backtrace`] and symbolizes it
+#[must_use]
+pub fn collect_backtrace_frames() -> Vec<StackFrame> {
+    let mut b = Backtrace::new_unresolved();
+    if b.frames().is_empty() {
+        return Vec::new();
+    }
+    b.resolve();
+    b.frames()[1..]
+        .iter()
+        .map(|frame| {
+            let symbol = frame.symbols().first();
+            StackFrame {
+                address: frame.ip() as u64,
+                function: symbol
+                    .and_then(BacktraceSymbol::name)
+                    .map(|name| format!("{name:#}")),
+                file: symbol
+                    .and_then(BacktraceSymbol::filename)
+                    .map(|file| file.to_string_lossy().into_owned()),
+                line: symbol.and_then(BacktraceSymbol::lineno),
+            }
+        })
+        .collect()
+}
+
 /// An enum encoding the types of harnesses
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum HarnessType {
@@ -116,6 +230,8 @@ pub struct BacktraceObserver<'a> {
     observer_name: Cow<'static, str>,
     hash: OwnedRefMut<'a, Option<u64>>,
     harness_type: HarnessType,
+    #[serde(default)]
+    frames: Vec<StackFrame>,
 }
 
 impl<'a> BacktraceObserver<'a> {
@@ -134,6 +250,7 @@ impl<'a> BacktraceObserver<'a> {
             observer_name: observer_name.into(),
             hash: backtrace_hash,
             harness_type,
+            frames: Vec::new(),
         }
     }
 
@@ -153,6 +270,7 @@ impl<'a> BacktraceObserver<'a> {
             observer_name: observer_name.into(),
             hash: backtrace_hash,
             harness_type,
+            frames: Vec::new(),
         }
     }
 
@@ -173,6 +291,7 @@ impl<'a> BacktraceObserver<'a> {
     /// Clears the current hash value (sets it to `None`)
     fn clear_hash(&mut self) {
         *self.hash.as_mut() = None;
+        self.frames.clear();
     }
 
     /// Fill the hash value if the harness type is external
@@ -194,11 +313,24 @@ impl ObserverWithHashField for BacktraceObserver<'_> {
     }
 }
 
+impl ObserverWithStacktrace for BacktraceObserver<'_> {
+    /// The frames of the last crash. Only collected for [`HarnessType::InProcess`].
+    fn frames(&self) -> &[StackFrame] {
+        &self.frames
+    }
+
+    /// The bug class of the last crash, as told by its frames, see [`bug_class_of_frames`]
+    fn bug_class(&self) -> Option<&str> {
+        bug_class_of_frames(&self.frames)
+    }
+}
+
 impl<I, S> Observer<I, S> for BacktraceObserver<'_> {
     fn post_exec(&mut self, _state: &mut S, _input: &I, exit_kind: &ExitKind) -> Result<(), Error> {
         if self.harness_type == HarnessType::InProcess {
             if *exit_kind == ExitKind::Crash {
                 self.update_hash(collect_backtrace());
+                self.frames = collect_backtrace_frames();
             } else {
                 self.clear_hash();
             }
@@ -255,6 +387,10 @@ pub fn get_asan_runtime_flags() -> String {
 pub struct AsanBacktraceObserver {
     observer_name: Cow<'static, str>,
     hash: Option<u64>,
+    #[serde(default)]
+    frames: Vec<StackFrame>,
+    #[serde(default)]
+    bug_class: Option<String>,
 }
 
 impl AsanBacktraceObserver {
@@ -268,6 +404,8 @@ impl AsanBacktraceObserver {
         Self {
             observer_name: observer_name.into(),
             hash: None,
+            frames: Vec::new(),
+            bug_class: None,
         }
     }
 
@@ -282,6 +420,8 @@ impl AsanBacktraceObserver {
         Self {
             observer_name: observer_name.into(),
             hash: None,
+            frames: Vec::new(),
+            bug_class: None,
         }
     }
 
@@ -312,6 +452,7 @@ impl AsanBacktraceObserver {
     #[cfg(not(feature = "casr"))]
     /// parse ASAN error output emited by the target command and compute the hash
     pub fn parse_asan_output(&mut self, output: &str) {
+        self.parse_asan_report(output);
         let mut hash = 0;
         let matcher = Regex::new("\\s*#[0-9]*\\s0x([0-9a-f]*)\\s.*").unwrap();
         matcher.captures_iter(output).for_each(|m| {
@@ -324,6 +465,7 @@ impl AsanBacktraceObserver {
     #[cfg(feature = "casr")]
     /// parse ASAN error output emited by the target command and compute the hash
     pub fn parse_asan_output(&mut self, output: &str) {
+        self.parse_asan_report(output);
         let mut hash = 0;
         if let Ok(st_vec) = AsanStacktrace::extract_stacktrace(output) {
             if let Ok(mut stacktrace) = AsanStacktrace::parse_stacktrace(&st_vec) {
@@ -340,6 +482,79 @@ impl AsanBacktraceObserver {
     fn update_hash(&mut self, hash: u64) {
         self.hash = Some(hash);
     }
+
+    /// Extracts the bug class and the frames of the crashing stack from an ASAN report
+    fn parse_asan_report(&mut self, output: &str) {
+        self.frames.clear();
+        self.bug_class = None;
+        for line in output.lines() {
+            let line = line.trim();
+            if self.bug_class.is_none() {
+                // e.g. `==1234==ERROR: AddressSanitizer: heap-buffer-overflow on address ...`
+                if let Some((_, report)) = line.split_once("Sanitizer: ") {
+                    let class = report.split(" on ").next().unwrap_or(report);
+                    self.bug_class = Some(class.trim().to_string());
+                } else if line.contains("runtime error: ") {
+                    self.bug_class = Some("undefined-behavior".to_string());
+                }
+            }
+            match parse_asan_frame(line) {
+                Some(frame) => self.frames.push(frame),
+                // Only keep the first stack, which is the one of the crash.
+                // Allocation and free stacks follow after an empty line.
+                None if !self.frames.is_empty() && line.is_empty() => break,
+                None => {}
+            }
+        }
+    }
+
+    /// Records the signal that terminated the target, used as bug class
+    /// if the ASAN report did not provide one.
+    pub fn record_signal(&mut self, signal: &str) {
+        if self.bug_class.is_none() {
+            self.bug_class = Some(signal.to_string());
+        }
+    }
+}
+
+/// Parses a frame of an ASAN stacktrace, e.g. `#0 0x4f8a1c in parse /src/parser.c:42:7`
+fn parse_asan_frame(line: &str) -> Option<StackFrame> {
+    let rest = line.strip_prefix('#')?;
+    let (index, rest) = rest.split_once(char::is_whitespace)?;
+    if index.parse::<usize>().is_err() {
+        return None;
+    }
+    let (address, rest) = rest
+        .trim_start()
+        .split_once(char::is_whitespace)
+        .unwrap_or((rest.trim_start(), ""));
+    let address = u64::from_str_radix(address.strip_prefix("0x")?, 16).ok()?;
+
+    let mut frame = StackFrame {
+        address,
+        function: None,
+        file: None,
+        line: None,
+    };
+    let Some(rest) = rest.trim().strip_prefix("in ") else {
+        // Unsymbolized frame, `(module+offset)`
+        return Some(frame);
+    };
+    let (function, location) = rest.rsplit_once(' ').unwrap_or((rest, ""));
+    if location.starts_with('(') {
+        // `in function (module+offset)`
+        frame.function = Some(function.to_string());
+    } else if let Some((file, line)) = location
+        .split_once(':')
+        .and_then(|(file, pos)| Some((file, pos.split(':').next()?.parse().ok()?)))
+    {
+        frame.function = Some(function.to_string());
+        frame.file = Some(file.to_string());
+        frame.line = Some(line);
+    } else {
+        frame.function = Some(rest.to_string());
+    }
+    Some(frame)
 }
 
 impl ObserverWithHashField for AsanBacktraceObserver {
@@ -349,6 +564,16 @@ impl ObserverWithHashField for AsanBacktraceObserver {
     }
 }
 
+impl ObserverWithStacktrace for AsanBacktraceObserver {
+    fn frames(&self) -> &[StackFrame] {
+        &self.frames
+    }
+
+    fn bug_class(&self) -> Option<&str> {
+        self.bug_class.as_deref()
+    }
+}
+
 impl Default for AsanBacktraceObserver {
     fn default() -> Self {
         Self::new("AsanBacktraceObserver")
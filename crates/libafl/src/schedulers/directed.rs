@@ -0,0 +1,544 @@
+//! Directed greybox fuzzing, as introduced by [`AFLGo`](https://github.com/aflgo/aflgo).
+//!
+//! Each coverage map index gets a distance to the closest target site, given as a [`DirectedDistanceMetadata`].
+//! The targets are identified by the map indexes themselves, so any distance computation works.
+//!
+//! `libafl_cc::cfg::ControlFlowGraph::calculate_distances_to_targets` computes distances to target functions,
+//! by name, from the call graph and the per-function edge distances of a CFG dump, like `AFLGo`.
+//! Targets given as `file:line` are rejected there, map them to their functions, or compute the distances
+//! with an external tool, such as `AFLGo`'s distance calculator, and build the metadata from them.
+//! The [`DirectedScheduler`] computes the distance of each new [`Testcase`] from the covered entries,
+//! and the [`DirectedTestcaseScore`] uses simulated annealing to move energy from the exploration
+//! phase to the testcases closest to the targets over time.
+
+use core::{hash::Hash, marker::PhantomData, time::Duration};
+
+use hashbrown::HashMap;
+use libafl_bolts::{
+    Named, current_time, generic_hash_std,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{Corpus, CorpusId, Testcase},
+    observers::MapObserver,
+    schedulers::{
+        RemovableScheduler, Scheduler,
+        testcase_score::{CorpusPowerTestcaseScore, TestcaseScore},
+    },
+    state::{HasCorpus, HasStartTime},
+};
+
+/// The maximum factor by which the directed power schedule changes the energy of a [`Testcase`]
+const DIRECTED_MAX_FACTOR: f64 = 32.0;
+
+/// The default time after which the [`DirectedScheduler`] is in the exploitation phase.
+pub const DEFAULT_TIME_TO_EXPLOIT: Duration = Duration::from_secs(45 * 60);
+
+/// A state metadata holding the distance of each map index to the closest target
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct DirectedDistanceMetadata {
+    /// map index -> distance to the closest target
+    distances: HashMap<usize, f64>,
+}
+
+libafl_bolts::impl_serdeany!(DirectedDistanceMetadata);
+
+impl DirectedDistanceMetadata {
+    /// Creates a new [`struct@DirectedDistanceMetadata`] from the distance of each map index.
+    ///
+    /// Map indexes that cannot reach any target should not be part of `distances`.
+    #[must_use]
+    pub fn new(distances: HashMap<usize, f64>) -> Self {
+        Self { distances }
+    }
+
+    /// The distance of each map index to the closest target
+    #[must_use]
+    pub fn distances(&self) -> &HashMap<usize, f64> {
+        &self.distances
+    }
+
+    /// The distance of each map index to the closest target (mutable)
+    pub fn distances_mut(&mut self) -> &mut HashMap<usize, f64> {
+        &mut self.distances
+    }
+
+    /// The average distance of all covered map indexes, or `None` if no covered index reaches a target
+    #[must_use]
+    #[expect(clippy::cast_precision_loss)]
+    pub fn distance_of<O>(&self, observer: &O) -> Option<f64>
+    where
+        O: MapObserver,
+    {
+        let initial = observer.initial();
+        let usable = observer.usable_count();
+        let mut sum = 0.0;
+        let mut count = 0_usize;
+        for (idx, distance) in &self.distances {
+            if *idx < usable && observer.get(*idx) != initial {
+                sum += *distance;
+                count += 1;
+            }
+        }
+        if count == 0 {
+            None
+        } else {
+            Some(sum / count as f64)
+        }
+    }
+}
+
+impl FromIterator<(usize, u32)> for DirectedDistanceMetadata {
+    fn from_iter<T: IntoIterator<Item = (usize, u32)>>(iter: T) -> Self {
+        Self::new(
+            iter.into_iter()
+                .map(|(idx, distance)| (idx, f64::from(distance)))
+                .collect(),
+        )
+    }
+}
+
+/// A testcase metadata holding the distance of this testcase to the targets
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct DirectedTestcaseMetadata {
+    distance: f64,
+}
+
+libafl_bolts::impl_serdeany!(DirectedTestcaseMetadata);
+
+impl DirectedTestcaseMetadata {
+    /// Creates a new [`struct@DirectedTestcaseMetadata`]
+    #[must_use]
+    pub fn new(distance: f64) -> Self {
+        Self { distance }
+    }
+
+    /// The distance of this testcase to the targets
+    #[must_use]
+    pub fn distance(&self) -> f64 {
+        self.distance
+    }
+}
+
+/// The cooling schedule of the simulated annealing, mapping the progress towards
+/// the exploitation phase to a temperature.
+///
+/// The temperature starts at `1.0` and reaches `0.05` when the exploitation phase starts.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum CoolingSchedule {
+    /// `T = 20^-progress`
+    Exponential,
+    /// `T = 1 / (1 + 2 * ln(1 + progress * (e^9.5 - 1)))`
+    Logarithmic,
+    /// `T = 1 / (1 + 19 * progress)`
+    Linear,
+    /// `T = 1 / (1 + 19 * progress^2)`
+    Quadratic,
+}
+
+impl CoolingSchedule {
+    /// The temperature after the given `progress`, where `1.0` is the start of the exploitation phase
+    #[must_use]
+    pub fn temperature(&self, progress: f64) -> f64 {
+        match self {
+            Self::Exponential => libm::pow(20.0, -progress),
+            // e^9.5 - 1
+            Self::Logarithmic => 1.0 / (1.0 + 2.0 * libm::log(1.0 + progress * 13358.7268297)),
+            Self::Linear => 1.0 / (1.0 + 19.0 * progress),
+            Self::Quadratic => 1.0 / (1.0 + 19.0 * progress * progress),
+        }
+    }
+}
+
+/// The state metadata of the [`DirectedScheduler`], used by the [`DirectedTestcaseScore`]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct DirectedSchedulerMetadata {
+    /// The smallest testcase distance seen so far
+    min_distance: f64,
+    /// The largest testcase distance seen so far
+    max_distance: f64,
+    /// The time after which the exploitation phase starts
+    time_to_exploit: Duration,
+    /// The cooling schedule
+    cooling: CoolingSchedule,
+}
+
+libafl_bolts::impl_serdeany!(DirectedSchedulerMetadata);
+
+impl DirectedSchedulerMetadata {
+    /// Creates a new [`struct@DirectedSchedulerMetadata`]
+    #[must_use]
+    pub fn new(time_to_exploit: Duration, cooling: CoolingSchedule) -> Self {
+        Self {
+            min_distance: f64::MAX,
+            max_distance: 0.0,
+            time_to_exploit,
+            cooling,
+        }
+    }
+
+    /// The smallest testcase distance seen so far
+    #[must_use]
+    pub fn min_distance(&self) -> f64 {
+        self.min_distance
+    }
+
+    /// The largest testcase distance seen so far
+    #[must_use]
+    pub fn max_distance(&self) -> f64 {
+        self.max_distance
+    }
+
+    /// The time after which the exploitation phase starts
+    #[must_use]
+    pub fn time_to_exploit(&self) -> Duration {
+        self.time_to_exploit
+    }
+
+    /// The cooling schedule
+    #[must_use]
+    pub fn cooling(&self) -> CoolingSchedule {
+        self.cooling
+    }
+
+    /// Record the distance of a new testcase
+    pub fn update_distance(&mut self, distance: f64) {
+        self.min_distance = self.min_distance.min(distance);
+        self.max_distance = self.max_distance.max(distance);
+    }
+
+    /// The factor to multiply the energy of a testcase with `distance` after `elapsed` time.
+    ///
+    /// Early on, all testcases get about the same energy (exploration).
+    /// As the temperature drops, close testcases get up to 32 times more and far testcases
+    /// up to 32 times less energy (exploitation).
+    #[must_use]
+    pub fn power_factor(&self, distance: f64, elapsed: Duration) -> f64 {
+        let progress = elapsed.as_secs_f64() / self.time_to_exploit.as_secs_f64().max(1.0);
+        let temperature = self.cooling.temperature(progress);
+
+        let normalized_distance = if self.max_distance > self.min_distance {
+            ((distance - self.min_distance) / (self.max_distance - self.min_distance))
+                .clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let p = (1.0 - normalized_distance) * (1.0 - temperature) + 0.5 * temperature;
+        libm::pow(2.0, 2.0 * libm::log2(DIRECTED_MAX_FACTOR) * (p - 0.5))
+    }
+}
+
+/// A scheduler wrapper that computes the distance to the targets of each new [`Testcase`].
+///
+/// The distances of the map indexes are taken from the [`struct@DirectedDistanceMetadata`].
+/// Use it together with a [`DirectedTestcaseScore`] in the power stage to steer the fuzzer.
+#[derive(Debug, Clone)]
+pub struct DirectedScheduler<C, CS, O> {
+    base: CS,
+    observer_handle: Handle<C>,
+    /// The hash of the last evaluated input, and its distance
+    last_distance: Option<(u64, f64)>,
+    phantom: PhantomData<O>,
+}
+
+impl<C, CS, I, O, S> RemovableScheduler<I, S> for DirectedScheduler<C, CS, O>
+where
+    CS: RemovableScheduler<I, S>,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        id: CorpusId,
+        testcase: &Option<Testcase<I>>,
+    ) -> Result<(), Error> {
+        self.base.on_remove(state, id, testcase)
+    }
+
+    fn on_replace(&mut self, state: &mut S, id: CorpusId, prev: &Testcase<I>) -> Result<(), Error> {
+        self.base.on_replace(state, id, prev)
+    }
+}
+
+impl<C, CS, I, O, S> Scheduler<I, S> for DirectedScheduler<C, CS, O>
+where
+    CS: Scheduler<I, S>,
+    C: AsRef<O>,
+    I: Hash,
+    O: MapObserver,
+    S: HasCorpus<I> + HasMetadata,
+{
+    /// Called when a [`Testcase`] is added to the corpus
+    fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+        // Testcases added without an evaluation, e.g. from other clients, get no distance
+        if let Some((input_hash, distance)) = self.last_distance.take() {
+            let mut testcase = state.corpus().get(id)?.borrow_mut();
+            if generic_hash_std(testcase.load_input(state.corpus())?) == input_hash {
+                testcase.add_metadata(DirectedTestcaseMetadata::new(distance));
+                drop(testcase);
+                state
+                    .metadata_mut::<DirectedSchedulerMetadata>()?
+                    .update_distance(distance);
+            }
+        }
+        self.base.on_add(state, id)
+    }
+
+    /// An input has been evaluated, compute its distance to the targets
+    fn on_evaluation<OT>(&mut self, state: &mut S, input: &I, observers: &OT) -> Result<(), Error>
+    where
+        OT: MatchName,
+    {
+        let observer = observers
+            .get(&self.observer_handle)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found"))?
+            .as_ref();
+        self.last_distance = state
+            .metadata::<DirectedDistanceMetadata>()?
+            .distance_of(observer)
+            .map(|distance| (generic_hash_std(input), distance));
+
+        self.base.on_evaluation(state, input, observers)
+    }
+
+    /// Gets the next entry
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        self.base.next(state)
+    }
+
+    /// Set current fuzzed corpus id and `scheduled_count`
+    fn set_current_scheduled(
+        &mut self,
+        _state: &mut S,
+        _next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        // We do nothing here, the inner scheduler will take care of it
+        Ok(())
+    }
+}
+
+impl<C, CS, O> DirectedScheduler<C, CS, O>
+where
+    C: AsRef<O> + Named,
+{
+    /// Creates a new [`DirectedScheduler`], wrapping `base`.
+    ///
+    /// Uses an exponential cooling schedule and starts exploiting after [`DEFAULT_TIME_TO_EXPLOIT`].
+    #[must_use]
+    pub fn new<S>(
+        state: &mut S,
+        observer: &C,
+        base: CS,
+        distances: DirectedDistanceMetadata,
+    ) -> Self
+    where
+        S: HasMetadata,
+    {
+        Self::with_annealing(
+            state,
+            observer,
+            base,
+            distances,
+            DEFAULT_TIME_TO_EXPLOIT,
+            CoolingSchedule::Exponential,
+        )
+    }
+
+    /// Creates a new [`DirectedScheduler`], wrapping `base`, with a custom annealing configuration.
+    ///
+    /// The exploitation phase starts after `time_to_exploit`.
+    #[must_use]
+    pub fn with_annealing<S>(
+        state: &mut S,
+        observer: &C,
+        base: CS,
+        distances: DirectedDistanceMetadata,
+        time_to_exploit: Duration,
+        cooling: CoolingSchedule,
+    ) -> Self
+    where
+        S: HasMetadata,
+    {
+        state.add_metadata(distances);
+        if !state.has_metadata::<DirectedSchedulerMetadata>() {
+            state.add_metadata(DirectedSchedulerMetadata::new(time_to_exploit, cooling));
+        }
+        Self {
+            base,
+            observer_handle: observer.handle(),
+            last_distance: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Get a reference to the base scheduler
+    pub fn base(&self) -> &CS {
+        &self.base
+    }
+
+    /// Get a reference to the base scheduler (mut)
+    pub fn base_mut(&mut self) -> &mut CS {
+        &mut self.base
+    }
+}
+
+/// Scales the score of the inner [`TestcaseScore`] by the distance of the [`Testcase`] to the targets,
+/// using simulated annealing. [`Testcase`]s without a distance keep their score.
+#[derive(Debug, Clone)]
+pub struct DirectedTestcaseScore<F> {
+    phantom: PhantomData<F>,
+}
+
+impl<F, I, S> TestcaseScore<I, S> for DirectedTestcaseScore<F>
+where
+    F: TestcaseScore<I, S>,
+    S: HasMetadata + HasStartTime,
+{
+    fn compute(state: &S, entry: &mut Testcase<I>) -> Result<f64, Error> {
+        let score = F::compute(state, entry)?;
+        let Ok(tcmeta) = entry.metadata::<DirectedTestcaseMetadata>() else {
+            return Ok(score);
+        };
+        let elapsed = current_time().saturating_sub(*state.start_time());
+        let factor = state
+            .metadata::<DirectedSchedulerMetadata>()?
+            .power_factor(tcmeta.distance(), elapsed);
+        Ok(score * factor)
+    }
+}
+
+/// The AFL-style power schedule with `AFLGo`'s directed annealing on top
+pub type DirectedPowerTestcaseScore = DirectedTestcaseScore<CorpusPowerTestcaseScore>;
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use libafl_bolts::{rands::StdRand, tuples::tuple_list};
+
+    use super::{
+        CoolingSchedule, DirectedDistanceMetadata, DirectedScheduler, DirectedSchedulerMetadata,
+        DirectedTestcaseMetadata,
+    };
+    use crate::{
+        HasMetadata,
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        observers::StdMapObserver,
+        schedulers::{QueueScheduler, Scheduler},
+        state::{HasCorpus, StdState},
+    };
+
+    #[test]
+    fn test_cooling_schedules() {
+        for cooling in [
+            CoolingSchedule::Exponential,
+            CoolingSchedule::Logarithmic,
+            CoolingSchedule::Linear,
+            CoolingSchedule::Quadratic,
+        ] {
+            assert!((cooling.temperature(0.0) - 1.0).abs() < 1e-6);
+            assert!((cooling.temperature(1.0) - 0.05).abs() < 1e-3);
+            assert!(cooling.temperature(2.0) < cooling.temperature(1.0));
+        }
+    }
+
+    #[test]
+    fn test_directed_power_factor() {
+        let mut meta =
+            DirectedSchedulerMetadata::new(Duration::from_secs(60), CoolingSchedule::Exponential);
+        meta.update_distance(1.0);
+        meta.update_distance(11.0);
+
+        // Exploration: no preference
+        let start = Duration::from_secs(0);
+        assert!((meta.power_factor(1.0, start) - 1.0).abs() < 1e-6);
+        assert!((meta.power_factor(11.0, start) - 1.0).abs() < 1e-6);
+
+        // Exploitation: close testcases get more energy than far ones
+        let late = Duration::from_secs(600);
+        assert!(meta.power_factor(1.0, late) > 16.0);
+        assert!(meta.power_factor(11.0, late) < 1.0 / 16.0);
+        assert!(meta.power_factor(6.0, late) < meta.power_factor(1.0, late));
+    }
+
+    #[test]
+    fn test_directed_scheduler_distance_of_evaluated_input() {
+        // # Safety
+        // No concurrency per testcase
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            DirectedDistanceMetadata::register();
+            DirectedSchedulerMetadata::register();
+            DirectedTestcaseMetadata::register();
+        }
+
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        let observer = StdMapObserver::owned("map", vec![0_u8, 1, 0, 0]);
+        let mut scheduler = DirectedScheduler::<_, _, StdMapObserver<'static, u8, false>>::new(
+            &mut state,
+            &observer,
+            QueueScheduler::new(),
+            [(1_usize, 3_u32)].into_iter().collect(),
+        );
+        let observers = tuple_list!(observer);
+        let evaluated = BytesInput::new(vec![0]);
+        scheduler
+            .on_evaluation(&mut state, &evaluated, &observers)
+            .unwrap();
+
+        // A testcase added without an evaluation, e.g. from another client, gets no distance
+        let other = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![1])))
+            .unwrap();
+        scheduler.on_add(&mut state, other).unwrap();
+        assert!(
+            !state
+                .corpus()
+                .get(other)
+                .unwrap()
+                .borrow()
+                .has_metadata::<DirectedTestcaseMetadata>()
+        );
+
+        scheduler
+            .on_evaluation(&mut state, &evaluated, &observers)
+            .unwrap();
+        let id = state.corpus_mut().add(Testcase::new(evaluated)).unwrap();
+        scheduler.on_add(&mut state, id).unwrap();
+        let distance = state
+            .corpus()
+            .get(id)
+            .unwrap()
+            .borrow()
+            .metadata::<DirectedTestcaseMetadata>()
+            .unwrap()
+            .distance();
+        assert!((distance - 3.0).abs() < 1e-6);
+    }
+}
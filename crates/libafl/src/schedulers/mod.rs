@@ -26,6 +26,14 @@ pub use accounting::CoverageAccountingScheduler;
 pub mod weighted;
 pub use weighted::{StdWeightedScheduler, WeightedScheduler};
 
+pub mod directed;
+pub use directed::{DirectedPowerTestcaseScore, DirectedScheduler, DirectedTestcaseScore};
+
+#[cfg(feature = "std")]
+pub mod protocol_state;
+#[cfg(feature = "std")]
+pub use protocol_state::ProtocolStateScheduler;
+
 pub mod tuneable;
 use libafl_bolts::{
     generic_hash_std,
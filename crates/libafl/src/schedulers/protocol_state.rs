@@ -0,0 +1,263 @@
+//! The [`ProtocolStateScheduler`] prioritizes testcases reaching rarely visited protocol states, like `AFLNet`.
+//!
+//! It needs the [`ProtocolStateGraph`] and [`ProtocolStatesMetadata`] of the [`crate::feedbacks::ProtocolStateFeedback`].
+
+use alloc::vec::Vec;
+use core::num::NonZero;
+
+use libafl_bolts::{rands::Rand, tuples::MatchName};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{Corpus, CorpusId, Testcase},
+    feedbacks::{ProtocolStateGraph, ProtocolStatesMetadata},
+    schedulers::{RemovableScheduler, Scheduler},
+    state::{HasCorpus, HasRand},
+};
+
+/// The default probability to pick a testcase by its rare states, instead of asking the base scheduler
+pub const DEFAULT_RARE_STATE_PROBABILITY: f64 = 0.5;
+
+/// The weight of a state never visited, states visited `n` times have a weight of `STATE_WEIGHT_SCALE / (n + 1)`
+const STATE_WEIGHT_SCALE: usize = 1 << 16;
+
+/// A scheduler that picks a protocol state, weighted by the inverse of its visits,
+/// and then a random testcase reaching it.
+///
+/// The other times, and while no testcase reaches a known state, the `base` scheduler picks the testcase,
+/// so testcases without interesting states still get fuzzed.
+#[derive(Debug, Clone)]
+pub struct ProtocolStateScheduler<CS> {
+    base: CS,
+    rare_probability: f64,
+}
+
+impl<CS> ProtocolStateScheduler<CS> {
+    /// Creates a new [`ProtocolStateScheduler`] that wraps a `base` [`Scheduler`]
+    #[must_use]
+    pub fn new(base: CS) -> Self {
+        Self {
+            base,
+            rare_probability: DEFAULT_RARE_STATE_PROBABILITY,
+        }
+    }
+
+    /// Set the probability to pick a testcase by its rare states, instead of asking the base scheduler.
+    /// Defaults to [`DEFAULT_RARE_STATE_PROBABILITY`].
+    #[must_use]
+    pub fn with_rare_probability(mut self, rare_probability: f64) -> Self {
+        self.rare_probability = rare_probability;
+        self
+    }
+
+    /// Get a reference to the base scheduler
+    pub fn base(&self) -> &CS {
+        &self.base
+    }
+
+    /// Get a reference to the base scheduler (mut)
+    pub fn base_mut(&mut self) -> &mut CS {
+        &mut self.base
+    }
+
+    /// Register the testcase in the [`ProtocolStateGraph`], under the states of its [`ProtocolStatesMetadata`]
+    fn register<I, S>(state: &mut S, id: CorpusId) -> Result<(), Error>
+    where
+        S: HasCorpus<I> + HasMetadata,
+    {
+        let states = state
+            .corpus()
+            .get(id)?
+            .borrow()
+            .metadata_map()
+            .get::<ProtocolStatesMetadata>()
+            .map(|meta| meta.states.clone());
+        if let Some(states) = states {
+            state
+                .metadata_or_insert_with(ProtocolStateGraph::new)
+                .add_testcase(id, &states);
+        }
+        Ok(())
+    }
+
+    /// Pick a rarely visited state, and a testcase reaching it
+    fn next_rare<I, S>(state: &mut S) -> Result<Option<CorpusId>, Error>
+    where
+        S: HasCorpus<I> + HasMetadata + HasRand,
+    {
+        let Some(graph) = state.metadata_map().get::<ProtocolStateGraph>() else {
+            return Ok(None);
+        };
+        let mut weights: Vec<(u64, usize)> = graph
+            .states()
+            .filter(|(s, _)| !graph.testcases(*s).is_empty())
+            .map(|(s, visits)| {
+                let visits = usize::try_from(visits).unwrap_or(usize::MAX);
+                (s, (STATE_WEIGHT_SCALE / visits.saturating_add(1)).max(1))
+            })
+            .collect();
+        // Sort, so the choice only depends on the rand
+        weights.sort_unstable();
+
+        let Some(total) = NonZero::new(weights.iter().map(|(_, w)| w).sum::<usize>()) else {
+            return Ok(None);
+        };
+        let mut pick = state.rand_mut().below(total);
+        let Some(&(chosen, _)) = weights.iter().find(|(_, weight)| {
+            if pick < *weight {
+                true
+            } else {
+                pick -= *weight;
+                false
+            }
+        }) else {
+            return Ok(None);
+        };
+
+        let ids = state
+            .metadata::<ProtocolStateGraph>()?
+            .testcases(chosen)
+            .to_vec();
+        let id = state.rand_mut().choose(ids);
+        // Disabled testcases are left to the base scheduler
+        Ok(id.filter(|id| state.corpus().get(*id).is_ok()))
+    }
+}
+
+impl<CS, I, S> RemovableScheduler<I, S> for ProtocolStateScheduler<CS>
+where
+    CS: RemovableScheduler<I, S>,
+    S: HasCorpus<I> + HasMetadata,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        id: CorpusId,
+        testcase: &Option<Testcase<I>>,
+    ) -> Result<(), Error> {
+        if let Some(graph) = state.metadata_map_mut().get_mut::<ProtocolStateGraph>() {
+            graph.remove_testcase(id);
+        }
+        self.base.on_remove(state, id, testcase)
+    }
+
+    fn on_replace(&mut self, state: &mut S, id: CorpusId, prev: &Testcase<I>) -> Result<(), Error> {
+        if let Some(graph) = state.metadata_map_mut().get_mut::<ProtocolStateGraph>() {
+            graph.remove_testcase(id);
+        }
+        Self::register(state, id)?;
+        self.base.on_replace(state, id, prev)
+    }
+}
+
+impl<CS, I, S> Scheduler<I, S> for ProtocolStateScheduler<CS>
+where
+    CS: Scheduler<I, S>,
+    S: HasCorpus<I> + HasMetadata + HasRand,
+{
+    fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+        self.base.on_add(state, id)?;
+        Self::register(state, id)
+    }
+
+    fn on_evaluation<OT>(&mut self, state: &mut S, input: &I, observers: &OT) -> Result<(), Error>
+    where
+        OT: MatchName,
+    {
+        self.base.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        if state.corpus().count() == 0 {
+            return Err(Error::empty(
+                "No entries in corpus. This often implies the target is not properly instrumented.",
+            ));
+        }
+        if state.rand_mut().coinflip(self.rare_probability) {
+            if let Some(id) = Self::next_rare(state)? {
+                self.base.set_current_scheduled(state, Some(id))?;
+                return Ok(id);
+            }
+        }
+        self.base.next(state)
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut S,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        self.base.set_current_scheduled(state, next_id)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "std")]
+mod tests {
+    use alloc::vec::Vec;
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::ProtocolStateScheduler;
+    use crate::{
+        HasMetadata,
+        corpus::{Corpus, CorpusId, InMemoryCorpus, Testcase},
+        feedbacks::{ConstFeedback, ProtocolStateGraph, ProtocolStatesMetadata},
+        inputs::BytesInput,
+        schedulers::{QueueScheduler, Scheduler},
+        state::{HasCorpus, StdState},
+    };
+
+    /// Schedule 100 testcases of a corpus where only the second one reaches a state
+    fn schedule(rare_probability: f64) -> Vec<CorpusId> {
+        // # Safety
+        // No concurrency per testcase
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            ProtocolStateGraph::register();
+            ProtocolStatesMetadata::register();
+        }
+
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let mut graph = ProtocolStateGraph::new();
+        graph.record(&[220]);
+        state.add_metadata(graph);
+
+        let mut scheduler = ProtocolStateScheduler::new(QueueScheduler::new())
+            .with_rare_probability(rare_probability);
+        let plain = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![0])))
+            .unwrap();
+        scheduler.on_add(&mut state, plain).unwrap();
+        let mut testcase = Testcase::new(BytesInput::new(vec![1]));
+        testcase.add_metadata(ProtocolStatesMetadata { states: vec![220] });
+        let rare = state.corpus_mut().add(testcase).unwrap();
+        scheduler.on_add(&mut state, rare).unwrap();
+
+        (0..100)
+            .map(|_| scheduler.next(&mut state).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_protocol_state_scheduler() {
+        assert!(schedule(1.0).iter().all(|id| *id == CorpusId(1)));
+        // The base scheduler goes through all testcases
+        let base = schedule(0.0);
+        assert!(base.contains(&CorpusId(0)) && base.contains(&CorpusId(1)));
+        // Mixed, the testcase without states still gets scheduled
+        let mixed = schedule(0.5);
+        let rare = mixed.iter().filter(|id| **id == CorpusId(1)).count();
+        assert!(rare > 50 && rare < 100);
+    }
+}
@@ -0,0 +1,454 @@
+//! Stages to run side-by-side with AFL++ instances in a shared sync directory.
+//!
+//! AFL++ instances started with `-M`/`-S <name>` write their corpus to `<sync_dir>/<name>/queue/`,
+//! naming each entry `id:NNNNNN,...`. They remember the next id to import from each peer in
+//! `<sync_dir>/<name>/.synced/<peer>`.
+//!
+//! The [`AflQueueExportStage`] writes the corpus in this layout, so AFL++ picks it up,
+//! and the [`AflQueueImportStage`] imports the queues of all other instances incrementally,
+//! keeping the same `.synced` bookkeeping.
+//! Fuzzers without an AFL++ queue, such as honggfuzz or libFuzzer, can read the exported queue
+//! as a corpus directory. Their own corpus directories can be imported with a
+//! [`crate::stages::SyncFromDiskStage`].
+//!
+//! If several clients share their corpus over the event manager, only one of them needs these stages.
+
+use alloc::{
+    borrow::Cow,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{marker::PhantomData, time::Duration};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libafl_bolts::{Named, current_time, fs::write_file_atomic, impl_serdeany};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::{Corpus, CorpusId, HasCurrentCorpusId},
+    fuzzer::Evaluator,
+    inputs::Input,
+    stages::{Restartable, RetryCountRestartHelper, Stage, sync::import_without_current_corpus_id},
+    state::{HasCorpus, HasExecutions, HasStartTime, MaybeHasClientPerfMonitor},
+};
+
+/// Default name for [`AflQueueImportStage`]
+pub const AFL_QUEUE_IMPORT_STAGE_NAME: &str = "afl_queue_import";
+
+/// The prefix of the name of each AFL++ queue entry
+pub const AFL_QUEUE_ID_PREFIX: &str = "id:";
+
+/// Parse the id of an AFL++ queue entry from its file name, such as `id:000042,src:000001,...`
+#[must_use]
+pub fn parse_afl_queue_id(file_name: &str) -> Option<u32> {
+    let rest = file_name.strip_prefix(AFL_QUEUE_ID_PREFIX)?;
+    let digits = rest.split(',').next()?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// The entries of an AFL++ queue directory with an id of at least `min_id`, sorted by id.
+///
+/// Hidden files and files not named like queue entries are skipped, as AFL++ does.
+pub fn afl_queue_entries(queue_dir: &Path, min_id: u32) -> Result<Vec<(u32, PathBuf)>, Error> {
+    let mut entries = vec![];
+    for entry in fs::read_dir(queue_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let Some(id) = entry.file_name().to_str().and_then(parse_afl_queue_id) else {
+            continue;
+        };
+        if id >= min_id {
+            entries.push((id, entry.path()));
+        }
+    }
+    entries.sort_unstable();
+    Ok(entries)
+}
+
+/// Read the next id to import from a `.synced` file, as written by AFL++
+fn read_synced_id(path: &Path) -> Result<Option<u32>, Error> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(bytes
+            .first_chunk::<4>()
+            .map(|bytes| u32::from_ne_bytes(*bytes))),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::os_error(
+            err,
+            format!("Could not read {}", path.display()),
+        )),
+    }
+}
+
+/// Write the next id to import to a `.synced` file, in the format of AFL++
+fn write_synced_id(path: &Path, id: u32) -> Result<(), Error> {
+    write_file_atomic(path, &id.to_ne_bytes())
+}
+
+fn create_dir_all(dir: &Path) -> Result<(), Error> {
+    fs::create_dir_all(dir)
+        .map_err(|err| Error::os_error(err, format!("Error creating directory {}", dir.display())))
+}
+
+/// Metadata used to store which testcases the [`AflQueueExportStage`] exported
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct AflQueueExportMetadata {
+    /// The last exported testcase
+    pub last_corpus: Option<CorpusId>,
+    /// The next free queue id
+    pub next_id: u32,
+    /// The queue ids of the exported testcases, to name their children
+    pub queue_ids: BTreeMap<CorpusId, u32>,
+}
+
+impl_serdeany!(AflQueueExportMetadata);
+
+/// A stage writing all new testcases of the corpus to `<sync_dir>/<name>/queue/`,
+/// named like AFL++ queue entries, for other fuzzers to import
+#[derive(Debug)]
+pub struct AflQueueExportStage<EM, I, S, Z> {
+    queue_dir: PathBuf,
+    /// The first id not used by existing entries, when resuming into an existing queue
+    first_free_id: u32,
+    phantom: PhantomData<(EM, I, S, Z)>,
+}
+
+impl<EM, I, S, Z> AflQueueExportStage<EM, I, S, Z> {
+    /// Create a new [`AflQueueExportStage`] for the instance `name` in the shared `sync_dir`
+    pub fn new<P>(sync_dir: P, name: &str) -> Result<Self, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        let queue_dir = sync_dir.into().join(name).join("queue");
+        create_dir_all(&queue_dir)?;
+        let first_free_id = afl_queue_entries(&queue_dir, 0)?
+            .last()
+            .map_or(0, |(id, _)| id + 1);
+        Ok(Self {
+            queue_dir,
+            first_free_id,
+            phantom: PhantomData,
+        })
+    }
+
+    /// The queue directory testcases are written to
+    #[must_use]
+    pub fn queue_dir(&self) -> &Path {
+        &self.queue_dir
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for AflQueueExportStage<EM, I, S, Z>
+where
+    I: Input,
+    S: HasCorpus<I> + HasMetadata + HasExecutions + HasStartTime,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        let mut meta = state
+            .metadata_map_mut()
+            .remove::<AflQueueExportMetadata>()
+            .map_or_else(AflQueueExportMetadata::default, |meta| *meta);
+        meta.next_id = meta.next_id.max(self.first_free_id);
+
+        let mut corpus_id = meta
+            .last_corpus
+            .map_or_else(|| state.corpus().first(), |id| state.corpus().next(id));
+        let time = current_time()
+            .saturating_sub(*state.start_time())
+            .as_millis();
+        let executions = *state.executions();
+        while let Some(id) = corpus_id {
+            if let Err(err) = self.export(state, &mut meta, id, time, executions) {
+                state.add_metadata(meta);
+                return Err(err);
+            }
+            meta.last_corpus = Some(id);
+            corpus_id = state.corpus().next(id);
+        }
+
+        state.add_metadata(meta);
+        Ok(())
+    }
+}
+
+impl<EM, I, S, Z> AflQueueExportStage<EM, I, S, Z>
+where
+    I: Input,
+    S: HasCorpus<I>,
+{
+    fn export(
+        &self,
+        state: &S,
+        meta: &mut AflQueueExportMetadata,
+        id: CorpusId,
+        time: u128,
+        executions: u64,
+    ) -> Result<(), Error> {
+        let input = state.corpus().cloned_input_for_id(id)?;
+        let parent = state.corpus().get(id)?.borrow().parent_id();
+        let queue_id = meta.next_id;
+
+        let mut name_parts = vec![format!("{AFL_QUEUE_ID_PREFIX}{queue_id:06}")];
+        if let Some(src) = parent.and_then(|parent| meta.queue_ids.get(&parent)) {
+            name_parts.push(format!("src:{src:06}"));
+        }
+        name_parts.push(format!("time:{time}"));
+        name_parts.push(format!("execs:{executions}"));
+
+        // `to_file` writes to a hidden file first, so other fuzzers never see partial entries
+        input.to_file(self.queue_dir.join(name_parts.join(",")))?;
+        meta.queue_ids.insert(id, queue_id);
+        meta.next_id += 1;
+        Ok(())
+    }
+}
+
+impl<EM, I, S, Z> Restartable<S> for AflQueueExportStage<EM, I, S, Z> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(())
+    }
+}
+
+/// Metadata used to store the progress of the [`AflQueueImportStage`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct AflQueueImportMetadata {
+    /// The last time the sync was done
+    pub last_time: Option<Duration>,
+    /// The next id to import, for each peer
+    pub next_ids: BTreeMap<String, u32>,
+}
+
+impl_serdeany!(AflQueueImportMetadata);
+
+/// A stage importing new entries from the queues of all other instances in the shared `sync_dir`,
+/// tracking the progress in `.synced` files like AFL++.
+///
+/// When loading a file fails with [`Error::InvalidInput`], the file is skipped.
+#[derive(Debug)]
+pub struct AflQueueImportStage<E, EM, I, S, Z> {
+    name: Cow<'static, str>,
+    sync_dir: PathBuf,
+    own_name: String,
+    synced_dir: PathBuf,
+    interval: Duration,
+    phantom: PhantomData<(E, EM, I, S, Z)>,
+}
+
+impl<E, EM, I, S, Z> AflQueueImportStage<E, EM, I, S, Z> {
+    /// Create a new [`AflQueueImportStage`] for the instance `name` in the shared `sync_dir`,
+    /// syncing at most once every `interval`
+    pub fn new<P>(sync_dir: P, name: &str, interval: Duration) -> Result<Self, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        let sync_dir = sync_dir.into();
+        let synced_dir = sync_dir.join(name).join(".synced");
+        create_dir_all(&synced_dir)?;
+        Ok(Self {
+            name: Cow::Borrowed(AFL_QUEUE_IMPORT_STAGE_NAME),
+            sync_dir,
+            own_name: name.to_string(),
+            synced_dir,
+            interval,
+            phantom: PhantomData,
+        })
+    }
+
+    /// The names of all other instances with a queue in the sync dir
+    fn peers(&self) -> Result<Vec<String>, Error> {
+        let mut peers = vec![];
+        for entry in fs::read_dir(&self.sync_dir)? {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if name.starts_with('.') || name == self.own_name {
+                continue;
+            }
+            if entry.path().join("queue").is_dir() {
+                peers.push(name);
+            }
+        }
+        peers.sort_unstable();
+        Ok(peers)
+    }
+}
+
+impl<E, EM, I, S, Z> Named for AflQueueImportStage<E, EM, I, S, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for AflQueueImportStage<E, EM, I, S, Z>
+where
+    I: Input,
+    Z: Evaluator<E, EM, I, S>,
+    S: HasCorpus<I>
+        + HasMetadata
+        + HasNamedMetadata
+        + HasCurrentCorpusId
+        + MaybeHasClientPerfMonitor,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let last = state
+            .metadata_map()
+            .get::<AflQueueImportMetadata>()
+            .and_then(|m| m.last_time);
+        if let Some(last) = last {
+            if current_time().saturating_sub(last) < self.interval {
+                return Ok(());
+            }
+        }
+        state
+            .metadata_or_insert_with(AflQueueImportMetadata::default)
+            .last_time = Some(current_time());
+
+        let peers = self.peers()?;
+        import_without_current_corpus_id(state, |state| {
+            for peer in peers {
+                let synced_path = self.synced_dir.join(&peer);
+                let next_id = state
+                    .metadata_map()
+                    .get::<AflQueueImportMetadata>()
+                    .and_then(|m| m.next_ids.get(&peer).copied());
+                let next_id = match next_id {
+                    Some(next_id) => next_id,
+                    None => read_synced_id(&synced_path)?.unwrap_or(0),
+                };
+
+                let entries = afl_queue_entries(&self.sync_dir.join(&peer).join("queue"), next_id)?;
+                if entries.is_empty() {
+                    continue;
+                }
+                log::debug!("Importing {} entries from {peer}", entries.len());
+                for (id, path) in entries {
+                    // Advance before evaluating,
+                    // so an input crashing the target is not imported again
+                    state
+                        .metadata_mut::<AflQueueImportMetadata>()?
+                        .next_ids
+                        .insert(peer.clone(), id + 1);
+                    let input = match I::from_file(&path) {
+                        Ok(input) => input,
+                        Err(Error::InvalidInput(reason, _)) => {
+                            log::warn!(
+                                "Invalid input found in {} when syncing; reason {reason}; skipping;",
+                                path.display()
+                            );
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    fuzzer.evaluate_input(state, executor, manager, &input)?;
+                }
+
+                let next_id = state.metadata::<AflQueueImportMetadata>()?.next_ids[&peer];
+                write_synced_id(&synced_path, next_id)?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl<E, EM, I, S, Z> Restartable<S> for AflQueueImportStage<E, EM, I, S, Z>
+where
+    S: HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+{
+    #[inline]
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        // The progress is tracked per entry, so an entry crashing the target is skipped on restart
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use std::fs;
+
+    use super::{afl_queue_entries, parse_afl_queue_id, read_synced_id, write_synced_id};
+
+    #[test]
+    fn test_parse_afl_queue_id() {
+        assert_eq!(
+            parse_afl_queue_id("id:000042,src:000001,op:havoc"),
+            Some(42)
+        );
+        assert_eq!(parse_afl_queue_id("id:1234567"), Some(1_234_567));
+        assert_eq!(parse_afl_queue_id("id:,src:000001"), None);
+        assert_eq!(parse_afl_queue_id(".id:000001"), None);
+        assert_eq!(parse_afl_queue_id("crash-1234"), None);
+    }
+
+    #[test]
+    fn test_afl_queue_entries() {
+        let dir = std::env::temp_dir().join(format!("afl_sync_test_{}", std::process::id()));
+        let queue_dir = dir.join("queue");
+        fs::create_dir_all(&queue_dir).unwrap();
+        for name in [
+            "id:000002,src:000000",
+            "id:000000,time:0,execs:0,orig:seed",
+            "id:000001,src:000000",
+            ".id:000003,src:000002",
+            "README.txt",
+        ] {
+            fs::write(queue_dir.join(name), b"").unwrap();
+        }
+        let ids: Vec<u32> = afl_queue_entries(&queue_dir, 1)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, [1, 2]);
+
+        let synced = dir.join("peer");
+        assert_eq!(read_synced_id(&synced).unwrap(), None);
+        write_synced_id(&synced, 3).unwrap();
+        assert_eq!(read_synced_id(&synced).unwrap(), Some(3));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+//! The [`CheckpointStage`] periodically writes the complete fuzzer state to disk,
+//! so that a campaign can be resumed with [`crate::state::StdState::resume_from_checkpoint`]
+//! after the machine went down.
+
+use core::{marker::PhantomData, time::Duration};
+use std::path::{Path, PathBuf};
+
+use libafl_bolts::current_time;
+use serde::Serialize;
+
+use crate::{
+    Error,
+    stages::{Restartable, Stage},
+    state::write_checkpoint,
+};
+
+/// A stage that writes a checkpoint of the state to a directory every `interval`
+#[derive(Debug)]
+pub struct CheckpointStage<S> {
+    dir: PathBuf,
+    interval: Duration,
+    last_checkpoint: Duration,
+    phantom: PhantomData<S>,
+}
+
+impl<S> CheckpointStage<S> {
+    /// Creates a new [`CheckpointStage`], checkpointing to `dir` every `interval`.
+    /// The first checkpoint is written once `interval` has passed.
+    #[must_use]
+    pub fn new<P>(dir: P, interval: Duration) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            dir: dir.into(),
+            interval,
+            last_checkpoint: current_time(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// The checkpoint directory
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl<E, EM, S, Z> Stage<E, EM, S, Z> for CheckpointStage<S>
+where
+    S: Serialize,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        let now = current_time();
+        if now.saturating_sub(self.last_checkpoint) >= self.interval {
+            write_checkpoint(state, &self.dir)?;
+            log::info!("Wrote checkpoint to {}", self.dir.display());
+            self.last_checkpoint = now;
+        }
+        Ok(())
+    }
+}
+
+impl<S> Restartable<S> for CheckpointStage<S> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(())
+    }
+}
@@ -0,0 +1,516 @@
+//! The [`DdminStage`] deterministically minimizes corpus entries using delta debugging.
+//!
+//! Unlike the [`super::StdTMinMutationalStage`], which randomly applies mutators, this implements
+//! [`ddmin`](https://www.st.cs.uni-saarland.de/papers/tse2002/), hierarchically applied to all
+//! reducible sequences of an input (e.g. first the entries of a [`crate::inputs::ListInput`], then
+//! the bytes of each entry). The [`DdminMinimizer`] can also be used on its own, e.g. for objectives.
+
+use alloc::{
+    borrow::{Cow, ToOwned},
+    vec::Vec,
+};
+use core::{marker::PhantomData, ops::Range};
+
+use libafl_bolts::{Named, impl_serdeany};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "multipart_inputs")]
+use crate::inputs::ListInput;
+use crate::{
+    Error, ExecutesInput, HasFeedback, HasMetadata, HasNamedMetadata, HasScheduler,
+    corpus::{Corpus, CorpusId, HasCurrentCorpusId, Testcase},
+    events::{Event, EventFirer, EventWithStats},
+    executors::HasObservers,
+    feedbacks::{Feedback, FeedbackFactory},
+    inputs::{HasMutatorBytes, Input, ResizableMutator},
+    monitors::stats::{AggregatorOps, UserStats, UserStatsValue},
+    observers::ObserversTuple,
+    schedulers::RemovableScheduler,
+    stages::{Restartable, RetryCountRestartHelper, Stage},
+    state::{HasCorpus, HasCurrentTestcase, HasExecutions, HasSolutions},
+};
+
+/// The name for the ddmin stage
+pub static DDMIN_STAGE_NAME: &str = "ddmin";
+
+/// An input that can be reduced by removing contiguous chunks of one of its sequences.
+///
+/// Inputs may consist of multiple sequences, that are minimized one after the other.
+/// Removing units from a sequence must not change the number or order of the sequences before it.
+pub trait DeltaMinimizable {
+    /// The number of sequences in this input that can be reduced independently, e.g. `1` for bytes
+    fn delta_sequences(&self) -> usize;
+
+    /// The number of units (e.g. bytes) in the sequence at `seq`
+    fn delta_len(&self, seq: usize) -> usize;
+
+    /// Removes the units in `range` from the sequence at `seq`
+    fn delta_remove(&mut self, seq: usize, range: Range<usize>);
+
+    /// The number of units in all sequences
+    fn delta_total_len(&self) -> usize {
+        (0..self.delta_sequences())
+            .map(|seq| self.delta_len(seq))
+            .sum()
+    }
+}
+
+impl<I> DeltaMinimizable for I
+where
+    I: HasMutatorBytes + ResizableMutator<u8>,
+{
+    fn delta_sequences(&self) -> usize {
+        1
+    }
+
+    fn delta_len(&self, _seq: usize) -> usize {
+        self.mutator_bytes().len()
+    }
+
+    fn delta_remove(&mut self, _seq: usize, range: Range<usize>) {
+        drop(ResizableMutator::<u8>::drain(self, range));
+    }
+}
+
+/// The first sequence are the entries themselves, followed by the sequences of every entry.
+#[cfg(feature = "multipart_inputs")]
+impl<I> DeltaMinimizable for ListInput<I>
+where
+    I: DeltaMinimizable,
+{
+    fn delta_sequences(&self) -> usize {
+        1 + self
+            .parts()
+            .iter()
+            .map(DeltaMinimizable::delta_sequences)
+            .sum::<usize>()
+    }
+
+    fn delta_len(&self, seq: usize) -> usize {
+        if seq == 0 {
+            return self.len();
+        }
+        let mut seq = seq - 1;
+        for part in self.parts() {
+            let sequences = part.delta_sequences();
+            if seq < sequences {
+                return part.delta_len(seq);
+            }
+            seq -= sequences;
+        }
+        0
+    }
+
+    fn delta_remove(&mut self, seq: usize, range: Range<usize>) {
+        if seq == 0 {
+            for idx in range.rev() {
+                self.remove_part_at_index(idx);
+            }
+            return;
+        }
+        let mut seq = seq - 1;
+        for part in self.parts_mut() {
+            let sequences = part.delta_sequences();
+            if seq < sequences {
+                part.delta_remove(seq, range);
+                return;
+            }
+            seq -= sequences;
+        }
+    }
+}
+
+/// The parts of a [`crate::inputs::MultipartInput`] are minimized by value, keeping the key.
+#[cfg(feature = "multipart_inputs")]
+impl<K, I> DeltaMinimizable for (K, I)
+where
+    I: DeltaMinimizable,
+{
+    fn delta_sequences(&self) -> usize {
+        self.1.delta_sequences()
+    }
+
+    fn delta_len(&self, seq: usize) -> usize {
+        self.1.delta_len(seq)
+    }
+
+    fn delta_remove(&mut self, seq: usize, range: Range<usize>) {
+        self.1.delta_remove(seq, range);
+    }
+}
+
+/// Minimize `input` using delta debugging, applied to each of its sequences in order.
+///
+/// `test` decides if a reduced candidate still has the property of interest.
+/// The result is 1-minimal for each sequence: removing any single unit fails the `test`.
+pub fn ddmin<I, T>(input: I, test: &mut T) -> Result<I, Error>
+where
+    I: DeltaMinimizable + Clone,
+    T: FnMut(&I) -> Result<bool, Error>,
+{
+    let mut current = input;
+    let mut seq = 0;
+    while seq < current.delta_sequences() {
+        current = ddmin_sequence(current, seq, test)?;
+        seq += 1;
+    }
+    Ok(current)
+}
+
+/// Minimize a single sequence of `input` using delta debugging
+fn ddmin_sequence<I, T>(mut current: I, seq: usize, test: &mut T) -> Result<I, Error>
+where
+    I: DeltaMinimizable + Clone,
+    T: FnMut(&I) -> Result<bool, Error>,
+{
+    let mut granularity = 2;
+    loop {
+        let len = current.delta_len(seq);
+        if len < 2 {
+            break;
+        }
+        let chunk_len = len.div_ceil(granularity.min(len));
+        let chunks = (0..len)
+            .step_by(chunk_len)
+            .map(|start| start..(start + chunk_len).min(len))
+            .collect::<Vec<_>>();
+
+        // Reduce to a subset
+        let mut reduced = false;
+        for chunk in &chunks {
+            let mut candidate = current.clone();
+            candidate.delta_remove(seq, chunk.end..len);
+            candidate.delta_remove(seq, 0..chunk.start);
+            if test(&candidate)? {
+                current = candidate;
+                granularity = 2;
+                reduced = true;
+                break;
+            }
+        }
+
+        // Reduce to a complement, for two chunks this is the same as the subsets
+        if !reduced && chunks.len() > 2 {
+            for chunk in &chunks {
+                let mut candidate = current.clone();
+                candidate.delta_remove(seq, chunk.clone());
+                if test(&candidate)? {
+                    current = candidate;
+                    granularity = chunks.len() - 1;
+                    reduced = true;
+                    break;
+                }
+            }
+        }
+
+        if !reduced {
+            if chunks.len() >= len {
+                // Every unit was tried on its own, we are done
+                break;
+            }
+            granularity = (chunks.len() * 2).min(len);
+        }
+    }
+    Ok(current)
+}
+
+/// Minimizes inputs with [`ddmin`] by running them in the target.
+///
+/// A reduced candidate is kept if it leads to the same [`ExitKind`](crate::executors::ExitKind)
+/// as the original input, and the feedback created by the factory (e.g. an
+/// [`super::ObserverEqualityFactory`] over a `BacktraceObserver` or a map observer) deems it interesting.
+/// Candidate executions are not evaluated by the fuzzer, so they never add corpus entries or objectives.
+#[derive(Debug, Clone)]
+pub struct DdminMinimizer<FF> {
+    factory: FF,
+}
+
+impl<FF> DdminMinimizer<FF> {
+    /// Creates a new [`DdminMinimizer`] using the given predicate factory
+    pub fn new(factory: FF) -> Self {
+        Self { factory }
+    }
+
+    /// Minimizes `input`, returning the smallest input found that keeps the property of interest.
+    ///
+    /// Reports the remaining size as `ddmin` user stat after each successful reduction.
+    pub fn minimize<E, EM, F, I, S, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        input: I,
+    ) -> Result<I, Error>
+    where
+        E: HasObservers,
+        E::Observers: ObserversTuple<I, S>,
+        EM: EventFirer<I, S>,
+        F: Feedback<EM, I, E::Observers, S>,
+        FF: FeedbackFactory<F, E::Observers>,
+        I: DeltaMinimizable + Clone,
+        S: HasExecutions,
+        Z: ExecutesInput<E, EM, I, S>,
+    {
+        let orig_exit_kind = fuzzer.execute_input(state, executor, manager, &input)?;
+        let mut feedback = {
+            let observers = executor.observers();
+            self.factory.create_feedback(&*observers)
+        };
+
+        let orig_len = input.delta_total_len() as u64;
+        let mut test = |candidate: &I| -> Result<bool, Error> {
+            let exit_kind = fuzzer.execute_input(state, executor, manager, candidate)?;
+            if exit_kind != orig_exit_kind {
+                return Ok(false);
+            }
+            let observers = executor.observers();
+            if !feedback.is_interesting(state, manager, candidate, &*observers, &exit_kind)? {
+                return Ok(false);
+            }
+            let len = candidate.delta_total_len() as u64;
+            log::debug!("ddmin reduced input to {len}/{orig_len} units");
+            manager.fire(
+                state,
+                EventWithStats::with_current_time(
+                    Event::UpdateUserStats {
+                        name: Cow::from(DDMIN_STAGE_NAME),
+                        value: UserStats::new(
+                            UserStatsValue::Ratio(len, orig_len),
+                            AggregatorOps::None,
+                        ),
+                        phantom: PhantomData,
+                    },
+                    *state.executions(),
+                ),
+            )?;
+            Ok(true)
+        };
+
+        ddmin(input, &mut test)
+    }
+
+    /// Minimizes the objective at `id` in the solutions and replaces it, if it could be reduced.
+    pub fn minimize_solution<E, EM, F, I, S, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        id: CorpusId,
+    ) -> Result<(), Error>
+    where
+        E: HasObservers,
+        E::Observers: ObserversTuple<I, S>,
+        EM: EventFirer<I, S>,
+        F: Feedback<EM, I, E::Observers, S>,
+        FF: FeedbackFactory<F, E::Observers>,
+        I: DeltaMinimizable + Clone,
+        S: HasExecutions + HasSolutions<I>,
+        Z: ExecutesInput<E, EM, I, S>,
+    {
+        let input = state.solutions().cloned_input_for_id(id)?;
+        let parent_id = state.solutions().get(id)?.borrow().parent_id();
+        let orig_len = input.delta_total_len();
+        let minimized = self.minimize(fuzzer, executor, state, manager, input)?;
+        if minimized.delta_total_len() < orig_len {
+            let exit_kind = fuzzer.execute_input(state, executor, manager, &minimized)?;
+            let mut testcase = Testcase::from(minimized);
+            testcase.set_executions(*state.executions());
+            testcase.set_parent_id_optional(parent_id);
+            testcase.add_metadata(exit_kind);
+            testcase.add_metadata(DdminMetadata);
+            state.solutions_mut().replace(id, testcase)?;
+        }
+        Ok(())
+    }
+}
+
+/// A testcase metadata marking that this testcase was already minimized using [`ddmin`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DdminMetadata;
+
+impl_serdeany!(DdminMetadata);
+
+/// A stage minimizing each corpus entry once using [`ddmin`], see [`DdminMinimizer`].
+#[derive(Debug, Clone)]
+pub struct DdminStage<E, EM, F, FF, I, S, Z> {
+    name: Cow<'static, str>,
+    minimizer: DdminMinimizer<FF>,
+    phantom: PhantomData<(E, EM, F, I, S, Z)>,
+}
+
+impl<E, EM, F, FF, I, S, Z> Named for DdminStage<E, EM, F, FF, I, S, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<E, EM, F, FF, I, S, Z> Restartable<S> for DdminStage<E, EM, F, FF, I, S, Z>
+where
+    S: HasNamedMetadata + HasCurrentCorpusId,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        // A reduced candidate may crash or time out, skip the entry if it keeps happening
+        RetryCountRestartHelper::should_restart(state, &self.name, 3)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+impl<E, EM, F, FF, I, S, Z> Stage<E, EM, S, Z> for DdminStage<E, EM, F, FF, I, S, Z>
+where
+    Z: HasScheduler<I, S> + ExecutesInput<E, EM, I, S> + HasFeedback,
+    Z::Scheduler: RemovableScheduler<I, S>,
+    Z::Feedback: Feedback<EM, I, E::Observers, S>,
+    E: HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    EM: EventFirer<I, S>,
+    F: Feedback<EM, I, E::Observers, S>,
+    FF: FeedbackFactory<F, E::Observers>,
+    I: DeltaMinimizable + Input,
+    S: HasCorpus<I> + HasCurrentTestcase<I> + HasCurrentCorpusId + HasExecutions,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let Some(base_corpus_id) = state.current_corpus_id()? else {
+            return Err(Error::illegal_state(
+                "state is not currently processing a corpus index",
+            ));
+        };
+        if state.current_testcase()?.has_metadata::<DdminMetadata>() {
+            return Ok(());
+        }
+
+        let base = state.current_input_cloned()?;
+        let orig_len = base.delta_total_len();
+        let minimized = self
+            .minimizer
+            .minimize(fuzzer, executor, state, manager, base)?;
+
+        if minimized.delta_total_len() < orig_len {
+            let exit_kind = fuzzer.execute_input(state, executor, manager, &minimized)?;
+            let observers = executor.observers();
+            // like in the tmin stage, this input should not be interesting to the feedback
+            fuzzer.feedback_mut().is_interesting(
+                state,
+                manager,
+                &minimized,
+                &*observers,
+                &exit_kind,
+            )?;
+            let mut testcase = Testcase::from(minimized);
+            testcase.set_executions(*state.executions());
+            testcase.set_parent_id(base_corpus_id);
+            testcase.add_metadata(DdminMetadata);
+
+            fuzzer
+                .feedback_mut()
+                .append_metadata(state, manager, &*observers, &mut testcase)?;
+            let prev = state.corpus_mut().replace(base_corpus_id, testcase)?;
+            fuzzer
+                .scheduler_mut()
+                .on_replace(state, base_corpus_id, &prev)?;
+        } else {
+            state.current_testcase_mut()?.add_metadata(DdminMetadata);
+        }
+
+        Ok(())
+    }
+}
+
+impl<E, EM, F, FF, I, S, Z> DdminStage<E, EM, F, FF, I, S, Z> {
+    /// Creates a new [`DdminStage`], keeping reductions that the feedback built by `factory` deems interesting
+    pub fn new(factory: FF) -> Self {
+        Self {
+            name: Cow::Borrowed(DDMIN_STAGE_NAME),
+            minimizer: DdminMinimizer::new(factory),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Creates a new [`DdminStage`] with a custom name
+    pub fn with_name(factory: FF, name: &str) -> Self {
+        Self {
+            name: Cow::Owned(DDMIN_STAGE_NAME.to_owned() + ":" + name),
+            minimizer: DdminMinimizer::new(factory),
+            phantom: PhantomData,
+        }
+    }
+
+    /// The underlying [`DdminMinimizer`]
+    pub fn minimizer(&self) -> &DdminMinimizer<FF> {
+        &self.minimizer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::ddmin;
+    use crate::inputs::{BytesInput, HasMutatorBytes};
+
+    #[test]
+    fn test_ddmin_bytes() {
+        let input = BytesInput::new(b"xxxxAxxxxxxxxBxxxCxxxx".to_vec());
+        let mut runs = 0;
+        let mut test = |candidate: &BytesInput| {
+            runs += 1;
+            let bytes = candidate.mutator_bytes();
+            Ok(bytes.contains(&b'A') && bytes.contains(&b'C'))
+        };
+        let minimized = ddmin(input, &mut test).unwrap();
+        assert_eq!(minimized.mutator_bytes(), b"AC");
+        assert!(runs > 0);
+    }
+
+    #[test]
+    fn test_ddmin_is_deterministic() {
+        let input = BytesInput::new((0..=255).collect::<Vec<u8>>());
+        let mut test = |candidate: &BytesInput| {
+            let bytes = candidate.mutator_bytes();
+            Ok(bytes.iter().map(|b| u32::from(*b)).sum::<u32>() >= 500)
+        };
+        let first = ddmin(input.clone(), &mut test).unwrap();
+        let second = ddmin(input, &mut test).unwrap();
+        assert_eq!(first, second);
+
+        // ddmin only guarantees 1-minimality: removing any single byte loses the property
+        assert!(test(&first).unwrap());
+        let bytes = first.mutator_bytes();
+        for i in 0..bytes.len() {
+            let mut reduced = bytes.to_vec();
+            reduced.remove(i);
+            assert!(!test(&BytesInput::new(reduced)).unwrap());
+        }
+    }
+
+    #[cfg(feature = "multipart_inputs")]
+    #[test]
+    fn test_ddmin_list() {
+        use crate::inputs::ListInput;
+
+        let input = ListInput::new(vec![
+            BytesInput::new(b"hello".to_vec()),
+            BytesInput::new(b"crash here".to_vec()),
+            BytesInput::new(b"world".to_vec()),
+        ]);
+        let mut test = |candidate: &ListInput<BytesInput>| {
+            Ok(candidate
+                .parts()
+                .iter()
+                .any(|part| part.mutator_bytes().contains(&b'c')))
+        };
+        let minimized = ddmin(input, &mut test).unwrap();
+        assert_eq!(minimized.len(), 1);
+        assert_eq!(minimized.parts()[0].mutator_bytes(), b"c");
+    }
+}
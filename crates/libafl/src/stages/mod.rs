@@ -14,12 +14,17 @@ use core::{fmt, marker::PhantomData};
 
 #[cfg(feature = "std")]
 pub use afl_stats::{AflStatsStage, CalibrationTime, FuzzTime, SyncTime};
+#[cfg(feature = "std")]
+pub use afl_sync::{AflQueueExportStage, AflQueueImportStage};
 pub use calibrate::CalibrationStage;
+#[cfg(feature = "std")]
+pub use checkpoint::CheckpointStage;
 pub use colorization::*;
 #[cfg(all(feature = "std", unix))]
 pub use concolic::ConcolicTracingStage;
 #[cfg(all(feature = "std", feature = "concolic_mutation", unix))]
 pub use concolic::SimpleConcolicMutationalStage;
+pub use ddmin::{DdminMinimizer, DdminStage, DeltaMinimizable};
 #[cfg(feature = "std")]
 pub use dump::*;
 pub use generalization::GeneralizationStage;
@@ -29,6 +34,7 @@ use libafl_bolts::{
     tuples::{HasConstLen, IntoVec},
 };
 pub use logics::*;
+pub use mutation_stats::MutationStatsStage;
 pub use mutational::{MutationalStage, StdMutationalStage};
 pub use power::{PowerMutationalStage, StdPowerMutationalStage};
 use serde::{Deserialize, Serialize};
@@ -65,16 +71,22 @@ pub use replay::*;
 
 #[cfg(feature = "std")]
 pub mod afl_stats;
+#[cfg(feature = "std")]
+pub mod afl_sync;
 pub mod calibrate;
+#[cfg(feature = "std")]
+pub mod checkpoint;
 pub mod colorization;
 #[cfg(all(feature = "std", unix))]
 pub mod concolic;
+pub mod ddmin;
 #[cfg(feature = "std")]
 pub mod dump;
 pub mod dynamic;
 pub mod generalization;
 pub mod generation;
 pub mod logics;
+pub mod mutation_stats;
 pub mod nop;
 pub mod power;
 #[cfg(feature = "std")]
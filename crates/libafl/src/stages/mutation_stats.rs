@@ -0,0 +1,136 @@
+//! The [`MutationStatsStage`] reports the per-mutation counters of the
+//! [`crate::mutators::StatsScheduledMutator`]s to the monitors, and optionally dumps them to disk.
+
+use alloc::{borrow::Cow, collections::BTreeMap, vec::Vec};
+use core::{marker::PhantomData, time::Duration};
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use libafl_bolts::current_time;
+#[cfg(feature = "std")]
+use libafl_bolts::fs::write_file_atomic;
+
+use crate::{
+    Error, HasMetadata,
+    events::{Event, EventFirer, EventWithStats},
+    monitors::stats::{AggregatorOps, UserStats, UserStatsValue},
+    mutators::MutationStatsMetadata,
+    stages::{Restartable, Stage},
+    state::HasExecutions,
+};
+
+/// A stage reporting the [`MutationStatsMetadata`] every `interval`.
+///
+/// Each mutation is sent as one `mutation|<mutation>` user stat: the ratio of its corpus and objective finds
+/// to its applications over all stages, averaged over all clients.
+/// The counters of each stage are only in the optional JSON dump, see [`Self::with_dump_path`].
+#[derive(Debug)]
+pub struct MutationStatsStage<I> {
+    interval: Duration,
+    last_report: Duration,
+    #[cfg(feature = "std")]
+    dump_path: Option<PathBuf>,
+    phantom: PhantomData<I>,
+}
+
+impl<I> MutationStatsStage<I> {
+    /// Creates a new [`MutationStatsStage`], reporting every `interval`
+    #[must_use]
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_report: current_time(),
+            #[cfg(feature = "std")]
+            dump_path: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Also dump the counters of this client as JSON to `path` on each report
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn with_dump_path<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.dump_path = Some(path.into());
+        self
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for MutationStatsStage<I>
+where
+    EM: EventFirer<I, S>,
+    S: HasMetadata + HasExecutions,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let now = current_time();
+        if now.saturating_sub(self.last_report) < self.interval {
+            return Ok(());
+        }
+        self.last_report = now;
+
+        let Ok(meta) = state.metadata::<MutationStatsMetadata>() else {
+            return Ok(());
+        };
+
+        #[cfg(feature = "std")]
+        if let Some(dump_path) = &self.dump_path {
+            let json = serde_json::to_vec_pretty(&meta.stages)
+                .map_err(|err| Error::serialize(format!("{err:?}")))?;
+            write_file_atomic(dump_path, &json)?;
+        }
+
+        // The finds and applications of each mutation, over all stages
+        let mut stats: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+        for mutation in meta.stages.values().flatten() {
+            let (finds, applied) = stats.entry(mutation.name.as_ref()).or_default();
+            *finds += mutation.corpus_finds + mutation.objective_finds;
+            *applied += mutation.applied;
+        }
+        let stats: Vec<_> = stats
+            .into_iter()
+            .filter(|(_, (_, applied))| *applied > 0)
+            .map(|(name, (finds, applied))| (format!("mutation|{name}"), finds, applied))
+            .collect();
+
+        let executions = *state.executions();
+        for (name, finds, applied) in stats {
+            manager.fire(
+                state,
+                EventWithStats::with_current_time(
+                    Event::UpdateUserStats {
+                        name: Cow::Owned(name),
+                        value: UserStats::new(
+                            UserStatsValue::Ratio(finds, applied),
+                            AggregatorOps::Avg,
+                        ),
+                        phantom: PhantomData,
+                    },
+                    executions,
+                ),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<I, S> Restartable<S> for MutationStatsStage<I> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(())
+    }
+}
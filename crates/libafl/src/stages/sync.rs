@@ -28,6 +28,27 @@ use crate::{
     },
 };
 
+/// Runs `import` without a current corpus id, and restores it afterwards.
+///
+/// Imported inputs are not derived from the testcase currently being fuzzed,
+/// so feedbacks such as the `LineageFeedback` must not see it as their parent.
+pub(crate) fn import_without_current_corpus_id<F, R, S>(
+    state: &mut S,
+    import: F,
+) -> Result<R, Error>
+where
+    F: FnOnce(&mut S) -> Result<R, Error>,
+    S: HasCurrentCorpusId,
+{
+    let current_id = state.current_corpus_id()?;
+    state.clear_corpus_id()?;
+    let res = import(state);
+    if let Some(id) = current_id {
+        state.set_corpus_id(id)?;
+    }
+    res
+}
+
 /// Default name for `SyncFromDiskStage`; derived from AFL++
 pub const SYNC_FROM_DISK_STAGE_NAME: &str = "sync";
 
@@ -125,31 +146,32 @@ where
         // even in the event of a target restart.
         let to_sync = sync_from_disk_metadata.left_to_sync.clone();
         log::debug!("Number of files to sync: {:?}", to_sync.len());
-        for path in to_sync {
-            // Removing each path from the `left_to_sync` Vec before evaluating
-            // prevents duplicate processing and ensures that each file is evaluated only once. This approach helps
-            // avoid potential infinite loops that may occur if a file is an objective or an invalid input.
-            state
-                .metadata_mut::<SyncFromDiskMetadata>()
-                .unwrap()
-                .left_to_sync
-                .retain(|p| p != &path);
-            let input = match (self.load_callback)(fuzzer, state, &path) {
-                Ok(input) => input,
-                Err(Error::InvalidInput(reason, _)) => {
-                    log::warn!(
-                        "Invalid input found in {} when syncing; reason {reason}; skipping;",
-                        path.display()
-                    );
-                    continue;
-                }
-                Err(e) => return Err(e),
-            };
-            log::debug!("Syncing and evaluating {}", path.display());
-            fuzzer.evaluate_input(state, executor, manager, &input)?;
-        }
-
-        Ok(())
+        import_without_current_corpus_id(state, |state| {
+            for path in to_sync {
+                // Removing each path from the `left_to_sync` Vec before evaluating
+                // prevents duplicate processing and ensures that each file is evaluated only once. This approach helps
+                // avoid potential infinite loops that may occur if a file is an objective or an invalid input.
+                state
+                    .metadata_mut::<SyncFromDiskMetadata>()
+                    .unwrap()
+                    .left_to_sync
+                    .retain(|p| p != &path);
+                let input = match (self.load_callback)(fuzzer, state, &path) {
+                    Ok(input) => input,
+                    Err(Error::InvalidInput(reason, _)) => {
+                        log::warn!(
+                            "Invalid input found in {} when syncing; reason {reason}; skipping;",
+                            path.display()
+                        );
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                log::debug!("Syncing and evaluating {}", path.display());
+                fuzzer.evaluate_input(state, executor, manager, &input)?;
+            }
+            Ok(())
+        })
     }
 }
 
@@ -256,6 +278,7 @@ where
         + HasMetadata
         + HasSolutions<I>
         + HasCurrentTestcase<I>
+        + HasCurrentCorpusId
         + Stoppable
         + MaybeHasClientPerfMonitor,
     SHM: ShMem,
@@ -288,6 +311,7 @@ where
                         Event::NewTestcase {
                             input,
                             observers_buf: None,
+                            metadata_buf: None,
                             exit_kind: ExitKind::Ok,
                             corpus_size: 0, // TODO choose if sending 0 or the actual real value
                             client_config: EventConfig::AlwaysUnique,
@@ -316,7 +340,9 @@ where
             }
         }
 
-        self.client.process(fuzzer, state, executor, manager)?;
+        import_without_current_corpus_id(state, |state| {
+            self.client.process(fuzzer, state, executor, manager)
+        })?;
         Ok(())
     }
 }
@@ -16,7 +16,10 @@ use std::{
 };
 
 #[cfg(feature = "std")]
-use libafl_bolts::core_affinity::{CoreId, Cores};
+use libafl_bolts::{
+    core_affinity::{CoreId, Cores},
+    fs::write_file_atomic,
+};
 use libafl_bolts::{
     rands::{Rand, StdRand},
     serdeany::{NamedSerdeAnyMap, SerdeAnyMap},
@@ -43,6 +46,10 @@ use crate::{
 /// The maximum size of a testcase
 pub const DEFAULT_MAX_SIZE: usize = 1_048_576;
 
+/// The name of the checkpoint file inside a checkpoint directory
+#[cfg(feature = "std")]
+pub const CHECKPOINT_FILE: &str = "state.checkpoint";
+
 /// Trait for elements offering a corpus
 pub trait HasCorpus<I> {
     /// The associated type implementing [`Corpus`].
@@ -1169,6 +1176,62 @@ where
     }
 }
 
+/// Serializes the complete `state` into `dir`, atomically replacing an older checkpoint.
+#[cfg(feature = "std")]
+pub fn write_checkpoint<S, P>(state: &S, dir: P) -> Result<(), Error>
+where
+    S: Serialize,
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    write_file_atomic(dir.join(CHECKPOINT_FILE), &postcard::to_allocvec(state)?)
+}
+
+#[cfg(feature = "std")]
+impl<C, I, R, SC> StdState<C, I, R, SC>
+where
+    C: Serialize + DeserializeOwned,
+    R: Serialize + DeserializeOwned,
+    SC: Serialize + DeserializeOwned,
+{
+    /// Writes a checkpoint of this state to `dir`.
+    ///
+    /// The checkpoint contains everything needed to continue the campaign: the corpus and the
+    /// solutions (for on-disk corpora, references to the files), all metadata including the
+    /// feedback and scheduler states, the execution counters and the state of the rand.
+    pub fn checkpoint<P>(&self, dir: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        write_checkpoint(self, dir)
+    }
+
+    /// Returns `true` if `dir` contains a checkpoint to resume from
+    #[must_use]
+    pub fn has_checkpoint<P>(dir: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        dir.as_ref().join(CHECKPOINT_FILE).is_file()
+    }
+
+    /// Restores the state from the checkpoint in `dir`, written by [`StdState::checkpoint`]
+    /// or a [`crate::stages::CheckpointStage`].
+    ///
+    /// The fuzzer continues in the stage it was in when the checkpoint was written,
+    /// just like after a restart.
+    pub fn resume_from_checkpoint<P>(dir: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let bytes = fs::read(dir.as_ref().join(CHECKPOINT_FILE))?;
+        let mut state: Self = postcard::from_bytes(&bytes)?;
+        state.on_restart()?;
+        Ok(state)
+    }
+}
+
 impl StdState<InMemoryCorpus<NopInput>, NopInput, StdRand, InMemoryCorpus<NopInput>> {
     /// Create an empty [`StdState`] that has very minimal uses.
     /// Potentially good for testing.
@@ -1352,10 +1415,41 @@ impl<I> HasClientPerfMonitor for NopState<I> {
 
 #[cfg(test)]
 mod test {
+    #[cfg(feature = "std")]
+    use libafl_bolts::rands::{Rand, StdRand};
+
     use crate::state::StdState;
+    #[cfg(feature = "std")]
+    use crate::{
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        inputs::NopInput,
+        state::{HasCorpus, HasExecutions, HasRand},
+    };
 
     #[test]
     fn test_std_state() {
         StdState::nop().expect("couldn't instantiate the test state");
     }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_checkpoint_resume() {
+        let dir = std::env::temp_dir().join(format!("libafl_checkpoint_{}", std::process::id()));
+        let mut state = StdState::nop().unwrap();
+        state.corpus_mut().add(Testcase::new(NopInput {})).unwrap();
+        *state.executions_mut() = 1337;
+        state.rand_mut().next();
+        state.checkpoint(&dir).unwrap();
+
+        let mut resumed: StdState<
+            InMemoryCorpus<NopInput>,
+            NopInput,
+            StdRand,
+            InMemoryCorpus<NopInput>,
+        > = StdState::resume_from_checkpoint(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(*resumed.executions(), 1337);
+        assert_eq!(resumed.corpus().count(), 1);
+        assert_eq!(resumed.rand_mut().next(), state.rand_mut().next());
+    }
 }
@@ -97,6 +97,8 @@ pub mod os;
 #[cfg(feature = "alloc")]
 pub mod ownedref;
 pub mod rands;
+#[cfg(feature = "secure_channel")]
+pub mod secure_channel;
 #[cfg(feature = "alloc")]
 pub mod serdeany;
 pub mod shmem;
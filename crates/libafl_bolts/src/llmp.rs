@@ -53,6 +53,12 @@ Finally, call [`LlmpBroker::loop_forever()`].
 
 For broker2broker communication, all messages are forwarded via network sockets.
 
+Local clients usually connect to the broker on a tcp port on localhost.
+On unix, they can instead connect to a unix domain socket (see [`LlmpConnection::on_unix_path`]),
+for example, to run clients in their own network namespace.
+Since the shared maps are announced by id, such clients also need a [`ShMemProvider`]
+they can map the broker's pages with, such as a `ServedShMemProvider` handing out `memfd` maps over a unix socket.
+
 Check out the `llmp_test` example in ./examples, or build it with `cargo run --example llmp_test`.
 
 */
@@ -61,6 +67,8 @@ Check out the `llmp_test` example in ./examples, or build it with `cargo run --e
 use alloc::boxed::Box;
 #[cfg(feature = "std")]
 use alloc::string::ToString;
+#[cfg(feature = "secure_channel")]
+use alloc::sync::Arc;
 use alloc::{string::String, vec::Vec};
 #[cfg(feature = "std")]
 use core::net::SocketAddr;
@@ -68,6 +76,8 @@ use core::net::SocketAddr;
 use core::sync::atomic::AtomicU32;
 #[cfg(target_pointer_width = "64")]
 use core::sync::atomic::AtomicU64;
+#[cfg(feature = "secure_channel")]
+use core::sync::atomic::AtomicUsize;
 use core::{
     cmp::max,
     fmt::Debug,
@@ -87,6 +97,15 @@ use std::{
     sync::mpsc::channel,
     thread,
 };
+#[cfg(all(unix, feature = "std"))]
+use std::{
+    fs,
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+};
 
 #[cfg(all(debug_assertions, feature = "llmp_debug", feature = "std"))]
 use backtrace::Backtrace;
@@ -103,6 +122,8 @@ use crate::os::unix_signals::setup_signal_handler;
 use crate::os::unix_signals::{Signal, SignalHandler, siginfo_t, ucontext_t};
 #[cfg(all(windows, feature = "std"))]
 use crate::os::windows_exceptions::{CtrlHandler, setup_ctrl_handler};
+#[cfg(feature = "secure_channel")]
+use crate::secure_channel::{SECURE_HANDSHAKE_TIMEOUT, SecureChannelConfig, SecureStream};
 use crate::{
     ClientId, Error,
     shmem::{ShMem, ShMemDescription, ShMemId, ShMemProvider},
@@ -114,6 +135,10 @@ use crate::{IP_LOCALHOST, current_time};
 /// Usually, this value should not exceed `1`, else the broker cannot keep up with the amount of incoming messages.
 /// Instead of increasing this value, you may consider sending new messages at a lower rate, else your Sender will eventually `OOM`.
 const LLMP_CFG_MAX_PENDING_UNREAD_PAGES: usize = 3;
+/// The max number of secure handshakes the broker runs in parallel, each on its own thread.
+/// Further connections are dropped until a handshake finishes or times out.
+#[cfg(feature = "secure_channel")]
+const LLMP_CFG_MAX_PENDING_HANDSHAKES: usize = 64;
 /// We'll start off with 256 megabyte maps per fuzzer client
 #[cfg(not(feature = "llmp_small_maps"))]
 const LLMP_CFG_INITIAL_MAP_SIZE: usize = 1 << 28;
@@ -371,6 +396,9 @@ impl TryFrom<Vec<u8>> for TcpResponse {
 pub enum Listener {
     /// Listener listening on `tcp`.
     Tcp(TcpListener),
+    /// Listener listening on a unix domain socket, for local clients only.
+    #[cfg(unix)]
+    Unix(UnixListener),
 }
 
 /// A listener stream abstraction
@@ -379,6 +407,9 @@ pub enum Listener {
 pub enum ListenerStream {
     /// Listener listening on `tcp`.
     Tcp(TcpStream, SocketAddr),
+    /// Listener listening on a unix domain socket.
+    #[cfg(unix)]
+    Unix(UnixStream),
     /// No listener provided.
     Empty(),
 }
@@ -394,10 +425,116 @@ impl Listener {
                     ListenerStream::Empty()
                 }
             },
+            #[cfg(unix)]
+            Listener::Unix(inner) => match inner.accept() {
+                Ok((stream, _addr)) => ListenerStream::Unix(stream),
+                Err(err) => {
+                    log::warn!("Ignoring failed accept: {err:?}");
+                    ListenerStream::Empty()
+                }
+            },
+        }
+    }
+}
+
+/// A tcp connection between llmp peers.
+///
+/// With the `secure_channel` feature, the connection may be authenticated with a [`SecureChannelConfig`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum LlmpTcpStream {
+    /// A plain tcp connection
+    Plain(TcpStream),
+    /// A tcp connection, authenticated (and maybe encrypted)
+    #[cfg(feature = "secure_channel")]
+    Secure(Box<SecureStream<TcpStream>>),
+}
+
+#[cfg(feature = "std")]
+impl LlmpTcpStream {
+    /// Wrap a connection we accepted, running the handshake if a [`SecureChannelConfig`] is given.
+    /// Rejects peers that fail the handshake.
+    pub fn accept(
+        stream: TcpStream,
+        #[cfg(feature = "secure_channel")] secure_channel: Option<&SecureChannelConfig>,
+    ) -> Result<Self, Error> {
+        #[cfg(feature = "secure_channel")]
+        if let Some(config) = secure_channel {
+            // Don't let a silent peer stall us
+            stream.set_read_timeout(Some(SECURE_HANDSHAKE_TIMEOUT))?;
+            let stream = SecureStream::accept(stream, config)?;
+            stream.get_ref().set_read_timeout(None)?;
+            return Ok(Self::Secure(Box::new(stream)));
+        }
+        Ok(Self::Plain(stream))
+    }
+
+    /// Wrap a connection we opened, running the handshake if a [`SecureChannelConfig`] is given
+    pub fn connect(
+        stream: TcpStream,
+        #[cfg(feature = "secure_channel")] secure_channel: Option<&SecureChannelConfig>,
+    ) -> Result<Self, Error> {
+        #[cfg(feature = "secure_channel")]
+        if let Some(config) = secure_channel {
+            stream.set_read_timeout(Some(SECURE_HANDSHAKE_TIMEOUT))?;
+            let stream = SecureStream::connect(stream, config)?;
+            stream.get_ref().set_read_timeout(None)?;
+            return Ok(Self::Secure(Box::new(stream)));
+        }
+        Ok(Self::Plain(stream))
+    }
+
+    /// The underlying [`TcpStream`]
+    #[must_use]
+    pub fn tcp(&self) -> &TcpStream {
+        match self {
+            Self::Plain(stream) => stream,
+            #[cfg(feature = "secure_channel")]
+            Self::Secure(stream) => stream.get_ref(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Read for LlmpTcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "secure_channel")]
+            Self::Secure(stream) => stream.read(buf),
         }
     }
 }
 
+#[cfg(feature = "std")]
+impl Write for LlmpTcpStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "secure_channel")]
+            Self::Secure(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(stream) => stream.flush(),
+            #[cfg(feature = "secure_channel")]
+            Self::Secure(stream) => stream.flush(),
+        }
+    }
+}
+
+/// A connection accepted by the listener of a broker, ready for the peer's request
+#[cfg(feature = "std")]
+enum AcceptedStream {
+    /// A tcp connection, after the handshake, if any
+    Tcp(LlmpTcpStream),
+    /// A connection on the unix domain socket
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
 /// Get sharedmem from a page
 #[inline]
 #[expect(clippy::cast_ptr_alignment)]
@@ -466,10 +603,36 @@ fn tcp_bind(port: u16) -> Result<TcpListener, Error> {
     Ok(listener)
 }
 
+/// Bind to a unix domain socket at the given `path`.
+/// Removes a stale socket file left behind by a broker that no longer runs.
+/// Fails with [`ErrorKind::AddrInUse`], if a broker is listening on this path.
+#[cfg(all(unix, feature = "std"))]
+fn unix_bind(path: &Path) -> Result<UnixListener, Error> {
+    match UnixListener::bind(path) {
+        Ok(listener) => Ok(listener),
+        Err(err)
+            if err.kind() == ErrorKind::AddrInUse
+                && fs::metadata(path).is_ok_and(|meta| meta.file_type().is_socket())
+                && UnixStream::connect(path).is_err() =>
+        {
+            log::info!("Removing stale unix socket {}", path.display());
+            fs::remove_file(path)?;
+            UnixListener::bind(path).map_err(|err| {
+                Error::os_error(err, format!("Failed to bind to {}", path.display()))
+            })
+        }
+        Err(err) => Err(Error::os_error(
+            err,
+            format!("Failed to bind to {}", path.display()),
+        )),
+    }
+}
+
 /// Send one message as `u32` len and `[u8;len]` bytes
 #[cfg(feature = "std")]
-pub fn send_tcp_msg<T>(stream: &mut TcpStream, msg: &T) -> Result<(), Error>
+pub fn send_tcp_msg<S, T>(stream: &mut S, msg: &T) -> Result<(), Error>
 where
+    S: Write,
     T: Serialize,
 {
     let msg = postcard::to_allocvec(msg)?;
@@ -486,6 +649,7 @@ where
     let size_bytes = (msg.len() as u32).to_be_bytes();
     stream.write_all(&size_bytes)?;
     stream.write_all(&msg)?;
+    stream.flush()?;
 
     #[cfg(feature = "llmp_debug")]
     log::trace!("LLMP TCP: Sending {} bytes finished.", msg.len());
@@ -495,14 +659,14 @@ where
 
 /// Receive one message of `u32` len and `[u8; len]` bytes
 #[cfg(feature = "std")]
-pub fn recv_tcp_msg(stream: &mut TcpStream) -> Result<Vec<u8>, Error> {
+pub fn recv_tcp_msg<S>(stream: &mut S) -> Result<Vec<u8>, Error>
+where
+    S: Read,
+{
     // Always receive one be u32 of size, then the command.
 
     #[cfg(feature = "llmp_debug")]
-    log::trace!(
-        "LLMP TCP: Waiting for packet... (Timeout: {:?})",
-        stream.read_timeout().unwrap_or(None)
-    );
+    log::trace!("LLMP TCP: Waiting for packet...");
 
     let mut size_bytes = [0_u8; 4];
     stream.read_exact(&mut size_bytes)?;
@@ -788,6 +952,115 @@ where
         let conn = LlmpConnection::IsClient { client };
         Ok(conn)
     }
+
+    /// Creates either a broker, if no broker listens on the unix domain socket at `path`, or a client, connected to it,
+    /// like [`LlmpConnection::on_port`], but without any tcp listener.
+    #[cfg(all(unix, feature = "std"))]
+    pub fn on_unix_path<P>(shmem_provider: SP, path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        match unix_bind(path.as_ref()) {
+            Ok(listener) => {
+                log::info!("We're the broker");
+
+                let mut broker = LlmpBroker::new(shmem_provider, tuple_list!())?;
+                let _listener_thread = broker
+                    .inner_mut()
+                    .launch_listener(Listener::Unix(listener))?;
+                Ok(LlmpConnection::IsBroker { broker })
+            }
+            Err(Error::OsError(e, ..)) if e.kind() == ErrorKind::AddrInUse => {
+                log::info!("We're the client (unix socket already bound by broker, {e:#?})");
+                Self::client_on_unix_path(shmem_provider, path)
+            }
+            Err(e) => {
+                log::error!("{e:?}");
+                Err(e)
+            }
+        }
+    }
+
+    /// Creates a new broker, listening on the unix domain socket at `path`
+    #[cfg(all(unix, feature = "std"))]
+    pub fn broker_on_unix_path<P>(shmem_provider: SP, path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(LlmpConnection::IsBroker {
+            broker: LlmpBroker::create_attach_to_unix(shmem_provider, tuple_list!(), path)?,
+        })
+    }
+
+    /// Creates a new client, connected to the broker on the unix domain socket at `path`
+    #[cfg(all(unix, feature = "std"))]
+    pub fn client_on_unix_path<P>(shmem_provider: SP, path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let client = LlmpClient::create_attach_to_unix(shmem_provider, path)?;
+        Ok(LlmpConnection::IsClient { client })
+    }
+
+    /// Creates either a broker, if the tcp port is not bound, or a client, connected to this port,
+    /// like [`LlmpConnection::on_port`].
+    /// All tcp connections are authenticated with the given [`SecureChannelConfig`],
+    /// peers that fail to authenticate are rejected.
+    #[cfg(feature = "secure_channel")]
+    pub fn on_port_secure(
+        shmem_provider: SP,
+        port: u16,
+        secure_channel: &SecureChannelConfig,
+    ) -> Result<Self, Error> {
+        match tcp_bind(port) {
+            Ok(listener) => {
+                log::info!("We're the broker");
+
+                let mut broker = LlmpBroker::new(shmem_provider, tuple_list!())?;
+                broker
+                    .inner_mut()
+                    .set_secure_channel(Some(secure_channel.clone()));
+                let _listener_thread = broker
+                    .inner_mut()
+                    .launch_listener(Listener::Tcp(listener))?;
+                Ok(LlmpConnection::IsBroker { broker })
+            }
+            Err(Error::OsError(e, ..)) if e.kind() == ErrorKind::AddrInUse => {
+                log::info!("We're the client (internal port already bound by broker, {e:#?})");
+                Self::client_on_port_secure(shmem_provider, port, secure_channel)
+            }
+            Err(e) => {
+                log::error!("{e:?}");
+                Err(e)
+            }
+        }
+    }
+
+    /// Creates a new broker on the given port, only accepting peers authenticated with the given [`SecureChannelConfig`]
+    #[cfg(feature = "secure_channel")]
+    pub fn broker_on_port_secure(
+        shmem_provider: SP,
+        port: u16,
+        secure_channel: &SecureChannelConfig,
+    ) -> Result<Self, Error> {
+        let mut broker = LlmpBroker::new(shmem_provider, tuple_list!())?;
+        broker
+            .inner_mut()
+            .set_secure_channel(Some(secure_channel.clone()));
+        broker.inner_mut().launch_tcp_listener_on(port)?;
+        Ok(LlmpConnection::IsBroker { broker })
+    }
+
+    /// Creates a new client on the given port, authenticating with the given [`SecureChannelConfig`]
+    #[cfg(feature = "secure_channel")]
+    pub fn client_on_port_secure(
+        shmem_provider: SP,
+        port: u16,
+        secure_channel: &SecureChannelConfig,
+    ) -> Result<Self, Error> {
+        let client = LlmpClient::create_attach_to_tcp_secure(shmem_provider, port, secure_channel)?;
+        Ok(LlmpConnection::IsClient { client })
+    }
 }
 
 impl<MT, SHM, SP> LlmpConnection<MT, SHM, SP>
@@ -2150,6 +2423,9 @@ pub struct LlmpBrokerInner<SHM, SP> {
     clients_to_remove: Vec<ClientId>,
     /// The `ShMemProvider` to use
     shmem_provider: SP,
+    /// If set, all tcp peers of this broker need to authenticate with this config
+    #[cfg(feature = "secure_channel")]
+    secure_channel: Option<SecureChannelConfig>,
 }
 
 /// The broker (node 0)
@@ -2539,6 +2815,17 @@ where
         })
     }
 
+    /// Create a new [`LlmpBroker`] listening on a unix domain socket at the given `path`, instead of a TCP port
+    #[cfg(all(unix, feature = "std"))]
+    pub fn create_attach_to_unix<P>(shmem_provider: SP, hooks: HT, path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut broker = Self::new(shmem_provider, hooks)?;
+        broker.inner.launch_unix_listener_on(path)?;
+        Ok(broker)
+    }
+
     /// Create a new [`LlmpBroker`] attaching to a TCP port and telling if it has to keep pages forever
     #[cfg(feature = "std")]
     pub fn with_keep_pages_attach_to_tcp(
@@ -2935,9 +3222,19 @@ where
             exit_cleanly_after: None,
             num_clients_seen: 0,
             shmem_provider,
+            #[cfg(feature = "secure_channel")]
+            secure_channel: None,
         })
     }
 
+    /// Require all tcp peers of this broker, local clients connecting via tcp as well as other brokers,
+    /// to authenticate with the given [`SecureChannelConfig`].
+    /// Applies to listeners launched and brokers connected after this call.
+    #[cfg(feature = "secure_channel")]
+    pub fn set_secure_channel(&mut self, secure_channel: Option<SecureChannelConfig>) {
+        self.secure_channel = secure_channel;
+    }
+
     /// Gets the [`ClientId`] the next client attaching to this broker will get.
     /// In its current implementation, the inner value of the next [`ClientId`]
     /// is equal to `self.num_clients_seen`.
@@ -3030,8 +3327,13 @@ where
     where
         A: ToSocketAddrs,
     {
-        let mut stream = TcpStream::connect(addr)?;
+        let stream = TcpStream::connect(addr)?;
         log::info!("B2B: Connected to {stream:?}");
+        let mut stream = LlmpTcpStream::connect(
+            stream,
+            #[cfg(feature = "secure_channel")]
+            self.secure_channel.as_ref(),
+        )?;
 
         match recv_tcp_msg(&mut stream)?.try_into()? {
             TcpResponse::BrokerConnectHello {
@@ -3160,6 +3462,22 @@ where
         self.launch_listener(Listener::Tcp(listener))
     }
 
+    /// Launches a thread using a unix domain socket listener, on which new local clients may connect to this broker.
+    /// Unlike tcp, this works for clients in other network namespaces, as long as they can reach the socket file.
+    /// Only local clients can connect this way, brokers on other machines still need tcp.
+    #[cfg(all(unix, feature = "std"))]
+    pub fn launch_unix_listener_on<P>(&mut self, path: P) -> Result<thread::JoinHandle<()>, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let listener = unix_bind(path.as_ref())?;
+        log::info!(
+            "Server listening on unix socket {}",
+            path.as_ref().display()
+        );
+        self.launch_listener(Listener::Unix(listener))
+    }
+
     /// Announces a new client on the given shared map.
     /// Called from a background thread, typically.
     /// Upon receiving this message, the broker should map the announced page and start tracking it for new messages.
@@ -3206,7 +3524,7 @@ where
     #[cfg(feature = "std")]
     #[expect(clippy::too_many_lines)]
     fn b2b_thread_on(
-        mut stream: TcpStream,
+        mut stream: LlmpTcpStream,
         b2b_client_id: ClientId,
         broker_shmem_description: &ShMemDescription,
     ) -> Result<ShMemDescription, Error> {
@@ -3225,6 +3543,7 @@ where
 
             // The background thread blocks on the incoming connection for 15 seconds (if no data is available), then checks if it should forward own messages, then blocks some more.
             stream
+                .tcp()
                 .set_read_timeout(Some(_LLMP_B2B_BLOCK_TIME))
                 .expect("Failed to set tcp stream timeout");
 
@@ -3252,7 +3571,7 @@ where
             #[cfg(feature = "llmp_debug")]
             log::info!("B2B: Starting proxy loop :)");
 
-            let peer_address = stream.peer_addr().unwrap();
+            let peer_address = stream.tcp().peer_addr().unwrap();
 
             loop {
                 // first, forward all data we have.
@@ -3351,15 +3670,18 @@ where
         ret
     }
 
-    /// handles a single tcp request in the current context.
+    /// Handles a request of a client on this machine, received over tcp or a unix socket.
+    /// Returns `false` if this is not a request of a local client.
     #[cfg(feature = "std")]
-    fn handle_tcp_request(
-        mut stream: TcpStream,
+    fn handle_local_request<S>(
+        stream: &mut S,
         request: &TcpRequest,
         current_client_id: &mut ClientId,
         sender: &mut LlmpSender<SHM, SP>,
-        broker_shmem_description: &ShMemDescription,
-    ) {
+    ) -> bool
+    where
+        S: Write,
+    {
         match request {
             TcpRequest::ClientQuit { client_id } => {
                 // todo search the ancestor_id and remove it.
@@ -3375,7 +3697,7 @@ where
                 }
 
                 if let Err(e) = send_tcp_msg(
-                    &mut stream,
+                    stream,
                     &TcpResponse::LocalClientAccepted {
                         client_id: *current_client_id,
                     },
@@ -3384,6 +3706,24 @@ where
                 }
                 current_client_id.0 += 1;
             }
+            TcpRequest::RemoteBrokerHello { .. } => return false,
+        }
+        true
+    }
+
+    /// handles a single tcp request in the current context.
+    #[cfg(feature = "std")]
+    fn handle_tcp_request(
+        mut stream: LlmpTcpStream,
+        request: &TcpRequest,
+        current_client_id: &mut ClientId,
+        sender: &mut LlmpSender<SHM, SP>,
+        broker_shmem_description: &ShMemDescription,
+    ) {
+        match request {
+            TcpRequest::ClientQuit { .. } | TcpRequest::LocalClientHello { .. } => {
+                Self::handle_local_request(&mut stream, request, current_client_id, sender);
+            }
             TcpRequest::RemoteBrokerHello { hostname } => {
                 log::info!("B2B new client: {hostname}");
 
@@ -3412,6 +3752,36 @@ where
         }
     }
 
+    /// Send the initial `broker_hello` to a newly connected peer, without anyone asking,
+    /// and receive its request.
+    /// This makes it a tiny bit easier to map the broker map for new Clients.
+    #[cfg(feature = "std")]
+    fn recv_peer_request<S>(stream: &mut S, broker_hello: &TcpResponse) -> Option<TcpRequest>
+    where
+        S: Read + Write,
+    {
+        if let Err(e) = send_tcp_msg(stream, broker_hello) {
+            log::error!("Error sending initial hello: {e:?}");
+            return None;
+        }
+
+        let buf = match recv_tcp_msg(stream) {
+            Ok(buf) => buf,
+            Err(e) => {
+                log::error!("Error receving from tcp: {e:?}");
+                return None;
+            }
+        };
+
+        match buf.try_into() {
+            Ok(req) => Some(req),
+            Err(e) => {
+                log::error!("Could not deserialize tcp message: {e:?}");
+                None
+            }
+        }
+    }
+
     #[cfg(feature = "std")]
     /// Launches a thread using a listener socket, on which new clients may connect to this broker
     pub fn launch_listener(&mut self, listener: Listener) -> Result<thread::JoinHandle<()>, Error> {
@@ -3440,8 +3810,15 @@ where
         );
         let tcp_out_shmem_description = tcp_out_shmem.shmem.description();
         let listener_id = self.register_client(tcp_out_shmem);
+        #[cfg(feature = "secure_channel")]
+        let secure_channel = self.secure_channel.clone();
+        #[cfg(feature = "secure_channel")]
+        let pending_handshakes = Arc::new(AtomicUsize::new(0));
 
-        let ret = thread::spawn(move || {
+        // Peers are handled one by one on a separate thread, so that handshakes can run in parallel
+        let (accepted_sender, accepted_receiver) = channel::<AcceptedStream>();
+
+        thread::spawn(move || {
             // Create a new ShMemProvider for this background thread.
             let mut shmem_provider_bg = SP::new().unwrap();
 
@@ -3462,51 +3839,93 @@ where
                 unused_shmem_cache: vec![],
             };
 
+            for accepted in accepted_receiver {
+                match accepted {
+                    AcceptedStream::Tcp(mut stream) => {
+                        let Some(req) = Self::recv_peer_request(&mut stream, &broker_hello) else {
+                            continue;
+                        };
+
+                        Self::handle_tcp_request(
+                            stream,
+                            &req,
+                            &mut current_client_id,
+                            &mut tcp_incoming_sender,
+                            &broker_shmem_description,
+                        );
+                    }
+                    #[cfg(unix)]
+                    AcceptedStream::Unix(mut stream) => {
+                        let Some(req) = Self::recv_peer_request(&mut stream, &broker_hello) else {
+                            continue;
+                        };
+
+                        if !Self::handle_local_request(
+                            &mut stream,
+                            &req,
+                            &mut current_client_id,
+                            &mut tcp_incoming_sender,
+                        ) {
+                            log::warn!(
+                                "Ignoring {req:?} on unix socket, only local clients may connect there"
+                            );
+                        }
+                    }
+                }
+            }
+        });
+
+        let ret = thread::spawn(move || {
             loop {
-                match listener.accept() {
-                    ListenerStream::Tcp(mut stream, addr) => {
+                let accepted = match listener.accept() {
+                    ListenerStream::Tcp(stream, addr) => {
                         log::info!(
                             "New connection: {:?}/{:?}",
                             addr,
                             stream.peer_addr().unwrap()
                         );
 
-                        // Send initial information, without anyone asking.
-                        // This makes it a tiny bit easier to map the broker map for new Clients.
-                        match send_tcp_msg(&mut stream, &broker_hello) {
-                            Ok(()) => {}
-                            Err(e) => {
-                                log::error!("Error sending initial hello: {e:?}");
+                        // Run the handshake on its own thread, so a slow peer does not block others
+                        #[cfg(feature = "secure_channel")]
+                        if let Some(secure_channel) = secure_channel.clone() {
+                            if pending_handshakes.fetch_add(1, Ordering::AcqRel)
+                                >= LLMP_CFG_MAX_PENDING_HANDSHAKES
+                            {
+                                pending_handshakes.fetch_sub(1, Ordering::AcqRel);
+                                log::warn!(
+                                    "Too many pending handshakes, dropping connection from {addr:?}"
+                                );
                                 continue;
                             }
+                            let pending_handshakes = pending_handshakes.clone();
+                            let accepted_sender = accepted_sender.clone();
+                            thread::spawn(move || {
+                                match LlmpTcpStream::accept(stream, Some(&secure_channel)) {
+                                    Ok(stream) => {
+                                        // The handler only exits with the listener
+                                        let _ = accepted_sender.send(AcceptedStream::Tcp(stream));
+                                    }
+                                    Err(e) => {
+                                        log::warn!("Rejecting connection from {addr:?}: {e:?}");
+                                    }
+                                }
+                                pending_handshakes.fetch_sub(1, Ordering::AcqRel);
+                            });
+                            continue;
                         }
 
-                        let buf = match recv_tcp_msg(&mut stream) {
-                            Ok(buf) => buf,
-                            Err(e) => {
-                                log::error!("Error receving from tcp: {e:?}");
-                                continue;
-                            }
-                        };
-
-                        // log::info!("{:#?}", buf);
-                        let req = match buf.try_into() {
-                            Ok(req) => req,
-                            Err(e) => {
-                                log::error!("Could not deserialize tcp message: {e:?}");
-                                continue;
-                            }
-                        };
-
-                        Self::handle_tcp_request(
-                            stream,
-                            &req,
-                            &mut current_client_id,
-                            &mut tcp_incoming_sender,
-                            &broker_shmem_description,
-                        );
+                        AcceptedStream::Tcp(LlmpTcpStream::Plain(stream))
                     }
-                    ListenerStream::Empty() => {}
+                    #[cfg(unix)]
+                    ListenerStream::Unix(stream) => {
+                        log::info!("New connection on unix socket: {stream:?}");
+                        AcceptedStream::Unix(stream)
+                    }
+                    ListenerStream::Empty() => continue,
+                };
+                if accepted_sender.send(accepted).is_err() {
+                    log::error!("The peer handler thread exited, stopping the listener");
+                    break;
                 }
             }
         });
@@ -3715,8 +4134,58 @@ where
     #[cfg(feature = "std")]
     /// Create a [`LlmpClient`], getting the ID from a given port, then also tell the restarter's ID so we ask to be removed later
     /// This is called when, for the first time, the restarter attaches to this process.
-    pub fn create_attach_to_tcp(mut shmem_provider: SP, port: u16) -> Result<Self, Error> {
-        let mut stream = match TcpStream::connect((IP_LOCALHOST, port)) {
+    pub fn create_attach_to_tcp(shmem_provider: SP, port: u16) -> Result<Self, Error> {
+        let stream = Self::connect_to_broker_port(port)?;
+        Self::attach_to_stream(shmem_provider, LlmpTcpStream::Plain(stream))
+    }
+
+    /// Create a [`LlmpClient`], attaching to the broker listening on the unix domain socket at `path`,
+    /// waiting for it to come up.
+    #[cfg(all(unix, feature = "std"))]
+    pub fn create_attach_to_unix<P>(shmem_provider: SP, path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let stream = loop {
+            match UnixStream::connect(path) {
+                Ok(stream) => break stream,
+                // The broker did not bind (yet), loop till the broker is up
+                Err(e)
+                    if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) =>
+                {
+                    log::debug!("Unix socket {} not ready. Retrying...", path.display());
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => {
+                    return Err(Error::os_error(
+                        e,
+                        format!("Failed to connect to {}", path.display()),
+                    ));
+                }
+            }
+        };
+        log::info!("Connected to unix socket {}", path.display());
+        Self::attach_to_stream(shmem_provider, stream)
+    }
+
+    /// Create a [`LlmpClient`], getting the ID from a given port, like [`LlmpClient::create_attach_to_tcp`].
+    /// Authenticates to the broker with the given [`SecureChannelConfig`].
+    #[cfg(feature = "secure_channel")]
+    pub fn create_attach_to_tcp_secure(
+        shmem_provider: SP,
+        port: u16,
+        secure_channel: &SecureChannelConfig,
+    ) -> Result<Self, Error> {
+        let stream = Self::connect_to_broker_port(port)?;
+        let stream = LlmpTcpStream::connect(stream, Some(secure_channel))?;
+        Self::attach_to_stream(shmem_provider, stream)
+    }
+
+    /// Connect to the broker on the given local port, waiting for it to come up
+    #[cfg(feature = "std")]
+    fn connect_to_broker_port(port: u16) -> Result<TcpStream, Error> {
+        let stream = match TcpStream::connect((IP_LOCALHOST, port)) {
             Ok(stream) => stream,
             Err(e) => {
                 match e.kind() {
@@ -3738,7 +4207,15 @@ where
             }
         };
         log::info!("Connected to port {port}");
+        Ok(stream)
+    }
 
+    /// Attach to the broker on the other end of the given stream
+    #[cfg(feature = "std")]
+    fn attach_to_stream<S>(mut shmem_provider: SP, mut stream: S) -> Result<Self, Error>
+    where
+        S: Read + Write,
+    {
         let TcpResponse::BrokerConnectHello {
             broker_shmem_description,
             hostname: _,
@@ -3933,4 +4410,84 @@ mod tests {
         // We want at least the tcp and sender clients.
         assert_eq!(broker.inner.llmp_clients.len(), 2);
     }
+
+    #[test]
+    #[serial]
+    #[cfg(unix)]
+    #[cfg_attr(miri, ignore)]
+    fn test_llmp_connection_unix() {
+        let path = std::env::temp_dir().join(format!("libafl_llmp_{}.sock", std::process::id()));
+        let shmem_provider = StdShMemProvider::new().unwrap();
+        let mut broker = match LlmpConnection::on_unix_path(shmem_provider.clone(), &path).unwrap()
+        {
+            IsClient { client: _ } => panic!("Could not bind to unix socket as broker"),
+            IsBroker { broker } => broker,
+        };
+        let mut client = match LlmpConnection::on_unix_path(shmem_provider, &path).unwrap() {
+            IsBroker { broker: _ } => panic!("Second connect should be a client!"),
+            IsClient { client } => client,
+        };
+
+        // Give the (background) listener thread a few millis to post the message
+        sleep(Duration::from_millis(100));
+        broker.broker_once().unwrap();
+
+        let tag: Tag = Tag(0x1337);
+        client.send_buf(tag, &[1_u8]).unwrap();
+        broker.broker_once().unwrap();
+        let (_sender_id, tag2, arr2) = client.recv_buf_blocking().unwrap();
+        assert_eq!(tag, tag2);
+        assert_eq!(arr2, [1_u8]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(feature = "secure_channel")]
+    #[cfg_attr(miri, ignore)]
+    fn test_llmp_connection_secure() {
+        use std::net::TcpStream;
+
+        use crate::{
+            IP_LOCALHOST,
+            secure_channel::{PreSharedKey, SecureChannelConfig},
+        };
+
+        let config = SecureChannelConfig::new(PreSharedKey::from_passphrase("correct horse"));
+        let wrong = SecureChannelConfig::new(PreSharedKey::from_passphrase("battery staple"));
+        let shmem_provider = StdShMemProvider::new().unwrap();
+        let mut broker =
+            match LlmpConnection::broker_on_port_secure(shmem_provider.clone(), 1338, &config)
+                .unwrap()
+            {
+                IsClient { client: _ } => panic!("Could not bind to port as broker"),
+                IsBroker { broker } => broker,
+            };
+
+        // A silent peer must not stall the handshakes of others
+        let _silent = TcpStream::connect((IP_LOCALHOST, 1338)).unwrap();
+
+        // A peer with the wrong key is rejected
+        assert!(
+            LlmpClient::create_attach_to_tcp_secure(shmem_provider.clone(), 1338, &wrong).is_err()
+        );
+
+        let mut client =
+            LlmpClient::create_attach_to_tcp_secure(shmem_provider, 1338, &config).unwrap();
+
+        // Give the (background) tcp thread a few millis to post the message
+        sleep(Duration::from_millis(100));
+        broker.broker_once().unwrap();
+
+        let tag: Tag = Tag(0x1337);
+        client.send_buf(tag, &[1_u8]).unwrap();
+        broker.broker_once().unwrap();
+        let (_sender_id, tag2, arr2) = client.recv_buf_blocking().unwrap();
+        assert_eq!(tag, tag2);
+        assert_eq!(arr2, [1_u8]);
+
+        // Only the tcp listener client and the authenticated client were added
+        assert_eq!(broker.inner.llmp_clients.len(), 2);
+    }
 }
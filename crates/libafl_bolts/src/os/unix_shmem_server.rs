@@ -3,6 +3,11 @@ On `Android`, we can only share maps between processes by serializing fds over s
 On `MacOS`, we cannot rely on reference counting for Maps.
 Hence, the `unix_shmem_server` keeps track of existing maps, creates new maps for clients,
 and forwards them over unix domain sockets.
+
+Since the maps are passed as fds, this also works for processes in other namespaces,
+for example, with `memfd` maps (see `MemfdServedShMemProvider`).
+Abstract unix sockets are bound to the network namespace, so for these,
+set [`LIBAFL_SHMEM_SERVICE_PATH`] to a socket path all processes can reach.
 */
 
 use alloc::{
@@ -21,10 +26,8 @@ use core::{
     mem::ManuallyDrop,
     ops::{Deref, DerefMut},
 };
-#[cfg(target_vendor = "apple")]
-use std::fs;
 use std::{
-    env,
+    env, fs,
     io::{Read, Write},
     os::fd::{AsFd, BorrowedFd},
     sync::{Condvar, Mutex},
@@ -62,6 +65,15 @@ const UNIX_SERVER_NAME: &str = "./libafl_unix_shmem_server";
 /// Env variable. If set, we won't try to spawn the service
 const AFL_SHMEM_SERVICE_STARTED: &str = "AFL_SHMEM_SERVICE_STARTED";
 
+/// Env variable to set the path of the unix socket of the [`ShMemService`].
+/// Names starting with `@` are abstract sockets.
+pub const LIBAFL_SHMEM_SERVICE_PATH: &str = "LIBAFL_SHMEM_SERVICE_PATH";
+
+/// The name of the unix socket of the [`ShMemService`], from [`LIBAFL_SHMEM_SERVICE_PATH`], if set
+fn service_name() -> String {
+    env::var(LIBAFL_SHMEM_SERVICE_PATH).unwrap_or_else(|_| UNIX_SERVER_NAME.to_string())
+}
+
 ///     s out served shared maps, as used on Android.
 #[derive(Debug)]
 pub struct ServedShMemProvider<SP> {
@@ -181,11 +193,12 @@ where
         // Needed for `MacOS` and Android to get sharedmaps working.
         let service = ShMemService::<SP>::start();
 
+        let service_name = service_name();
         let mut res = Self {
-            stream: UnixStream::connect_to_unix_addr(&UnixSocketAddr::new(UNIX_SERVER_NAME)?).map_err(|err| Error::illegal_state(if cfg!(target_vendor = "apple") {
-                format!("The ServedShMemProvider was not started or is no longer running. You may need to remove the './libafl_unix_shmem_server' file and retry. Error details: {err:?}")
-            } else {
+            stream: UnixStream::connect_to_unix_addr(&UnixSocketAddr::new(&service_name)?).map_err(|err| Error::illegal_state(if service_name.starts_with('@') {
                 format!("The ServedShMemProvider was not started or is no longer running. Error details: {err:?}")
+            } else {
+                format!("The ServedShMemProvider was not started or is no longer running. You may need to remove the '{service_name}' file and retry. Error details: {err:?}")
             }))?,
             inner: SP::new()?,
             id: -1,
@@ -241,8 +254,7 @@ where
             //self.stream = UnixStream::connect_to_unix_addr(&UnixSocketAddr::new(UNIX_SERVER_NAME)?)?,
 
             // After fork, the child needs to reconnect as to not share the fds with the parent.
-            self.stream =
-                UnixStream::connect_to_unix_addr(&UnixSocketAddr::new(UNIX_SERVER_NAME)?)?;
+            self.stream = UnixStream::connect_to_unix_addr(&UnixSocketAddr::new(&service_name())?)?;
             let (id, _) = self.send_receive(ServedShMemRequest::PostForkChildHello(self.id))?;
             self.id = id;
         }
@@ -348,8 +360,9 @@ impl Drop for ShMemServiceThread {
     fn drop(&mut self) {
         if self.join_handle.is_some() {
             log::info!("Stopping ShMemService");
+            let service_name = service_name();
             let Ok(mut stream) =
-                UnixStream::connect_to_unix_addr(&UnixSocketAddr::new(UNIX_SERVER_NAME).unwrap())
+                UnixStream::connect_to_unix_addr(&UnixSocketAddr::new(&service_name).unwrap())
             else {
                 return;
             };
@@ -370,8 +383,9 @@ impl Drop for ShMemServiceThread {
                 .expect("Failed to join ShMemService thread!")
                 .expect("Error in ShMemService background thread!");
             // try to remove the file from fs, and ignore errors.
-            #[cfg(target_vendor = "apple")]
-            fs::remove_file(UNIX_SERVER_NAME).unwrap();
+            if !service_name.starts_with('@') {
+                let _ = fs::remove_file(&service_name);
+            }
 
             // TODO: Audit that the environment access only happens in single-threaded code.
             unsafe { env::remove_var(AFL_SHMEM_SERVICE_STARTED) };
@@ -410,7 +424,7 @@ where
                     return Err(e);
                 }
             };
-            match worker.listen(UNIX_SERVER_NAME, &childsyncpair) {
+            match worker.listen(&service_name(), &childsyncpair) {
                 Err(e) => {
                     log::error!("Error spawning ShMemService: {e:?}");
                     Err(e)
@@ -0,0 +1,646 @@
+//! Authenticated, and optionally encrypted, channels over byte streams, based on a pre-shared key.
+//!
+//! Both peers prove knowledge of the [`PreSharedKey`] in a challenge-response [`SecureHandshake`],
+//! then derive fresh per-direction session keys.
+//! Each message is authenticated with a keyed `blake3` MAC, or encrypted with `ChaCha20Poly1305`,
+//! and numbered, so that forged, replayed, or reordered messages are rejected.
+//!
+//! The handshake and the message layer are transport-agnostic, see [`SecureSender`] and [`SecureReceiver`].
+//! For blocking streams, such as [`std::net::TcpStream`], the [`SecureStream`] wraps all of this up.
+
+use alloc::{string::ToString, vec::Vec};
+use core::{fmt, time::Duration};
+use std::io::{self, ErrorKind, Read, Write};
+
+use chacha20poly1305::{
+    ChaCha20Poly1305, KeyInit, Nonce,
+    aead::{Aead, Payload},
+};
+
+use crate::Error;
+
+/// The magic bytes at the start of each handshake hello
+const HANDSHAKE_MAGIC: [u8; 8] = *b"LIBAFLSC";
+/// The version of the handshake and message format
+const HANDSHAKE_VERSION: u8 = 1;
+/// The length of the random nonces exchanged in the handshake
+const NONCE_LEN: usize = 32;
+/// The length of a handshake hello: magic, version, encryption flag, nonce
+const HELLO_LEN: usize = HANDSHAKE_MAGIC.len() + 2 + NONCE_LEN;
+/// The length of a handshake proof, and of a MAC
+const MAC_LEN: usize = 32;
+/// The length of the transcript both sides agree on: both nonces and the encryption flag
+const TRANSCRIPT_LEN: usize = 2 * NONCE_LEN + 1;
+
+/// How long to wait for the peer during the handshake, before rejecting it
+pub const SECURE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The largest frame a [`SecureStream`] sends or accepts:
+/// a message as large as a default-sized LLMP map (256 MiB), and its MAC
+pub const SECURE_FRAME_MAX_LEN: usize = (1 << 28) + MAC_LEN;
+
+/// A 256-bit key, shared among all peers in advance
+#[derive(Clone, PartialEq, Eq)]
+pub struct PreSharedKey([u8; 32]);
+
+impl PreSharedKey {
+    /// Creates a [`PreSharedKey`] from raw key bytes
+    #[must_use]
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    /// Derives a [`PreSharedKey`] from a passphrase.
+    ///
+    /// The passphrase should be long and random, since it is not stretched.
+    #[must_use]
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        Self(blake3::derive_key(
+            "LibAFL secure channel v1 pre-shared key",
+            passphrase.as_bytes(),
+        ))
+    }
+}
+
+impl fmt::Debug for PreSharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PreSharedKey(..)")
+    }
+}
+
+/// The configuration of a secure channel, shared by both peers
+#[derive(Debug, Clone)]
+pub struct SecureChannelConfig {
+    psk: PreSharedKey,
+    encrypt: bool,
+}
+
+impl SecureChannelConfig {
+    /// Creates a new [`SecureChannelConfig`], authenticating, but not encrypting, all messages
+    #[must_use]
+    pub fn new(psk: PreSharedKey) -> Self {
+        Self {
+            psk,
+            encrypt: false,
+        }
+    }
+
+    /// Also encrypt all messages. Both peers need to agree on this.
+    #[must_use]
+    pub fn with_encryption(mut self, encrypt: bool) -> Self {
+        self.encrypt = encrypt;
+        self
+    }
+
+    /// If messages are encrypted
+    #[must_use]
+    pub fn encrypt(&self) -> bool {
+        self.encrypt
+    }
+}
+
+/// The side of the connection, used to tell the derived keys apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecureChannelRole {
+    /// The peer that opened the connection
+    Initiator,
+    /// The peer that accepted the connection
+    Responder,
+}
+
+impl SecureChannelRole {
+    fn peer(self) -> Self {
+        match self {
+            Self::Initiator => Self::Responder,
+            Self::Responder => Self::Initiator,
+        }
+    }
+
+    fn proof_context(self) -> &'static [u8] {
+        match self {
+            Self::Initiator => b"LibAFL secure channel v1 initiator proof",
+            Self::Responder => b"LibAFL secure channel v1 responder proof",
+        }
+    }
+
+    fn key_context(self) -> &'static str {
+        match self {
+            Self::Initiator => "LibAFL secure channel v1 initiator session key",
+            Self::Responder => "LibAFL secure channel v1 responder session key",
+        }
+    }
+}
+
+/// The handshake of a secure channel.
+///
+/// 1. Both peers send their [`SecureHandshake::hello`], containing a fresh random nonce.
+/// 2. Both peers feed the peer's hello into [`SecureHandshake::receive_hello`], and send the returned proof.
+/// 3. Both peers check the peer's proof with [`SecureHandshake::finish`], and get the [`SecureSender`] and [`SecureReceiver`].
+pub struct SecureHandshake {
+    config: SecureChannelConfig,
+    role: SecureChannelRole,
+    nonce: [u8; NONCE_LEN],
+    transcript: Option<[u8; TRANSCRIPT_LEN]>,
+}
+
+impl fmt::Debug for SecureHandshake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureHandshake")
+            .field("config", &self.config)
+            .field("role", &self.role)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SecureHandshake {
+    /// Starts a new handshake for the given side of the connection
+    pub fn new(config: &SecureChannelConfig, role: SecureChannelRole) -> Result<Self, Error> {
+        let mut nonce = [0; NONCE_LEN];
+        getrandom::fill(&mut nonce)
+            .map_err(|err| Error::unknown(format!("Could not get random nonce: {err}")))?;
+        Ok(Self {
+            config: config.clone(),
+            role,
+            nonce,
+            transcript: None,
+        })
+    }
+
+    /// The hello to send to the peer
+    #[must_use]
+    pub fn hello(&self) -> Vec<u8> {
+        let mut hello = Vec::with_capacity(HELLO_LEN);
+        hello.extend_from_slice(&HANDSHAKE_MAGIC);
+        hello.push(HANDSHAKE_VERSION);
+        hello.push(u8::from(self.config.encrypt));
+        hello.extend_from_slice(&self.nonce);
+        hello
+    }
+
+    /// Receive the hello of the peer, returns the proof to send to the peer
+    pub fn receive_hello(&mut self, peer_hello: &[u8]) -> Result<Vec<u8>, Error> {
+        if peer_hello.len() != HELLO_LEN || peer_hello[..HANDSHAKE_MAGIC.len()] != HANDSHAKE_MAGIC {
+            return Err(Error::illegal_state(
+                "Peer did not start a secure channel handshake",
+            ));
+        }
+        let version = peer_hello[HANDSHAKE_MAGIC.len()];
+        if version != HANDSHAKE_VERSION {
+            return Err(Error::illegal_state(format!(
+                "Peer uses secure channel version {version}, expected {HANDSHAKE_VERSION}"
+            )));
+        }
+        if peer_hello[HANDSHAKE_MAGIC.len() + 1] != u8::from(self.config.encrypt) {
+            return Err(Error::illegal_state(
+                "Peer does not agree on secure channel encryption",
+            ));
+        }
+        let peer_nonce = &peer_hello[HELLO_LEN - NONCE_LEN..];
+
+        let (initiator_nonce, responder_nonce) = match self.role {
+            SecureChannelRole::Initiator => (&self.nonce[..], peer_nonce),
+            SecureChannelRole::Responder => (peer_nonce, &self.nonce[..]),
+        };
+        let mut transcript = [0; TRANSCRIPT_LEN];
+        transcript[..NONCE_LEN].copy_from_slice(initiator_nonce);
+        transcript[NONCE_LEN..2 * NONCE_LEN].copy_from_slice(responder_nonce);
+        transcript[2 * NONCE_LEN] = u8::from(self.config.encrypt);
+        self.transcript = Some(transcript);
+
+        Ok(self.proof(self.role, &transcript).as_bytes().to_vec())
+    }
+
+    /// Check the proof of the peer, returns the channel halves on success
+    pub fn finish(self, peer_proof: &[u8]) -> Result<(SecureSender, SecureReceiver), Error> {
+        let Some(transcript) = self.transcript else {
+            return Err(Error::illegal_state(
+                "Secure channel handshake finished before receiving the peer hello",
+            ));
+        };
+        let Ok(peer_proof) = <[u8; MAC_LEN]>::try_from(peer_proof) else {
+            return Err(Error::illegal_state(
+                "Peer sent a malformed secure channel proof",
+            ));
+        };
+        // `blake3::Hash` compares in constant time
+        if self.proof(self.role.peer(), &transcript) != peer_proof {
+            return Err(Error::illegal_state(
+                "Peer failed secure channel authentication",
+            ));
+        }
+
+        let encrypt = self.config.encrypt;
+        Ok((
+            SecureSender::new(self.session_key(self.role, &transcript), encrypt),
+            SecureReceiver::new(self.session_key(self.role.peer(), &transcript), encrypt),
+        ))
+    }
+
+    fn proof(&self, role: SecureChannelRole, transcript: &[u8]) -> blake3::Hash {
+        blake3::Hasher::new_keyed(&self.config.psk.0)
+            .update(role.proof_context())
+            .update(transcript)
+            .finalize()
+    }
+
+    fn session_key(&self, role: SecureChannelRole, transcript: &[u8]) -> [u8; 32] {
+        blake3::Hasher::new_derive_key(role.key_context())
+            .update(&self.config.psk.0)
+            .update(transcript)
+            .finalize()
+            .into()
+    }
+}
+
+/// The nonce for the message with the given sequence number
+fn nonce_for(seq: u64) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[4..].copy_from_slice(&seq.to_le_bytes());
+    nonce
+}
+
+/// The sending half of a secure channel
+pub struct SecureSender {
+    key: [u8; 32],
+    cipher: Option<ChaCha20Poly1305>,
+    seq: u64,
+}
+
+impl fmt::Debug for SecureSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureSender")
+            .field("encrypt", &self.cipher.is_some())
+            .field("seq", &self.seq)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SecureSender {
+    fn new(key: [u8; 32], encrypt: bool) -> Self {
+        Self {
+            key,
+            cipher: encrypt.then(|| ChaCha20Poly1305::new(&key.into())),
+            seq: 0,
+        }
+    }
+
+    /// Authenticate, and maybe encrypt, the next message
+    pub fn seal(&mut self, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        let seq = self.seq;
+        self.seq += 1;
+        if let Some(cipher) = &self.cipher {
+            cipher
+                .encrypt(&nonce_for(seq), msg)
+                .map_err(|_| Error::illegal_state("Failed to encrypt message"))
+        } else {
+            let mac = blake3::Hasher::new_keyed(&self.key)
+                .update(&seq.to_le_bytes())
+                .update(msg)
+                .finalize();
+            let mut sealed = Vec::with_capacity(msg.len() + MAC_LEN);
+            sealed.extend_from_slice(msg);
+            sealed.extend_from_slice(mac.as_bytes());
+            Ok(sealed)
+        }
+    }
+}
+
+/// The receiving half of a secure channel
+pub struct SecureReceiver {
+    key: [u8; 32],
+    cipher: Option<ChaCha20Poly1305>,
+    seq: u64,
+}
+
+impl fmt::Debug for SecureReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureReceiver")
+            .field("encrypt", &self.cipher.is_some())
+            .field("seq", &self.seq)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SecureReceiver {
+    fn new(key: [u8; 32], encrypt: bool) -> Self {
+        Self {
+            key,
+            cipher: encrypt.then(|| ChaCha20Poly1305::new(&key.into())),
+            seq: 0,
+        }
+    }
+
+    /// Check, and maybe decrypt, the next message.
+    ///
+    /// Fails for forged, replayed, or reordered messages.
+    pub fn open(&mut self, sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        let seq = self.seq;
+        let msg = if let Some(cipher) = &self.cipher {
+            cipher
+                .decrypt(
+                    &nonce_for(seq),
+                    Payload {
+                        msg: sealed,
+                        aad: &[],
+                    },
+                )
+                .map_err(|_| Error::illegal_state("Message failed secure channel authentication"))?
+        } else {
+            if sealed.len() < MAC_LEN {
+                return Err(Error::illegal_state(
+                    "Message too short for secure channel authentication",
+                ));
+            }
+            let (msg, mac) = sealed.split_at(sealed.len() - MAC_LEN);
+            let expected = blake3::Hasher::new_keyed(&self.key)
+                .update(&seq.to_le_bytes())
+                .update(msg)
+                .finalize();
+            // `blake3::Hash` compares in constant time
+            if expected != <[u8; MAC_LEN]>::try_from(mac).unwrap() {
+                return Err(Error::illegal_state(
+                    "Message failed secure channel authentication",
+                ));
+            }
+            msg.to_vec()
+        };
+        self.seq += 1;
+        Ok(msg)
+    }
+}
+
+/// Write one frame as `u32` big endian len and `[u8; len]` bytes
+fn write_frame<S>(stream: &mut S, frame: &[u8]) -> io::Result<()>
+where
+    S: Write,
+{
+    let len = u32::try_from(frame.len())
+        .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "Secure channel frame too large"))?;
+    stream.write_all(&len.to_be_bytes())?;
+    stream.write_all(frame)?;
+    stream.flush()
+}
+
+/// Read one frame written by [`write_frame`], of at most `max_len` bytes
+fn read_frame<S>(stream: &mut S, max_len: usize) -> io::Result<Vec<u8>>
+where
+    S: Read,
+{
+    let mut len_buf = [0; 4];
+    stream.read_exact(&mut len_buf)?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > max_len {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "Secure channel frame too large",
+        ));
+    }
+    let mut frame = vec![0; len];
+    stream.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+/// A blocking stream, authenticated (and maybe encrypted) with a secure channel.
+///
+/// Written bytes are buffered, and sent as one message on [`Write::flush`].
+/// Make sure to flush after each message, for the peer to receive it.
+pub struct SecureStream<S> {
+    inner: S,
+    sender: SecureSender,
+    receiver: SecureReceiver,
+    write_buf: Vec<u8>,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    /// A partially received frame, including its length, kept across [`ErrorKind::WouldBlock`]
+    pending: Vec<u8>,
+    pending_len: usize,
+}
+
+impl<S> fmt::Debug for SecureStream<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureStream")
+            .field("inner", &self.inner)
+            .field("sender", &self.sender)
+            .field("receiver", &self.receiver)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> SecureStream<S>
+where
+    S: Read + Write,
+{
+    /// Run the handshake as the given side of the connection, and wrap the stream.
+    ///
+    /// Fails, if the peer does not know the [`PreSharedKey`], or does not agree on the configuration.
+    pub fn handshake(
+        mut inner: S,
+        config: &SecureChannelConfig,
+        role: SecureChannelRole,
+    ) -> Result<Self, Error> {
+        let mut handshake = SecureHandshake::new(config, role)?;
+        write_frame(&mut inner, &handshake.hello())?;
+        let peer_hello = read_frame(&mut inner, HELLO_LEN)?;
+        let proof = handshake.receive_hello(&peer_hello)?;
+        write_frame(&mut inner, &proof)?;
+        let peer_proof = read_frame(&mut inner, MAC_LEN)?;
+        let (sender, receiver) = handshake.finish(&peer_proof)?;
+        Ok(Self {
+            inner,
+            sender,
+            receiver,
+            write_buf: vec![],
+            read_buf: vec![],
+            read_pos: 0,
+            pending: vec![],
+            pending_len: 0,
+        })
+    }
+
+    /// Connect as [`SecureChannelRole::Initiator`], see [`SecureStream::handshake`]
+    pub fn connect(inner: S, config: &SecureChannelConfig) -> Result<Self, Error> {
+        Self::handshake(inner, config, SecureChannelRole::Initiator)
+    }
+
+    /// Accept as [`SecureChannelRole::Responder`], see [`SecureStream::handshake`]
+    pub fn accept(inner: S, config: &SecureChannelConfig) -> Result<Self, Error> {
+        Self::handshake(inner, config, SecureChannelRole::Responder)
+    }
+
+    /// The inner stream
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// The inner stream (mutable).
+    ///
+    /// Reading from or writing to it directly will break the channel.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Read until the pending frame is complete, then open it
+    fn fill_read_buf(&mut self) -> io::Result<()> {
+        loop {
+            if self.pending_len < 4 {
+                self.pending.resize(4, 0);
+            } else {
+                let len = u32::from_be_bytes(self.pending[..4].try_into().unwrap()) as usize;
+                if len > SECURE_FRAME_MAX_LEN {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "Secure channel frame too large",
+                    ));
+                }
+                if self.pending_len == 4 + len {
+                    break;
+                }
+                self.pending.resize(4 + len, 0);
+            }
+            let read = self.inner.read(&mut self.pending[self.pending_len..])?;
+            if read == 0 {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+            self.pending_len += read;
+        }
+        self.read_buf = self
+            .receiver
+            .open(&self.pending[4..self.pending_len])
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err.to_string()))?;
+        self.read_pos = 0;
+        self.pending.clear();
+        self.pending_len = 0;
+        Ok(())
+    }
+}
+
+impl<S> Read for SecureStream<S>
+where
+    S: Read + Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.read_pos == self.read_buf.len() {
+            self.fill_read_buf()?;
+        }
+        let len = buf.len().min(self.read_buf.len() - self.read_pos);
+        buf[..len].copy_from_slice(&self.read_buf[self.read_pos..self.read_pos + len]);
+        self.read_pos += len;
+        Ok(len)
+    }
+}
+
+impl<S> Write for SecureStream<S>
+where
+    S: Read + Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.write_buf.is_empty() {
+            let sealed = self
+                .sender
+                .seal(&self.write_buf)
+                .map_err(|err| io::Error::new(ErrorKind::InvalidData, err.to_string()))?;
+            self.write_buf.clear();
+            if sealed.len() > SECURE_FRAME_MAX_LEN {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "Secure channel message too large",
+                ));
+            }
+            write_frame(&mut self.inner, &sealed)?;
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use super::{
+        PreSharedKey, SecureChannelConfig, SecureChannelRole, SecureHandshake, SecureReceiver,
+        SecureSender, SecureStream,
+    };
+    use crate::Error;
+
+    type Halves = (SecureSender, SecureReceiver);
+
+    fn handshake(
+        initiator: &SecureChannelConfig,
+        responder: &SecureChannelConfig,
+    ) -> Result<(Halves, Halves), Error> {
+        let mut a = SecureHandshake::new(initiator, SecureChannelRole::Initiator)?;
+        let mut b = SecureHandshake::new(responder, SecureChannelRole::Responder)?;
+        let a_proof = a.receive_hello(&b.hello())?;
+        let b_proof = b.receive_hello(&a.hello())?;
+        Ok((a.finish(&b_proof)?, b.finish(&a_proof)?))
+    }
+
+    #[test]
+    fn test_secure_channel() {
+        for encrypt in [false, true] {
+            let config = SecureChannelConfig::new(PreSharedKey::from_passphrase("correct horse"))
+                .with_encryption(encrypt);
+            let ((mut a_send, _a_recv), (_b_send, mut b_recv)) =
+                handshake(&config, &config).unwrap();
+
+            let first = a_send.seal(b"first").unwrap();
+            let second = a_send.seal(b"second").unwrap();
+            assert_eq!(encrypt, !first.windows(5).any(|w| w == b"first"));
+            // reordered
+            assert!(b_recv.open(&second).is_err());
+            assert_eq!(b_recv.open(&first).unwrap(), b"first");
+            // replayed
+            assert!(b_recv.open(&first).is_err());
+
+            let mut forged = second.clone();
+            forged[0] ^= 1;
+            assert!(b_recv.open(&forged).is_err());
+            assert_eq!(b_recv.open(&second).unwrap(), b"second");
+        }
+
+        let config = SecureChannelConfig::new(PreSharedKey::from_passphrase("correct horse"));
+        let wrong = SecureChannelConfig::new(PreSharedKey::from_passphrase("battery staple"));
+        assert!(handshake(&config, &wrong).is_err());
+        assert!(handshake(&config, &config.clone().with_encryption(true)).is_err());
+    }
+
+    #[test]
+    fn test_secure_stream() {
+        let config = SecureChannelConfig::new(PreSharedKey::new([7; 32])).with_encryption(true);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server_config = config.clone();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = SecureStream::accept(stream, &server_config).unwrap();
+            let mut buf = [0; 5];
+            stream.read_exact(&mut buf).unwrap();
+            stream.write_all(&buf).unwrap();
+            stream.flush().unwrap();
+        });
+
+        let mut stream = SecureStream::connect(TcpStream::connect(addr).unwrap(), &config).unwrap();
+        stream.write_all(b"hello").unwrap();
+        stream.flush().unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        server.join().unwrap();
+    }
+}
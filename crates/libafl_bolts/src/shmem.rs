@@ -106,6 +106,15 @@ pub type StdServedShMemProvider = RcShMemProvider<ServedShMemProvider<MmapShMemP
 ))]
 pub type StdServedShMemProvider = RcShMemProvider<ServedShMemProvider<MmapShMemProvider>>;
 
+/// A served shmem provider, handing out `memfd` maps as fds over a unix socket.
+/// Processes can map these even without a shared `/dev/shm` or IPC namespace, such as sandboxed clients.
+#[cfg(all(
+    feature = "std",
+    any(target_os = "linux", target_os = "android", target_os = "freebsd")
+))]
+pub type MemfdServedShMemProvider =
+    RcShMemProvider<ServedShMemProvider<unix_shmem::memfd::MemfdShMemProvider>>;
+
 /// Description of a shared map.
 /// May be used to restore the map by id.
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -1,14 +1,21 @@
 //! LLVM style control flow graph with information of AFL-style index of the each
 //! edges, use together with ``AFLCoverage`` pass having --dump-afl-cfg flag enabled.
+//! Calls are read from `=>{function name}` lines following a basic block.
 
 extern crate alloc;
 
-use alloc::collections::BinaryHeap;
-use core::marker::PhantomData;
+use alloc::collections::{BinaryHeap, VecDeque};
+use core::{cmp::Reverse, marker::PhantomData};
 use std::collections::{HashMap, HashSet};
 
 use serde::{Deserialize, Serialize};
 
+use crate::Error;
+
+/// How much further away a call is than an edge, for the distances of
+/// [`ControlFlowGraph::calculate_distances_to_targets`]. `AFLGo` uses the same factor.
+pub const CALL_DISTANCE_FACTOR: u32 = 10;
+
 /// Compute the weight of a [`CfgEdge`]. Lower means shorter distance in the graph.
 pub trait HasWeight<T> {
     /// Compute the weight of a [`CfgEdge`]. Lower means shorter distance in the graph.
@@ -76,7 +83,7 @@ impl EntryBasicBlockInfo {
 }
 
 /// An LLVM style control flow graph.
-/// Note: This does not track across functions.
+/// Note: Edges do not cross functions, calls are only tracked per basic block.
 #[derive(Debug)]
 pub struct ControlFlowGraph<T>
 where
@@ -88,6 +95,8 @@ where
     edges: Vec<Option<CfgEdge<T>>>,
     /// Mapping each function's name to its corresponding entry basic block information.
     func_to_entry_bb: HashMap<String, EntryBasicBlockInfo>,
+    /// Mapping each basic block to the names of the functions it calls.
+    bb_to_calls: HashMap<usize, Vec<String>>,
 }
 
 impl<T> ControlFlowGraph<T>
@@ -103,6 +112,7 @@ where
         Self {
             edges: (0..map_size).map(|_| None).collect(),
             func_to_entry_bb: HashMap::default(),
+            bb_to_calls: HashMap::default(),
         }
     }
 
@@ -127,6 +137,7 @@ where
     current_bb: usize,
     bb_to_func: HashMap<usize, String>,
     bb_to_successors: HashMap<usize, Vec<usize>>,
+    bb_to_calls: HashMap<usize, Vec<String>>,
     func_to_entry_bb: HashMap<String, usize>,
     phantom: PhantomData<T>,
 }
@@ -140,6 +151,7 @@ where
             current_bb: 0,
             bb_to_func: HashMap::default(),
             bb_to_successors: HashMap::default(),
+            bb_to_calls: HashMap::default(),
             func_to_entry_bb: HashMap::default(),
             phantom: PhantomData,
         }
@@ -167,6 +179,13 @@ where
                     }
                 }
             }
+            "=>" => {
+                // "=>{function name}": Current basic block calls {function name}.
+                self.bb_to_calls
+                    .entry(self.current_bb)
+                    .or_default()
+                    .push(line_content.to_string());
+            }
             "%%" => {
                 // "%%{function name}+{index}": Make current basic block to be {index}.
                 let mut splitter = line_content.split('+');
@@ -216,11 +235,12 @@ where
         }
 
         for (bb_loc, successor_locs) in &bb_to_successors_with_zero {
-            let current_func = match bb_loc {
-                0 => self.bb_to_func.get(&successor_locs[0]).unwrap(),
-                _ => self.bb_to_func.get(bb_loc).unwrap(),
-            };
             for successor_loc in successor_locs {
+                // The edges from zero lead into the entries of different functions
+                let current_func = match bb_loc {
+                    0 => self.bb_to_func.get(successor_loc).unwrap(),
+                    _ => self.bb_to_func.get(bb_loc).unwrap(),
+                };
                 let xored_loc = (*bb_loc >> 1) ^ (*successor_loc);
                 let mut edge = CfgEdge {
                     xored_loc,
@@ -239,6 +259,7 @@ where
                 cfg.insert_edge(xored_loc, edge);
             }
         }
+        cfg.bb_to_calls.clone_from(&self.bb_to_calls);
         cfg
     }
 }
@@ -292,6 +313,12 @@ where
         self.func_to_entry_bb.get_mut(func_name)
     }
 
+    /// Get the names of the functions called by a basic block.
+    #[must_use]
+    pub fn get_calls(&self, node_loc: usize) -> &[String] {
+        self.bb_to_calls.get(&node_loc).map_or(&[], Vec::as_slice)
+    }
+
     /// Calculate shortest distance from start edge to all other edges
     /// in the function containing such ``start``.
     ///
@@ -330,6 +357,106 @@ where
         }
         distances
     }
+
+    /// Calculate the distance from every edge to the ``target_funcs``, e.g. for directed fuzzing,
+    /// similar to `AFLGo`.
+    ///
+    /// Each function first gets its shortest distance to a target function in the call graph.
+    /// Edges inside a target function have a distance of `0`. An edge into a basic block calling
+    /// a function at call graph distance `d` has a distance of [`CALL_DISTANCE_FACTOR`] `* (d + 1)`.
+    /// Going backwards within each function, every other edge is one step further away than its
+    /// closest successor, weighted by [`HasWeight`].
+    /// Edges that cannot reach any target would not be inserted in the returned hash map.
+    ///
+    /// Targets are function names, `file:line` targets are rejected as the CFG has no line information.
+    pub fn calculate_distances_to_targets(
+        &self,
+        target_funcs: &[&str],
+    ) -> Result<HashMap<usize, u32>, Error> {
+        if let Some(target) = target_funcs.iter().find(|target| {
+            target
+                .rsplit_once(':')
+                .is_some_and(|(_, line)| line.parse::<u32>().is_ok())
+        }) {
+            return Err(Error::InvalidArguments(format!(
+                "Unsupported target {target}: the CFG has no line information, use function names"
+            )));
+        }
+
+        // Reverse the call graph, mapping each function to its callers.
+        let mut callers: HashMap<&str, HashSet<&str>> = HashMap::new();
+        for edge in self.edges.iter().flatten() {
+            for callee in self.get_calls(edge.bottom_node_loc) {
+                callers
+                    .entry(callee.as_str())
+                    .or_default()
+                    .insert(edge.calling_func.as_str());
+            }
+        }
+        let mut func_distances: HashMap<&str, u32> =
+            target_funcs.iter().map(|func| (*func, 0)).collect();
+        let mut to_visit_funcs: VecDeque<&str> = target_funcs.iter().copied().collect();
+        while let Some(func) = to_visit_funcs.pop_front() {
+            let distance = func_distances[func] + 1;
+            for &caller in callers.get(func).into_iter().flatten() {
+                if !func_distances.contains_key(caller) {
+                    func_distances.insert(caller, distance);
+                    to_visit_funcs.push_back(caller);
+                }
+            }
+        }
+
+        // Reverse the graph, mapping each edge to the edges that lead into it.
+        let mut predecessors: HashMap<usize, Vec<usize>> = HashMap::new();
+        for edge in self.edges.iter().flatten() {
+            for successor in &edge.successor_edges {
+                predecessors
+                    .entry(*successor)
+                    .or_default()
+                    .push(edge.xored_loc);
+            }
+        }
+
+        let mut distances: HashMap<usize, u32> = HashMap::new();
+        let mut to_visit = BinaryHeap::new(); // BinaryHeap<Reverse<(distance, loc)>>
+        for edge in self.edges.iter().flatten() {
+            let distance = if target_funcs.contains(&edge.calling_func.as_str()) {
+                Some(0)
+            } else {
+                self.get_calls(edge.bottom_node_loc)
+                    .iter()
+                    .filter_map(|callee| func_distances.get(callee.as_str()))
+                    .min()
+                    .map(|distance| CALL_DISTANCE_FACTOR * (distance + 1))
+            };
+            if let Some(distance) = distance {
+                distances.insert(edge.xored_loc, distance);
+                to_visit.push(Reverse((distance, edge.xored_loc)));
+            }
+        }
+
+        let mut visited = HashSet::new();
+        while let Some(Reverse((distance, edge))) = to_visit.pop() {
+            if !visited.insert(edge) {
+                continue;
+            }
+            let Some(edge_info) = self.get_edge(edge) else {
+                continue;
+            };
+            let new_distance = distance + edge_info.get_weight();
+            for predecessor in predecessors.get(&edge).into_iter().flatten() {
+                let is_shorter = distances
+                    .get(predecessor)
+                    .is_none_or(|&current| new_distance < current);
+
+                if is_shorter {
+                    distances.insert(*predecessor, new_distance);
+                    to_visit.push(Reverse((new_distance, *predecessor)));
+                }
+            }
+        }
+        Ok(distances)
+    }
 }
 
 impl<T> Default for ControlFlowGraph<T>
@@ -343,7 +470,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::cfg::{ControlFlowGraph, HasWeight};
+    use crate::cfg::{CALL_DISTANCE_FACTOR, ControlFlowGraph, HasWeight};
 
     struct TestMetadata {}
 
@@ -400,4 +527,36 @@ mod tests {
         assert_eq!(*distances.get(&((26911 >> 1) ^ 41925)).unwrap(), 2);
         assert!(!distances.contains_key(&((41864 >> 1) ^ 52706)));
     }
+
+    // As above, with main (26911) calling _ZN7MyClass1VEi
+    const TEST_CALL_GRAPH_STR: &str = "$$main+41864\n$$_ZN7MyClass1VEi+50306\n%%_ZN7MyClass1VEi+50306\n->19123\n%%main+41864\n->52706\n->26911\n%%main+52706\n%%main+26911\n->52706\n->41925\n=>_ZN7MyClass1VEi\n";
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Testcase takes too long in miri. :/
+    fn test_distances_to_targets() {
+        let cfg: ControlFlowGraph<TestMetadata> =
+            ControlFlowGraph::from_content(TEST_CALL_GRAPH_STR);
+        assert_eq!(cfg.get_calls(26911), ["_ZN7MyClass1VEi"]);
+
+        let distances = cfg
+            .calculate_distances_to_targets(&["_ZN7MyClass1VEi"])
+            .unwrap();
+        assert_eq!(*distances.get(&((50306 >> 1) ^ 19123)).unwrap(), 0);
+        // 26911 calls the target directly
+        assert_eq!(
+            *distances.get(&((41864 >> 1) ^ 26911)).unwrap(),
+            CALL_DISTANCE_FACTOR
+        );
+        // The synthetic edge into the entry of main is one edge further away
+        assert_eq!(*distances.get(&41864).unwrap(), CALL_DISTANCE_FACTOR + 1);
+        // Edges after the call cannot reach the target
+        assert!(!distances.contains_key(&((26911 >> 1) ^ 41925)));
+        assert!(!distances.contains_key(&((41864 >> 1) ^ 52706)));
+
+        let distances = cfg.calculate_distances_to_targets(&["main"]).unwrap();
+        assert_eq!(*distances.get(&((41864 >> 1) ^ 26911)).unwrap(), 0);
+        assert!(!distances.contains_key(&((50306 >> 1) ^ 19123)));
+
+        assert!(cfg.calculate_distances_to_targets(&["main.c:42"]).is_err());
+    }
 }
@@ -8,7 +8,7 @@ use std::{
 
 use libafl::{
     Error, HasScheduler, StdFuzzer,
-    corpus::Corpus,
+    corpus::{Corpus, StdGreedyCorpusMinimizer},
     events::{SendExiting, SimpleRestartingEventManager},
     executors::{ExitKind, InProcessExecutor},
     feedback_and_fast, feedback_or_fast,
@@ -103,6 +103,7 @@ pub fn merge(
         MappedEdgeMapObserver::new(edges_observer, SizeTimeValueObserver::new(time));
 
     let map_feedback = MinMapFeedback::new(&edges_observer);
+    let minimizer = StdGreedyCorpusMinimizer::new(&edges_observer);
 
     // Create an OOM observer to monitor if an OOM has occurred
     let oom_observer = OomObserver::new(options.rss_limit(), options.malloc_limit());
@@ -210,6 +211,9 @@ pub fn merge(
             .on_remove(&mut state, id, &Some(testcase))?;
     }
 
+    // The scheduler kept the smallest and fastest input per edge; distill these to a greedy set cover
+    minimizer.minimize(&mut fuzzer, &mut executor, &mut mgr, &mut state)?;
+
     for id in fuzzer.scheduler().current().clone() {
         let mut testcase = state.corpus_mut().get(id)?.borrow_mut();
         let file_path = testcase
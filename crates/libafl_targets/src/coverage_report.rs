@@ -0,0 +1,502 @@
+//! Source-level coverage reports (lcov and HTML) for targets instrumented with `sancov_pcguard`.
+//!
+//! The corpus is replayed, hit guards are mapped to the PCs of the `sanitizer_cov` `pc_table`
+//! and these PCs are symbolized using the DWARF info of the loaded modules.
+//! The target needs to be compiled with `-fsanitize-coverage=trace-pc-guard,pc-table` and `-g`.
+//!
+//! Guards and PC tables are both registered per module, in load order, so the `n`-th entry of
+//! the concatenated PC tables belongs to the `n`-th guard, i.e. to the `n`-th entry of the edges map.
+//! This does not hold for `pointer_maps` wrapping around the map.
+
+use alloc::{
+    borrow::Cow,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{ffi::c_void, fmt::Write as _, marker::PhantomData, mem::MaybeUninit, time::Duration};
+use std::{
+    ffi::CStr,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use addr2line::Loader;
+use hashbrown::HashMap;
+use libafl::{
+    Error,
+    corpus::{Corpus, CorpusId},
+    executors::HasObservers,
+    fuzzer::ExecutesInput,
+    inputs::Input,
+    observers::{MapObserver, ObserversTuple},
+    stages::{Restartable, Stage},
+    state::HasCorpus,
+};
+use libafl_bolts::{
+    current_time,
+    tuples::{Handle, Handled},
+};
+
+use crate::sancov_pcguard::sanitizer_cov_pc_table;
+
+/// The file name of the lcov trace file written by [`CoverageReport::write`]
+pub const LCOV_FILE: &str = "coverage.info";
+
+/// The file name of the HTML summary written by [`CoverageReport::write`]
+pub const HTML_FILE: &str = "index.html";
+
+/// `e_type` of non-relocatable ELF executables
+const ET_EXEC: u16 = 2;
+
+/// A PC resolved to its source location
+#[derive(Debug, Clone)]
+struct SourceLocation {
+    file: String,
+    line: u32,
+    function: Option<String>,
+}
+
+/// A loaded module of the current process
+struct Module {
+    loader: Loader,
+    /// If DWARF addresses are relative to the load address (PIE and shared objects)
+    relative: bool,
+}
+
+/// Resolves PCs of the current process to source locations via `dladdr` and DWARF
+#[derive(Default)]
+struct Symbolizer {
+    modules: HashMap<usize, Option<Module>>,
+}
+
+impl Symbolizer {
+    fn load_module(path: &str) -> Option<Module> {
+        let mut header = [0_u8; 18];
+        File::open(path).ok()?.read_exact(&mut header).ok()?;
+        if &header[..4] != b"\x7fELF" {
+            return None;
+        }
+        let e_type = u16::from_le_bytes([header[16], header[17]]);
+        Some(Module {
+            loader: Loader::new(path).ok()?,
+            relative: e_type != ET_EXEC,
+        })
+    }
+
+    fn resolve(&mut self, pc: usize) -> Option<SourceLocation> {
+        let mut info = MaybeUninit::<libc::Dl_info>::zeroed();
+        // # Safety
+        // `dladdr` only writes to `info`, which is zeroed and large enough.
+        if unsafe { libc::dladdr(pc as *const c_void, info.as_mut_ptr()) } == 0 {
+            return None;
+        }
+        // # Safety
+        // `dladdr` succeeded, so `info` is initialized.
+        let info = unsafe { info.assume_init() };
+        let base = info.dli_fbase as usize;
+        let module = self.modules.entry(base).or_insert_with(|| {
+            if info.dli_fname.is_null() {
+                return None;
+            }
+            // # Safety
+            // `dli_fname` is a valid C string for loaded modules.
+            let path = unsafe { CStr::from_ptr(info.dli_fname) }.to_string_lossy();
+            Self::load_module(&path)
+        });
+        let module = module.as_ref()?;
+
+        let probe = if module.relative { pc - base } else { pc } as u64;
+        let location = module.loader.find_location(probe).ok()??;
+        let function = module
+            .loader
+            .find_symbol(probe)
+            .map(|name| addr2line::demangle_auto(Cow::from(name), None).into_owned());
+        Some(SourceLocation {
+            file: location.file?.to_string(),
+            line: location.line?,
+            function,
+        })
+    }
+}
+
+/// The coverage of a single function
+#[derive(Debug, Clone, Default)]
+pub struct FunctionCoverage {
+    /// The first line of this function
+    pub line: u32,
+    /// The number of instrumented PCs in this function
+    pub pcs: usize,
+    /// The number of hit PCs in this function
+    pub hit_pcs: usize,
+}
+
+/// The coverage of a single source file
+#[derive(Debug, Clone, Default)]
+pub struct FileCoverage {
+    /// All instrumented lines, and if they were hit
+    pub lines: BTreeMap<u32, bool>,
+    /// All instrumented functions
+    pub functions: BTreeMap<String, FunctionCoverage>,
+}
+
+impl FileCoverage {
+    /// The number of hit lines
+    #[must_use]
+    pub fn hit_lines(&self) -> usize {
+        self.lines.values().filter(|hit| **hit).count()
+    }
+
+    /// The number of functions with at least one hit PC
+    #[must_use]
+    pub fn hit_functions(&self) -> usize {
+        self.functions
+            .values()
+            .filter(|function| function.hit_pcs > 0)
+            .count()
+    }
+}
+
+/// Source-level coverage, built from the hit guards of the edges map
+#[derive(Debug, Clone, Default)]
+pub struct CoverageReport {
+    files: BTreeMap<String, FileCoverage>,
+}
+
+impl CoverageReport {
+    /// Builds the report from per-guard hits, where `hits[n]` tells if the `n`-th guard was hit.
+    #[must_use]
+    pub fn from_guard_hits(hits: &[bool]) -> Self {
+        let mut symbolizer = Symbolizer::default();
+        let mut report = Self::default();
+        for (idx, entry) in sanitizer_cov_pc_table().flatten().enumerate() {
+            let hit = hits.get(idx).copied().unwrap_or(false);
+            if let Some(location) = symbolizer.resolve(entry.addr()) {
+                report.add(location, hit, entry.is_function_entry());
+            }
+        }
+        report
+    }
+
+    fn add(&mut self, location: SourceLocation, hit: bool, function_entry: bool) {
+        let file = self.files.entry(location.file).or_default();
+        *file.lines.entry(location.line).or_default() |= hit;
+        if let Some(function) = location.function {
+            let function = file
+                .functions
+                .entry(function)
+                .or_insert_with(|| FunctionCoverage {
+                    line: location.line,
+                    ..FunctionCoverage::default()
+                });
+            if function_entry || location.line < function.line {
+                function.line = location.line;
+            }
+            function.pcs += 1;
+            if hit {
+                function.hit_pcs += 1;
+            }
+        }
+    }
+
+    /// The coverage of all source files, by path
+    #[must_use]
+    pub fn files(&self) -> &BTreeMap<String, FileCoverage> {
+        &self.files
+    }
+
+    /// Renders this report as lcov trace file
+    #[must_use]
+    pub fn to_lcov(&self) -> String {
+        let mut out = String::from("TN:\n");
+        for (path, file) in &self.files {
+            let _ = writeln!(out, "SF:{path}");
+            for (name, function) in &file.functions {
+                let _ = writeln!(out, "FN:{},{name}", function.line);
+            }
+            for (name, function) in &file.functions {
+                let _ = writeln!(out, "FNDA:{},{name}", u8::from(function.hit_pcs > 0));
+            }
+            let _ = writeln!(out, "FNF:{}", file.functions.len());
+            let _ = writeln!(out, "FNH:{}", file.hit_functions());
+            for (line, hit) in &file.lines {
+                let _ = writeln!(out, "DA:{line},{}", u8::from(*hit));
+            }
+            let _ = writeln!(out, "LF:{}", file.lines.len());
+            let _ = writeln!(out, "LH:{}", file.hit_lines());
+            out.push_str("end_of_record\n");
+        }
+        out
+    }
+
+    /// Renders a simple HTML summary, with the coverage per file and per function
+    #[must_use]
+    pub fn to_html(&self) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Coverage</title></head><body>\n",
+        );
+        out.push_str("<h1>Coverage</h1>\n<table border=\"1\">\n");
+        out.push_str("<tr><th>File</th><th>Lines</th><th>Functions</th></tr>\n");
+        for (path, file) in &self.files {
+            let _ = writeln!(
+                out,
+                "<tr><td><a href=\"#{}\">{}</a></td><td>{}</td><td>{}</td></tr>",
+                html_escape(path),
+                html_escape(path),
+                ratio(file.hit_lines(), file.lines.len()),
+                ratio(file.hit_functions(), file.functions.len()),
+            );
+        }
+        out.push_str("</table>\n");
+        for (path, file) in &self.files {
+            let _ = writeln!(
+                out,
+                "<h2 id=\"{}\">{}</h2>\n<table border=\"1\">",
+                html_escape(path),
+                html_escape(path)
+            );
+            out.push_str("<tr><th>Function</th><th>Line</th><th>PCs</th></tr>\n");
+            for (name, function) in &file.functions {
+                let _ = writeln!(
+                    out,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    html_escape(name),
+                    function.line,
+                    ratio(function.hit_pcs, function.pcs),
+                );
+            }
+            out.push_str("</table>\n");
+        }
+        out.push_str("</body></html>\n");
+        out
+    }
+
+    /// Writes the lcov trace file and the HTML summary to `dir`
+    pub fn write<P>(&self, dir: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(LCOV_FILE), self.to_lcov())?;
+        fs::write(dir.join(HTML_FILE), self.to_html())?;
+        Ok(())
+    }
+}
+
+/// Formats `hit / total (percent)`
+#[expect(clippy::cast_precision_loss)]
+fn ratio(hit: usize, total: usize) -> String {
+    if total == 0 {
+        return "-".to_string();
+    }
+    format!(
+        "{hit} / {total} ({:.1}%)",
+        hit as f64 * 100.0 / total as f64
+    )
+}
+
+/// Escapes a string for use in HTML
+fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Runs the corpus entry `id` and marks all covered entries of the map in `hits`
+fn replay_entry<C, E, EM, I, O, S, Z>(
+    fuzzer: &mut Z,
+    executor: &mut E,
+    state: &mut S,
+    manager: &mut EM,
+    map_observer_handle: &Handle<C>,
+    id: CorpusId,
+    hits: &mut Vec<bool>,
+) -> Result<(), Error>
+where
+    C: AsRef<O>,
+    E: HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    I: Input,
+    O: MapObserver,
+    S: HasCorpus<I>,
+    Z: ExecutesInput<E, EM, I, S>,
+{
+    let input = state.corpus().cloned_input_for_id(id)?;
+    fuzzer.execute_input(state, executor, manager, &input)?;
+
+    let observers = executor.observers();
+    let map = observers[map_observer_handle].as_ref();
+    let initial = map.initial();
+    let len = map.usable_count();
+    if hits.len() < len {
+        hits.resize(len, false);
+    }
+    for (idx, hit) in hits.iter_mut().enumerate().take(len) {
+        *hit |= map.get(idx) != initial;
+    }
+    Ok(())
+}
+
+/// Replays the whole corpus and returns which entries of the edges map were hit.
+/// Pass the result to [`CoverageReport::from_guard_hits`].
+pub fn replay_corpus_coverage<C, E, EM, I, O, S, Z>(
+    fuzzer: &mut Z,
+    executor: &mut E,
+    state: &mut S,
+    manager: &mut EM,
+    map_observer: &C,
+) -> Result<Vec<bool>, Error>
+where
+    C: AsRef<O> + Handled,
+    E: HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    I: Input,
+    O: MapObserver,
+    S: HasCorpus<I>,
+    Z: ExecutesInput<E, EM, I, S>,
+{
+    let handle = map_observer.handle();
+    let mut hits = Vec::new();
+    let ids: Vec<CorpusId> = state.corpus().ids().collect();
+    for id in ids {
+        replay_entry::<C, E, EM, I, O, S, Z>(
+            fuzzer, executor, state, manager, &handle, id, &mut hits,
+        )?;
+    }
+    Ok(hits)
+}
+
+/// A stage replaying new corpus entries and writing a [`CoverageReport`] to a directory every `interval`
+#[derive(Debug)]
+pub struct CoverageReportStage<C, I, O> {
+    map_observer_handle: Handle<C>,
+    output_dir: PathBuf,
+    interval: Duration,
+    last_report: Duration,
+    /// The last corpus entry replayed
+    last_replayed: Option<CorpusId>,
+    hits: Vec<bool>,
+    phantom: PhantomData<(I, O)>,
+}
+
+impl<C, I, O> CoverageReportStage<C, I, O>
+where
+    C: Handled,
+{
+    /// Creates a new [`CoverageReportStage`] for the edges map observed by `map_observer`
+    #[must_use]
+    pub fn new<P>(map_observer: &C, output_dir: P, interval: Duration) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            map_observer_handle: map_observer.handle(),
+            output_dir: output_dir.into(),
+            interval,
+            last_report: current_time(),
+            last_replayed: None,
+            hits: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<C, E, EM, I, O, S, Z> Stage<E, EM, S, Z> for CoverageReportStage<C, I, O>
+where
+    C: AsRef<O>,
+    E: HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    I: Input,
+    O: MapObserver,
+    S: HasCorpus<I>,
+    Z: ExecutesInput<E, EM, I, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let now = current_time();
+        if now.saturating_sub(self.last_report) < self.interval {
+            return Ok(());
+        }
+
+        let mut next = match self.last_replayed {
+            Some(id) => state.corpus().next(id),
+            None => state.corpus().first(),
+        };
+        while let Some(id) = next {
+            replay_entry::<C, E, EM, I, O, S, Z>(
+                fuzzer,
+                executor,
+                state,
+                manager,
+                &self.map_observer_handle,
+                id,
+                &mut self.hits,
+            )?;
+            self.last_replayed = Some(id);
+            next = state.corpus().next(id);
+        }
+
+        CoverageReport::from_guard_hits(&self.hits).write(&self.output_dir)?;
+        self.last_report = now;
+        Ok(())
+    }
+}
+
+impl<C, I, O, S> Restartable<S> for CoverageReportStage<C, I, O> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Replays are not critical, the entries will be replayed again if we crash
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CoverageReport, SourceLocation};
+
+    #[test]
+    fn test_lcov_output() {
+        let mut report = CoverageReport::default();
+        let location = |line, function: &str| SourceLocation {
+            file: "/src/a.c".into(),
+            line,
+            function: Some(function.into()),
+        };
+        report.add(location(3, "foo"), true, true);
+        report.add(location(4, "foo"), false, false);
+        report.add(location(10, "bar<int>"), false, true);
+
+        let lcov = report.to_lcov();
+        assert!(lcov.contains("SF:/src/a.c\n"));
+        assert!(lcov.contains("FN:3,foo\n"));
+        assert!(lcov.contains("FNDA:1,foo\n"));
+        assert!(lcov.contains("FNDA:0,bar<int>\n"));
+        assert!(lcov.contains("FNH:1\n"));
+        assert!(lcov.contains("DA:4,0\n"));
+        assert!(lcov.contains("LF:3\nLH:1\n"));
+
+        let html = report.to_html();
+        assert!(html.contains("bar&lt;int&gt;"));
+        assert!(html.contains("1 / 2 (50.0%)"));
+    }
+}
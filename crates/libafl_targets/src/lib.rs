@@ -69,6 +69,13 @@ pub mod sancov_pcguard;
 ))]
 pub use sancov_pcguard::*;
 
+#[cfg(all(
+    feature = "coverage_report",
+    target_os = "linux",
+    any(feature = "sancov_pcguard_edges", feature = "sancov_pcguard_hitcounts")
+))]
+pub mod coverage_report;
+
 #[cfg(any(feature = "sancov_cmplog", feature = "sancov_value_profile"))]
 pub mod sancov_cmp;
 #[cfg(any(feature = "sancov_cmplog", feature = "sancov_value_profile"))]
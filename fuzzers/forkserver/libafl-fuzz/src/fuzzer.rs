@@ -10,7 +10,7 @@ use std::{
 #[cfg(feature = "fuzzbench")]
 use libafl::events::SimpleEventManager;
 #[cfg(not(feature = "fuzzbench"))]
-use libafl::events::{CentralizedEventManager, LlmpRestartingEventManager};
+use libafl::events::{CentralizedEventManager, StdCentralizedInnerMgr};
 #[cfg(feature = "fuzzbench")]
 use libafl::monitors::SimpleMonitor;
 use libafl::{
@@ -80,7 +80,7 @@ pub type LibaflFuzzState =
 
 #[cfg(not(feature = "fuzzbench"))]
 type LibaflFuzzManager = CentralizedEventManager<
-    LlmpRestartingEventManager<(), BytesInput, LibaflFuzzState, StdShMem, StdShMemProvider>,
+    StdCentralizedInnerMgr<BytesInput, LibaflFuzzState, StdShMem, StdShMemProvider>,
     BytesInput,
     LibaflFuzzState,
     StdShMem,